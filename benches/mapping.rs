@@ -0,0 +1,116 @@
+//! Benchmarks of the per-event mapping under storms of synthetic events: the stick's mapping
+//! (`map_axis`, through `map_stick`), with its default and a longer filter pipeline, and resolving
+//! chord bindings. Run with `cargo bench --features bench`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use evdev::Key;
+use joykbd::bindings::{Bindings, KeyAction, Remap};
+use joykbd::stick::{Axis, DeadzoneShape, Stage, StickConstants, StickSide};
+use std::hint::black_box;
+
+/// How many events each storm has.
+const STORM_LEN: usize = 10_000;
+
+/// The stick going around in circles of growing and shrinking size, alternating axes, as a
+/// controller reports it.
+fn stick_storm() -> Vec<(Axis, i32)> {
+    (0..STORM_LEN)
+        .map(|i| {
+            let angle = i as f64 * 0.05;
+            let radius = 32767.0 * ((i % 200) as f64 / 100.0 - 1.0).abs();
+            match i % 2 {
+                0 => (Axis::X, (angle.cos() * radius) as i32),
+                _ => (Axis::Y, (angle.sin() * radius) as i32),
+            }
+        })
+        .collect()
+}
+
+/// Buttons mashed in overlapping presses, some of them bound to chords.
+fn key_storm() -> Vec<(Key, i32)> {
+    const KEYS: [Key; 4] = [Key::BTN_EAST, Key::BTN_SOUTH, Key::BTN_NORTH, Key::BTN_WEST];
+    (0..STORM_LEN)
+        .map(|i| {
+            let key = KEYS[(i / 2 + i / 7) % KEYS.len()];
+            (key, (i % 2 == 0) as i32)
+        })
+        .collect()
+}
+
+fn map_stick(c: &mut Criterion, name: &str, constants: impl Fn() -> StickConstants) {
+    let storm = stick_storm();
+    let mut group = c.benchmark_group("map_stick");
+    group.throughput(Throughput::Elements(storm.len() as u64));
+    group.bench_function(name, |b| {
+        b.iter_batched_ref(
+            &constants,
+            |constants| {
+                for &(axis, value) in &storm {
+                    black_box(constants.map_stick(StickSide::Left, axis, black_box(value)));
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+fn stick(c: &mut Criterion) {
+    let new = || StickConstants::new([20.0, 20.0], 2000, (150, -150));
+    map_stick(c, "radial", new);
+    map_stick(c, "axial", || {
+        let mut constants = new();
+        constants.deadzone_shape = DeadzoneShape::Axial;
+        constants
+    });
+    map_stick(c, "filtered", || {
+        let stages = [
+            Stage::Calibration,
+            Stage::Deadzone,
+            Stage::Smoothing(0.5),
+            Stage::Curve,
+            Stage::RateLimit(20.0),
+        ];
+        let mut constants = new();
+        constants.set_pipeline(Axis::X, &stages);
+        constants.set_pipeline(Axis::Y, &stages);
+        constants
+    });
+}
+
+fn chords(c: &mut Criterion) {
+    let remaps: Vec<Remap> = [
+        "BTN_EAST=KEY_LEFTCTRL+KEY_C",
+        "BTN_SOUTH=KEY_LEFTCTRL+KEY_LEFTSHIFT+KEY_V",
+        "BTN_NORTH=KEY_A",
+    ]
+    .iter()
+    .map(|remap| remap.parse().unwrap())
+    .collect();
+    let layer_remaps: Vec<Remap> = vec!["BTN_EAST=KEY_LEFTALT+KEY_TAB".parse().unwrap()];
+    let storm = key_storm();
+    let mut group = c.benchmark_group("bindings");
+    group.throughput(Throughput::Elements(storm.len() as u64));
+    group.bench_function("chords", |b| {
+        b.iter_batched_ref(
+            || Bindings::new(&remaps, &[Key::BTN_WEST], &[], &layer_remaps).unwrap(),
+            |bindings| {
+                for &(key, value) in &storm {
+                    match bindings.key(key, black_box(value)) {
+                        KeyAction::Chord(chord) => {
+                            black_box(chord.events(value));
+                        }
+                        action => {
+                            black_box(action);
+                        }
+                    }
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, stick, chords);
+criterion_main!(benches);
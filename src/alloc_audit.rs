@@ -0,0 +1,71 @@
+//! With the `alloc-audit` feature, heap allocations are counted, and any input event whose
+//! handling allocates is reported, to keep the per-event path free of them (and so cheap on
+//! battery-powered handhelds). Only the event loop's own thread's allocations count, not those
+//! of the sources' and other tasks' threads running at the same time.
+
+use evdev::InputEvent;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    // constant and without a destructor, so counting can't itself allocate
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count() {
+    let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+}
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Watches the handling of one event, reporting how many allocations it made when dropped.
+pub struct Audit {
+    event: InputEvent,
+    start: usize,
+}
+
+impl Audit {
+    pub fn new(event: InputEvent) -> Self {
+        Audit {
+            event,
+            start: allocations(),
+        }
+    }
+}
+
+impl Drop for Audit {
+    fn drop(&mut self) {
+        let allocations = allocations() - self.start;
+        if allocations > 0 {
+            eprintln!(
+                "alloc-audit: {} allocation(s) handling {:?} {}",
+                allocations,
+                self.event.kind(),
+                self.event.value()
+            );
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Using a stick as arrow keys, e.g. the left one of a pair of Joy-Cons while the right one moves
+//! the cursor. Pushing the stick past halfway holds down the arrow in that direction, until it's
+//! let back.
+
+use crate::stick::StickSide;
+use evdev::{EventType, InputEvent, InputEventKind, Key};
+use smallvec::{smallvec, SmallVec};
+
+/// How far the stick has to be pushed to press an arrow.
+const THRESHOLD: i32 = 15_000;
+
+/// The arrows for the horizontal (negative, positive) and vertical axes.
+pub const KEYS: [[Key; 2]; 2] = [
+    [Key::KEY_LEFT, Key::KEY_RIGHT],
+    [Key::KEY_UP, Key::KEY_DOWN],
+];
+
+pub struct StickArrows {
+    side: StickSide,
+    /// The arrow held for each axis.
+    pressed: [Option<Key>; 2],
+}
+
+impl StickArrows {
+    pub fn new(side: StickSide) -> Self {
+        StickArrows {
+            side,
+            pressed: [None; 2],
+        }
+    }
+
+    /// Whether `ev` is from the stick this is using.
+    pub fn handles(&self, ev: &InputEvent) -> bool {
+        match ev.kind() {
+            InputEventKind::AbsAxis(axis) => self.side.axis_index(axis).is_some(),
+            _ => false,
+        }
+    }
+
+    pub fn map(&mut self, ev: InputEvent) -> SmallVec<[InputEvent; 2]> {
+        let i = match ev.kind() {
+            InputEventKind::AbsAxis(axis) => match self.side.axis_index(axis) {
+                Some(i) => i,
+                None => return smallvec![],
+            },
+            _ => return smallvec![],
+        };
+        let key = match ev.value() {
+            v if v <= -THRESHOLD => Some(KEYS[i][0]),
+            v if v >= THRESHOLD => Some(KEYS[i][1]),
+            _ => None,
+        };
+        if key == self.pressed[i] {
+            return smallvec![];
+        }
+        let mut events = SmallVec::new();
+        if let Some(old) = self.pressed[i] {
+            events.push(InputEvent::new(EventType::KEY, old.code(), 0));
+        }
+        if let Some(new) = key {
+            events.push(InputEvent::new(EventType::KEY, new.code(), 1));
+        }
+        self.pressed[i] = key;
+        events
+    }
+}
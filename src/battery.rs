@@ -0,0 +1,72 @@
+//! Reading the controller's battery level from sysfs, as reported by e.g. hid-nintendo, and
+//! warning when it's low (`--battery-warn`).
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// Battery levels (in percent) to warn at when dropping below them.
+pub const WARN_LEVELS: &[u8] = &[20, 10, 5];
+
+pub struct Battery {
+    /// The power_supply directory in sysfs.
+    path: PathBuf,
+}
+
+impl Battery {
+    /// Find the battery of the input device at `dev_path` (e.g. /dev/input/event5), if it has one.
+    pub fn find(dev_path: &Path) -> Option<Self> {
+        let event = fs::canonicalize(dev_path).ok()?;
+        let event = event.file_name()?;
+        // the event device's parent is the input device, whose parent is e.g. the HID device
+        // that the power supply is registered under
+        let hid = Path::new("/sys/class/input")
+            .join(event)
+            .join("device/device/power_supply");
+        let supply = fs::read_dir(hid).ok()?.flatten().next()?;
+        Some(Battery {
+            path: supply.path(),
+        })
+    }
+
+    /// The current charge in percent.
+    pub fn level(&self) -> Option<u8> {
+        if let Ok(capacity) = fs::read_to_string(self.path.join("capacity")) {
+            return capacity.trim().parse().ok();
+        }
+        // hid-nintendo only reports a coarse level
+        let level = fs::read_to_string(self.path.join("capacity_level")).ok()?;
+        match level.trim() {
+            "Full" => Some(100),
+            "High" => Some(75),
+            "Normal" => Some(50),
+            "Low" => Some(20),
+            "Critical" => Some(5),
+            _ => None,
+        }
+    }
+
+    pub fn charging(&self) -> bool {
+        fs::read_to_string(self.path.join("status")).is_ok_and(|s| s.trim() == "Charging")
+    }
+}
+
+/// Show a desktop notification that the battery is down to `level`, through notify-send.
+pub async fn notify_low(level: u8) {
+    // without a notification daemon, the log's warning has to do
+    let _ = Command::new("notify-send")
+        .args([
+            "--app-name",
+            "joykbd",
+            "--urgency",
+            "critical",
+            "--icon",
+            "battery-low",
+        ])
+        .arg("Controller battery low")
+        .arg(format!("{}% left", level))
+        .stderr(Stdio::null())
+        .status()
+        .await;
+}
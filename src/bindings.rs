@@ -0,0 +1,855 @@
+//! User-defined remapping of source keys/buttons to output keys.
+
+use crate::config::Ignore;
+use crate::drift::DriftAdjust;
+use crate::gamepad::StickTuning;
+use crate::speed::SpeedMode;
+use evdev::{AttributeSet, EventType, InputEvent, Key, RelativeAxisType};
+use smallvec::SmallVec;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// The number of key codes the kernel defines (`KEY_CNT`).
+const KEY_CNT: u16 = 0x300;
+
+/// The most keys a chord can have.
+pub const MAX_CHORD: usize = 4;
+
+/// The most steps a macro can have.
+pub const MAX_MACRO: usize = 8;
+
+/// How long a `turbo` binding's press-release cycle takes by default, in milliseconds.
+pub const DEFAULT_TURBO_RATE_MS: u16 = 100;
+
+/// Parse an evdev key name like `KEY_ENTER` or `BTN_SOUTH`.
+pub fn parse_key(name: &str) -> Option<Key> {
+    (0..KEY_CNT)
+        .map(Key::new)
+        .find(|key| format!("{:?}", key) == name)
+}
+
+/// What a key can be bound to.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Key(Key),
+    /// Press the keys in order, e.g. modifiers and then a key, and release them in reverse.
+    Chord(Chord),
+    /// Move the cursor by exactly this many pixels along the axis, e.g. for precise positioning
+    /// with the D-pad.
+    Nudge(RelativeAxisType, i32),
+    /// Adjust (or save) the stick's drift threshold.
+    DriftThreshold(DriftAdjust),
+    /// Swap the left and right mouse buttons, or swap them back.
+    SwapClicks,
+    /// Change the stick's speed while held.
+    Speed(SpeedMode),
+    /// Press the key and keep it held until the binding is pressed again.
+    Latch(Key),
+    /// Disconnect the controller, putting it to sleep, see [`crate::power`].
+    PowerOff,
+    /// Press and release the key over and over while held, once every this many milliseconds.
+    Turbo(Key, u16),
+    /// Hold the first key if another key is pressed while this is held, e.g. a modifier, and
+    /// otherwise tap the second when it's released, like keyd's `overload`.
+    ModTap(Key, Key),
+    /// Tap the steps in order when pressed.
+    Macro(Macro),
+}
+
+impl FromStr for Action {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let (axis, sign) = match name {
+            "nudge-left" => (RelativeAxisType::REL_X, -1),
+            "nudge-right" => (RelativeAxisType::REL_X, 1),
+            "nudge-up" => (RelativeAxisType::REL_Y, -1),
+            "nudge-down" => (RelativeAxisType::REL_Y, 1),
+            "swap-clicks" | "sprint" | "precision" | "power-off" => {
+                if let Some(extra) = words.next() {
+                    return Err(format!("unexpected {:?} after {}", extra, name));
+                }
+                return Ok(match name {
+                    "swap-clicks" => Action::SwapClicks,
+                    "sprint" => Action::Speed(SpeedMode::Sprint),
+                    "power-off" => Action::PowerOff,
+                    _ => Action::Speed(SpeedMode::Precision),
+                });
+            }
+            "drift-threshold" => {
+                return match (words.next(), words.next()) {
+                    (Some(adjust), None) => adjust.parse().map(Action::DriftThreshold),
+                    _ => Err("usage: drift-threshold +[N]|-[N]|N|save".to_owned()),
+                };
+            }
+            "turbo" => {
+                let usage = || "usage: turbo KEY [MS]".to_owned();
+                let key = words.next().ok_or_else(usage)?;
+                let key = parse_key(key).ok_or_else(|| format!("unknown key {:?}", key))?;
+                let rate = match words.next() {
+                    Some(ms) => ms
+                        .parse()
+                        .ok()
+                        .filter(|&ms| ms > 0)
+                        .ok_or_else(|| format!("invalid turbo rate {:?}", ms))?,
+                    None => DEFAULT_TURBO_RATE_MS,
+                };
+                if words.next().is_some() {
+                    return Err(usage());
+                }
+                return Ok(Action::Turbo(key, rate));
+            }
+            "latch" => {
+                return match (words.next(), words.next()) {
+                    (Some(key), None) => parse_key(key)
+                        .map(Action::Latch)
+                        .ok_or_else(|| format!("unknown key {:?}", key)),
+                    _ => Err("usage: latch KEY".to_owned()),
+                };
+            }
+            "mod-tap" => {
+                let usage = || "usage: mod-tap HOLD TAP".to_owned();
+                let (hold, tap) = match (words.next(), words.next(), words.next()) {
+                    (Some(hold), Some(tap), None) => (hold, tap),
+                    _ => return Err(usage()),
+                };
+                let key = |name| parse_key(name).ok_or_else(|| format!("unknown key {:?}", name));
+                return Ok(Action::ModTap(key(hold)?, key(tap)?));
+            }
+            "macro" => {
+                let steps = words.map(str::parse).collect::<Result<Vec<_>, _>>()?;
+                return Macro::new(&steps).map(Action::Macro);
+            }
+            "page-up" => return Ok(Action::Key(Key::KEY_PAGEUP)),
+            "page-down" => return Ok(Action::Key(Key::KEY_PAGEDOWN)),
+            "home" => return Ok(Action::Key(Key::KEY_HOME)),
+            "end" => return Ok(Action::Key(Key::KEY_END)),
+            _ if s.contains('+') => return s.parse().map(Action::Chord),
+            _ => {
+                return parse_key(s)
+                    .map(Action::Key)
+                    .ok_or_else(|| format!("unknown key or action {:?}", s))
+            }
+        };
+        let px = match words.next() {
+            Some(px) => px
+                .parse::<u16>()
+                .map_err(|e| format!("invalid {} distance {:?}: {}", name, px, e))?,
+            None => 1,
+        };
+        if let Some(extra) = words.next() {
+            return Err(format!("unexpected {:?} after {}", extra, name));
+        }
+        Ok(Action::Nudge(axis, sign * i32::from(px)))
+    }
+}
+
+/// The action as it's written in a remap.
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Key(key) => write!(f, "{:?}", key),
+            Action::Chord(chord) => write!(f, "{}", chord),
+            Action::Nudge(axis, px) => {
+                let direction = match (*axis == RelativeAxisType::REL_X, *px < 0) {
+                    (true, true) => "left",
+                    (true, false) => "right",
+                    (false, true) => "up",
+                    (false, false) => "down",
+                };
+                write!(f, "nudge-{} {}", direction, px.abs())
+            }
+            Action::DriftThreshold(adjust) => write!(f, "drift-threshold {}", adjust),
+            Action::SwapClicks => f.write_str("swap-clicks"),
+            Action::Speed(mode) => f.write_str(mode.name()),
+            Action::Latch(key) => write!(f, "latch {:?}", key),
+            Action::PowerOff => f.write_str("power-off"),
+            Action::Turbo(key, ms) => write!(f, "turbo {:?} {}", key, ms),
+            Action::ModTap(hold, tap) => write!(f, "mod-tap {:?} {:?}", hold, tap),
+            Action::Macro(steps) => write!(f, "macro {}", steps),
+        }
+    }
+}
+
+/// Keys pressed together by a single binding, written joined by `+`, e.g.
+/// `KEY_LEFTCTRL+KEY_C`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    keys: [Key; MAX_CHORD],
+    len: u8,
+}
+
+impl Chord {
+    pub fn new(keys: &[Key]) -> Result<Self, String> {
+        if keys.is_empty() || keys.len() > MAX_CHORD {
+            return Err(format!("a chord has 1 to {} keys", MAX_CHORD));
+        }
+        let mut chord = Chord {
+            keys: [Key::KEY_RESERVED; MAX_CHORD],
+            len: keys.len() as u8,
+        };
+        chord.keys[..keys.len()].copy_from_slice(keys);
+        Ok(chord)
+    }
+
+    pub fn keys(&self) -> &[Key] {
+        &self.keys[..usize::from(self.len)]
+    }
+
+    /// The events for the chord's keys being pressed (in order), released (in reverse), or
+    /// repeated (just the last key, like holding a shortcut).
+    pub fn events(&self, value: i32) -> SmallVec<[InputEvent; MAX_CHORD]> {
+        let event = |key: &Key| InputEvent::new(EventType::KEY, key.code(), value);
+        match value {
+            0 => self.keys().iter().rev().map(event).collect(),
+            1 => self.keys().iter().map(event).collect(),
+            _ => self.keys().last().map(event).into_iter().collect(),
+        }
+    }
+}
+
+impl From<Key> for Chord {
+    fn from(key: Key) -> Self {
+        Chord::new(&[key]).unwrap()
+    }
+}
+
+impl FromStr for Chord {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keys = s
+            .split('+')
+            .map(|key| parse_key(key.trim()).ok_or_else(|| format!("unknown key {:?}", key)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Chord::new(&keys)
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, key) in self.keys().iter().enumerate() {
+            if i > 0 {
+                f.write_str("+")?;
+            }
+            write!(f, "{:?}", key)?;
+        }
+        Ok(())
+    }
+}
+
+/// Chords tapped one after another by a single binding, written separated by spaces, e.g.
+/// `KEY_H KEY_LEFTSHIFT+KEY_I`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Macro {
+    steps: [Chord; MAX_MACRO],
+    len: u8,
+}
+
+impl Macro {
+    pub fn new(steps: &[Chord]) -> Result<Self, String> {
+        if steps.is_empty() || steps.len() > MAX_MACRO {
+            return Err(format!("a macro has 1 to {} steps", MAX_MACRO));
+        }
+        let mut macro_ = Macro {
+            steps: [Key::KEY_RESERVED.into(); MAX_MACRO],
+            len: steps.len() as u8,
+        };
+        macro_.steps[..steps.len()].copy_from_slice(steps);
+        Ok(macro_)
+    }
+
+    pub fn steps(&self) -> &[Chord] {
+        &self.steps[..usize::from(self.len)]
+    }
+
+    /// The events for each step being pressed and released in turn.
+    pub fn events(&self) -> impl Iterator<Item = InputEvent> + '_ {
+        self.steps()
+            .iter()
+            .flat_map(|step| step.events(1).into_iter().chain(step.events(0)))
+    }
+}
+
+impl From<Key> for Macro {
+    fn from(key: Key) -> Self {
+        Macro::new(&[key.into()]).unwrap()
+    }
+}
+
+impl fmt::Display for Macro {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, step) in self.steps().iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}", step)?;
+        }
+        Ok(())
+    }
+}
+
+/// A single `[NAME:]FROM=TO` remap, as passed on the command line.
+#[derive(Clone)]
+pub struct Remap {
+    /// What the remap can be enabled/disabled as at runtime, instead of its FROM key's name.
+    pub name: Option<String>,
+    pub from: Key,
+    pub to: Action,
+}
+
+impl FromStr for Remap {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (from, to) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected [NAME:]FROM=TO, got {:?}", s))?;
+        let (name, from) = match from.rsplit_once(':') {
+            Some((name, from)) => (Some(name.to_owned()), from),
+            None => (None, from),
+        };
+        Ok(Remap {
+            name,
+            from: parse_key(from).ok_or_else(|| format!("unknown key {:?}", from))?,
+            to: to.parse()?,
+        })
+    }
+}
+
+/// What the bindings did with a key event.
+pub enum KeyAction {
+    /// Emit the event as this key instead.
+    Emit(Key),
+    /// Emit the event as these keys instead, see [`Chord::events`].
+    Chord(Chord),
+    /// Emit this relative motion instead of the key press.
+    Nudge(RelativeAxisType, i32),
+    DriftThreshold(DriftAdjust),
+    /// Emit these keys' taps instead, whatever the event was, see [`Macro::events`].
+    Macro(Macro),
+    /// The key is used by the bindings themselves (e.g. a layer key) and shouldn't be emitted.
+    Consumed,
+    /// There's no binding for the key, so it should get its default treatment.
+    Unbound,
+}
+
+/// A held `turbo` binding's output key, and when it next changes.
+struct Turbo {
+    to: Key,
+    /// How long the key stays pressed, and then released.
+    half_period: Duration,
+    down: bool,
+    next: Instant,
+}
+
+/// A held `mod-tap` binding's keys, and whether another key was pressed while it was held, so
+/// it's holding its modifier rather than waiting to tap.
+struct ModTap {
+    hold: Key,
+    tap: Key,
+    held: bool,
+}
+
+/// The remapping state shared by every input source, so e.g. a layer key held on one device
+/// affects the keys of all the others.
+#[derive(Default)]
+pub struct Bindings {
+    base: HashMap<Key, Action>,
+    layer: HashMap<Key, Action>,
+    layer_keys: AttributeSet<Key>,
+    layer_held: AttributeSet<Key>,
+    layer_toggle_keys: AttributeSet<Key>,
+    layer_latched: bool,
+    /// Whether the left and right mouse buttons are swapped on the output.
+    clicks_swapped: bool,
+    /// The keys bound to a speed mode that are currently held.
+    speed_held: HashMap<Key, SpeedMode>,
+    /// Each remap's name, and the layer (whether it's a --layer-remap) and key it's for.
+    names: HashMap<String, (bool, Key)>,
+    /// The remaps that were disabled at runtime, by layer and key.
+    disabled: HashSet<(bool, Key)>,
+    /// Groups of remaps, by layer and key, of which only one can be active at a time.
+    groups: Vec<Vec<(bool, Key)>>,
+    /// The output keys held by `latch` bindings.
+    latched: HashSet<Key>,
+    /// Output keys to release, because another member of their group was activated.
+    releases: Vec<Key>,
+    /// The output key each currently pressed source key was mapped to, so it's released properly
+    /// even if the layer changed in the meantime.
+    pressed: HashMap<Key, Chord>,
+    /// The currently pressed source keys that weren't bound when pressed, so they get their
+    /// default treatment when released too, even if the layer changed in the meantime.
+    unbound: HashSet<Key>,
+    /// Whether a `power-off` binding was pressed.
+    power_off: bool,
+    /// The held `turbo` bindings, by source key.
+    turbo: HashMap<Key, Turbo>,
+    /// The held `mod-tap` bindings, by source key.
+    mod_taps: HashMap<Key, ModTap>,
+    /// Output keys to press before the event that caused it, because a `mod-tap` binding
+    /// became its modifier.
+    presses: Vec<Key>,
+    /// The remaps that take the place of the base ones in keyboard mode.
+    keyboard: HashMap<Key, Action>,
+    keyboard_mode: bool,
+    /// The kinds of input that are ignored altogether.
+    ignore: Ignore,
+    /// How the sticks are reshaped in gamepad mode.
+    stick_tuning: StickTuning,
+}
+
+impl Bindings {
+    /// Fails if two bindings have the same name. A later remap of the same key (e.g. a --remap of
+    /// one the config file maps) replaces the earlier one, and can keep its name.
+    pub fn new(
+        remaps: &[Remap],
+        layer_keys: &[Key],
+        layer_toggle_keys: &[Key],
+        layer_remaps: &[Remap],
+    ) -> Result<Self, String> {
+        // remaps are named after their key unless they're given a name, with the layer's
+        // prefixed by "layer:"
+        let mut names = HashMap::new();
+        for (r, layer) in remaps
+            .iter()
+            .map(|r| (r, false))
+            .chain(layer_remaps.iter().map(|r| (r, true)))
+        {
+            let name = match (&r.name, layer) {
+                (Some(name), _) => name.clone(),
+                (None, false) => format!("{:?}", r.from),
+                (None, true) => format!("layer:{:?}", r.from),
+            };
+            match names.get(&name) {
+                Some(&other) if other != (layer, r.from) => {
+                    return Err(format!("two bindings are called {:?}", name))
+                }
+                _ => names.insert(name, (layer, r.from)),
+            };
+        }
+        Ok(Bindings {
+            base: remaps.iter().map(|r| (r.from, r.to)).collect(),
+            layer: layer_remaps.iter().map(|r| (r.from, r.to)).collect(),
+            names,
+            layer_keys: layer_keys.iter().copied().collect(),
+            layer_toggle_keys: layer_toggle_keys.iter().copied().collect(),
+            ..Default::default()
+        })
+    }
+
+    pub fn layer_latched(&self) -> bool {
+        self.layer_latched
+    }
+
+    pub fn set_layer_latched(&mut self, latched: bool) {
+        self.layer_latched = latched;
+    }
+
+    pub fn clicks_swapped(&self) -> bool {
+        self.clicks_swapped
+    }
+
+    /// Swap the left and right mouse buttons (or swap them back), returning whether they're now
+    /// swapped.
+    pub fn toggle_clicks_swapped(&mut self) -> bool {
+        self.clicks_swapped = !self.clicks_swapped;
+        self.clicks_swapped
+    }
+
+    pub fn set_clicks_swapped(&mut self, swapped: bool) {
+        self.clicks_swapped = swapped;
+    }
+
+    /// Use `remaps` in place of the base remaps while in keyboard mode.
+    pub fn set_keyboard_remaps(&mut self, remaps: &[Remap]) {
+        self.keyboard = remaps.iter().map(|r| (r.from, r.to)).collect();
+    }
+
+    pub fn keyboard_mode(&self) -> bool {
+        self.keyboard_mode
+    }
+
+    pub fn set_keyboard_mode(&mut self, keyboard_mode: bool) {
+        self.keyboard_mode = keyboard_mode;
+    }
+
+    pub fn ignore(&self) -> &Ignore {
+        &self.ignore
+    }
+
+    pub fn set_ignore(&mut self, ignore: Ignore) {
+        self.ignore = ignore;
+    }
+
+    pub fn stick_tuning(&self) -> &StickTuning {
+        &self.stick_tuning
+    }
+
+    pub fn set_stick_tuning(&mut self, stick_tuning: StickTuning) {
+        self.stick_tuning = stick_tuning;
+    }
+
+    /// Whether a key bound to `mode` is held.
+    pub fn speed_held(&self, mode: SpeedMode) -> bool {
+        self.speed_held.values().any(|&held| held == mode)
+    }
+
+    /// Whether the layer map currently applies, because it's latched or a layer key is held.
+    pub fn layer_active(&self) -> bool {
+        self.layer_latched || self.layer_held.iter().next().is_some()
+    }
+
+    pub fn key(&mut self, key: Key, value: i32) -> KeyAction {
+        if value == 1 {
+            for mod_tap in self.mod_taps.values_mut().filter(|mod_tap| !mod_tap.held) {
+                mod_tap.held = true;
+                self.presses.push(mod_tap.hold);
+            }
+        }
+        if self.layer_toggle_keys.contains(key) {
+            if value == 1 {
+                self.layer_latched = !self.layer_latched;
+            }
+            return KeyAction::Consumed;
+        }
+        if self.layer_keys.contains(key) {
+            if value == 0 {
+                self.layer_held.remove(key);
+            } else {
+                self.layer_held.insert(key);
+            }
+            return KeyAction::Consumed;
+        }
+        if value == 0 {
+            if let Some(turbo) = self.turbo.remove(&key) {
+                return match turbo.down {
+                    true => KeyAction::Emit(turbo.to),
+                    false => KeyAction::Consumed,
+                };
+            }
+            if let Some(to) = self.pressed.remove(&key) {
+                return KeyAction::Chord(to);
+            }
+            if let Some(mod_tap) = self.mod_taps.remove(&key) {
+                return match mod_tap.held {
+                    true => KeyAction::Emit(mod_tap.hold),
+                    false => KeyAction::Macro(mod_tap.tap.into()),
+                };
+            }
+            if self.speed_held.remove(&key).is_some() {
+                return KeyAction::Consumed;
+            }
+            if self.unbound.remove(&key) {
+                return KeyAction::Unbound;
+            }
+        }
+        // keyboard mode's remaps stand in for the base ones, so they share their names
+        let maps = [
+            (true, &self.layer, self.layer_active()),
+            (false, &self.keyboard, self.keyboard_mode),
+            (false, &self.base, true),
+        ];
+        let binding = maps
+            .into_iter()
+            .filter(|&(layer, _, active)| active && !self.disabled.contains(&(layer, key)))
+            .find_map(|(layer, map, _)| Some(((layer, key), *map.get(&key)?)));
+        let to = binding.map(|(_, action)| action);
+        if let (Some((binding, _)), 1) = (binding, value) {
+            self.deactivate_group(binding);
+        }
+        match to.as_ref() {
+            Some(&Action::Latch(to)) => {
+                if value != 1 {
+                    KeyAction::Consumed
+                } else if self.latched.remove(&to) {
+                    self.releases.push(to);
+                    KeyAction::Consumed
+                } else {
+                    self.latched.insert(to);
+                    KeyAction::Emit(to)
+                }
+            }
+            Some(&Action::Key(to)) => {
+                if value != 0 {
+                    self.pressed.insert(key, to.into());
+                }
+                KeyAction::Emit(to)
+            }
+            Some(&Action::Chord(to)) => {
+                if value != 0 {
+                    self.pressed.insert(key, to);
+                }
+                KeyAction::Chord(to)
+            }
+            // nudges repeat along with the key, but there's nothing to release
+            Some(&Action::Nudge(axis, px)) if value != 0 => KeyAction::Nudge(axis, px),
+            Some(&Action::DriftThreshold(adjust)) if value == 1 => {
+                KeyAction::DriftThreshold(adjust)
+            }
+            Some(&Action::Speed(mode)) => {
+                if value != 0 {
+                    self.speed_held.insert(key, mode);
+                }
+                KeyAction::Consumed
+            }
+            Some(&Action::SwapClicks) => {
+                if value == 1 {
+                    self.clicks_swapped = !self.clicks_swapped;
+                }
+                KeyAction::Consumed
+            }
+            Some(&Action::Turbo(to, rate)) => {
+                if value != 1 {
+                    return KeyAction::Consumed;
+                }
+                let half_period = Duration::from_millis(u64::from(rate)) / 2;
+                self.turbo.insert(
+                    key,
+                    Turbo {
+                        to,
+                        half_period,
+                        down: true,
+                        next: Instant::now() + half_period,
+                    },
+                );
+                KeyAction::Emit(to)
+            }
+            Some(&Action::PowerOff) => {
+                if value == 1 {
+                    self.power_off = true;
+                }
+                KeyAction::Consumed
+            }
+            Some(&Action::ModTap(hold, tap)) => {
+                if value == 1 {
+                    let held = false;
+                    self.mod_taps.insert(key, ModTap { hold, tap, held });
+                }
+                KeyAction::Consumed
+            }
+            Some(&Action::Macro(steps)) if value == 1 => KeyAction::Macro(steps),
+            Some(&Action::Nudge(..) | &Action::DriftThreshold(_) | &Action::Macro(_)) => {
+                KeyAction::Consumed
+            }
+            None => {
+                if value == 1 {
+                    self.unbound.insert(key);
+                }
+                KeyAction::Unbound
+            }
+        }
+    }
+
+    /// Release (or unlatch) the other members of `binding`'s groups, since it's being activated.
+    fn deactivate_group(&mut self, binding: (bool, Key)) {
+        let others: SmallVec<[_; 8]> = self
+            .groups
+            .iter()
+            .filter(|group| group.contains(&binding))
+            .flatten()
+            .copied()
+            .filter(|&other| other != binding)
+            .collect();
+        for (layer, key) in others {
+            if let Some(to) = self.pressed.remove(&key) {
+                self.releases.extend(to.keys().iter().rev());
+            }
+            self.speed_held.remove(&key);
+            if let Some(turbo) = self.turbo.remove(&key) {
+                if turbo.down {
+                    self.releases.push(turbo.to);
+                }
+            }
+            if let Some(mod_tap) = self.mod_taps.remove(&key) {
+                if mod_tap.held {
+                    self.releases.push(mod_tap.hold);
+                }
+            }
+            let map = if layer { &self.layer } else { &self.base };
+            if let Some(&Action::Latch(to)) = map.get(&key) {
+                if self.latched.remove(&to) {
+                    self.releases.push(to);
+                }
+            }
+        }
+    }
+
+    /// Make the remaps called `names` a group, of which only one can be active (held or latched)
+    /// at a time.
+    pub fn add_group(&mut self, names: &[&str]) -> Result<(), String> {
+        let group = names
+            .iter()
+            .map(|name| {
+                self.names
+                    .get(*name)
+                    .copied()
+                    .ok_or_else(|| format!("no remap named {:?}", name))
+            })
+            .collect::<Result<_, _>>()?;
+        self.groups.push(group);
+        Ok(())
+    }
+
+    /// Output keys that need to be released, because another member of their group was
+    /// activated.
+    pub fn take_releases(&mut self) -> std::vec::Drain<'_, Key> {
+        self.releases.drain(..)
+    }
+
+    /// Output keys that need to be pressed before the last key event's own output, because a held
+    /// `mod-tap` binding became its modifier.
+    pub fn take_presses(&mut self) -> std::vec::Drain<'_, Key> {
+        self.presses.drain(..)
+    }
+
+    /// When the next held `turbo` binding's key is due to be pressed or released.
+    pub fn turbo_deadline(&self) -> Option<Instant> {
+        self.turbo.values().map(|turbo| turbo.next).min()
+    }
+
+    /// Press or release the keys of the held `turbo` bindings that are due, returning each key
+    /// and whether it's now pressed.
+    pub fn turbo_tick(&mut self, now: Instant) -> SmallVec<[(Key, bool); 4]> {
+        let mut changes = SmallVec::new();
+        for turbo in self.turbo.values_mut().filter(|turbo| turbo.next <= now) {
+            turbo.down = !turbo.down;
+            turbo.next = now + turbo.half_period;
+            changes.push((turbo.to, turbo.down));
+        }
+        changes
+    }
+
+    /// Ask for the controller to be powered off, as if a `power-off` binding was pressed.
+    pub fn request_power_off(&mut self) {
+        self.power_off = true;
+    }
+
+    /// Whether the controller should be powered off, since the last call.
+    pub fn take_power_off(&mut self) -> bool {
+        std::mem::take(&mut self.power_off)
+    }
+
+    /// Latch `key`, or unlatch it if it's already latched, returning whether it's now latched.
+    /// Latching it lets go of the other members of the groups of the bindings that latch it, as
+    /// pressing one of them would; see [`Bindings::take_releases`].
+    pub fn toggle_latch(&mut self, key: Key) -> bool {
+        if self.latched.remove(&key) {
+            return false;
+        }
+        let latching: SmallVec<[_; 2]> = [(false, &self.base), (true, &self.layer)]
+            .into_iter()
+            .flat_map(|(layer, map)| {
+                map.iter()
+                    .filter(|&(_, &action)| action == Action::Latch(key))
+                    .map(move |(&from, _)| (layer, from))
+            })
+            .collect();
+        for binding in latching {
+            self.deactivate_group(binding);
+        }
+        self.latched.insert(key);
+        true
+    }
+
+    /// Enable or disable the remap called `name`. A disabled remap's key gets whatever treatment
+    /// it would without it.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        let &binding = self
+            .names
+            .get(name)
+            .ok_or_else(|| format!("no remap named {:?}", name))?;
+        if enabled {
+            self.disabled.remove(&binding);
+        } else {
+            self.disabled.insert(binding);
+        }
+        Ok(())
+    }
+
+    /// The names of every remap, and whether it's enabled, sorted by name.
+    pub fn names(&self) -> Vec<(&str, bool)> {
+        let mut names: Vec<_> = self
+            .names
+            .iter()
+            .map(|(name, binding)| (name.as_str(), !self.disabled.contains(binding)))
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Whether `key` is always handled by these bindings, so its default mapping never applies.
+    pub fn is_bound(&self, key: Key) -> bool {
+        self.base.contains_key(&key)
+            || self.layer_keys.contains(key)
+            || self.layer_toggle_keys.contains(key)
+    }
+
+    /// What `key` is bound to, with the layer active if `layer` (where it's only what the layer
+    /// binds it to, not the base binding it falls back to), unless it's been disabled.
+    pub fn action(&self, key: Key, layer: bool) -> Option<Action> {
+        let map = match layer {
+            true => &self.layer,
+            false => &self.base,
+        };
+        map.get(&key)
+            .filter(|_| !self.disabled.contains(&(layer, key)))
+            .copied()
+    }
+
+    /// Whether `key` activates the layer while held.
+    pub fn is_layer_key(&self, key: Key) -> bool {
+        self.layer_keys.contains(key)
+    }
+
+    /// Whether `key` toggles the layer.
+    pub fn is_layer_toggle_key(&self, key: Key) -> bool {
+        self.layer_toggle_keys.contains(key)
+    }
+
+    /// Every key these bindings can output.
+    pub fn output_keys(&self) -> impl Iterator<Item = Key> + '_ {
+        self.actions().flat_map(|action| match action {
+            Action::Key(key) | Action::Latch(key) | Action::Turbo(key, _) => vec![key],
+            Action::Chord(chord) => chord.keys().to_vec(),
+            Action::ModTap(hold, tap) => vec![hold, tap],
+            Action::Macro(steps) => steps
+                .steps()
+                .iter()
+                .flat_map(|step| step.keys())
+                .copied()
+                .collect(),
+            Action::Nudge(..)
+            | Action::DriftThreshold(_)
+            | Action::SwapClicks
+            | Action::Speed(_)
+            | Action::PowerOff => vec![],
+        })
+    }
+
+    /// Every relative axis these bindings can output.
+    pub fn output_axes(&self) -> impl Iterator<Item = RelativeAxisType> + '_ {
+        self.actions().filter_map(|action| match action {
+            Action::Key(_)
+            | Action::Chord(_)
+            | Action::DriftThreshold(_)
+            | Action::SwapClicks
+            | Action::Speed(_)
+            | Action::Latch(_)
+            | Action::Turbo(..)
+            | Action::ModTap(..)
+            | Action::Macro(_)
+            | Action::PowerOff => None,
+            Action::Nudge(axis, _) => Some(axis),
+        })
+    }
+
+    fn actions(&self) -> impl Iterator<Item = Action> + '_ {
+        self.base
+            .values()
+            .chain(self.layer.values())
+            .chain(self.keyboard.values())
+            .copied()
+    }
+}
@@ -0,0 +1,156 @@
+//! `joykbd calibrate`: measuring a stick's resting center, its drift and how far it reaches along
+//! each axis, by having the user leave it alone and then roll it around its rim, and saving the
+//! results to the config file's `[stick]` table, instead of guessing at `--x-bias` and friends.
+
+use evdev::{AbsoluteAxisType, InputEvent, InputEventKind};
+use joykbd::source::{InputSource, SourceEvent, SourceKind};
+use joykbd::stick::StickSide;
+use std::fs;
+use std::io;
+use std::path::Path;
+use tokio::sync::mpsc;
+use tokio::time;
+use toml::value::{Table, Value};
+
+/// How long to measure the stick at rest.
+const REST_TIME: time::Duration = time::Duration::from_secs(3);
+/// How long to measure the stick being rolled around its rim.
+const RIM_TIME: time::Duration = time::Duration::from_secs(6);
+/// How often to sample the stick's position.
+const SAMPLE_INTERVAL: time::Duration = time::Duration::from_millis(10);
+/// How much further than the measured drift the threshold goes, to stay clear of it.
+const DRIFT_MARGIN: f64 = 1.5;
+/// The lowest drift threshold to suggest, since a few seconds of rest don't show every wobble.
+const MIN_DRIFT_THRESHOLD: u32 = 500;
+
+/// What calibrating a stick found, in the terms of the config file.
+pub struct Calibration {
+    pub x_bias: i32,
+    pub y_bias: i32,
+    pub drift_threshold: u32,
+    /// How far the stick reaches below and above its center, along each axis.
+    pub range: [(i32, i32); 2],
+}
+
+/// Walk the user through calibrating `side`'s stick on `dev`.
+pub async fn run(dev: Box<dyn InputSource>, side: StickSide) -> anyhow::Result<Calibration> {
+    let axes = side.axes();
+    // evdev devices only report changes, so start from where the stick is now
+    let mut position = [0; 2];
+    if let Some(state) = dev.evdev().and_then(|dev| dev.get_abs_state().ok()) {
+        position = axes.map(|axis| state[axis.0 as usize].value);
+    }
+    let (tx, mut rx) = mpsc::channel(64);
+    dev.spawn(SourceKind::JoyCon, tx)?;
+
+    prompt("Let go of the stick, and press Enter").await?;
+    eprintln!("Measuring the stick at rest...");
+    let rest = sample(&mut rx, &mut position, axes, REST_TIME).await?;
+    let center = [0, 1].map(|i| {
+        let sum: i64 = rest.iter().map(|p| i64::from(p[i])).sum();
+        (sum / rest.len().max(1) as i64) as i32
+    });
+    let drift = rest
+        .iter()
+        .map(|p| f64::from(p[0] - center[0]).hypot(f64::from(p[1] - center[1])))
+        .fold(0.0, f64::max);
+
+    prompt("Press Enter, then roll the stick around its rim a few times").await?;
+    eprintln!("Measuring the stick's range...");
+    let rim = sample(&mut rx, &mut position, axes, RIM_TIME).await?;
+    let range = [0, 1].map(|i| {
+        let min = rim.iter().map(|p| p[i]).min().unwrap_or(center[i]);
+        let max = rim.iter().map(|p| p[i]).max().unwrap_or(center[i]);
+        (min - center[i], max - center[i])
+    });
+    if range.iter().any(|&(below, above)| below >= 0 || above <= 0) {
+        anyhow::bail!("the stick didn't move far enough, try again and roll it all the way out");
+    }
+
+    Ok(Calibration {
+        x_bias: -center[0],
+        y_bias: -center[1],
+        drift_threshold: ((drift * DRIFT_MARGIN).ceil() as u32).max(MIN_DRIFT_THRESHOLD),
+        range,
+    })
+}
+
+/// Print `message` and wait for the user to press Enter.
+pub async fn prompt(message: &str) -> io::Result<()> {
+    eprintln!("{}", message);
+    tokio::task::spawn_blocking(|| io::stdin().read_line(&mut String::new()))
+        .await
+        .map_err(io::Error::other)?
+        .map(drop)
+}
+
+/// Sample the stick's position for `duration`, keeping `position` up to date with its events.
+async fn sample(
+    rx: &mut mpsc::Receiver<SourceEvent>,
+    position: &mut [i32; 2],
+    axes: [AbsoluteAxisType; 2],
+    duration: time::Duration,
+) -> io::Result<Vec<[i32; 2]>> {
+    // drop whatever happened while waiting for Enter
+    while let Ok(SourceEvent { event, .. }) = rx.try_recv() {
+        track(position, axes, event?);
+    }
+    let deadline = time::Instant::now() + duration;
+    let mut ticks = time::interval(SAMPLE_INTERVAL);
+    let mut samples = vec![];
+    loop {
+        tokio::select! {
+            _ = time::sleep_until(deadline) => return Ok(samples),
+            _ = ticks.tick() => samples.push(*position),
+            event = rx.recv() => match event {
+                Some(SourceEvent { event, .. }) => track(position, axes, event?),
+                None => {
+                    return Err(io::Error::new(io::ErrorKind::NotConnected, "the controller went away"))
+                }
+            },
+        }
+    }
+}
+
+fn track(position: &mut [i32; 2], axes: [AbsoluteAxisType; 2], event: InputEvent) {
+    if let InputEventKind::AbsAxis(axis) = event.kind() {
+        if let Some(i) = axes.iter().position(|&a| a == axis) {
+            position[i] = event.value();
+        }
+    }
+}
+
+/// Write `calibration` into the config file at `path`'s `[stick]` table, keeping the original
+/// next to it with a `.bak` extension.
+pub fn save(path: &Path, calibration: &Calibration) -> anyhow::Result<()> {
+    let mut table: Table = match fs::read_to_string(path) {
+        Ok(s) => {
+            fs::copy(path, path.with_extension("toml.bak"))?;
+            toml::from_str(&s)?
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Table::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let stick = table
+        .entry("stick")
+        .or_insert_with(|| Value::Table(Table::new()))
+        .as_table_mut()
+        .ok_or_else(|| anyhow::anyhow!("`stick` in {} isn't a table", path.display()))?;
+    stick.insert("x-bias".to_owned(), calibration.x_bias.into());
+    stick.insert("y-bias".to_owned(), calibration.y_bias.into());
+    stick.insert(
+        "drift-threshold".to_owned(),
+        i64::from(calibration.drift_threshold).into(),
+    );
+    let range = calibration
+        .range
+        .iter()
+        .map(|&(below, above)| Value::Array(vec![below.into(), above.into()]))
+        .collect();
+    stick.insert("range".to_owned(), Value::Array(range));
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, toml::to_string(&table)?)?;
+    Ok(())
+}
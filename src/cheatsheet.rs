@@ -0,0 +1,179 @@
+//! `joykbd cheatsheet`: what each of the controller's buttons does, with and without the layer,
+//! as a plain text table, an HTML page or an SVG diagram of the controller, to print or keep on
+//! screen while a heavily layered config is still new.
+
+use joykbd::layout;
+use joykbd::remapper::Remapper;
+use std::fmt::Write as _;
+
+#[derive(clap::ArgEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Html,
+    Svg,
+}
+
+/// What a button does.
+struct Row {
+    button: &'static str,
+    action: Option<String>,
+    /// What it does while the layer's active, if that's different.
+    layer: Option<String>,
+}
+
+/// The diagram's size.
+const WIDTH: u32 = 1200;
+const HEIGHT: u32 = 400;
+
+/// Where the buttons are on the diagram's controller, from top to bottom on each side, which is
+/// also the order their labels are stacked in down its margins.
+const LEFT: &[(&str, u32, u32)] = &[
+    ("ZL", 410, 70),
+    ("L", 450, 95),
+    ("MINUS", 530, 120),
+    ("LSTICK", 450, 165),
+    ("CAPTURE", 550, 185),
+    ("UP", 480, 230),
+    ("LEFT", 455, 255),
+    ("RIGHT", 505, 255),
+    ("DOWN", 480, 280),
+];
+const RIGHT: &[(&str, u32, u32)] = &[
+    ("ZR", 790, 70),
+    ("R", 750, 95),
+    ("PLUS", 670, 120),
+    ("X", 750, 130),
+    ("Y", 725, 155),
+    ("A", 775, 155),
+    ("HOME", 650, 185),
+    ("B", 750, 180),
+    ("RSTICK", 720, 245),
+];
+
+/// Render `remapper`'s bindings, under the heading `title`.
+pub fn render(remapper: &Remapper, title: &str, format: Format) -> String {
+    let rows = rows(remapper);
+    match format {
+        Format::Text => text(&rows, title),
+        Format::Html => html(&rows, title),
+        Format::Svg => svg(&rows, title),
+    }
+}
+
+fn rows(remapper: &Remapper) -> Vec<Row> {
+    let bindings = &remapper.bindings;
+    layout::BUTTONS
+        .iter()
+        .map(|&(button, key)| {
+            let action = if bindings.is_layer_toggle_key(key) {
+                Some("toggle the layer".to_owned())
+            } else if bindings.is_layer_key(key) {
+                Some("layer, while held".to_owned())
+            } else if let Some(action) = bindings.action(key, false) {
+                Some(action.to_string())
+            } else {
+                (remapper.pointer)
+                    .button(remapper.preset, key)
+                    .map(|to| format!("{:?}", to))
+            };
+            Row {
+                button,
+                action,
+                layer: bindings.action(key, true).map(|action| action.to_string()),
+            }
+        })
+        .collect()
+}
+
+fn text(rows: &[Row], title: &str) -> String {
+    let mut s = format!("{}\n\n", title);
+    // the layer's actions line up in a column after the rest
+    let width = rows
+        .iter()
+        .map(|row| row.action.as_deref().unwrap_or("-").len())
+        .max()
+        .unwrap_or(0);
+    for row in rows {
+        let action = row.action.as_deref().unwrap_or("-");
+        let _ = match &row.layer {
+            Some(layer) => writeln!(s, "{:<8} {:<width$}  layer: {}", row.button, action, layer),
+            None => writeln!(s, "{:<8} {}", row.button, action),
+        };
+    }
+    s
+}
+
+fn html(rows: &[Row], title: &str) -> String {
+    let title = escape(title);
+    let mut s = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>body {{ font-family: sans-serif; }} table {{ border-collapse: collapse; }} \
+         td, th {{ border: 1px solid #999; padding: 4px 12px; text-align: left; }}</style>\n\
+         </head>\n<body>\n<h1>{}</h1>\n<table>\n<tr><th>Button</th><th>Action</th><th>With the layer</th></tr>\n",
+        title, title
+    );
+    for row in rows {
+        let _ = writeln!(
+            s,
+            "<tr><th>{}</th><td>{}</td><td>{}</td></tr>",
+            row.button,
+            escape(row.action.as_deref().unwrap_or("")),
+            escape(row.layer.as_deref().unwrap_or(""))
+        );
+    }
+    s.push_str("</table>\n</body>\n</html>\n");
+    s
+}
+
+fn svg(rows: &[Row], title: &str) -> String {
+    let mut s = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+         viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\" font-size=\"13\">\n\
+         <rect width=\"{w}\" height=\"{h}\" fill=\"white\"/>\n\
+         <text x=\"{cx}\" y=\"24\" text-anchor=\"middle\" font-size=\"16\" font-weight=\"bold\">{}</text>\n\
+         <rect x=\"380\" y=\"55\" width=\"440\" height=\"270\" rx=\"90\" fill=\"#eee\" stroke=\"#333\" stroke-width=\"2\"/>\n",
+        escape(title),
+        w = WIDTH,
+        h = HEIGHT,
+        cx = WIDTH / 2,
+    );
+    for (side, buttons) in [(-1, LEFT), (1, RIGHT)] {
+        for (i, &(button, x, y)) in buttons.iter().enumerate() {
+            let Some(row) = rows.iter().find(|row| row.button == button) else {
+                continue;
+            };
+            let label_y = 60 + 36 * i as u32;
+            let (label_x, anchor) = match side {
+                -1 => (360, "end"),
+                _ => (WIDTH - 360, "start"),
+            };
+            let mut label = row.action.clone().unwrap_or_else(|| "-".to_owned());
+            if let Some(layer) = &row.layer {
+                let _ = write!(label, " / layer: {}", layer);
+            }
+            let _ = write!(
+                s,
+                "<line x1=\"{x}\" y1=\"{y}\" x2=\"{label_x}\" y2=\"{label_y}\" stroke=\"#999\"/>\n\
+                 <circle cx=\"{x}\" cy=\"{y}\" r=\"11\" fill=\"white\" stroke=\"#333\"/>\n\
+                 <text x=\"{x}\" y=\"{text_y}\" text-anchor=\"middle\" font-size=\"8\">{}</text>\n\
+                 <text x=\"{label_x}\" y=\"{label_y}\" dy=\"4\" dx=\"{dx}\" text-anchor=\"{anchor}\">\
+                 <tspan font-weight=\"bold\">{}</tspan> {}</text>\n",
+                button,
+                button,
+                escape(&label),
+                text_y = y + 3,
+                dx = side * 6,
+            );
+        }
+    }
+    s.push_str("</svg>\n");
+    s
+}
+
+/// `s`, with what HTML and SVG would read as markup escaped.
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
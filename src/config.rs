@@ -0,0 +1,551 @@
+//! The config file, `$XDG_CONFIG_HOME/joykbd/config.toml` (or `--config`), mapping the
+//! controller's buttons to keys or actions by their names on the controller, without a long list
+//! of `--remap`s:
+//!
+//! ```toml
+//! [buttons]
+//! A = "KEY_ENTER"
+//! ZL = "BTN_LEFT"
+//! PLUS = "swap-clicks"
+//! # a shortcut, pressed in order and released in reverse
+//! X = ["KEY_LEFTCTRL", "KEY_C"]
+//! # pressed and released every 50ms while held
+//! ZR = { to = "BTN_LEFT", turbo-rate-ms = 50 }
+//!
+//! # while a --layer-key is held
+//! [layer]
+//! A = "KEY_SPACE"
+//! ```
+//!
+//! In keyboard mode (`--mode-key`), the `[keyboard-mode]` table's mapping takes the place of the
+//! rest, by default `A = "KEY_ENTER"` and `B = "KEY_ESC"`, while the sticks press arrow keys.
+//!
+//! It can also reorder or extend how the stick's position is processed, see [`crate::stick`],
+//! name HD rumble envelopes, see [`crate::rumble`], lay out the characters for text entry, see
+//! [`crate::textentry`], give the layout of a controller that isn't a Joy-Con, see
+//! [`crate::layout`], and forward what isn't mapped, see [`crate::unmapped`].
+//!
+//! Further profiles, switched between at runtime with `--profile-chord`, change the mapping on
+//! top of the rest of the config (and the command line):
+//!
+//! ```toml
+//! [profiles.browsing.buttons]
+//! A = "BTN_LEFT"
+//! X = "KEY_F5"
+//! ```
+//!
+//! Buttons can also be named by their evdev names (e.g. `BTN_EAST`), and `--remap`s given on the
+//! command line take precedence.
+//!
+//! The same config can be written as YAML (`.yaml`/`.yml`) or JSON (`.json`) instead, e.g. when
+//! it's generated by another tool, told apart by its extension:
+//!
+//! ```text
+//! {"buttons": {"A": "KEY_ENTER", "X": ["KEY_LEFTCTRL", "KEY_C"]}, "stick": {"speed-y": 12}}
+//! ```
+//!
+//! Its strings (and tables' keys) can take values from the environment, so one config can be
+//! shared between machines that differ a little, with `${env:VAR}`, or `${env:VAR:-DEFAULT}` for
+//! a default if it's unset or empty. Any other `$` is left alone, and `$${env:` is a literal
+//! `${env:`. A string that's just one of them can stand in for a number or boolean:
+//!
+//! ```toml
+//! [buttons]
+//! HOME = "${env:JOYKBD_HOME_KEY:-KEY_HOMEPAGE}"
+//!
+//! [stick]
+//! speed-y = "${env:JOYKBD_SPEED:-12}"
+//! ```
+
+use crate::bindings::{parse_key, Action, Chord, Remap};
+use crate::layout;
+use crate::migrate;
+use crate::option_set;
+use crate::rumble::Envelope;
+use crate::source::SourceKind;
+use crate::stick::{Axis, Curve, Sensitivity, Stage, StickConstants};
+use evdev::{InputEvent, InputEventKind, Key};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The format version it's written in, see [`crate::migrate`].
+    pub version: i64,
+    pub buttons: Mapping,
+    pub layer: Mapping,
+    pub stick: StickConfig,
+    pub profiles: BTreeMap<String, ProfileConfig>,
+    /// Named HD rumble envelopes, see [`crate::rumble`].
+    pub rumble: BTreeMap<String, Envelope>,
+    /// What the buttons do in keyboard mode (`--mode-key`), instead of [`DEFAULT_KEYBOARD_MODE`].
+    #[serde(rename = "keyboard-mode")]
+    pub keyboard_mode: Option<Mapping>,
+    #[serde(rename = "text-entry")]
+    pub text_entry: TextEntryConfig,
+    /// The evdev codes the controller reports its buttons as, if they aren't the Joy-Con's, see
+    /// [`crate::layout`].
+    pub layout: BTreeMap<String, String>,
+    /// What to ignore, unless a profile says otherwise, see [`Ignore`].
+    pub ignore: Ignore,
+    /// Forward what isn't mapped to a second virtual device, see [`crate::unmapped`].
+    #[serde(rename = "forward-unmapped")]
+    pub forward_unmapped: bool,
+}
+
+/// What the buttons do in keyboard mode, unless the config file says otherwise.
+const DEFAULT_KEYBOARD_MODE: &[(&str, &str)] = &[("A", "KEY_ENTER"), ("B", "KEY_ESC")];
+
+impl Config {
+    /// The remaps that stand in for the rest in keyboard mode.
+    pub fn keyboard_mode_remaps(&self) -> Result<Vec<Remap>, String> {
+        match &self.keyboard_mode {
+            Some(mapping) => mapping.remaps(),
+            None => Mapping(
+                DEFAULT_KEYBOARD_MODE
+                    .iter()
+                    .map(|&(button, to)| (button.to_owned(), Entry::Action(to.to_owned())))
+                    .collect(),
+            )
+            .remaps(),
+        }
+    }
+}
+
+/// The pages of characters for text entry (`--text-entry-key`), see [`crate::textentry`].
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TextEntryConfig {
+    pub pages: Option<Vec<String>>,
+}
+
+/// A profile's changes to the mapping.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileConfig {
+    pub buttons: Mapping,
+    pub layer: Mapping,
+    /// What the profile ignores, instead of the rest of the config's `[ignore]`.
+    pub ignore: Option<Ignore>,
+    /// What happens after a while without input while the profile's active, instead of what
+    /// --idle-timeout and its options say.
+    pub idle: Option<IdleConfig>,
+    /// The classes of the windows (as --passthrough-window takes them) that switch to the
+    /// profile as they're focused, e.g. a game's, switching back once none of them is (unless
+    /// another profile was switched to in the meantime).
+    pub windows: Vec<String>,
+    /// How the sticks are reshaped with --gamepad while the profile's active, instead of as
+    /// --gamepad-deadzone and --gamepad-curve say.
+    pub gamepad: GamepadConfig,
+}
+
+/// A profile's stick tuning for gamepad mode, e.g. for a game whose own deadzone is too small for
+/// the joy-con's drift:
+///
+/// ```toml
+/// [profiles.racing]
+/// windows = ["steam_app_244210"]
+///
+/// [profiles.racing.gamepad]
+/// deadzone = 0.15
+/// curve = 1.5
+/// ```
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct GamepadConfig {
+    pub deadzone: Option<f64>,
+    pub curve: Option<f64>,
+}
+
+/// What happens after a while without any input, and is undone on the next, e.g. for an HTPC
+/// profile that blanks the screen and switches to one that only uses the controller as a remote:
+///
+/// ```toml
+/// [profiles.htpc.idle]
+/// timeout-minutes = 20
+/// command = "xset dpms force off"
+/// resume-command = "xset dpms force on"
+/// profile = "remote"
+/// ```
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct IdleConfig {
+    /// How many minutes without input count as idle; without it, the profile never goes idle.
+    pub timeout_minutes: Option<u64>,
+    /// A shell command to run when going idle.
+    pub command: Option<String>,
+    /// A shell command to run on the first input after going idle.
+    pub resume_command: Option<String>,
+    /// A profile to switch to while idle, switching back on the first input.
+    pub profile: Option<String>,
+}
+
+/// Whole kinds of the controller's input to ignore, e.g. for a profile that only uses it as a
+/// remote, where a damaged stick's noise should never move the cursor:
+///
+/// ```toml
+/// [profiles.remote.ignore]
+/// axes = true
+/// gyro = true
+/// ```
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ignore {
+    /// Every absolute axis: the sticks, and anything else reported as one.
+    pub axes: bool,
+    /// The IMU (see `--gyro`).
+    pub gyro: bool,
+    /// The D-pad's buttons.
+    pub dpad: bool,
+}
+
+impl Ignore {
+    /// Whether `ev`, from a source of `kind`, is ignored.
+    pub fn ignores(&self, kind: SourceKind, ev: &InputEvent) -> bool {
+        match (kind, ev.kind()) {
+            (SourceKind::Imu, _) => self.gyro,
+            (SourceKind::JoyCon | SourceKind::Partner, InputEventKind::AbsAxis(_)) => self.axes,
+            (
+                SourceKind::JoyCon | SourceKind::Partner,
+                InputEventKind::Key(
+                    Key::BTN_DPAD_UP
+                    | Key::BTN_DPAD_DOWN
+                    | Key::BTN_DPAD_LEFT
+                    | Key::BTN_DPAD_RIGHT,
+                ),
+            ) => self.dpad,
+            _ => false,
+        }
+    }
+}
+
+/// The stick's pipelines and speeds, see [`crate::stick`].
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct StickConfig {
+    pub x: Option<Vec<Stage>>,
+    pub y: Option<Vec<Stage>>,
+    pub curve: Option<Curve>,
+    /// Like --x-bias, --y-bias and --drift-threshold, which take precedence, as measured by
+    /// `joykbd calibrate`.
+    pub x_bias: Option<i32>,
+    pub y_bias: Option<i32>,
+    pub drift_threshold: Option<u32>,
+    /// How far the stick reaches below and above its center along each axis, as measured by
+    /// `joykbd calibrate`, so every direction reaches full speed.
+    pub range: Option<[(i32, i32); 2]>,
+    pub speed_x: Option<f64>,
+    pub speed_y: Option<f64>,
+    pub sensitivity: Sensitivity,
+}
+
+impl StickConfig {
+    /// Apply these settings to `stick_constants`.
+    pub fn apply(&self, stick_constants: &mut StickConstants) -> Result<(), String> {
+        if let Some(stages) = &self.x {
+            stick_constants.set_pipeline(Axis::X, stages);
+        }
+        if let Some(stages) = &self.y {
+            stick_constants.set_pipeline(Axis::Y, stages);
+        }
+        if let Some(bias) = self.x_bias {
+            stick_constants.axis_bias.0 = bias;
+        }
+        if let Some(bias) = self.y_bias {
+            stick_constants.axis_bias.1 = bias;
+        }
+        if let Some(threshold) = self.drift_threshold {
+            stick_constants.drift_threshold = threshold;
+        }
+        if let Some(range) = self.range {
+            if range.iter().any(|&(below, above)| below >= 0 || above <= 0) {
+                return Err(
+                    "invalid stick range: it has to reach below and above the center".to_owned(),
+                );
+            }
+            stick_constants.range =
+                range.map(|(below, above)| (f64::from(below), f64::from(above)));
+        }
+        if let Some(speed) = self.speed_x {
+            stick_constants.speed[Axis::X as usize] = speed;
+        }
+        if let Some(speed) = self.speed_y {
+            stick_constants.speed[Axis::Y as usize] = speed;
+        }
+        stick_constants.sensitivity = self.sensitivity;
+        if let Some(curve) = &self.curve {
+            curve
+                .validate()
+                .map_err(|e| format!("invalid curve: {}", e))?;
+            stick_constants.curve = curve.clone();
+        }
+        Ok(())
+    }
+}
+
+/// What each button does, by its name.
+#[derive(Deserialize, Default)]
+#[serde(transparent)]
+pub struct Mapping(BTreeMap<String, Entry>);
+
+/// What a button does: an action, as passed to `--remap`, keys pressed together, or a key with
+/// options.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Action(String),
+    Chord(Vec<String>),
+    Key(KeyEntry),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct KeyEntry {
+    to: String,
+    /// Press and release the key over and over while the button's held, like `turbo KEY MS`.
+    turbo_rate_ms: Option<u16>,
+}
+
+impl Entry {
+    fn action(&self) -> Result<Action, String> {
+        match self {
+            Entry::Action(action) => action.parse(),
+            Entry::Chord(keys) => {
+                let keys = keys
+                    .iter()
+                    .map(|key| parse_key(key).ok_or_else(|| format!("unknown key {:?}", key)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Chord::new(&keys).map(Action::Chord)
+            }
+            Entry::Key(KeyEntry { to, turbo_rate_ms }) => {
+                let key = parse_key(to).ok_or_else(|| format!("unknown key {:?}", to))?;
+                match turbo_rate_ms {
+                    Some(0) => Err("turbo-rate-ms has to be more than 0".to_owned()),
+                    Some(rate) => Ok(Action::Turbo(key, *rate)),
+                    None => Ok(Action::Key(key)),
+                }
+            }
+        }
+    }
+}
+
+impl Mapping {
+    /// The mapping as remaps, as if passed to `--remap`.
+    pub fn remaps(&self) -> Result<Vec<Remap>, String> {
+        self.0
+            .iter()
+            .map(|(button, to)| {
+                let from =
+                    parse_button(button).ok_or_else(|| format!("unknown button {:?}", button))?;
+                let to = to
+                    .action()
+                    .map_err(|e| format!("invalid mapping for {}: {}", button, e))?;
+                Ok(Remap {
+                    name: None,
+                    from,
+                    to,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A button by its name on the controller (case-insensitively), or its evdev name.
+pub fn parse_button(name: &str) -> Option<Key> {
+    layout::button(name).or_else(|| parse_key(name))
+}
+
+/// What the config file is written in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl Format {
+    /// Each format's extensions, in order of preference.
+    const EXTENSIONS: &'static [(&'static str, Format)] = &[
+        ("toml", Format::Toml),
+        ("yaml", Format::Yaml),
+        ("yml", Format::Yaml),
+        ("json", Format::Json),
+    ];
+
+    /// The format of the file at `path`, by its extension, TOML if it's none of theirs.
+    pub fn of(path: &Path) -> Self {
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        Format::EXTENSIONS
+            .iter()
+            .find(|(ext, _)| ext.eq_ignore_ascii_case(extension))
+            .map_or(Format::Toml, |&(_, format)| format)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Toml => "TOML",
+            Format::Yaml => "YAML",
+            Format::Json => "JSON",
+        }
+    }
+}
+
+/// The default location, `$XDG_CONFIG_HOME/joykbd/config.toml`, or `config.yaml`, `config.yml`
+/// or `config.json` if there's one of those instead.
+pub fn default_path() -> Option<PathBuf> {
+    let dir = option_set::config_dir()?;
+    let path = Format::EXTENSIONS
+        .iter()
+        .map(|(ext, _)| dir.join("config").with_extension(ext))
+        .find(|path| path.exists());
+    Some(path.unwrap_or_else(|| dir.join("config.toml")))
+}
+
+/// Load the config file at `path`. A missing file is only an error if it was asked for
+/// explicitly, with `required`.
+pub fn load(path: &Path, required: bool) -> anyhow::Result<Config> {
+    let s = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !required => return Ok(Config::default()),
+        Err(e) => anyhow::bail!("can't read config {}: {}", path.display(), e),
+    };
+    let invalid = |e| anyhow::anyhow!("invalid config {}: {}", path.display(), e);
+    let format = Format::of(path);
+    // upgraded from older formats as a generic value, whatever it's written in
+    let mut value: serde_json::Value = parse(format, &s).map_err(invalid)?;
+    let table = (value.as_object_mut()).ok_or_else(|| invalid("it isn't a table".to_owned()))?;
+    migrate::migrate(table).map_err(invalid)?;
+    if s.contains(REFERENCE) {
+        interpolate_value(&mut value).map_err(invalid)?;
+    }
+    serde_json::from_value(value).map_err(|e| {
+        // a generic value doesn't know where in the file the invalid value was, but the format's
+        // own parser does, in all likelihood for the same reason
+        invalid(
+            parse::<Config>(format, &s)
+                .err()
+                .unwrap_or_else(|| e.to_string()),
+        )
+    })
+}
+
+/// Parse `s`, written in `format`.
+pub fn parse<T: DeserializeOwned>(format: Format, s: &str) -> Result<T, String> {
+    match format {
+        Format::Toml => toml::from_str(s).map_err(|e| e.to_string()),
+        Format::Yaml => serde_yaml::from_str(s).map_err(|e| e.to_string()),
+        Format::Json => serde_json::from_str(s).map_err(|e| e.to_string()),
+    }
+}
+
+/// What starts a reference to an environment variable in the config.
+const REFERENCE: &str = "${env:";
+
+/// Interpolate the environment into every string in `value`, table keys included (e.g. a
+/// profile's name), see [`interpolate`]. A string that's only a reference becomes a number or
+/// boolean if its value is one.
+fn interpolate_value(value: &mut serde_json::Value) -> Result<(), String> {
+    match value {
+        serde_json::Value::String(s) => {
+            let whole = s.starts_with(REFERENCE) && s.find('}') == Some(s.len() - 1);
+            let interpolated = interpolate(s)?;
+            *value = match serde_json::from_str(&interpolated) {
+                Ok(typed @ (serde_json::Value::Number(_) | serde_json::Value::Bool(_)))
+                    if whole =>
+                {
+                    typed
+                }
+                _ => serde_json::Value::String(interpolated),
+            };
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                interpolate_value(value)?;
+            }
+        }
+        serde_json::Value::Object(table) => {
+            *table = std::mem::take(table)
+                .into_iter()
+                .map(|(key, mut value)| {
+                    interpolate_value(&mut value)?;
+                    Ok((interpolate(&key)?, value))
+                })
+                .collect::<Result<_, String>>()?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// `s`, with each `${env:VAR}` replaced by the environment variable's value, or for
+/// `${env:VAR:-DEFAULT}`, by `DEFAULT` if it's unset or empty. `$${env:` is a literal `${env:`,
+/// and any other `$` is left as it is, so e.g. shell syntax isn't mangled. An unset variable
+/// without a default is an error, rather than silently nothing.
+fn interpolate(s: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find(REFERENCE) {
+        let after = &rest[i + REFERENCE.len()..];
+        if let Some(before) = rest[..i].strip_suffix('$') {
+            out.push_str(before);
+            out.push_str(REFERENCE);
+            rest = after;
+            continue;
+        }
+        out.push_str(&rest[..i]);
+        let end = after
+            .find('}')
+            .ok_or_else(|| format!("unclosed {} in {:?}", REFERENCE, s))?;
+        let (name, default) = match after[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&after[..end], None),
+        };
+        let value = std::env::var(name).ok();
+        match (value, default) {
+            (Some(value), Some(default)) if value.is_empty() => out.push_str(default),
+            (Some(value), _) => out.push_str(&value),
+            (None, Some(default)) => out.push_str(default),
+            (None, None) => return Err(format!("{}{}}} is unset, in {:?}", REFERENCE, name, s)),
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::interpolate;
+
+    #[test]
+    fn interpolates() {
+        std::env::set_var("JOYKBD_TEST_SET", "on");
+        std::env::set_var("JOYKBD_TEST_EMPTY", "");
+        std::env::remove_var("JOYKBD_TEST_UNSET");
+        let cases = [
+            ("plain", Ok("plain")),
+            ("${env:JOYKBD_TEST_SET}", Ok("on")),
+            (
+                "a ${env:JOYKBD_TEST_SET} b ${env:JOYKBD_TEST_SET}",
+                Ok("a on b on"),
+            ),
+            ("${env:JOYKBD_TEST_SET:-off}", Ok("on")),
+            ("${env:JOYKBD_TEST_UNSET:-off}", Ok("off")),
+            ("${env:JOYKBD_TEST_EMPTY:-off}", Ok("off")),
+            ("${env:JOYKBD_TEST_EMPTY}", Ok("")),
+            ("$${env:JOYKBD_TEST_SET}", Ok("${env:JOYKBD_TEST_SET}")),
+            ("echo $HOME ${HOME}", Ok("echo $HOME ${HOME}")),
+            ("${env:JOYKBD_TEST_UNSET}", Err(())),
+            ("${env:JOYKBD_TEST_SET", Err(())),
+        ];
+        for (s, expected) in cases {
+            let interpolated = interpolate(s);
+            assert_eq!(interpolated.as_deref().map_err(|_| ()), expected, "{:?}", s);
+        }
+    }
+}
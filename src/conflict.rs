@@ -0,0 +1,292 @@
+//! Detecting other programs that are already consuming the joy-con, which would otherwise lead to
+//! every button press being acted on twice, and grabbing devices so only joykbd sees them.
+
+use crate::source::InputSource;
+use evdev::Device;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::fd::{AsRawFd, BorrowedFd, OwnedFd};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// How often to check whether the keys held down have been released, before grabbing.
+const RELEASE_POLL: Duration = Duration::from_millis(20);
+
+/// `EVIOCGRAB`, `_IOW('E', 0x90, int)`.
+const EVIOCGRAB: u64 = 0x4004_4590;
+
+/// Process names of remappers/input layers that are known to act on joy-con input themselves.
+const KNOWN_REMAPPERS: &[&str] = &[
+    "joycond",
+    "antimicrox",
+    "steam",
+    "sc-controller",
+    "input-remapper",
+    "xboxdrv",
+    "keyd",
+    "kmonad",
+    "evsieve",
+    "makima",
+];
+
+#[derive(clap::ArgEnum, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Report conflicts and carry on anyway.
+    Warn,
+    /// Wait until the conflicting programs have let go of the device.
+    Defer,
+    /// Grab the device exclusively, so other readers stop receiving its events.
+    TakeOver,
+}
+
+pub struct Holder {
+    pub pid: u32,
+    pub name: String,
+}
+
+impl Holder {
+    fn is_known_remapper(&self) -> bool {
+        KNOWN_REMAPPERS
+            .iter()
+            .any(|known| self.name.eq_ignore_ascii_case(known))
+    }
+}
+
+impl fmt::Display for Holder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (pid {})", self.name, self.pid)
+    }
+}
+
+pub struct Conflict {
+    pub grabbed: bool,
+    pub holders: Vec<Holder>,
+}
+
+impl Conflict {
+    pub fn check(path: &Path) -> Self {
+        Conflict {
+            grabbed: grabbed_elsewhere(path),
+            holders: holders(path),
+        }
+    }
+
+    /// Whether using the device would likely produce duplicate (or no) input.
+    pub fn is_conflict(&self) -> bool {
+        self.grabbed || self.holders.iter().any(Holder::is_known_remapper)
+    }
+
+    pub fn report(&self) {
+        if self.grabbed {
+            eprintln!("warning: the device is grabbed exclusively by another process");
+        }
+        for holder in &self.holders {
+            if holder.is_known_remapper() {
+                eprintln!("warning: the device is in use by known remapper {}", holder);
+            } else {
+                eprintln!("note: the device is also open in {}", holder);
+            }
+        }
+    }
+}
+
+/// Apply `policy` to whatever conflicts there are on the device at `path`, returning the grab if
+/// it took the device over.
+pub async fn resolve(
+    policy: ConflictPolicy,
+    path: &Path,
+    dev: &mut dyn InputSource,
+    grab_grace: Duration,
+) -> anyhow::Result<Option<Grab>> {
+    let mut conflict = Conflict::check(path);
+    if !conflict.is_conflict() {
+        return Ok(None);
+    }
+    conflict.report();
+    match policy {
+        ConflictPolicy::Warn => {}
+        ConflictPolicy::Defer => {
+            eprintln!("Waiting for the other programs to release the device...");
+            while conflict.is_conflict() {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                conflict = Conflict::check(path);
+            }
+            eprintln!("Device released, continuing");
+        }
+        ConflictPolicy::TakeOver => {
+            if conflict.grabbed {
+                anyhow::bail!("can't take over the device, another process has already grabbed it");
+            }
+            let grab = match dev.evdev_mut() {
+                Some(dev) => Grab::new(dev, grab_grace).await?,
+                None => anyhow::bail!("taking over the device needs the evdev backend"),
+            };
+            eprintln!("Grabbed the device, other programs will no longer see its input");
+            return Ok(Some(grab));
+        }
+    }
+    Ok(None)
+}
+
+/// An exclusive grab on a device, released when it's dropped, even though the device itself is
+/// handed over to the task reading it.
+pub struct Grab {
+    /// A duplicate of the device's file descriptor, which shares its grab.
+    fd: OwnedFd,
+}
+
+impl Grab {
+    /// Grab `dev`, the same way as [`grab`].
+    pub async fn new(dev: &mut Device, grace: Duration) -> io::Result<Self> {
+        grab(dev, grace).await?;
+        let fd = unsafe { BorrowedFd::borrow_raw(dev.as_raw_fd()) }.try_clone_to_owned()?;
+        Ok(Grab { fd })
+    }
+}
+
+impl Drop for Grab {
+    fn drop(&mut self) {
+        // closing the duplicate isn't enough, the device's own descriptor still holds the grab
+        unsafe { libc::ioctl(self.fd.as_raw_fd(), EVIOCGRAB as _, 0) };
+    }
+}
+
+/// Grab `dev` once none of its keys are held down, waiting up to `grace` for them to be released:
+/// a key that's held when the device is grabbed is never released as far as everything else is
+/// concerned, e.g. the Enter that started joykbd from a terminal.
+pub async fn grab(dev: &mut Device, grace: Duration) -> io::Result<()> {
+    let deadline = Instant::now() + grace;
+    let mut waiting = false;
+    loop {
+        let held: Vec<_> = dev.get_key_state()?.iter().collect();
+        if held.is_empty() {
+            break;
+        }
+        let name = dev.name().unwrap_or("the device");
+        if Instant::now() >= deadline {
+            eprintln!(
+                "warning: grabbing {} while {:?} is held, which may stay stuck down for other programs",
+                name, held
+            );
+            break;
+        }
+        if !waiting {
+            eprintln!(
+                "Waiting for {:?} to be released before grabbing {}",
+                held, name
+            );
+            waiting = true;
+        }
+        tokio::time::sleep(RELEASE_POLL).await;
+    }
+    dev.grab()
+}
+
+/// Check whether another process holds an EVIOCGRAB on the device, by briefly trying to grab it
+/// ourselves. That's only fit for a one-off check before using the device, as the grab could get
+/// in the way of a program grabbing it at the same time, see [`GrabWatch`] for watching it.
+pub fn grabbed_elsewhere(path: &Path) -> bool {
+    let mut dev = match Device::open(path) {
+        Ok(dev) => dev,
+        Err(_) => return false,
+    };
+    match dev.grab() {
+        Ok(()) => {
+            let _ = dev.ungrab();
+            false
+        }
+        Err(e) => e.raw_os_error() == Some(libc::EBUSY),
+    }
+}
+
+/// Watches whether another process has grabbed a device, without grabbing it: a grabbed device's
+/// state still changes, but its events stop reaching every other reader, this one included, so
+/// the state as of the events it's read falls behind.
+pub struct GrabWatch {
+    dev: Device,
+}
+
+impl GrabWatch {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let dev = Device::open(path)?;
+        let fd = dev.as_raw_fd();
+        // the events are only ever read as far as there are any
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(GrabWatch { dev })
+    }
+
+    /// Whether the device is grabbed elsewhere, as far as can be told: only once its state has
+    /// changed since it was grabbed.
+    pub fn grabbed(&mut self) -> bool {
+        // the state can change between reading the events and reading it, which is only behind
+        // if it's still behind after reading the events again
+        self.behind() && self.behind()
+    }
+
+    fn behind(&mut self) -> bool {
+        loop {
+            match self.dev.fetch_events() {
+                // reading the events is what applies them to the cached state
+                Ok(events) => events.for_each(drop),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(_) => return false,
+            }
+        }
+        let read = self.dev.cached_state();
+        let keys = match (read.key_vals(), self.dev.get_key_state()) {
+            (Some(read), Ok(now)) => !read.iter().eq(now.iter()),
+            _ => false,
+        };
+        let axes = match (read.abs_vals(), self.dev.get_abs_state()) {
+            (Some(read), Ok(now)) => {
+                (read.iter().zip(&now)).any(|(read, now)| read.value != now.value)
+            }
+            _ => false,
+        };
+        keys || axes
+    }
+}
+
+/// Every other process that has the device open, found by scanning /proc/*/fd.
+pub fn holders(path: &Path) -> Vec<Holder> {
+    let target = match fs::canonicalize(path) {
+        Ok(target) => target,
+        Err(_) => return vec![],
+    };
+    let own_pid = std::process::id();
+    let procs = match fs::read_dir("/proc") {
+        Ok(procs) => procs,
+        Err(_) => return vec![],
+    };
+    let mut holders = vec![];
+    for entry in procs.flatten() {
+        let pid = match entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        {
+            Some(pid) if pid != own_pid => pid,
+            _ => continue,
+        };
+        // we can't look inside processes owned by other users without privileges, so those are
+        // silently skipped
+        let fds = match fs::read_dir(entry.path().join("fd")) {
+            Ok(fds) => fds,
+            Err(_) => continue,
+        };
+        let has_open = fds
+            .flatten()
+            .any(|fd| fs::read_link(fd.path()).is_ok_and(|link| link == target));
+        if has_open {
+            let name = fs::read_to_string(entry.path().join("comm"))
+                .map(|comm| comm.trim().to_owned())
+                .unwrap_or_else(|_| "<unknown>".to_owned());
+            holders.push(Holder { pid, name });
+        }
+    }
+    holders
+}
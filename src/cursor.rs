@@ -0,0 +1,182 @@
+//! Tracking the cursor's position ourselves, so it can be confined to part of the screen. With a
+//! known screen size, joykbd positions the cursor absolutely instead of emitting relative motion:
+//! all relative pointer motion is added up into a position, which the virtual device reports.
+
+use crate::edges::Edge;
+use evdev::{
+    AbsInfo, AbsoluteAxisType, EventType, InputEvent, InputEventKind, RelativeAxisType,
+    UinputAbsSetup,
+};
+use std::str::FromStr;
+
+/// A screen size, `WxH`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Size {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl FromStr for Size {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s
+            .split_once('x')
+            .ok_or_else(|| format!("expected WIDTHxHEIGHT, got {:?}", s))?;
+        let parse = |n: &str| {
+            n.parse::<i32>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("invalid size {:?}", s))
+        };
+        Ok(Size {
+            width: parse(width)?,
+            height: parse(height)?,
+        })
+    }
+}
+
+/// A region of the screen, in X11 geometry syntax: `WxH+X+Y`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub size: Size,
+}
+
+impl FromStr for Rect {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected WIDTHxHEIGHT+X+Y, got {:?}", s);
+        let mut parts = s.split('+');
+        let size = parts.next().ok_or_else(invalid)?.parse()?;
+        let mut offset = || -> Result<i32, String> {
+            parts
+                .next()
+                .and_then(|n| n.parse().ok())
+                .ok_or_else(invalid)
+        };
+        let (x, y) = (offset()?, offset()?);
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Rect { x, y, size })
+    }
+}
+
+pub struct Cursor {
+    screen: Size,
+    /// Where the cursor is allowed to go, the whole screen by default.
+    bounds: Rect,
+    /// The range absolute positions from sources (e.g. a Wii Remote's IR camera) are in.
+    abs_input: Size,
+    pos: (f64, f64),
+}
+
+impl Cursor {
+    pub fn new(screen: Size, confine: Option<Rect>) -> Self {
+        let bounds = confine.unwrap_or(Rect {
+            x: 0,
+            y: 0,
+            size: screen,
+        });
+        Cursor {
+            screen,
+            bounds,
+            abs_input: screen,
+            // start in the middle of where it's allowed to be
+            pos: (
+                f64::from(bounds.x + bounds.size.width / 2),
+                f64::from(bounds.y + bounds.size.height / 2),
+            ),
+        }
+    }
+
+    /// Scale absolute positions from sources from `size` to the screen.
+    pub fn set_abs_input(&mut self, size: Size) {
+        self.abs_input = size;
+    }
+
+    /// The absolute axes the virtual device needs to position the cursor.
+    pub fn axes(&self) -> [UinputAbsSetup; 2] {
+        [
+            UinputAbsSetup::new(
+                AbsoluteAxisType::ABS_X,
+                AbsInfo::new(0, 0, self.screen.width - 1, 0, 0, 0),
+            ),
+            UinputAbsSetup::new(
+                AbsoluteAxisType::ABS_Y,
+                AbsInfo::new(0, 0, self.screen.height - 1, 0, 0, 0),
+            ),
+        ]
+    }
+
+    pub fn position(&self) -> (i32, i32) {
+        (self.pos.0 as i32, self.pos.1 as i32)
+    }
+
+    /// The edge or corner of its bounds the cursor is up against, if any.
+    pub fn edge(&self) -> Option<Edge> {
+        let (x, y) = self.position();
+        let b = self.bounds;
+        Edge::at(
+            x <= b.x,
+            x >= b.x + b.size.width - 1,
+            y <= b.y,
+            y >= b.y + b.size.height - 1,
+        )
+    }
+
+    /// Turn pointer motion in `events` into absolute positions within the bounds, leaving other
+    /// events as they are, appending the result to `out`.
+    pub fn map(&mut self, events: &[InputEvent], out: &mut Vec<InputEvent>) {
+        let before = self.position();
+        for ev in events {
+            match ev.kind() {
+                InputEventKind::RelAxis(RelativeAxisType::REL_X) => {
+                    self.pos.0 += f64::from(ev.value())
+                }
+                InputEventKind::RelAxis(RelativeAxisType::REL_Y) => {
+                    self.pos.1 += f64::from(ev.value())
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_X) => {
+                    self.pos.0 = scale(ev.value(), self.abs_input.width, self.screen.width)
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_Y) => {
+                    self.pos.1 = scale(ev.value(), self.abs_input.height, self.screen.height)
+                }
+                _ => {
+                    out.push(*ev);
+                    continue;
+                }
+            }
+        }
+        let b = self.bounds;
+        self.pos.0 = self
+            .pos
+            .0
+            .clamp(f64::from(b.x), f64::from(b.x + b.size.width - 1));
+        self.pos.1 = self
+            .pos
+            .1
+            .clamp(f64::from(b.y), f64::from(b.y + b.size.height - 1));
+        let after = self.position();
+        if after.0 != before.0 {
+            out.push(InputEvent::new(
+                EventType::ABSOLUTE,
+                AbsoluteAxisType::ABS_X.0,
+                after.0,
+            ));
+        }
+        if after.1 != before.1 {
+            out.push(InputEvent::new(
+                EventType::ABSOLUTE,
+                AbsoluteAxisType::ABS_Y.0,
+                after.1,
+            ));
+        }
+    }
+}
+
+fn scale(value: i32, from: i32, to: i32) -> f64 {
+    f64::from(value) * f64::from(to) / f64::from(from)
+}
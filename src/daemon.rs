@@ -0,0 +1,172 @@
+//! Running in the background (`--daemon`): detaching from the terminal, writing a pidfile, and
+//! sending what would've been printed to syslog (which journald also collects). And telling
+//! systemd when joykbd's ready, for `Type=notify` services, whether or not it's detached.
+//!
+//! The process that was started only exits once the daemon's ready (with a failure if the daemon
+//! didn't get that far), so whatever started it can count on it running by then. That's also the
+//! process systemd expects to hear from, so it's the one that tells systemd, along with the
+//! daemon's PID.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The syslog facility joykbd logs as, `LOG_DAEMON`.
+const FACILITY: u8 = 3;
+const LOG_ERR: u8 = 3;
+const LOG_WARNING: u8 = 4;
+const LOG_INFO: u8 = 6;
+
+/// Once detached, where to tell the process that was started that the daemon's ready.
+static READY: Mutex<Option<io::PipeWriter>> = Mutex::new(None);
+
+/// The pidfile, removed when it's dropped, however joykbd exits.
+pub struct Pidfile(PathBuf);
+
+impl Drop for Pidfile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// The default location, `$XDG_RUNTIME_DIR/joykbd.pid`.
+pub fn default_pidfile() -> Option<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR").map(|dir| Path::new(&dir).join("joykbd.pid"))
+}
+
+/// Fork into the background, leaving the terminal and writing the new process's ID to
+/// `pidfile`, then send stdout and stderr to syslog. This has to happen before any threads are
+/// started, since only the calling one survives the fork. The process this is called in waits for
+/// [`notify_ready`], and exits.
+pub fn detach(pidfile: Option<&Path>) -> io::Result<Option<Pidfile>> {
+    // connect first, so there's still a terminal to complain to if there's no syslog
+    let syslog = UnixDatagram::unbound()?;
+    syslog.connect("/dev/log").map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("can't connect to syslog at /dev/log: {}", e),
+        )
+    })?;
+    let (mut ready_reader, ready_writer) = io::pipe()?;
+    // fork twice, with a new session in between, so the daemon can never get a controlling
+    // terminal back
+    match fork()? {
+        0 => {}
+        _ => {
+            drop(ready_writer);
+            wait_for_ready(&mut ready_reader)
+        }
+    }
+    drop(ready_reader);
+    if unsafe { libc::setsid() } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // the working directory stays, since relative paths in the other options are relative to it
+    if fork()? != 0 {
+        unsafe { libc::_exit(0) }
+    }
+    *READY.lock().unwrap() = Some(ready_writer);
+    let pidfile = match pidfile {
+        Some(path) => {
+            fs::write(path, format!("{}\n", std::process::id()))?;
+            Some(Pidfile(path.to_owned()))
+        }
+        None => None,
+    };
+
+    let null = fs::File::open("/dev/null")?;
+    let (reader, writer) = io::pipe()?;
+    for (from, to) in [
+        (null.as_raw_fd(), 0),
+        (writer.as_raw_fd(), 1),
+        (writer.as_raw_fd(), 2),
+    ] {
+        if unsafe { libc::dup2(from, to) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    std::thread::spawn(move || {
+        let pid = std::process::id();
+        for line in BufReader::new(reader).lines() {
+            let Ok(line) = line else { break };
+            let message = format!(
+                "<{}>joykbd[{}]: {}",
+                FACILITY * 8 + severity(&line),
+                pid,
+                line
+            );
+            let _ = syslog.send(message.as_bytes());
+        }
+    });
+    Ok(pidfile)
+}
+
+/// Fork, returning the child's PID in the parent, and 0 in the child.
+fn fork() -> io::Result<libc::pid_t> {
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        pid => Ok(pid),
+    }
+}
+
+/// In the process that was started, wait for the daemon to send its PID once it's ready, then
+/// pass that on to systemd and exit. If the daemon exits first, so does this, with a failure.
+fn wait_for_ready(reader: &mut impl Read) -> ! {
+    let mut pid = String::new();
+    let code = match reader.read_to_string(&mut pid) {
+        Ok(_) if !pid.is_empty() => {
+            notify(&format!("READY=1\nMAINPID={}", pid.trim()));
+            0
+        }
+        _ => {
+            eprintln!("joykbd exited before it was ready, see syslog for why");
+            1
+        }
+    };
+    unsafe { libc::_exit(code) }
+}
+
+/// Guess a line's severity from how joykbd words it.
+fn severity(line: &str) -> u8 {
+    if line.starts_with("Error") || line.starts_with("error") {
+        LOG_ERR
+    } else if line.starts_with("warning") {
+        LOG_WARNING
+    } else {
+        LOG_INFO
+    }
+}
+
+/// Tell systemd that joykbd is ready, if it's running as a `Type=notify` service. Once detached,
+/// this goes through the process that was started, which then exits.
+pub fn notify_ready() {
+    if let Some(mut ready) = READY.lock().unwrap().take() {
+        let _ = write!(ready, "{}", std::process::id());
+        return;
+    }
+    notify("READY=1");
+}
+
+/// Send `state` to systemd's notification socket, if there is one.
+fn notify(state: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let result = (|| {
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::ffi::OsStrExt;
+        let bytes = path.as_bytes();
+        // a leading @ means the abstract namespace
+        let addr = match bytes.strip_prefix(b"@") {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(&path)?,
+        };
+        UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &addr)
+    })();
+    if let Err(e) = result {
+        eprintln!("warning: can't notify systemd: {}", e);
+    }
+}
@@ -0,0 +1,220 @@
+//! Finding and opening the joy-con.
+
+use crate::devwatch::DevWatch;
+use crate::preset;
+use crate::source::{Backend, Found, SourceEvent, SourceKind};
+use evdev::InputEventKind;
+use regex::Regex;
+use std::path::Path;
+use std::str::FromStr;
+use tokio::sync::mpsc;
+use tokio::time;
+
+/// How often to look for the device again while waiting for it to show up, if device nodes can't
+/// be watched.
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(500);
+
+/// How often to look for the device again even though no device node changed, in case it only
+/// became usable some other way.
+const WATCH_FALLBACK: time::Duration = time::Duration::from_secs(5);
+
+/// How long to wait for a button press when claiming one of several controllers.
+const CLAIM_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
+/// How long to keep looking for a device before giving up.
+#[derive(Clone, Copy)]
+pub enum WaitWindow {
+    Secs(u64),
+    Forever,
+}
+
+impl FromStr for WaitWindow {
+    type Err = std::num::ParseIntError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "forever" {
+            Ok(WaitWindow::Forever)
+        } else {
+            s.parse().map(WaitWindow::Secs)
+        }
+    }
+}
+
+/// Open the device at `path` with `backend`, or search for a joy-con (or a device whose name
+/// matches `match_name`) if it's `None`, retrying for up to `wait`. With `claim`, if several are
+/// found, the one to use is picked by pressing a button on it.
+pub async fn open(
+    backend: &Backend,
+    path: Option<&Path>,
+    match_name: Option<&Regex>,
+    wait: WaitWindow,
+    claim: bool,
+) -> anyhow::Result<Found> {
+    let deadline = match wait {
+        WaitWindow::Secs(secs) => Some(time::Instant::now() + time::Duration::from_secs(secs)),
+        WaitWindow::Forever => None,
+    };
+    if path.is_none() {
+        eprintln!("Searching for joy-con, please wait...");
+    }
+    let watch = DevWatch::new().ok();
+    loop {
+        let res = match path {
+            Some(path) => (backend.open)(path)
+                .map(|dev| (path.to_owned(), dev))
+                .map_err(anyhow::Error::from),
+            None if claim => {
+                let mut candidates = find_joycons(backend, match_name);
+                match candidates.len() {
+                    0 => Err(anyhow::anyhow!(
+                        "could not find a connected joy-con, please pass one on the command line"
+                    )),
+                    1 => Ok(candidates.remove(0)),
+                    _ => return claim_one(backend, candidates).await,
+                }
+            }
+            None => find_joycons(backend, match_name)
+                .into_iter()
+                .next()
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "could not find a connected joy-con, please pass one on the command line"
+                    )
+                }),
+        };
+        match res {
+            Ok(found) => {
+                if path.is_none() {
+                    eprintln!("Found joy-con!");
+                }
+                return Ok(found);
+            }
+            Err(e) if deadline.is_some_and(|d| time::Instant::now() >= d) => return Err(e),
+            Err(_) => wait_for_change(watch.as_ref(), deadline).await,
+        }
+    }
+}
+
+/// Wait until it's worth looking for the device again: a device node was created or changed, or
+/// it's been a while (but not past `deadline`).
+async fn wait_for_change(watch: Option<&DevWatch>, deadline: Option<time::Instant>) {
+    let Some(watch) = watch else {
+        time::sleep(POLL_INTERVAL).await;
+        return;
+    };
+    let mut until = time::Instant::now() + WATCH_FALLBACK;
+    if let Some(deadline) = deadline {
+        until = until.min(deadline);
+    }
+    tokio::select! {
+        changed = watch.changed() => {
+            if changed.is_err() {
+                time::sleep(POLL_INTERVAL).await;
+            }
+        }
+        () = time::sleep_until(until) => {}
+    }
+}
+
+/// Wait for the controller to come back after it's disconnected. If it has a unique ID (e.g. its
+/// bluetooth address), it's found by that, so it's the same physical controller even if it
+/// reconnects as a different device node, or other controllers are connected. Otherwise,
+/// whatever turns up at `path` is used. With `any`, so is any other joy-con (or device whose name
+/// matches `match_name`) that turns up. Like when it was first opened, its sensors' devices (which
+/// share its unique ID) are never picked instead of it.
+pub async fn reopen(
+    backend: &Backend,
+    uniq: Option<&str>,
+    path: &Path,
+    any: bool,
+    match_name: Option<&Regex>,
+) -> Found {
+    let watch = DevWatch::new().ok();
+    loop {
+        let found = match uniq {
+            Some(uniq) => (backend.enumerate)().into_iter().find(|(_, dev)| {
+                dev.unique_name() == Some(uniq) && !preset::is_sensor(dev.name().unwrap_or(""))
+            }),
+            None => (backend.open)(path).ok().map(|dev| (path.to_owned(), dev)),
+        };
+        let found = match found {
+            None if any => find_joycons(backend, match_name).into_iter().next(),
+            found => found,
+        };
+        if let Some(found) = found {
+            return found;
+        }
+        wait_for_change(watch.as_ref(), None).await;
+    }
+}
+
+/// Find every joy-con, or other controller that there's a preset for. With `match_name`, it's
+/// the devices whose names match it instead, other than controllers' sensors.
+fn find_joycons(backend: &Backend, match_name: Option<&Regex>) -> Vec<Found> {
+    (backend.enumerate)()
+        .into_iter()
+        .filter(|(_, dev)| {
+            let name = dev.name().unwrap_or("");
+            match match_name {
+                Some(regex) => regex.is_match(name) && !preset::is_sensor(name),
+                None => preset::detect(name, dev.input_id()).is_some(),
+            }
+        })
+        .collect()
+}
+
+/// Pick one of several controllers by waiting for a button to be pressed (and released) on it.
+async fn claim_one(backend: &Backend, candidates: Vec<Found>) -> anyhow::Result<Found> {
+    eprintln!(
+        "Found {} controllers, press a button on the one to use within {} seconds...",
+        candidates.len(),
+        CLAIM_TIMEOUT.as_secs()
+    );
+    // the candidates' own handles would queue up the press that picks one, so they're closed,
+    // and the one that's picked is opened again once it's been let go of
+    let paths: Vec<_> = candidates.into_iter().map(|(path, _)| path).collect();
+    let (pressed_tx, mut pressed_rx) = mpsc::channel(1);
+    let mut watchers = vec![];
+    for (i, path) in paths.iter().enumerate() {
+        let watcher = match (backend.open)(path) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                eprintln!("warning: can't watch {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let (tx, mut rx) = mpsc::channel(16);
+        watcher.spawn(SourceKind::JoyCon, tx)?;
+        let pressed_tx = pressed_tx.clone();
+        watchers.push(tokio::spawn(async move {
+            let mut pressed = None;
+            while let Some(SourceEvent { event: Ok(ev), .. }) = rx.recv().await {
+                match (ev.kind(), ev.value(), pressed) {
+                    (InputEventKind::Key(key), 1, None) => pressed = Some(key),
+                    (InputEventKind::Key(key), 0, Some(held)) if key == held => {
+                        let _ = pressed_tx.send(i).await;
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }));
+    }
+    drop(pressed_tx);
+    let picked = time::timeout(CLAIM_TIMEOUT, pressed_rx.recv()).await;
+    // dropping the watchers' receivers stops them reading, and closes their handles
+    for watcher in watchers {
+        watcher.abort();
+    }
+    match picked {
+        Ok(Some(i)) => {
+            let path = paths[i].clone();
+            eprintln!("Using {}", path.display());
+            let dev = (backend.open)(&path)
+                .map_err(|e| anyhow::anyhow!("can't open {}: {}", path.display(), e))?;
+            Ok((path, dev))
+        }
+        _ => Err(anyhow::anyhow!(
+            "no button was pressed, pass the controller to use on the command line"
+        )),
+    }
+}
@@ -0,0 +1,65 @@
+//! Noticing device nodes appearing with inotify, so a controller that's waited for is picked up
+//! as soon as it connects, rather than on the next poll.
+
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use tokio::io::unix::AsyncFd;
+
+/// Where evdev's and hidraw's device nodes show up.
+const DIRS: [&str; 2] = ["/dev/input", "/dev"];
+
+pub struct DevWatch {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl DevWatch {
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        // e.g. /dev/input doesn't exist until there's an input device, so it's enough to watch
+        // either
+        let mut watched = 0;
+        let mut error = None;
+        for dir in DIRS {
+            let dir = std::ffi::CString::new(Path::new(dir).as_os_str().as_bytes())?;
+            // a node's permissions are often only set up by udev after it's created
+            let mask = libc::IN_CREATE | libc::IN_ATTRIB;
+            match unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), mask) } {
+                -1 => error = Some(io::Error::last_os_error()),
+                _ => watched += 1,
+            }
+        }
+        if let (0, Some(e)) = (watched, error) {
+            return Err(e);
+        }
+        Ok(DevWatch {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+
+    /// Wait for a device node to be created, or its permissions to change.
+    pub async fn changed(&self) -> io::Result<()> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+            let mut buf = [0u8; 4096];
+            // what changed doesn't matter, whoever's waiting looks for the device again anyway
+            let read = guard.try_io(|fd| {
+                let n = unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+                match n {
+                    n if n < 0 => Err(io::Error::last_os_error()),
+                    n => Ok(n),
+                }
+            });
+            match read {
+                Ok(Ok(_)) => return Ok(()),
+                Ok(Err(e)) => return Err(e),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
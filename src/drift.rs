@@ -0,0 +1,42 @@
+//! Adjusting the stick's drift threshold while joykbd is running, so it can be dialed in while
+//! watching the cursor.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// How much `+`/`-` change the threshold by.
+const DEFAULT_STEP: i32 = 250;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DriftAdjust {
+    /// `+[N]`/`-[N]`: raise or lower the threshold.
+    By(i32),
+    /// `N`: set the threshold.
+    Set(u32),
+    /// `save`: persist the current threshold, to be used by default from now on.
+    Save,
+}
+
+impl FromStr for DriftAdjust {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |e| format!("invalid drift threshold adjustment {:?}: {}", s, e);
+        match s {
+            "save" => Ok(DriftAdjust::Save),
+            "+" => Ok(DriftAdjust::By(DEFAULT_STEP)),
+            "-" => Ok(DriftAdjust::By(-DEFAULT_STEP)),
+            _ if s.starts_with(['+', '-']) => s.parse().map(DriftAdjust::By).map_err(invalid),
+            _ => s.parse().map(DriftAdjust::Set).map_err(invalid),
+        }
+    }
+}
+
+impl fmt::Display for DriftAdjust {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DriftAdjust::By(by) => write!(f, "{:+}", by),
+            DriftAdjust::Set(threshold) => write!(f, "{}", threshold),
+            DriftAdjust::Save => f.write_str("save"),
+        }
+    }
+}
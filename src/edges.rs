@@ -0,0 +1,124 @@
+//! Hot corners/edges: actions that fire when the (absolutely tracked) cursor is pushed against an
+//! edge or into a corner of the screen and kept there for a moment.
+
+use crate::bindings::Action;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Edge {
+    const NAMES: &'static [(&'static str, Edge)] = &[
+        ("top", Edge::Top),
+        ("bottom", Edge::Bottom),
+        ("left", Edge::Left),
+        ("right", Edge::Right),
+        ("top-left", Edge::TopLeft),
+        ("top-right", Edge::TopRight),
+        ("bottom-left", Edge::BottomLeft),
+        ("bottom-right", Edge::BottomRight),
+    ];
+
+    /// The edge or corner a cursor is touching, given whether it's at the left/right and
+    /// top/bottom limits.
+    pub fn at(left: bool, right: bool, top: bool, bottom: bool) -> Option<Edge> {
+        Some(match (left, right, top, bottom) {
+            (true, _, true, _) => Edge::TopLeft,
+            (_, true, true, _) => Edge::TopRight,
+            (true, _, _, true) => Edge::BottomLeft,
+            (_, true, _, true) => Edge::BottomRight,
+            (true, ..) => Edge::Left,
+            (_, true, ..) => Edge::Right,
+            (_, _, true, _) => Edge::Top,
+            (_, _, _, true) => Edge::Bottom,
+            _ => return None,
+        })
+    }
+}
+
+impl FromStr for Edge {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Edge::NAMES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|&(_, edge)| edge)
+            .ok_or_else(|| format!("unknown edge {:?}", s))
+    }
+}
+
+/// An `EDGE=ACTION` pair, as passed on the command line.
+#[derive(Clone, Copy)]
+pub struct EdgeAction {
+    pub edge: Edge,
+    pub action: Action,
+}
+
+impl FromStr for EdgeAction {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (edge, action) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected EDGE=ACTION, got {:?}", s))?;
+        Ok(EdgeAction {
+            edge: edge.parse()?,
+            action: action.parse()?,
+        })
+    }
+}
+
+pub struct EdgeTriggers {
+    actions: Vec<EdgeAction>,
+    dwell: Duration,
+    /// The edge the cursor is at, since when, and whether its action already fired.
+    current: Option<(Edge, Instant, bool)>,
+}
+
+impl EdgeTriggers {
+    pub fn new(actions: Vec<EdgeAction>, dwell: Duration) -> Self {
+        EdgeTriggers {
+            actions,
+            dwell,
+            current: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Update with the edge the cursor is at now, returning the actions to fire, once per visit.
+    pub fn poll(&mut self, edge: Option<Edge>) -> Vec<Action> {
+        let now = Instant::now();
+        let (at, since, fired) = match (edge, &mut self.current) {
+            (None, _) => {
+                self.current = None;
+                return vec![];
+            }
+            (Some(edge), Some((at, since, fired))) if *at == edge => (edge, *since, fired),
+            (Some(edge), current) => {
+                *current = Some((edge, now, false));
+                return vec![];
+            }
+        };
+        if *fired || now - since < self.dwell {
+            return vec![];
+        }
+        *fired = true;
+        self.actions
+            .iter()
+            .filter(|a| a.edge == at)
+            .map(|a| a.action)
+            .collect()
+    }
+}
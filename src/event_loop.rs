@@ -0,0 +1,1578 @@
+//! joykbd's event loop: reading the controller (and the other sources), translating what it
+//! reads with a [`Remapper`] and emitting the result through a [`VirtualOutput`], along with
+//! everything around that, such as reconnecting, profiles, the control socket and suspending.
+
+#[cfg(feature = "alloc-audit")]
+use crate::alloc_audit;
+use crate::arrows::StickArrows;
+use crate::battery::Battery;
+use crate::bindings::{Action, Bindings};
+use crate::config::{self, Config, IdleConfig};
+use crate::conflict::{self, Grab};
+use crate::cursor::Cursor;
+use crate::device::{self, WaitWindow};
+use crate::edges::EdgeTriggers;
+use crate::feedback::{Cue, Feedback};
+use crate::gamepad::Gamepad;
+use crate::gyro::{self, Gyro, GyroSettings};
+use crate::holdstats::HoldStats;
+use crate::idle::Idle;
+use crate::ipc::{self, Command};
+use crate::layout::Layout;
+use crate::nfc::TagAction;
+use crate::options::Options;
+use crate::output::{stamp, Capabilities, VirtualOutput};
+use crate::overlay::{Indicators, Overlay};
+use crate::passthrough::{self, Passthrough};
+use crate::preset::{self, PointerButtons, Preset};
+use crate::record::{History, Recorder};
+use crate::remapper::Remapper;
+use crate::rumble::{Haptic, Rumbler};
+use crate::scroll::StickScroll;
+use crate::source::{InputSource, SourceEvent, SourceKind};
+use crate::speed::SpeedMode;
+use crate::state::{State, StateFile};
+use crate::status::Status;
+use crate::stick::{Axis, Repeat, StickConstants, StickSide};
+use crate::switcher::ProfileManager;
+use crate::textentry::TextEntry;
+use crate::trackpad::Trackpad;
+use crate::unmapped::Unmapped;
+use crate::{arrows, battery, idle, partner, policy, power, textentry, wiimote};
+use evdev::{
+    AbsoluteAxisType, AttributeSet, Device, EventType, InputEvent, InputEventKind, Key,
+    RelativeAxisType,
+};
+use smallvec::{smallvec, SmallVec};
+use std::path::Path;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, watch};
+use tokio::time;
+
+/// How often to check whether the cursor is at an edge with an action.
+const EDGE_POLL: time::Duration = time::Duration::from_millis(50);
+
+/// How often to check for keys held past --max-hold.
+const HOLD_POLL: time::Duration = time::Duration::from_secs(1);
+
+/// How often to retry emitting events that a slow --output couldn't take.
+const BACKLOG_POLL: time::Duration = time::Duration::from_millis(5);
+
+/// How often to look for the other Joy-Con of the pair.
+const PARTNER_POLL: time::Duration = time::Duration::from_secs(2);
+
+/// How often to check the controller's battery level.
+const BATTERY_POLL: time::Duration = time::Duration::from_secs(60);
+
+impl Remapper {
+    /// Run joykbd with `args`: find the controller, translate its events through a virtual
+    /// device until a signal says to stop, and clean up after. `option_set` is the name of the
+    /// option set (see [`Options::option_set`]) the options came from, if any, and `ready` is
+    /// called once the virtual device is up.
+    pub async fn run(
+        args: Options,
+        option_set: Option<String>,
+        ready: impl FnOnce(),
+    ) -> anyhow::Result<()> {
+        let repeat_timeout = time::Duration::from_millis(args.repeat_timeout);
+
+        let (dev_path, mut dev) = device::open(
+            args.backend,
+            args.device.as_deref(),
+            args.match_name.as_ref(),
+            match args.wait {
+                true => WaitWindow::Forever,
+                false => args.wait_for_device,
+            },
+            args.claim,
+        )
+        .await?;
+        let grab_grace = time::Duration::from_secs_f64(args.grab_grace.max(0.0));
+        let mut grab = claim(&args, &dev_path, &mut *dev, grab_grace).await?;
+        let uniq = dev.unique_name().map(str::to_owned);
+        let preset = args
+            .preset
+            .or_else(|| preset::detect(dev.name().unwrap_or(""), dev.input_id()))
+            .unwrap_or(&preset::JOYCON);
+
+        let config = match (&args.config, config::default_path()) {
+            (Some(path), _) => config::load(path, true)?,
+            (None, Some(path)) => config::load(&path, false)?,
+            (None, None) => Config::default(),
+        };
+        let stick_constants = args.stick_constants(&config.stick)?;
+        let layout =
+            Layout::new(&config.layout).map_err(|e| anyhow::anyhow!("invalid [layout]: {}", e))?;
+        let chord = match &args.profile_chord {
+            Some(chord) => chord
+                .split('+')
+                .map(|button| {
+                    config::parse_button(button.trim())
+                        .ok_or_else(|| anyhow::anyhow!("unknown button {:?}", button))
+                })
+                .collect::<anyhow::Result<_>>()?,
+            None => vec![],
+        };
+        if !chord.is_empty() && config.profiles.is_empty() {
+            anyhow::bail!("--profile-chord needs profiles in the config file");
+        }
+        let mut all = args.profile_bindings(&config)?;
+        let (mut profiles, bindings) = match config.profiles.is_empty() {
+            false => {
+                let (mut profiles, bindings) = ProfileManager::new(all, chord);
+                if args.profile_leds {
+                    profiles.find_leds(&dev_path);
+                }
+                (Some(profiles), bindings)
+            }
+            true => (None, all.remove(0).1),
+        };
+        let mut remapper = args.new_remapper(preset, bindings, stick_constants);
+        for trigger in &args.nfc_tags {
+            if let TagAction::Profile(name) = &trigger.action {
+                if profiles.as_ref().and_then(|p| p.position(name)).is_none() {
+                    anyhow::bail!("--nfc-tag: no profile {:?} in the config file", name);
+                }
+            }
+        }
+        if let Some(name) = &args.ir_presence_profile {
+            if profiles.as_ref().and_then(|p| p.position(name)).is_none() {
+                anyhow::bail!(
+                    "--ir-presence-profile: no profile {:?} in the config file",
+                    name
+                );
+            }
+        }
+        // the profiles focusing a window switches to, by its class
+        let window_profiles: Vec<(&str, usize)> = config
+            .profiles
+            .iter()
+            .enumerate()
+            .flat_map(|(i, (_, profile))| {
+                // after the default profile
+                profile
+                    .windows
+                    .iter()
+                    .map(move |class| (class.as_str(), i + 1))
+            })
+            .collect();
+        let window_profile = |class: &str| {
+            (window_profiles.iter())
+                .find(|(window, _)| window.eq_ignore_ascii_case(class))
+                .map(|&(_, index)| index)
+        };
+        // the profile to switch back to once none of their windows is focused
+        let mut window_return = None;
+        // the class of the window that was focused at the last poll
+        let mut last_focused = None;
+        // by profile, in the same order as they're switched between
+        let default_idle = IdleConfig {
+            timeout_minutes: args.idle_timeout,
+            command: args.idle_command.clone(),
+            resume_command: args.idle_resume_command.clone(),
+            profile: args.idle_profile.clone(),
+        };
+        let idle_settings: Vec<_> = std::iter::once(default_idle.clone())
+            .chain(
+                config
+                    .profiles
+                    .values()
+                    .map(|profile| profile.idle.clone().unwrap_or_else(|| default_idle.clone())),
+            )
+            .collect();
+        for name in idle_settings
+            .iter()
+            .filter_map(|idle| idle.profile.as_ref())
+        {
+            if profiles.as_ref().and_then(|p| p.position(name)).is_none() {
+                anyhow::bail!("idle: no profile {:?} in the config file", name);
+            }
+        }
+        let mut state_file = match args.state_file.clone().or_else(StateFile::default_path) {
+            Some(path) => {
+                let (state_file, state) = StateFile::load(path);
+                remapper.bindings.set_layer_latched(state.layer_latched);
+                remapper.bindings.set_clicks_swapped(state.clicks_swapped);
+                remapper.stick_constants.saved_drift_threshold = state.drift_threshold;
+                if let (None, Some(saved)) = (args.drift_threshold, state.drift_threshold) {
+                    remapper.stick_constants.drift_threshold = saved;
+                }
+                Some(state_file)
+            }
+            None => None,
+        };
+        let mut caps = Capabilities::default();
+        for bindings in
+            std::iter::once(&remapper.bindings).chain(profiles.iter().flat_map(|p| p.inactive()))
+        {
+            for key in bindings.output_keys() {
+                caps.keys.insert(key);
+            }
+            for axis in bindings.output_axes() {
+                caps.axes.insert(axis);
+            }
+        }
+        for edge_action in &args.edge_actions {
+            match edge_action.action {
+                Action::Key(key) | Action::Latch(key) | Action::Turbo(key, _) => {
+                    caps.keys.insert(key)
+                }
+                Action::Chord(chord) => {
+                    for &key in chord.keys() {
+                        caps.keys.insert(key);
+                    }
+                }
+                Action::ModTap(_, key) => caps.keys.insert(key),
+                Action::Macro(steps) => {
+                    for &key in steps.steps().iter().flat_map(|step| step.keys()) {
+                        caps.keys.insert(key);
+                    }
+                }
+                Action::Nudge(axis, _) => caps.axes.insert(axis),
+                Action::DriftThreshold(_)
+                | Action::SwapClicks
+                | Action::Speed(_)
+                | Action::PowerOff => {}
+            }
+        }
+        let mut edges = EdgeTriggers::new(
+            args.edge_actions.clone(),
+            time::Duration::from_millis(args.edge_dwell),
+        );
+        let mut edge_poll = time::interval(EDGE_POLL);
+        if args.stick_click_scroll {
+            caps.axes.insert(RelativeAxisType::REL_WHEEL);
+            caps.axes.insert(RelativeAxisType::REL_HWHEEL);
+        }
+        let mut stick_arrows = args.arrow_stick.map(StickArrows::new);
+        // in keyboard mode, both sticks press the arrow keys
+        let mut mode_arrows = [StickSide::Left, StickSide::Right].map(StickArrows::new);
+        if stick_arrows.is_some() || args.mode_key.is_some() {
+            for key in arrows::KEYS.into_iter().flatten() {
+                caps.keys.insert(key);
+            }
+        }
+        let text_entry_pages = match &config.text_entry.pages {
+            Some(pages) => pages.clone(),
+            None => textentry::DEFAULT_PAGES
+                .iter()
+                .map(|&page| page.to_owned())
+                .collect(),
+        };
+        let mut text_entry = match args.text_entry_key {
+            Some(_) => Some(
+                TextEntry::new(&text_entry_pages)
+                    .map_err(|e| anyhow::anyhow!("invalid [text-entry]: {}", e))?,
+            ),
+            None => None,
+        };
+        for key in text_entry.iter().flat_map(TextEntry::keys) {
+            caps.keys.insert(key);
+        }
+        // if the Joy-Con's own stick is used as arrows, its partner's moves the cursor instead of
+        // scrolling
+        let partner_moves_cursor =
+            args.arrow_stick.is_some() && args.arrow_stick == dev.name().and_then(partner::side);
+        for key in remapper.stick_scroll.page_keys() {
+            caps.keys.insert(key);
+        }
+        // the partner can't be asked what it supports until it shows up, so make room for anything
+        // it might emit up front
+        let partner_name = match preset.name == preset::JOYCON.name {
+            true => dev.name().and_then(partner::partner_name),
+            false => None,
+        };
+        if partner_name.is_some() && !args.gamepad {
+            for &(from, _) in preset.buttons {
+                for &key in from.iter().filter(|&&key| !remapper.bindings.is_bound(key)) {
+                    if let Some(to) = remapper.pointer.button(preset, key) {
+                        caps.keys.insert(to);
+                    }
+                }
+            }
+            caps.axes.insert(RelativeAxisType::REL_WHEEL);
+            caps.axes.insert(RelativeAxisType::REL_HWHEEL);
+        }
+        if args.scroll_stick.is_some() {
+            caps.axes.insert(RelativeAxisType::REL_WHEEL);
+            caps.axes.insert(RelativeAxisType::REL_HWHEEL);
+        }
+        let mut partner_connected = false;
+        // once the partner's joined, only the same one can rejoin
+        let mut partner_uniq = args.partner.clone();
+        // the partner's buttons held down, to release if it disconnects
+        let mut partner_held = AttributeSet::<Key>::new();
+        let mut partner_poll = time::interval(PARTNER_POLL);
+        // scrolls the scrolling stick's position, as cursor motion, by every repeat
+        let mut wheel_scroll = StickScroll::new(false, None);
+        let mut motion = Motion::default();
+        let mut scroll_repeat = time::interval(
+            args.scroll_repeat_timeout
+                .map_or(repeat_timeout, time::Duration::from_millis),
+        );
+        scroll_repeat.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        // the partner's stick scrolls (unless it moves the cursor), as does --scroll-stick
+        let scrolls = |kind: SourceKind, ev: &InputEvent| match ev.kind() {
+            InputEventKind::AbsAxis(axis) => {
+                kind == SourceKind::Partner && !partner_moves_cursor
+                    || args
+                        .scroll_stick
+                        .is_some_and(|side| side.axis_index(axis).is_some())
+            }
+            _ => false,
+        };
+        let mut gamepad = if args.gamepad {
+            let evdev = dev
+                .evdev()
+                .ok_or_else(|| anyhow::anyhow!("--gamepad needs the evdev backend"))?;
+            Some(Gamepad::spawn(
+                evdev,
+                &dev_path,
+                &remapper.bindings,
+                args.gamepad_xbox,
+                partner_name.is_some(),
+            )?)
+        } else {
+            add_source_capabilities(
+                SourceKind::JoyCon,
+                &*dev,
+                &layout,
+                preset,
+                &remapper.pointer,
+                &remapper.bindings,
+                &mut caps,
+            );
+            None
+        };
+        // the gamepad mirrors everything already
+        let mut unmapped = match (config.forward_unmapped && gamepad.is_none(), dev.evdev()) {
+            (false, _) => None,
+            (true, Some(evdev)) => Unmapped::new(
+                evdev,
+                |key| layout.key(key),
+                |key| {
+                    remapper.pointer.button(preset, key).is_some()
+                        || remapper.bindings.is_bound(key)
+                },
+            )?,
+            (true, None) => {
+                eprintln!("warning: forward-unmapped needs the evdev backend");
+                None
+            }
+        };
+
+        let (tx, mut rx) = mpsc::channel(64);
+        let mut trackpads = vec![];
+        let extra_sources = (args
+            .keyboards
+            .iter()
+            .map(|path| (SourceKind::Keyboard, path)))
+        .chain(args.mice.iter().map(|path| (SourceKind::Mouse, path)))
+        .chain(
+            args.trackpads
+                .iter()
+                .enumerate()
+                .map(|(i, path)| (SourceKind::Trackpad(i), path)),
+        );
+        for (kind, path) in extra_sources {
+            let mut extra = Device::open(path)?;
+            if let SourceKind::Trackpad(_) = kind {
+                trackpads.push(Trackpad::new(
+                    &extra,
+                    args.trackpad_speed,
+                    args.natural_scroll,
+                )?);
+            }
+            add_source_capabilities(
+                kind,
+                &extra,
+                &layout,
+                preset,
+                &remapper.pointer,
+                &remapper.bindings,
+                &mut caps,
+            );
+            // otherwise the desktop would see both the original and the remapped input
+            conflict::grab(&mut extra, grab_grace).await?;
+            Box::new(extra).spawn(kind, tx.clone())?;
+        }
+
+        if preset.name == preset::WIIMOTE.name {
+            match dev.evdev().and_then(wiimote::find_ir) {
+                Some((_, ir)) => {
+                    caps.abs.extend(wiimote::pointer_axes());
+                    Box::new(ir).spawn(SourceKind::IrPointer, tx.clone())?;
+                }
+                None => eprintln!("warning: couldn't find the Wii Remote's IR camera"),
+            }
+        }
+        let mut gyro = None;
+        if args.gyro {
+            match dev.evdev().and_then(gyro::find_imu) {
+                Some((_, imu)) => {
+                    let settings = GyroSettings {
+                        sensitivity: args.gyro_sensitivity,
+                        invert_x: args.gyro_invert_x,
+                        invert_y: args.gyro_invert_y,
+                        hold: args.gyro_hold,
+                    };
+                    gyro = Some(Gyro::new(settings, &imu));
+                    Box::new(imu).spawn(SourceKind::Imu, tx.clone())?;
+                }
+                None => eprintln!("warning: couldn't find the controller's IMU for --gyro"),
+            }
+        }
+
+        let cursor = args.screen.map(|screen| {
+            let mut cursor = Cursor::new(screen, args.confine);
+            // the cursor's axes replace the IR camera's and relative motion
+            caps.abs.clear();
+            caps.abs.extend(cursor.axes());
+            caps.axes.remove(RelativeAxisType::REL_X);
+            caps.axes.remove(RelativeAxisType::REL_Y);
+            cursor.set_abs_input(wiimote::IR_SIZE);
+            cursor
+        });
+        // either mouse button can come out as the other once they're swapped
+        if caps.keys.contains(Key::BTN_LEFT) || caps.keys.contains(Key::BTN_RIGHT) {
+            caps.keys.insert(Key::BTN_LEFT);
+            caps.keys.insert(Key::BTN_RIGHT);
+        }
+        let mut uinp = VirtualOutput::new(
+            args.output.open(&caps)?,
+            cursor,
+            args.max_hold.map(time::Duration::from_secs),
+            args.sticky_keys,
+        );
+        uinp.set_rotation(args.rotate);
+        uinp.set_batch_window(args.syn_batch.map(time::Duration::from_millis));
+        uinp.set_clicks_swapped(remapper.bindings.clicks_swapped())?;
+        ready();
+        let mut hold_poll = time::interval(HOLD_POLL);
+        let mut backlog_poll = time::interval(BACKLOG_POLL);
+
+        let mut rumbler = match Rumbler::spawn(&dev_path) {
+            Ok(rumbler) => Some(rumbler),
+            Err(e) => {
+                eprintln!("note: rumble unavailable: {}", e);
+                None
+            }
+        };
+
+        for (name, envelope) in &config.rumble {
+            envelope
+                .validate()
+                .map_err(|e| anyhow::anyhow!("invalid rumble envelope {:?}: {}", name, e))?;
+        }
+        let mut hd_rumbler = dev.hd_rumbler();
+        let mut feedback = Feedback::new(args.sounds, args.speak, config.rumble.clone());
+        feedback.set_hd_rumbler(hd_rumbler.clone());
+        let mut battery = Battery::find(&dev_path);
+        let mut battery_poll = time::interval(BATTERY_POLL);
+        // the lowest level we've already warned about
+        let mut battery_warned = None;
+        // whether --battery-warn has warned since the battery was last above its level
+        let mut battery_low = false;
+
+        let (ctl_tx, mut ctl_rx) = mpsc::channel(8);
+        if let Some(path) = args.control_socket.clone().or_else(ipc::default_path) {
+            ipc::listen(&path, ctl_tx)?;
+        }
+
+        let mut passthrough = Passthrough::new(
+            dev_path.clone(),
+            args.passthrough_on_grab,
+            args.passthrough_windows.clone(),
+        );
+        passthrough.set_grabbed(grab.is_some());
+        let owners = args
+            .owners
+            .as_deref()
+            .map(policy::Owners::load)
+            .transpose()?;
+        let logind = match &owners {
+            Some(_) => Some(
+                policy::Logind::connect()
+                    .await
+                    .map_err(|e| anyhow::anyhow!("can't check the controller's owner: {}", e))?,
+            ),
+            None => None,
+        };
+        let mut seats = match &logind {
+            Some(logind) => logind.seats(),
+            None => watch::channel(None).1,
+        };
+        // virtual devices start out on the first seat
+        let mut output_seat = "seat0".to_owned();
+        if let (Some(owners), Some(logind)) = (&owners, &logind) {
+            passthrough.set_ownership(owners.ownership(uniq.as_deref(), logind));
+            match passthrough.ownership() {
+                Some(ownership) => eprintln!("The controller belongs to {}", ownership.user),
+                None => eprintln!("note: the controller has no owner, so it isn't restricted"),
+            }
+            route_to_owner(
+                &passthrough,
+                logind,
+                &mut uinp,
+                &mut unmapped,
+                &mut output_seat,
+            )
+            .await;
+        }
+        let mut passthrough_poll =
+            time::interval(time::Duration::from_millis(args.passthrough_poll));
+        let mut window_poll = time::interval(time::Duration::from_millis(args.passthrough_poll));
+        let mut suspended = false;
+
+        let mut status = Status {
+            device: dev.name().unwrap_or("unknown device").to_owned(),
+            device_path: dev_path.clone(),
+            unique_name: uniq.clone(),
+            backend: args.backend.name,
+            preset: preset.name,
+            option_set,
+            profile: profiles.as_ref().map(|p| p.active_name().to_owned()),
+            keys: dev
+                .supported_keys()
+                .iter()
+                .map(|key| format!("{:?}", key))
+                .collect(),
+            axes: dev
+                .supported_relative_axes()
+                .iter()
+                .map(|axis| format!("{:?}", axis))
+                .collect(),
+            output: args.output.to_string(),
+            output_node: uinp.dev_node(),
+            connected: true,
+            partner_path: None,
+            suspended: false,
+            battery: battery.as_ref().and_then(Battery::level),
+            grabbed: grab.is_some(),
+        };
+        if args.json {
+            println!("{}", status.to_json());
+        } else {
+            eprintln!("Using {}", status);
+        }
+
+        let (nfc_tx, mut nfc_rx) = mpsc::channel(4);
+        if !args.nfc_tags.is_empty() && !dev.enable_nfc(nfc_tx.clone()) {
+            eprintln!(
+                "warning: --nfc-tag needs the hidraw backend, and a controller with an NFC reader"
+            );
+        }
+        let (presence_tx, mut presence_rx) = mpsc::channel(4);
+        if let Some(threshold) = args.ir_presence {
+            if !dev.enable_ir_presence(threshold, presence_tx.clone()) {
+                eprintln!("warning: --ir-presence needs the hidraw backend, and a right Joy-Con");
+            }
+        }
+        let (temperature_tx, mut temperature_rx) = mpsc::channel(4);
+        if args.drift_temperature && !dev.enable_temperature(temperature_tx.clone()) {
+            eprintln!("warning: --drift-temperature needs the hidraw backend");
+        }
+        // whether something's near the IR camera
+        let mut near = false;
+        sync_state(&*dev, args.sync_held, &tx);
+        dev.spawn(SourceKind::JoyCon, tx.clone())?;
+        let (reconnect_tx, mut reconnect_rx) = mpsc::channel(1);
+
+        let sleep_x = time::sleep(time::Duration::MAX);
+        let sleep_y = time::sleep(time::Duration::MAX);
+        // the stick that last moved the cursor, to work out its motion again when it's repeated
+        let mut cursor_stick = None;
+        let remap_on_repeat = args.repeat == Repeat::Live || remapper.stick_constants.smooths();
+        let mut idle = Idle::new(idle_settings);
+        let idle_timer = time::sleep(time::Duration::MAX);
+        tokio::pin!(sleep_x, sleep_y, idle_timer);
+
+        let mut hold_stats = args.hold_stats.then(HoldStats::default);
+        let mut history = History::default();
+        let mut recorder = match &args.record {
+            Some(path) => Some(
+                Recorder::create(path, args.record_private)
+                    .map_err(|e| anyhow::anyhow!("can't create {}: {}", path.display(), e))?,
+            ),
+            None => None,
+        };
+        let overlay = match &args.overlay {
+            Some(addr) => Some(Overlay::serve(addr, status.profile.clone()).await?),
+            None => None,
+        };
+        if let (Some(overlay), Some(_)) = (&overlay, &text_entry) {
+            overlay.set_text_entry_pages(text_entry_pages);
+        }
+
+        // what the overlay last showed
+        let mut indicators = Indicators::default();
+        // the profile the status and overlay last showed
+        let mut shown_profile = profiles.as_ref().map(ProfileManager::active);
+        let mut sigint = signal(SignalKind::interrupt())?;
+        let mut sigterm = signal(SignalKind::terminate())?;
+        loop {
+            if idle.take_restart() {
+                if let Some(timeout) = idle.timeout() {
+                    idle_timer.as_mut().reset(time::Instant::now() + timeout);
+                }
+            }
+            if let Some(profiles) = &profiles {
+                if shown_profile != Some(profiles.active()) {
+                    shown_profile = Some(profiles.active());
+                    status.profile = Some(profiles.active_name().to_owned());
+                    if let Some(overlay) = &overlay {
+                        overlay.set_profile(status.profile.clone());
+                    }
+                }
+            }
+            if let Some(overlay) = &overlay {
+                let now = Indicators {
+                    layer: remapper.bindings.layer_active(),
+                    speed: [SpeedMode::Sprint, SpeedMode::Precision]
+                        .into_iter()
+                        .find(|&mode| remapper.bindings.speed_held(mode))
+                        .map(SpeedMode::name),
+                    clicks_swapped: remapper.bindings.clicks_swapped(),
+                    rotation: uinp.rotation().degrees(),
+                    connected: status.connected,
+                    suspended: status.suspended,
+                    text_entry: text_entry
+                        .as_ref()
+                        .filter(|text_entry| text_entry.active())
+                        .map(TextEntry::selection),
+                };
+                // most events change none of them, and every change wakes the overlay's clients
+                if now != indicators {
+                    indicators = now;
+                    overlay.update(indicators);
+                }
+            }
+            tokio::select! {
+                Some(SourceEvent { kind, event }) = rx.recv() => {
+                    let event = match event {
+                        Ok(event) => event,
+                        Err(e) if kind == SourceKind::JoyCon && (args.watch || e.raw_os_error() == Some(libc::ENODEV)) => {
+                            if e.raw_os_error() == Some(libc::ENODEV) {
+                                eprintln!("Controller disconnected, waiting for it to reconnect...");
+                            } else {
+                                eprintln!("Controller error: {}, waiting for it to reconnect...", e);
+                            }
+                            status.connected = false;
+                            feedback.play(Cue::Disconnected);
+                            // if it's still there, other programs can have it while it's reopened
+                            drop(grab.take());
+                            status.grabbed = false;
+                            passthrough.set_grabbed(false);
+                            uinp.release_all()?;
+                            if let Some(unmapped) = &mut unmapped {
+                                unmapped.release_all()?;
+                            }
+                            motion.stop();
+                            let reconnect_tx = reconnect_tx.clone();
+                            let uniq = uniq.clone();
+                            let dev_path = dev_path.clone();
+                            let match_name = args.match_name.clone();
+                            tokio::spawn(async move {
+                                let found =
+                                    device::reopen(args.backend, uniq.as_deref(), &dev_path, args.watch, match_name.as_ref()).await;
+                                let _ = reconnect_tx.send(found).await;
+                            });
+                            continue;
+                        }
+                        // the IR camera goes away along with the rest of the Wii Remote, and is
+                        // found again when it reconnects
+                        Err(e) if e.raw_os_error() == Some(libc::ENODEV) && matches!(kind, SourceKind::IrPointer | SourceKind::Imu) => {
+                            continue;
+                        }
+                        Err(e) if e.raw_os_error() == Some(libc::ENODEV) && kind == SourceKind::Partner => {
+                            eprintln!("The other Joy-Con disconnected");
+                            partner_connected = false;
+                            motion.scroll = (0, 0);
+                            if partner_moves_cursor {
+                                motion.x = 0;
+                                motion.y = 0;
+                            }
+                            status.partner_path = None;
+                            // its releases are handled like it had sent them, so they go through the
+                            // same mapping (and layers, gamepad, ...) its presses did
+                            let tx = tx.clone();
+                            let held = std::mem::take(&mut partner_held);
+                            tokio::spawn(async move {
+                                for key in held.iter() {
+                                    let event = Ok(InputEvent::new(EventType::KEY, key.code(), 0));
+                                    let _ = tx.send(SourceEvent { kind: SourceKind::Partner, event }).await;
+                                }
+                            });
+                            continue;
+                        }
+                        Err(e) => {
+                            feedback.play_now(Cue::Error).await;
+                            return Err(e.into());
+                        }
+                    };
+                    if let (SourceKind::Partner, InputEventKind::Key(key)) = (kind, event.kind()) {
+                        match event.value() {
+                            0 => partner_held.remove(key),
+                            1 => partner_held.insert(key),
+                            _ => {}
+                        }
+                    }
+                    let event = match kind {
+                        SourceKind::JoyCon | SourceKind::Partner => layout.map(event),
+                        _ => event,
+                    };
+                    #[cfg(feature = "alloc-audit")]
+                    let _audit = alloc_audit::Audit::new(event);
+                    history.push(kind, &event);
+                    if let Some(rec) = &mut recorder {
+                        if let Err(e) = rec.record(kind, &event) {
+                            eprintln!("warning: stopped recording: {}", e);
+                            recorder = None;
+                        }
+                    }
+                    // only the joy-con is handed over to the game, other sources keep working
+                    if suspended && matches!(kind, SourceKind::JoyCon | SourceKind::Partner | SourceKind::Imu) {
+                        continue;
+                    }
+                    // before counting as activity, e.g. a damaged stick's noise
+                    if remapper.bindings.ignore().ignores(kind, &event) {
+                        continue;
+                    }
+                    // the IMU reports constantly, even when the controller is put down
+                    if kind != SourceKind::Imu {
+                        if let Some(index) = idle.activity() {
+                            // only ever switched away from with profiles
+                            let profiles = profiles.as_mut().unwrap();
+                            switch_profile(
+                                index,
+                                profiles,
+                                &mut remapper.bindings,
+                                &mut uinp,
+                                &mut idle,
+                                &mut motion,
+                                None,
+                            )?;
+                        }
+                    }
+                    // stick motion is repeated until the stick moves again, other motion (e.g. nudges)
+                    // isn't
+                    let from_stick = (kind == SourceKind::JoyCon
+                        || kind == SourceKind::Partner && partner_moves_cursor)
+                        && matches!(event.kind(), InputEventKind::AbsAxis(_));
+                    let source = event;
+                    if let Some(hold_stats) = &mut hold_stats {
+                        if matches!(kind, SourceKind::JoyCon | SourceKind::Partner) {
+                            hold_stats.record(&event);
+                        }
+                    }
+                    if let (Some(profiles), InputEventKind::Key(key)) = (&mut profiles, event.kind()) {
+                        if matches!(kind, SourceKind::JoyCon | SourceKind::Partner)
+                            && profiles.key(key, event.value())
+                        {
+                            let next = profiles.next();
+                            switch_profile(
+                                next,
+                                profiles,
+                                &mut remapper.bindings,
+                                &mut uinp,
+                                &mut idle,
+                                &mut motion,
+                                rumbler.as_ref().filter(|_| args.haptics),
+                            )?;
+                            continue;
+                        }
+                    }
+                    if let (Some(mode_key), InputEventKind::Key(key)) = (args.mode_key, event.kind()) {
+                        if key == mode_key && matches!(kind, SourceKind::JoyCon | SourceKind::Partner) {
+                            if event.value() == 1 {
+                                // what's held in one mode would be released as something else in the
+                                // other
+                                uinp.release_all()?;
+                                motion.stop();
+                                mode_arrows = [StickSide::Left, StickSide::Right].map(StickArrows::new);
+                                remapper.bindings.set_keyboard_mode(!remapper.bindings.keyboard_mode());
+                                match remapper.bindings.keyboard_mode() {
+                                    true => eprintln!("Switched to keyboard mode"),
+                                    false => eprintln!("Switched to mouse mode"),
+                                }
+                            }
+                            continue;
+                        }
+                    }
+                    if let (Some(text_entry), true) = (&mut text_entry, matches!(kind, SourceKind::JoyCon | SourceKind::Partner)) {
+                        match event.kind() {
+                            InputEventKind::Key(key) if Some(key) == args.text_entry_key => {
+                                if event.value() == 1 {
+                                    // the stick stops moving the cursor while it's picking characters
+                                    uinp.release_all()?;
+                                    motion.stop();
+                                    text_entry.set_active(!text_entry.active());
+                                    match text_entry.active() {
+                                        true => eprintln!("Text entry on, page: {:?}", text_entry.page()),
+                                        false => eprintln!("Text entry off"),
+                                    }
+                                }
+                                continue;
+                            }
+                            InputEventKind::Key(key) if text_entry.active() => {
+                                if let Some(events) = text_entry.button(key, event.value()) {
+                                    let events: SmallVec<[_; 4]> =
+                                        events.into_iter().map(|ev| stamp(ev, &source)).collect();
+                                    uinp.emit(&events)?;
+                                    continue;
+                                }
+                            }
+                            InputEventKind::AbsAxis(axis) if text_entry.active() => {
+                                if let Some((side, axis)) = StickSide::of(axis) {
+                                    text_entry.stick(side, axis, event.value());
+                                    continue;
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    if let (Some(gyro), InputEventKind::Key(key)) = (&mut gyro, event.kind()) {
+                        if matches!(kind, SourceKind::JoyCon | SourceKind::Partner) {
+                            gyro.key(key, event.value());
+                        }
+                    }
+                    let layer_was_active = remapper.bindings.layer_active();
+                    let evs = match (kind, &mut gamepad) {
+                        (SourceKind::JoyCon | SourceKind::Partner, Some(gamepad)) => {
+                            gamepad.forward(event, &mut remapper.bindings, kind == SourceKind::Partner).await;
+                            smallvec![]
+                        }
+                        (SourceKind::JoyCon, None) if remapper.stick_scroll.handles(&event, &remapper.bindings) => {
+                            if event.value() == 0 {
+                                // it wasn't moved, so it was just a click
+                                let to = remapper.pointer.button(preset, Key::new(event.code()));
+                                if let (true, Some(to)) = (remapper.stick_scroll.release(), to) {
+                                    haptic(&args, rumbler.as_ref(), Haptic::Click);
+                                    uinp.emit(&[
+                                        InputEvent::new(EventType::KEY, to.code(), 1),
+                                        InputEvent::new(EventType::KEY, to.code(), 0),
+                                    ])?;
+                                }
+                            } else if event.value() == 1 {
+                                remapper.stick_scroll.press();
+                            }
+                            smallvec![]
+                        }
+                        (SourceKind::JoyCon | SourceKind::Partner, None)
+                            if remapper.bindings.keyboard_mode() && mode_arrows.iter().any(|arrows| arrows.handles(&event)) =>
+                        {
+                            let events: SmallVec<[_; 2]> = mode_arrows
+                                .iter_mut()
+                                .find(|arrows| arrows.handles(&event))
+                                .unwrap()
+                                .map(event)
+                                .into_iter()
+                                .map(|ev| stamp(ev, &source))
+                                .collect();
+                            uinp.emit(&events)?;
+                            smallvec![]
+                        }
+                        (SourceKind::JoyCon | SourceKind::Partner, None)
+                            if stick_arrows.as_ref().is_some_and(|arrows| arrows.handles(&event)) =>
+                        {
+                            let events: SmallVec<[_; 2]> = stick_arrows
+                                .as_mut()
+                                .unwrap()
+                                .map(event)
+                                .into_iter()
+                                .map(|ev| stamp(ev, &source))
+                                .collect();
+                            uinp.emit(&events)?;
+                            smallvec![]
+                        }
+                        (SourceKind::JoyCon | SourceKind::Partner, _) if scrolls(kind, &event) => {
+                            if let Some((side, axis)) = StickSide::of(AbsoluteAxisType(event.code())) {
+                                let [x, y] = remapper.stick_constants
+                                    .map_stick(side, axis, event.value())
+                                    .map(|value| value.map(|value| (f64::from(value) * args.scroll_speed) as i32));
+                                motion.scroll.0 = x.unwrap_or(motion.scroll.0);
+                                motion.scroll.1 = y.unwrap_or(motion.scroll.1);
+                            }
+                            smallvec![]
+                        }
+                        (SourceKind::Imu, _) => {
+                            let events: SmallVec<[_; 2]> = gyro
+                                .as_mut()
+                                .map(|gyro| gyro.map(event))
+                                .unwrap_or_default()
+                                .into_iter()
+                                .map(|ev| stamp(ev, &source))
+                                .collect();
+                            if !events.is_empty() {
+                                uinp.emit(&events)?;
+                            }
+                            smallvec![]
+                        }
+                        (SourceKind::Trackpad(i), _) if Trackpad::is_touch_event(&event) => {
+                            let events: SmallVec<[_; 2]> = trackpads[i]
+                                .handle(event)
+                                .into_iter()
+                                .map(|ev| stamp(ev, &source))
+                                .collect();
+                            if !events.is_empty() {
+                                uinp.emit(&events)?;
+                            }
+                            smallvec![]
+                        }
+                        _ => {
+                            let evs = remapper.feed(kind, event, &mut uinp)?;
+                            if let Some(threshold) = remapper.take_drift_threshold() {
+                                eprintln!("Drift threshold: {}", threshold);
+                            }
+                            if let (true, Some(unmapped)) = (evs.is_empty() && matches!(kind, SourceKind::JoyCon | SourceKind::Partner), &mut unmapped) {
+                                unmapped.forward(event, &remapper.bindings)?;
+                            }
+                            evs
+                        }
+                    };
+                    if remapper.bindings.take_power_off() {
+                        if let Err(e) = power::power_off(uniq.as_deref()) {
+                            eprintln!("warning: can't power off: {}", e);
+                        }
+                    }
+                    match (layer_was_active, remapper.bindings.layer_active()) {
+                        (false, true) => feedback.play(Cue::LayerOn),
+                        (true, false) => feedback.play(Cue::LayerOff),
+                        _ => {}
+                    }
+                    if let Some(state_file) = &mut state_file {
+                        state_file.save_if_changed(&current_state(&remapper.bindings, &remapper.stick_constants));
+                    }
+                    // what was emitted for it, to repeat the stick's motion and give feedback
+                    for ev in evs {
+                        if from_stick {
+                            let mut next_repeat = time::Instant::now() + repeat_timeout;
+                            if args.repeat_latency_compensation {
+                                let age = source.timestamp().elapsed().unwrap_or_default();
+                                next_repeat -= age.min(repeat_timeout);
+                            }
+                            let was_still = (motion.x, motion.y) == (0, 0);
+                            cursor_stick = StickSide::of(AbsoluteAxisType(source.code())).map(|(side, _)| side);
+                            match ev.kind() {
+                                InputEventKind::RelAxis(RelativeAxisType::REL_X) => {
+                                    sleep_x.as_mut().reset(next_repeat);
+                                    motion.x = ev.value();
+                                }
+                                InputEventKind::RelAxis(RelativeAxisType::REL_Y) => {
+                                    sleep_y.as_mut().reset(next_repeat);
+                                    motion.y = ev.value();
+                                }
+                                _ => {}
+                            }
+                            if was_still && (motion.x, motion.y) != (0, 0) {
+                                haptic(&args, rumbler.as_ref(), Haptic::DriftThreshold);
+                            }
+                        } else if let (InputEventKind::Key(key), 1) = (ev.kind(), ev.value()) {
+                            if (Key::BTN_LEFT.code()..=Key::BTN_TASK.code()).contains(&key.code()) {
+                                haptic(&args, rumbler.as_ref(), Haptic::Click);
+                            }
+                        }
+                    }
+                }
+                () = &mut sleep_x => {
+                    // motion that was stopped stays stopped until the stick's next reading
+                    if let (Some(side), true) = (cursor_stick, motion.x != 0 && remap_on_repeat) {
+                        motion.x = remapper.stick_constants.remap(side, Axis::X);
+                    }
+                    let ev = InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_X.0, motion.x);
+                    #[cfg(feature = "alloc-audit")]
+                    let _audit = alloc_audit::Audit::new(ev);
+                    uinp.emit(&remapper.stick_scroll.map(remapper.speed.scale(ev, &remapper.bindings)))?;
+                    sleep_x.as_mut().reset(time::Instant::now() + repeat_timeout);
+                }
+                () = &mut sleep_y => {
+                    if let (Some(side), true) = (cursor_stick, motion.y != 0 && remap_on_repeat) {
+                        motion.y = remapper.stick_constants.remap(side, Axis::Y);
+                    }
+                    let ev = InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_Y.0, motion.y);
+                    #[cfg(feature = "alloc-audit")]
+                    let _audit = alloc_audit::Audit::new(ev);
+                    uinp.emit(&remapper.stick_scroll.map(remapper.speed.scale(ev, &remapper.bindings)))?;
+                    sleep_y.as_mut().reset(time::Instant::now() + repeat_timeout);
+                }
+                _ = scroll_repeat.tick(), if motion.scroll != (0, 0) => {
+                    let mut events = SmallVec::<[InputEvent; 4]>::new();
+                    for (axis, value) in [
+                        (RelativeAxisType::REL_X, motion.scroll.0),
+                        (RelativeAxisType::REL_Y, motion.scroll.1),
+                    ] {
+                        events.extend(wheel_scroll.wheel(InputEvent::new(EventType::RELATIVE, axis.0, value)));
+                    }
+                    uinp.emit(&events)?;
+                }
+                _ = partner_poll.tick(), if partner_name.is_some() && !partner_connected => {
+                    if let Some((path, partner)) = partner::find(args.backend, partner_name.as_deref().unwrap(), partner_uniq.as_deref()) {
+                        if partner_uniq.is_none() {
+                            partner_uniq = partner.unique_name().map(str::to_owned);
+                        }
+                        match (&mut gamepad, partner.evdev()) {
+                            (Some(gamepad), Some(evdev)) => {
+                                gamepad.set_partner(evdev)?;
+                                eprintln!("The other Joy-Con connected at {}, it joins the gamepad", path.display());
+                            }
+                            _ if partner_moves_cursor => eprintln!("The other Joy-Con connected at {}, its stick moves the cursor", path.display()),
+                            _ => eprintln!("The other Joy-Con connected at {}, its stick scrolls", path.display()),
+                        }
+                        partner.spawn(SourceKind::Partner, tx.clone())?;
+                        partner_connected = true;
+                        status.partner_path = Some(path);
+                    }
+                }
+                Some(request) = ctl_rx.recv() => {
+                    let reply = match request.command {
+                        Command::Rumble(pattern) => match &rumbler {
+                            Some(rumbler) => rumbler.rumble(pattern).map(|()| String::new()),
+                            None => Err("the controller doesn't support rumble".to_owned()),
+                        },
+                        Command::RumbleEnvelope(name) => match (&hd_rumbler, config.rumble.get(&name)) {
+                            (Some(hd_rumbler), Some(envelope)) => {
+                                hd_rumbler.play(envelope.clone()).map(|()| String::new())
+                            }
+                            (_, None) => Err(format!("no rumble envelope called {:?}", name)),
+                            (None, _) => Err("HD rumble needs --backend hidraw".to_owned()),
+                        },
+                        Command::DriftThreshold(adjust) => {
+                            let threshold = match adjust {
+                                Some(adjust) => remapper.stick_constants.adjust_drift(adjust),
+                                None => remapper.stick_constants.drift_threshold,
+                            };
+                            if let Some(state_file) = &mut state_file {
+                                state_file.save_if_changed(&current_state(&remapper.bindings, &remapper.stick_constants));
+                            }
+                            Ok(threshold.to_string())
+                        }
+                        Command::Rotate(rotation) => {
+                            if let Some(rotation) = rotation {
+                                uinp.set_rotation(rotation);
+                            }
+                            Ok(uinp.rotation().to_string())
+                        }
+                        Command::SetBindingEnabled(name, enabled) => {
+                            remapper.bindings.set_enabled(&name, enabled).map(|()| String::new())
+                        }
+                        Command::Bindings => Ok(remapper.bindings
+                            .names()
+                            .into_iter()
+                            .map(|(name, enabled)| match enabled {
+                                true => name.to_owned(),
+                                false => format!("{} (disabled)", name),
+                            })
+                            .collect::<Vec<_>>()
+                            .join(", ")),
+                        Command::Status { json: true } => Ok(status.to_json()),
+                        Command::Status { json: false } => Ok(status.to_string()),
+                        Command::HoldStats => match &hold_stats {
+                            Some(hold_stats) => Ok(hold_stats.report()),
+                            None => Err("joykbd wasn't started with --hold-stats".to_owned()),
+                        },
+                        Command::PowerOff => power::power_off(uniq.as_deref()).map(|()| String::new()),
+                        Command::RecentEvents => Ok(history.to_json()),
+                    };
+                    if reply.is_err() {
+                        feedback.play(Cue::Error);
+                    }
+                    let _ = request.reply.send(reply);
+                }
+                _ = passthrough_poll.tick(), if passthrough.enabled() => {
+                    let suspend = passthrough.should_suspend().await;
+                    if suspend && !suspended {
+                        eprintln!("Controller is in use elsewhere, suspending translation");
+                        uinp.release_all()?;
+                        if let Some(unmapped) = &mut unmapped {
+                            unmapped.release_all()?;
+                        }
+                        motion.x = 0;
+                        motion.y = 0;
+                        feedback.play(Cue::Suspended);
+                    } else if !suspend && suspended {
+                        eprintln!("Resuming translation");
+                        feedback.play(Cue::Resumed);
+                    }
+                    suspended = suspend;
+                    status.suspended = suspend;
+                }
+                Ok(()) = seats.changed(), if logind.is_some() => {
+                    // the passthrough check sees whether the owner's still active
+                    passthrough_poll.reset_immediately();
+                    if let Some(logind) = &logind {
+                        route_to_owner(&passthrough, logind, &mut uinp, &mut unmapped, &mut output_seat).await;
+                    }
+                }
+                Some((path, mut dev)) = reconnect_rx.recv() => {
+                    eprintln!("Controller reconnected at {}", path.display());
+                    grab = match claim(&args, &path, &mut *dev, grab_grace).await {
+                        Ok(grab) => grab,
+                        Err(e) => {
+                            eprintln!("warning: {}", e);
+                            None
+                        }
+                    };
+                    if preset.name == preset::WIIMOTE.name {
+                        if let Some((_, ir)) = dev.evdev().and_then(wiimote::find_ir) {
+                            Box::new(ir).spawn(SourceKind::IrPointer, tx.clone())?;
+                        }
+                    }
+                    if gyro.is_some() {
+                        if let Some((_, imu)) = dev.evdev().and_then(gyro::find_imu) {
+                            Box::new(imu).spawn(SourceKind::Imu, tx.clone())?;
+                        }
+                    }
+                    if gamepad.is_some() {
+                        eprintln!("note: force feedback isn't forwarded to a reconnected controller");
+                    }
+                    rumbler = Rumbler::spawn(&path).ok();
+                    hd_rumbler = dev.hd_rumbler();
+                    feedback.set_hd_rumbler(hd_rumbler.clone());
+                    battery = Battery::find(&path);
+                    battery_warned = None;
+                    battery_low = false;
+                    status.battery = battery.as_ref().and_then(Battery::level);
+                    status.device_path = path.clone();
+                    status.connected = true;
+                    status.grabbed = grab.is_some();
+                    passthrough.set_device_path(path);
+                    passthrough.set_grabbed(grab.is_some());
+                    // with --watch, it can be another controller, with another owner
+                    status.unique_name = dev.unique_name().map(str::to_owned);
+                    if let (Some(owners), Some(logind)) = (&owners, &logind) {
+                        passthrough.set_ownership(owners.ownership(dev.unique_name(), logind));
+                        route_to_owner(&passthrough, logind, &mut uinp, &mut unmapped, &mut output_seat).await;
+                        passthrough_poll.reset_immediately();
+                    }
+                    if !args.nfc_tags.is_empty() {
+                        dev.enable_nfc(nfc_tx.clone());
+                    }
+                    if let Some(threshold) = args.ir_presence {
+                        dev.enable_ir_presence(threshold, presence_tx.clone());
+                    }
+                    if args.drift_temperature {
+                        dev.enable_temperature(temperature_tx.clone());
+                    }
+                    sync_state(&*dev, args.sync_held, &tx);
+                    dev.spawn(SourceKind::JoyCon, tx.clone())?;
+                    feedback.play(Cue::Reconnected);
+                }
+                Some(temperature) = temperature_rx.recv() => {
+                    remapper.stick_constants.temperature = Some(temperature);
+                }
+                Some(uid) = nfc_rx.recv() => {
+                    eprintln!("NFC tag {} tapped", uid);
+                    for trigger in args.nfc_tags.iter().filter(|trigger| trigger.uid == uid) {
+                        match &trigger.action {
+                            TagAction::Profile(name) => {
+                                // checked at startup
+                                let profiles = profiles.as_mut().unwrap();
+                                let index = profiles.position(name).unwrap();
+                                switch_profile(
+                                    index,
+                                    profiles,
+                                    &mut remapper.bindings,
+                                    &mut uinp,
+                                    &mut idle,
+                                    &mut motion,
+                                    rumbler.as_ref().filter(|_| args.haptics),
+                                )?;
+                            }
+                            TagAction::Run(command) => idle::run(command),
+                        }
+                    }
+                }
+                // the timer is restarted by the next input
+                Some(now_near) = presence_rx.recv() => {
+                    near = now_near;
+                    // the timeout starts over once it's gone again
+                    idle.restart();
+                    if near {
+                        if let Some(index) = idle.activity() {
+                            // only ever switched away from with profiles
+                            let profiles = profiles.as_mut().unwrap();
+                            switch_profile(
+                                index,
+                                profiles,
+                                &mut remapper.bindings,
+                                &mut uinp,
+                                &mut idle,
+                                &mut motion,
+                                None,
+                            )?;
+                        }
+                        if let Some(name) = &args.ir_presence_profile {
+                            // checked at startup
+                            let profiles = profiles.as_mut().unwrap();
+                            let index = profiles.position(name).unwrap();
+                            switch_profile(
+                                index,
+                                profiles,
+                                &mut remapper.bindings,
+                                &mut uinp,
+                                &mut idle,
+                                &mut motion,
+                                rumbler.as_ref().filter(|_| args.haptics),
+                            )?;
+                        }
+                    }
+                }
+                _ = window_poll.tick(), if !window_profiles.is_empty() => {
+                    let focused = passthrough::focused_window_class().await;
+                    // only switching as the focus changes leaves a switch made some other way (e.g.
+                    // with the chord) alone while the same window stays focused
+                    if focused == last_focused {
+                        continue;
+                    }
+                    let left = last_focused.as_deref().and_then(window_profile);
+                    let entered = focused.as_deref().and_then(window_profile);
+                    last_focused = focused;
+                    // only there with profiles
+                    let profiles = profiles.as_mut().unwrap();
+                    let next = match entered {
+                        Some(index) if index != profiles.active() => {
+                            window_return.get_or_insert(profiles.active());
+                            Some(index)
+                        }
+                        Some(_) => None,
+                        // unless it was switched away from while the window was focused
+                        None => window_return.take().filter(|_| left == Some(profiles.active())),
+                    };
+                    if let Some(index) = next {
+                        switch_profile(
+                            index,
+                            profiles,
+                            &mut remapper.bindings,
+                            &mut uinp,
+                            &mut idle,
+                            &mut motion,
+                            rumbler.as_ref().filter(|_| args.haptics),
+                        )?;
+                    }
+                }
+                () = &mut idle_timer, if idle.timeout().is_some() && !idle.is_idle() && !near => {
+                    if let Some(name) = idle.start() {
+                        // checked at startup
+                        let profiles = profiles.as_mut().unwrap();
+                        let index = profiles.position(&name).unwrap();
+                        switch_profile(
+                            index,
+                            profiles,
+                            &mut remapper.bindings,
+                            &mut uinp,
+                            &mut idle,
+                            &mut motion,
+                            None,
+                        )?;
+                    }
+                }
+                _ = edge_poll.tick(), if !edges.is_empty() => {
+                    for action in edges.poll(uinp.cursor().and_then(|cursor| cursor.edge())) {
+                        run_action(action, &mut uinp, &mut remapper.bindings, &mut remapper.stick_constants)?;
+                    }
+                    if remapper.bindings.take_power_off() {
+                        if let Err(e) = power::power_off(uniq.as_deref()) {
+                            eprintln!("warning: can't power off: {}", e);
+                        }
+                    }
+                    if let Some(state_file) = &mut state_file {
+                        state_file.save_if_changed(&current_state(&remapper.bindings, &remapper.stick_constants));
+                    }
+                }
+                () = time::sleep_until(uinp.flush_deadline().map_or_else(time::Instant::now, Into::into)), if uinp.flush_deadline().is_some() => {
+                    uinp.flush()?;
+                }
+                () = time::sleep_until(remapper.bindings.turbo_deadline().map_or_else(time::Instant::now, Into::into)), if remapper.bindings.turbo_deadline().is_some() => {
+                    let changes = remapper.bindings.turbo_tick(std::time::Instant::now());
+                    // the turbo bindings were pressed on the joy-con, whose buttons the gamepad
+                    // has instead, if there is one
+                    if let Some(gamepad) = &mut gamepad {
+                        gamepad.keys(changes).await;
+                    } else {
+                        uinp.emit_iter(
+                            changes
+                                .into_iter()
+                                .map(|(key, down)| InputEvent::new(EventType::KEY, key.code(), down.into())),
+                        )?;
+                    }
+                }
+                _ = backlog_poll.tick(), if uinp.backed_up() => uinp.retry()?,
+                _ = hold_poll.tick(), if args.max_hold.is_some() => {
+                    for key in uinp.release_stuck()? {
+                        eprintln!("warning: released {:?}, which was held for too long", key);
+                    }
+                }
+                _ = battery_poll.tick(), if battery.is_some() => {
+                    let battery = battery.as_ref().unwrap();
+                    let level = battery.level();
+                    status.battery = level;
+                    if battery.charging() {
+                        battery_warned = None;
+                        battery_low = false;
+                    } else if let Some(level) = level {
+                        let warn_at = battery::WARN_LEVELS
+                            .iter()
+                            .copied()
+                            .filter(|&warn_at| level <= warn_at)
+                            .min();
+                        if let (Some(warn_at), true) = (warn_at, feedback.enabled()) {
+                            if battery_warned.is_none_or(|warned| warn_at < warned) {
+                                feedback.play(Cue::BatteryLow(level));
+                                battery_warned = Some(warn_at);
+                            }
+                        }
+                        match args.battery_warn {
+                            Some(warn_at) if level <= warn_at && !battery_low => {
+                                eprintln!("warning: controller battery at {}%", level);
+                                tokio::spawn(battery::notify_low(level));
+                                battery_low = true;
+                            }
+                            Some(warn_at) if level > warn_at => battery_low = false,
+                            _ => {}
+                        }
+                    }
+                }
+                Some(()) = sigint.recv() => break,
+                Some(()) = sigterm.recv() => break,
+            }
+        }
+        eprintln!("Shutting down");
+        // nothing stays held down for the rest of the desktop; the virtual devices are destroyed, and
+        // the grab let go of, as they're dropped
+        uinp.release_all()?;
+        uinp.flush()?;
+        if let Some(unmapped) = &mut unmapped {
+            unmapped.release_all()?;
+        }
+        Ok(())
+    }
+}
+
+/// Move the virtual devices to the seat the controller's owner is the active session on, if that
+/// isn't `output_seat`, where they are now.
+async fn route_to_owner(
+    passthrough: &Passthrough,
+    logind: &policy::Logind,
+    uinp: &mut VirtualOutput,
+    unmapped: &mut Option<Unmapped>,
+    output_seat: &mut String,
+) {
+    let Some(ownership) = passthrough.ownership() else {
+        return;
+    };
+    let Some(seat) = ownership.seat() else {
+        return;
+    };
+    if seat == *output_seat {
+        return;
+    }
+    let sys_paths = [
+        uinp.sys_path(),
+        unmapped.as_mut().and_then(Unmapped::sys_path),
+    ];
+    for sys_path in sys_paths.into_iter().flatten() {
+        if let Err(e) = logind.attach(&seat, &sys_path).await {
+            eprintln!(
+                "warning: can't route the output to {}: {}",
+                ownership.user, e
+            );
+            return;
+        }
+    }
+    eprintln!(
+        "Routing the output to {}'s session on {}",
+        ownership.user, seat
+    );
+    *output_seat = seat;
+}
+
+/// The motion the sticks' last readings left repeating until they move again: the cursor's, and
+/// the scrolling stick's position.
+#[derive(Default)]
+struct Motion {
+    x: i32,
+    y: i32,
+    scroll: (i32, i32),
+}
+
+impl Motion {
+    fn stop(&mut self) {
+        *self = Motion::default();
+    }
+}
+
+/// Switch to the config file's profile at `index`, rumbling with `haptic`, unless the switch
+/// wasn't the user's doing. The status and overlay catch up with it at the top of the loop.
+fn switch_profile(
+    index: usize,
+    profiles: &mut ProfileManager,
+    bindings: &mut Bindings,
+    uinp: &mut VirtualOutput,
+    idle: &mut Idle,
+    motion: &mut Motion,
+    haptic: Option<&Rumbler>,
+) -> std::io::Result<()> {
+    // the keys the old profile's bindings pressed would be released as something else by the
+    // new one's
+    uinp.release_all()?;
+    profiles.switch(index, bindings);
+    idle.select(index);
+    eprintln!("Switched to profile {}", profiles.active_name());
+    if bindings.ignore().axes {
+        // the stick's last motion would otherwise keep repeating
+        motion.stop();
+    }
+    if let Some(rumbler) = haptic {
+        rumbler.haptic(Haptic::ProfileSwitch);
+    }
+    Ok(())
+}
+
+/// Play `haptic`, with --haptics, if the controller can rumble.
+fn haptic(args: &Options, rumbler: Option<&Rumbler>, haptic: Haptic) {
+    if let (true, Some(rumbler)) = (args.haptics, rumbler) {
+        rumbler.haptic(haptic);
+    }
+}
+
+/// Deal with other programs using the joy-con at `path` as --on-conflict says, and grab it if
+/// --grab says to, returning the grab.
+async fn claim(
+    args: &Options,
+    path: &Path,
+    dev: &mut dyn InputSource,
+    grace: time::Duration,
+) -> anyhow::Result<Option<Grab>> {
+    let grab = conflict::resolve(args.on_conflict, path, dev, grace).await?;
+    if !args.grab || grab.is_some() {
+        return Ok(grab);
+    }
+    let dev = dev
+        .evdev_mut()
+        .ok_or_else(|| anyhow::anyhow!("--grab needs the evdev backend"))?;
+    Ok(Some(Grab::new(dev, grace).await?))
+}
+
+/// Queue up `dev`'s current state (see [`InputSource::current_state`]) ahead of the events it
+/// reads, which are only sent once it's spawned.
+fn sync_state(dev: &dyn InputSource, held: bool, tx: &mpsc::Sender<SourceEvent>) {
+    for event in dev.current_state(held) {
+        let event = SourceEvent {
+            kind: SourceKind::JoyCon,
+            event: Ok(event),
+        };
+        // waiting for room would never end: at startup nothing's reading the channel yet, and
+        // on a reconnect, what reads it is what's waiting
+        if tx.try_send(event).is_err() {
+            eprintln!("warning: couldn't pick up the controller's current state");
+            return;
+        }
+    }
+}
+
+/// Carry out a bound action outside of a key press, e.g. for an edge, tapping keys.
+fn run_action(
+    action: Action,
+    uinp: &mut VirtualOutput,
+    bindings: &mut Bindings,
+    stick_constants: &mut StickConstants,
+) -> std::io::Result<()> {
+    match action {
+        // there's no holding an edge, so a turbo one is a single tap
+        // nor is anything pressed along with it, so a mod-tap one taps its key
+        Action::Key(key) | Action::Turbo(key, _) | Action::ModTap(_, key) => uinp.emit(&[
+            InputEvent::new(EventType::KEY, key.code(), 1),
+            InputEvent::new(EventType::KEY, key.code(), 0),
+        ]),
+        Action::Chord(chord) => {
+            uinp.emit(&chord.events(1))?;
+            uinp.emit(&chord.events(0))
+        }
+        Action::Macro(steps) => uinp.emit_iter(steps.events()),
+        Action::Nudge(axis, px) => uinp.emit(&[InputEvent::new(EventType::RELATIVE, axis.0, px)]),
+        Action::Latch(key) => {
+            let latched = bindings.toggle_latch(key);
+            uinp.emit_iter(
+                (bindings.take_releases())
+                    .map(|key| InputEvent::new(EventType::KEY, key.code(), 0)),
+            )?;
+            uinp.emit(&[InputEvent::new(EventType::KEY, key.code(), latched.into())])
+        }
+        Action::DriftThreshold(adjust) => {
+            let threshold = stick_constants.adjust_drift(adjust);
+            eprintln!("Drift threshold: {}", threshold);
+            Ok(())
+        }
+        Action::SwapClicks => uinp.set_clicks_swapped(bindings.toggle_clicks_swapped()),
+        // there's nothing to hold
+        Action::Speed(_) => Ok(()),
+        Action::PowerOff => {
+            bindings.request_power_off();
+            Ok(())
+        }
+    }
+}
+
+/// The runtime state to persist in the state file.
+fn current_state(bindings: &Bindings, stick_constants: &StickConstants) -> State {
+    State {
+        layer_latched: bindings.layer_latched(),
+        drift_threshold: stick_constants.saved_drift_threshold,
+        clicks_swapped: bindings.clicks_swapped(),
+    }
+}
+
+/// Add everything the virtual device needs to advertise for events from `dev` to `caps`.
+fn add_source_capabilities(
+    kind: SourceKind,
+    dev: &dyn InputSource,
+    layout: &Layout,
+    preset: &Preset,
+    pointer: &PointerButtons,
+    bindings: &Bindings,
+    caps: &mut Capabilities,
+) {
+    let supported_keys = dev.supported_keys();
+    // keys that aren't remapped get their default treatment
+    let unbound_keys = supported_keys.iter().filter(|&key| !bindings.is_bound(key));
+    match kind {
+        SourceKind::JoyCon | SourceKind::Partner => {
+            let keys = supported_keys.iter().map(|key| layout.key(key));
+            for key in keys.filter(|&key| !bindings.is_bound(key)) {
+                if let Some(to) = pointer.button(preset, key) {
+                    caps.keys.insert(to);
+                }
+            }
+            caps.axes.insert(RelativeAxisType::REL_X);
+            caps.axes.insert(RelativeAxisType::REL_Y);
+        }
+        SourceKind::Keyboard | SourceKind::Mouse => {
+            for key in unbound_keys {
+                caps.keys.insert(key);
+            }
+        }
+        SourceKind::IrPointer | SourceKind::Imu => {}
+        SourceKind::Trackpad(_) => {
+            for key in unbound_keys {
+                // the touch reporting buttons are only for our own use
+                if !Trackpad::is_touch_key(key) {
+                    caps.keys.insert(key);
+                }
+            }
+            caps.keys.insert(Key::BTN_LEFT);
+            for axis in [
+                RelativeAxisType::REL_X,
+                RelativeAxisType::REL_Y,
+                RelativeAxisType::REL_WHEEL,
+                RelativeAxisType::REL_HWHEEL,
+            ] {
+                caps.axes.insert(axis);
+            }
+        }
+    }
+    if kind == SourceKind::Mouse {
+        for axis in dev.supported_relative_axes().iter() {
+            caps.axes.insert(axis);
+        }
+    }
+}
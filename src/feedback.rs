@@ -0,0 +1,223 @@
+//! Audible feedback on mode/layer changes, low battery and errors, for when the controller's LEDs
+//! and rumble aren't noticeable (or visible), either as sounds or spoken through
+//! speech-dispatcher. With the hidraw backend, cues can also rumble, with the config file's HD
+//! rumble envelopes named after them (e.g. `[rumble.battery-low]`), see [`crate::rumble`].
+
+use crate::rumble::{Envelope, HdRumbler};
+use std::collections::BTreeMap;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+#[derive(clap::ArgEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Sounds {
+    /// Events from the desktop's sound theme, played with canberra-gtk-play.
+    Theme,
+    /// Short generated tones, played with aplay.
+    Tones,
+}
+
+#[derive(Clone, Copy)]
+pub enum Cue {
+    LayerOn,
+    LayerOff,
+    Suspended,
+    Resumed,
+    Disconnected,
+    Reconnected,
+    Error,
+    /// The battery dropped to the given level in percent.
+    BatteryLow(u8),
+}
+
+impl Cue {
+    /// The name of the rumble envelope for this cue.
+    fn name(self) -> &'static str {
+        match self {
+            Cue::LayerOn => "layer-on",
+            Cue::LayerOff => "layer-off",
+            Cue::Suspended => "suspended",
+            Cue::Resumed => "resumed",
+            Cue::Disconnected => "disconnected",
+            Cue::Reconnected => "reconnected",
+            Cue::Error => "error",
+            Cue::BatteryLow(_) => "battery-low",
+        }
+    }
+
+    /// The freedesktop sound naming spec event for this cue.
+    fn theme_event(self) -> &'static str {
+        match self {
+            Cue::LayerOn => "button-toggle-on",
+            Cue::LayerOff => "button-toggle-off",
+            Cue::Suspended => "suspend-start",
+            Cue::Resumed => "suspend-resume",
+            Cue::Disconnected => "device-removed",
+            Cue::Reconnected => "device-added",
+            Cue::Error => "dialog-error",
+            Cue::BatteryLow(_) => "battery-low",
+        }
+    }
+
+    fn spoken(self) -> String {
+        match self {
+            Cue::LayerOn => "Layer on".to_owned(),
+            Cue::LayerOff => "Layer off".to_owned(),
+            Cue::Suspended => "Controller paused".to_owned(),
+            Cue::Resumed => "Controller resumed".to_owned(),
+            Cue::Disconnected => "Controller disconnected".to_owned(),
+            Cue::Reconnected => "Controller reconnected".to_owned(),
+            Cue::Error => "Controller error".to_owned(),
+            Cue::BatteryLow(level) => format!("Controller battery at {} percent", level),
+        }
+    }
+
+    /// The tones making up this cue, as (frequency in Hz, duration in ms), with a frequency of 0
+    /// being a pause.
+    fn tones(self) -> &'static [(u32, u32)] {
+        match self {
+            Cue::LayerOn => &[(660, 60), (880, 60)],
+            Cue::LayerOff => &[(880, 60), (660, 60)],
+            Cue::Suspended => &[(440, 200)],
+            Cue::Resumed => &[(880, 120)],
+            Cue::Disconnected => &[(660, 100), (440, 150)],
+            Cue::Reconnected => &[(440, 100), (660, 150)],
+            Cue::Error => &[(220, 100), (0, 60), (220, 100)],
+            Cue::BatteryLow(_) => &[(660, 80), (550, 80), (440, 80)],
+        }
+    }
+}
+
+pub struct Feedback {
+    sounds: Option<Sounds>,
+    speak: bool,
+    /// The rumble envelopes for cues, by [`Cue::name`].
+    rumbles: BTreeMap<String, Envelope>,
+    hd_rumbler: Option<HdRumbler>,
+}
+
+impl Feedback {
+    pub fn new(sounds: Option<Sounds>, speak: bool, rumbles: BTreeMap<String, Envelope>) -> Self {
+        Feedback {
+            sounds,
+            speak,
+            rumbles,
+            hd_rumbler: None,
+        }
+    }
+
+    /// Rumble cues with `hd_rumbler`, e.g. after reconnecting.
+    pub fn set_hd_rumbler(&mut self, hd_rumbler: Option<HdRumbler>) {
+        self.hd_rumbler = hd_rumbler;
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.sounds.is_some()
+            || self.speak
+            || (self.hd_rumbler.is_some() && !self.rumbles.is_empty())
+    }
+
+    fn rumble(&self, cue: Cue) {
+        if let (Some(hd_rumbler), Some(envelope)) = (&self.hd_rumbler, self.rumbles.get(cue.name()))
+        {
+            // a cue that can't be felt isn't worth a warning
+            let _ = hd_rumbler.play(envelope.clone());
+        }
+    }
+
+    /// Start playing (and/or announcing) `cue` in the background.
+    pub fn play(&self, cue: Cue) {
+        self.rumble(cue);
+        if let Some(sounds) = self.sounds {
+            tokio::spawn(play(sounds, cue));
+        }
+        if self.speak {
+            tokio::spawn(speak(cue.spoken(), false));
+        }
+    }
+
+    /// Play `cue` and wait for it to finish, e.g. right before exiting.
+    pub async fn play_now(&self, cue: Cue) {
+        if let Some(sounds) = self.sounds {
+            play(sounds, cue).await;
+        }
+        if self.speak {
+            speak(cue.spoken(), true).await;
+        }
+    }
+}
+
+async fn speak(text: String, wait: bool) {
+    let mut cmd = Command::new("spd-say");
+    cmd.args(["--application-name", "joykbd"]);
+    if wait {
+        cmd.arg("--wait");
+    }
+    let _ = cmd.arg(text).stderr(Stdio::null()).status().await;
+}
+
+async fn play(sounds: Sounds, cue: Cue) {
+    // a missing player is nothing worth interrupting anything over
+    let _ = match sounds {
+        Sounds::Theme => Command::new("canberra-gtk-play")
+            .args(["-i", cue.theme_event()])
+            .stderr(Stdio::null())
+            .status()
+            .await
+            .map(drop),
+        Sounds::Tones => play_wav(&wav(cue.tones())).await,
+    };
+}
+
+async fn play_wav(wav: &[u8]) -> std::io::Result<()> {
+    let mut child = Command::new("aplay")
+        .args(["-q", "-"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(wav).await?;
+    }
+    child.wait().await?;
+    Ok(())
+}
+
+const SAMPLE_RATE: u32 = 22050;
+
+/// Render `tones` as a mono 16-bit PCM WAV file.
+fn wav(tones: &[(u32, u32)]) -> Vec<u8> {
+    let mut samples = vec![];
+    for &(freq, ms) in tones {
+        let n = SAMPLE_RATE * ms / 1000;
+        for i in 0..n {
+            let t = f64::from(i) / f64::from(SAMPLE_RATE);
+            // fade in and out over a few ms, to avoid clicks
+            let fade = (f64::from(i.min(n - i)) / (f64::from(SAMPLE_RATE) * 0.005)).min(1.0);
+            let v = if freq == 0 {
+                0.0
+            } else {
+                (t * f64::from(freq) * std::f64::consts::TAU).sin() * fade * 0.3
+            };
+            samples.push((v * f64::from(i16::MAX)) as i16);
+        }
+    }
+    let data_len = samples.len() as u32 * 2;
+    let mut out = Vec::with_capacity(44 + data_len as usize);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data_len).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, mono
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    out.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    out.extend_from_slice(&2u16.to_le_bytes());
+    out.extend_from_slice(&16u16.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        out.extend_from_slice(&sample.to_le_bytes());
+    }
+    out
+}
@@ -0,0 +1,443 @@
+//! Gamepad passthrough mode: instead of being translated into mouse/keyboard input, the joy-con
+//! is mirrored onto a virtual gamepad (with bindings still applied to its buttons), and force
+//! feedback that games play on the virtual gamepad is forwarded back to the real controller.
+//! The sticks' response can be reshaped along the way, for games with poor deadzone handling,
+//! with each of the config file's profiles (e.g. one per game) having its own.
+//! When the other Joy-Con of a pair connects, it joins the same gamepad, so games see one full
+//! controller.
+
+use crate::bindings::{Bindings, KeyAction};
+use crate::hat;
+use crate::xbox::{self, Xbox};
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{
+    AbsInfo, AbsoluteAxisType, AttributeSet, Device, EventType, FFEffect, FFEffectType, InputEvent,
+    InputEventKind, Key, UInputEventType, UinputAbsSetup,
+};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use tokio::sync::mpsc;
+
+/// How many force feedback effects games can upload at once.
+const FF_EFFECTS_MAX: u32 = 16;
+
+/// The stick axes that [`StickTuning`] applies to.
+const STICK_AXES: [AbsoluteAxisType; 4] = [
+    AbsoluteAxisType::ABS_X,
+    AbsoluteAxisType::ABS_Y,
+    AbsoluteAxisType::ABS_RX,
+    AbsoluteAxisType::ABS_RY,
+];
+
+/// Each stick's pair of axes, which are tuned together.
+const STICKS: [(AbsoluteAxisType, AbsoluteAxisType); 2] = [
+    (AbsoluteAxisType::ABS_X, AbsoluteAxisType::ABS_Y),
+    (AbsoluteAxisType::ABS_RX, AbsoluteAxisType::ABS_RY),
+];
+
+/// Every button either half of a Joy-Con pair has.
+const JOYCON_PAIR_KEYS: [Key; 18] = [
+    Key::BTN_SOUTH,
+    Key::BTN_EAST,
+    Key::BTN_NORTH,
+    Key::BTN_WEST,
+    Key::BTN_TL,
+    Key::BTN_TR,
+    Key::BTN_TL2,
+    Key::BTN_TR2,
+    Key::BTN_SELECT,
+    Key::BTN_START,
+    Key::BTN_MODE,
+    Key::BTN_THUMBL,
+    Key::BTN_THUMBR,
+    Key::BTN_Z,
+    Key::BTN_DPAD_UP,
+    Key::BTN_DPAD_DOWN,
+    Key::BTN_DPAD_LEFT,
+    Key::BTN_DPAD_RIGHT,
+];
+
+/// How the sticks' positions are reshaped before games see them.
+#[derive(Clone, Copy)]
+pub struct StickTuning {
+    /// The fraction of the stick's travel from the center, in any direction, that reads as
+    /// centered. The rest of the travel is stretched to cover the whole range again.
+    pub deadzone: f64,
+    /// The exponent of the response curve: above 1 gives finer control near the center, below 1
+    /// makes small movements count for more.
+    pub curve: f64,
+}
+
+/// Leaves the sticks as they are.
+impl Default for StickTuning {
+    fn default() -> Self {
+        StickTuning {
+            deadzone: 0.0,
+            curve: 1.0,
+        }
+    }
+}
+
+impl StickTuning {
+    pub fn new(deadzone: f64, curve: f64) -> Result<Self, String> {
+        if !(0.0..1.0).contains(&deadzone) {
+            return Err("the deadzone must be at least 0 and less than 1".to_owned());
+        }
+        if curve <= 0.0 {
+            return Err("the curve must be positive".to_owned());
+        }
+        Ok(StickTuning { deadzone, curve })
+    }
+
+    /// Reshape the stick's position `(x, y)`, each from -1 to 1. The deadzone is around the
+    /// center in every direction, so pushing along one axis doesn't snap the other to center.
+    fn apply(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let distance = x.hypot(y);
+        if distance <= self.deadzone {
+            return (0.0, 0.0);
+        }
+        let magnitude = (distance.min(1.0) - self.deadzone) / (1.0 - self.deadzone);
+        let scale = magnitude.powf(self.curve) / distance;
+        (x * scale, y * scale)
+    }
+}
+
+pub struct Gamepad {
+    tx: mpsc::Sender<InputEvent>,
+    /// The ranges of the gamepad's stick axes, by axis code.
+    ranges: HashMap<u16, (i32, i32)>,
+    /// Where the sticks last were, before tuning, by axis code, as their axes come one at a
+    /// time.
+    positions: HashMap<u16, i32>,
+    /// The ranges of the other Joy-Con's stick axes, once it's connected.
+    partner_ranges: HashMap<u16, (i32, i32)>,
+}
+
+impl Gamepad {
+    /// Create a virtual gamepad mirroring `source` (found at `source_path`), and start the task
+    /// that feeds it. With `dual`, it also has room for the other half of a Joy-Con pair. The
+    /// sticks are tuned as the active bindings say, see [`Bindings::stick_tuning`].
+    pub fn spawn(
+        source: &Device,
+        source_path: &Path,
+        bindings: &Bindings,
+        xbox: bool,
+        dual: bool,
+    ) -> io::Result<Self> {
+        let mut keys = AttributeSet::new();
+        for key in source.supported_keys().into_iter().flatten() {
+            keys.insert(key);
+        }
+        // a hat D-pad arrives as buttons, see `crate::hat`
+        for key in hat::keys(source) {
+            keys.insert(key);
+        }
+        for key in bindings.output_keys() {
+            keys.insert(key);
+        }
+
+        let abs_state = source.get_abs_state()?;
+        let mut ranges = HashMap::new();
+        let mut abs = vec![];
+        for axis in source.supported_absolute_axes().into_iter().flatten() {
+            let info = &abs_state[axis.0 as usize];
+            if STICK_AXES.contains(&axis) {
+                ranges.insert(axis.0, (info.minimum, info.maximum));
+            }
+            let info = AbsInfo::new(
+                info.value,
+                info.minimum,
+                info.maximum,
+                info.fuzz,
+                info.flat,
+                info.resolution,
+            );
+            abs.push(UinputAbsSetup::new(axis, info));
+        }
+        if dual {
+            for key in JOYCON_PAIR_KEYS {
+                keys.insert(key);
+            }
+            // the other half's stick is on the other pair of axes, and is rescaled to the same
+            // range as this one's
+            for (axis, other) in [
+                (AbsoluteAxisType::ABS_X, AbsoluteAxisType::ABS_RX),
+                (AbsoluteAxisType::ABS_Y, AbsoluteAxisType::ABS_RY),
+                (AbsoluteAxisType::ABS_RX, AbsoluteAxisType::ABS_X),
+                (AbsoluteAxisType::ABS_RY, AbsoluteAxisType::ABS_Y),
+            ] {
+                let &(min, max) = match ranges.get(&axis.0) {
+                    Some(range) if !ranges.contains_key(&other.0) => range,
+                    _ => continue,
+                };
+                let info = &abs_state[axis.0 as usize];
+                let info = AbsInfo::new(
+                    (min + max) / 2,
+                    min,
+                    max,
+                    info.fuzz,
+                    info.flat,
+                    info.resolution,
+                );
+                ranges.insert(other.0, (min, max));
+                abs.push(UinputAbsSetup::new(other, info));
+            }
+        }
+
+        let mut builder = VirtualDeviceBuilder::new()?;
+        let xbox = if xbox {
+            (keys, abs) = xbox::capabilities();
+            builder = builder.name(xbox::NAME).input_id(xbox::id());
+            Some(Xbox::new(ranges.clone()))
+        } else {
+            builder = builder.name("joykbd gamepad");
+            None
+        };
+        builder = builder.with_keys(&keys)?;
+        for abs in &abs {
+            builder = builder.with_absolute_axis(abs)?;
+        }
+
+        let ff = if supports_rumble(source) {
+            builder = builder
+                .with_ff(&AttributeSet::from_iter([FFEffectType::FF_RUMBLE]))?
+                .with_ff_effects_max(FF_EFFECTS_MAX);
+            Some(FfForwarder {
+                dev: Device::open(source_path)?,
+                effects: HashMap::new(),
+            })
+        } else {
+            None
+        };
+
+        let dev = builder.build()?;
+        let (tx, rx) = mpsc::channel(64);
+        tokio::spawn(run(dev, ff, xbox, rx));
+        Ok(Gamepad {
+            tx,
+            ranges,
+            positions: HashMap::new(),
+            partner_ranges: HashMap::new(),
+        })
+    }
+
+    /// Take in the other Joy-Con of the pair, whose events are then forwarded with `partner`.
+    pub fn set_partner(&mut self, partner: &Device) -> io::Result<()> {
+        let abs_state = partner.get_abs_state()?;
+        self.partner_ranges = STICK_AXES
+            .into_iter()
+            .map(|axis| {
+                let info = &abs_state[axis.0 as usize];
+                (axis.0, (info.minimum, info.maximum))
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Press (or release) each of `keys` on the gamepad, e.g. as a held `turbo` binding's key
+    /// toggles.
+    pub async fn keys(&mut self, keys: impl IntoIterator<Item = (Key, bool)>) {
+        for (key, down) in keys {
+            let ev = InputEvent::new(EventType::KEY, key.code(), down.into());
+            let _ = self.tx.send(ev).await;
+        }
+    }
+
+    /// Forward an event from the joy-con (or its partner), applying `bindings` to its buttons.
+    pub async fn forward(&mut self, ev: InputEvent, bindings: &mut Bindings, partner: bool) {
+        let ev = match ev.kind() {
+            InputEventKind::Key(key) => {
+                let action = bindings.key(key, ev.value());
+                // the other members of an activated group let go first, as on the keyboard
+                for key in bindings.take_releases() {
+                    let release = InputEvent::new(EventType::KEY, key.code(), 0);
+                    let _ = self.tx.send(release).await;
+                }
+                // a held mod-tap binding's modifier goes down before the key it modifies
+                for key in bindings.take_presses() {
+                    let press = InputEvent::new(EventType::KEY, key.code(), 1);
+                    let _ = self.tx.send(press).await;
+                }
+                match action {
+                    KeyAction::Emit(to) => InputEvent::new(EventType::KEY, to.code(), ev.value()),
+                    KeyAction::Chord(to) => {
+                        for ev in to.events(ev.value()) {
+                            let _ = self.tx.send(ev).await;
+                        }
+                        return;
+                    }
+                    KeyAction::Macro(steps) => {
+                        for ev in steps.events() {
+                            let _ = self.tx.send(ev).await;
+                        }
+                        return;
+                    }
+                    // there's no cursor to nudge, and the stick is passed through as is
+                    KeyAction::Consumed | KeyAction::Nudge(..) | KeyAction::DriftThreshold(_) => {
+                        return
+                    }
+                    KeyAction::Unbound => ev,
+                }
+            }
+            InputEventKind::AbsAxis(axis) => match self.ranges.get(&axis.0) {
+                Some(&range) => {
+                    let value = match self.partner_ranges.get(&axis.0) {
+                        Some(&from) if partner => rescale(ev.value(), from, range),
+                        _ => ev.value(),
+                    };
+                    self.positions.insert(axis.0, value);
+                    // moving one of the stick's axes can move the other out of the deadzone, or
+                    // into it
+                    let Some(&(x, y)) = STICKS.iter().find(|&&(x, y)| axis == x || axis == y)
+                    else {
+                        return;
+                    };
+                    let pos = |axis: AbsoluteAxisType| match (
+                        self.ranges.get(&axis.0),
+                        self.positions.get(&axis.0),
+                    ) {
+                        (Some(&range), Some(&value)) => normalize(value, range),
+                        _ => 0.0,
+                    };
+                    let tuned = bindings.stick_tuning().apply((pos(x), pos(y)));
+                    for (axis, pos) in [(x, tuned.0), (y, tuned.1)] {
+                        if let Some(&range) = self.ranges.get(&axis.0) {
+                            let value = denormalize(pos, range);
+                            let ev = InputEvent::new(EventType::ABSOLUTE, axis.0, value);
+                            let _ = self.tx.send(ev).await;
+                        }
+                    }
+                    return;
+                }
+                None => ev,
+            },
+            _ => return,
+        };
+        let _ = self.tx.send(ev).await;
+    }
+}
+
+/// Where `value` is within `min..=max`, from -1 to 1.
+fn normalize(value: i32, (min, max): (i32, i32)) -> f64 {
+    let half = f64::from(max - min) / 2.0;
+    if half <= 0.0 {
+        return 0.0;
+    }
+    let center = f64::from(min + max) / 2.0;
+    ((f64::from(value) - center) / half).clamp(-1.0, 1.0)
+}
+
+/// The value `pos` (from -1 to 1) is at within `min..=max`.
+fn denormalize(pos: f64, (min, max): (i32, i32)) -> i32 {
+    let center = f64::from(min + max) / 2.0;
+    let half = f64::from(max - min) / 2.0;
+    (center + pos * half).round() as i32
+}
+
+/// Map `value` from the range `from` to the range `to`.
+fn rescale(value: i32, (from_min, from_max): (i32, i32), (to_min, to_max): (i32, i32)) -> i32 {
+    let pos = f64::from(value - from_min) / f64::from((from_max - from_min).max(1));
+    (f64::from(to_min) + pos * f64::from(to_max - to_min)).round() as i32
+}
+
+fn supports_rumble(dev: &Device) -> bool {
+    dev.supported_ff()
+        .is_some_and(|ff| ff.contains(FFEffectType::FF_RUMBLE))
+}
+
+async fn run(
+    dev: VirtualDevice,
+    mut ff: Option<FfForwarder>,
+    mut xbox: Option<Xbox>,
+    mut rx: mpsc::Receiver<InputEvent>,
+) {
+    let mut stream = match dev.into_event_stream() {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!(
+                "warning: can't receive force feedback from the virtual gamepad: {}",
+                e
+            );
+            return;
+        }
+    };
+    loop {
+        tokio::select! {
+            ev = rx.recv() => {
+                let ev = match (ev, &mut xbox) {
+                    (Some(ev), Some(xbox)) => match xbox.map(ev) {
+                        Some(ev) => ev,
+                        None => continue,
+                    },
+                    (Some(ev), None) => ev,
+                    (None, _) => break,
+                };
+                if let Err(e) = stream.device_mut().emit(&[ev]) {
+                    eprintln!("warning: failed to emit to the virtual gamepad: {}", e);
+                }
+            }
+            ev = stream.next_event() => {
+                let ev = match ev {
+                    Ok(ev) => ev,
+                    Err(e) => {
+                        eprintln!("warning: virtual gamepad error: {}", e);
+                        break;
+                    }
+                };
+                if let Some(ff) = &mut ff {
+                    if let Err(e) = ff.handle(stream.device_mut(), ev) {
+                        eprintln!("warning: failed to forward force feedback: {}", e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Mirrors the force feedback effects games upload to the virtual gamepad onto the real device.
+struct FfForwarder {
+    dev: Device,
+    /// Effects by the ID the virtual gamepad's clients know them as.
+    effects: HashMap<i16, FFEffect>,
+}
+
+impl FfForwarder {
+    fn handle(
+        &mut self,
+        vdev: &mut VirtualDevice,
+        ev: evdev::uinput::UInputEvent,
+    ) -> io::Result<()> {
+        match ev.kind() {
+            InputEventKind::UInput(code) if code == UInputEventType::UI_FF_UPLOAD.0 => {
+                let mut upload = vdev.process_ff_upload(ev).map_err(io::Error::other)?;
+                let data = upload.effect();
+                // the kernel has already given a new effect its ID, which is what the client
+                // plays and erases it by
+                let id = upload.effect_id();
+                let res = match self.effects.get_mut(&id) {
+                    Some(effect) => effect.update(data),
+                    None => self.dev.upload_ff_effect(data).map(|effect| {
+                        self.effects.insert(id, effect);
+                    }),
+                };
+                upload.set_retval(match &res {
+                    Ok(()) => 0,
+                    Err(e) => -e.raw_os_error().unwrap_or(libc::EIO),
+                });
+                res
+            }
+            InputEventKind::UInput(code) if code == UInputEventType::UI_FF_ERASE.0 => {
+                let erase = vdev.process_ff_erase(ev).map_err(io::Error::other)?;
+                self.effects.remove(&(erase.effect_id() as i16));
+                Ok(())
+            }
+            InputEventKind::ForceFeedback(id) => match self.effects.get_mut(&(id as i16)) {
+                Some(effect) if ev.value() > 0 => effect.play(ev.value()),
+                Some(effect) => effect.stop(),
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+}
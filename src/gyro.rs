@@ -0,0 +1,133 @@
+//! Moving the cursor by turning the controller, with the gyroscope in the Joy-Con's IMU, which
+//! hid-nintendo exposes as a separate evdev device. Angular velocity is integrated into cursor
+//! motion, alongside whatever the stick does, optionally only while a button is held (e.g. ZL,
+//! like aiming in a game).
+
+use evdev::{
+    AbsoluteAxisType, Device, EventType, InputEvent, InputEventKind, Key, RelativeAxisType,
+    Synchronization,
+};
+use smallvec::{smallvec, SmallVec};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// The gyro axes that turn into horizontal (turning left and right) and vertical (tilting up and
+/// down) cursor motion.
+const AXES: [AbsoluteAxisType; 2] = [AbsoluteAxisType::ABS_RZ, AbsoluteAxisType::ABS_RX];
+
+/// hid-nintendo's gyro resolution, in units per degree per second, for when it isn't reported.
+const DEFAULT_RESOLUTION: f64 = 14247.0;
+
+/// Angular velocities below this, in degrees per second, are the gyro's noise (or a hand's
+/// tremor) rather than turning it, and don't move the cursor.
+const NOISE_FLOOR: f64 = 1.5;
+
+/// How the gyro moves the cursor.
+pub struct GyroSettings {
+    /// Pixels per degree turned.
+    pub sensitivity: f64,
+    pub invert_x: bool,
+    pub invert_y: bool,
+    /// The button that has to be held for the gyro to move the cursor, if any.
+    pub hold: Option<Key>,
+}
+
+pub struct Gyro {
+    settings: GyroSettings,
+    /// Each axis's units per degree per second.
+    resolution: [f64; 2],
+    /// Each axis's angular velocity in degrees per second, as of the latest sample.
+    rate: [f64; 2],
+    /// When the latest sample was taken.
+    sampled: Option<SystemTime>,
+    hold_held: bool,
+    remainder: [f64; 2],
+}
+
+impl Gyro {
+    pub fn new(settings: GyroSettings, imu: &Device) -> Self {
+        let abs_state = imu.get_abs_state().ok();
+        let resolution = AXES.map(|axis| {
+            abs_state
+                .map(|state| f64::from(state[axis.0 as usize].resolution))
+                .filter(|&res| res > 0.0)
+                .unwrap_or(DEFAULT_RESOLUTION)
+        });
+        Gyro {
+            settings,
+            resolution,
+            rate: [0.0; 2],
+            sampled: None,
+            hold_held: false,
+            remainder: [0.0; 2],
+        }
+    }
+
+    /// Track the --gyro-hold button.
+    pub fn key(&mut self, key: Key, value: i32) {
+        if self.settings.hold == Some(key) {
+            self.hold_held = value != 0;
+        }
+    }
+
+    fn active(&self) -> bool {
+        self.settings.hold.is_none() || self.hold_held
+    }
+
+    /// Turn the IMU's events into cursor motion, once per sample.
+    pub fn map(&mut self, ev: InputEvent) -> SmallVec<[InputEvent; 2]> {
+        match ev.kind() {
+            InputEventKind::AbsAxis(axis) => {
+                if let Some(i) = AXES.iter().position(|&a| a == axis) {
+                    self.rate[i] = f64::from(ev.value()) / self.resolution[i];
+                }
+                smallvec![]
+            }
+            InputEventKind::Synchronization(Synchronization::SYN_REPORT) => {
+                let now = ev.timestamp();
+                let elapsed = self
+                    .sampled
+                    .replace(now)
+                    .and_then(|then| now.duration_since(then).ok());
+                match elapsed {
+                    Some(elapsed) if self.active() => self.motion(elapsed.as_secs_f64()),
+                    _ => {
+                        self.remainder = [0.0; 2];
+                        smallvec![]
+                    }
+                }
+            }
+            _ => smallvec![],
+        }
+    }
+
+    /// The cursor motion for turning at the current rate for `secs`.
+    fn motion(&mut self, secs: f64) -> SmallVec<[InputEvent; 2]> {
+        let mut events = smallvec![];
+        let invert = [self.settings.invert_x, self.settings.invert_y];
+        let rel = [RelativeAxisType::REL_X, RelativeAxisType::REL_Y];
+        for i in 0..2 {
+            let rate = self.rate[i];
+            if rate.abs() < NOISE_FLOOR {
+                continue;
+            }
+            // turning left and tilting up are positive rotations, but move the cursor the
+            // negative way
+            let sign = if invert[i] { 1.0 } else { -1.0 };
+            let exact = sign * rate * secs * self.settings.sensitivity + self.remainder[i];
+            let whole = exact.trunc();
+            self.remainder[i] = exact - whole;
+            if whole != 0.0 {
+                events.push(InputEvent::new(EventType::RELATIVE, rel[i].0, whole as i32));
+            }
+        }
+        events
+    }
+}
+
+/// Find the IMU device of the controller `dev`.
+pub fn find_imu(dev: &Device) -> Option<(PathBuf, Device)> {
+    let imu_name = format!("{} IMU", dev.name()?);
+    evdev::enumerate()
+        .find(|(_, imu)| imu.name() == Some(&imu_name) && imu.unique_name() == dev.unique_name())
+}
@@ -0,0 +1,72 @@
+//! Turning a D-pad that's reported as a hat (`ABS_HAT0X`/`ABS_HAT0Y`, as some versions of
+//! hid-nintendo do for the left Joy-Con) into the D-pad buttons other drivers report, so it's
+//! remapped and mapped to the arrow keys like them.
+
+use evdev::{AbsoluteAxisType, Device, EventType, InputEvent, InputEventKind, Key};
+use smallvec::{smallvec, SmallVec};
+
+/// The buttons for the negative and positive end of each hat axis.
+const BUTTONS: [(AbsoluteAxisType, [Key; 2]); 2] = [
+    (
+        AbsoluteAxisType::ABS_HAT0X,
+        [Key::BTN_DPAD_LEFT, Key::BTN_DPAD_RIGHT],
+    ),
+    (
+        AbsoluteAxisType::ABS_HAT0Y,
+        [Key::BTN_DPAD_UP, Key::BTN_DPAD_DOWN],
+    ),
+];
+
+#[derive(Default)]
+pub struct HatDpad {
+    /// The last value of each axis.
+    last: [i32; 2],
+}
+
+impl HatDpad {
+    /// Turn a hat event into releasing the button it was last at and pressing the one it's at
+    /// now. Other events are passed through.
+    pub fn map(&mut self, ev: InputEvent) -> SmallVec<[InputEvent; 2]> {
+        let i = match ev.kind() {
+            InputEventKind::AbsAxis(axis) => match BUTTONS.iter().position(|&(a, _)| a == axis) {
+                Some(i) => i,
+                None => return smallvec![ev],
+            },
+            _ => return smallvec![ev],
+        };
+        let buttons = BUTTONS[i].1;
+        let button = |value: i32| match value {
+            0 => None,
+            _ => Some(buttons[usize::from(value > 0)]),
+        };
+        let value = ev.value().signum();
+        let last = std::mem::replace(&mut self.last[i], value);
+        let mut events = smallvec![];
+        if last == value {
+            return events;
+        }
+        if let Some(key) = button(last) {
+            events.push(InputEvent::new(EventType::KEY, key.code(), 0));
+        }
+        if let Some(key) = button(value) {
+            events.push(InputEvent::new(EventType::KEY, key.code(), 1));
+        }
+        events
+    }
+}
+
+/// Whether `axis` is one that's turned into buttons.
+pub fn is_hat(axis: AbsoluteAxisType) -> bool {
+    BUTTONS.iter().any(|&(a, _)| a == axis)
+}
+
+/// The buttons `dev`'s hat (if it has one) is turned into.
+pub fn keys(dev: &Device) -> impl Iterator<Item = Key> + '_ {
+    BUTTONS
+        .into_iter()
+        .filter(|(axis, _)| {
+            dev.supported_absolute_axes()
+                .is_some_and(|axes| axes.contains(*axis))
+        })
+        .flat_map(|(_, keys)| keys)
+}
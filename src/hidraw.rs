@@ -0,0 +1,711 @@
+//! Reading Joy-Cons (and Pro Controllers) directly through their HID protocol on /dev/hidraw*,
+//! rather than through the kernel's hid-nintendo driver, e.g. on kernels without it.
+//!
+//! The sticks are calibrated with the calibration stored in the controller's SPI flash, as the
+//! Switch does: the user calibration if there is one, and the factory calibration otherwise.
+//! A user calibration can also be written back, see [`HidrawJoyCon::flash_user_calibration`].
+
+use crate::nfc::{self, TagUid};
+use crate::presence::{self, Presence};
+use crate::rumble::HdRumbler;
+use crate::source::{Backend, InputSource, SourceEvent, SourceKind};
+use crate::stick::StickSide;
+use evdev::{
+    AbsoluteAxisType, AttributeSet, BusType, Device, EventType, InputEvent, InputId, Key,
+    RelativeAxisType, Synchronization,
+};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use tokio::sync::mpsc;
+
+pub const BACKEND: Backend = Backend {
+    name: "hidraw",
+    open: |path| Ok(Box::new(HidrawJoyCon::open(path)?)),
+    enumerate: || {
+        let entries = match fs::read_dir("/dev") {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        let mut devices: Vec<_> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("hidraw"))
+            })
+            .filter_map(|path| {
+                let dev = HidrawJoyCon::open(&path).ok()?;
+                Some((path, Box::new(dev) as Box<dyn InputSource>))
+            })
+            .collect();
+        devices.sort_by(|(a, _), (b, _)| a.cmp(b));
+        devices
+    },
+};
+
+/// The input report with the full button and stick state, sent at 60Hz.
+const REPORT_STANDARD_FULL: u8 = 0x30;
+/// The input report with a subcommand's reply.
+const REPORT_SUBCOMMAND_REPLY: u8 = 0x21;
+/// The output report for sending a subcommand (along with rumble data).
+const OUTPUT_SUBCOMMAND: u8 = 0x01;
+const SUBCOMMAND_SET_REPORT_MODE: u8 = 0x03;
+const SUBCOMMAND_SPI_READ: u8 = 0x10;
+const SUBCOMMAND_SPI_WRITE: u8 = 0x11;
+const SUBCOMMAND_ENABLE_IMU: u8 = 0x40;
+const SUBCOMMAND_READ_IMU: u8 = 0x43;
+/// The IMU's (an LSM6DS3's) temperature registers, a little-endian i16 in 16ths of a °C above
+/// 25°C.
+const IMU_REGISTER_TEMPERATURE: u8 = 0x20;
+/// How many reports to read between asking for the temperature, about every 10 seconds.
+const TEMPERATURE_POLL_REPORTS: u32 = 600;
+/// How many reports to look through for a subcommand's reply before giving up on it.
+const REPLY_ATTEMPTS: usize = 64;
+/// The length of the longest input report, the one with the MCU's data.
+const REPORT_LEN_MAX: usize = 362;
+/// Rumble data that doesn't rumble.
+const RUMBLE_NEUTRAL: [u8; 8] = [0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40];
+
+/// The buttons in the standard report, by byte offset and bit, and the evdev key hid-nintendo
+/// reports them as.
+const BUTTONS: &[(usize, u8, Key)] = &[
+    (3, 0x01, Key::BTN_WEST),
+    (3, 0x02, Key::BTN_NORTH),
+    (3, 0x04, Key::BTN_SOUTH),
+    (3, 0x08, Key::BTN_EAST),
+    (3, 0x40, Key::BTN_TR),
+    (3, 0x80, Key::BTN_TR2),
+    (4, 0x01, Key::BTN_SELECT),
+    (4, 0x02, Key::BTN_START),
+    (4, 0x04, Key::BTN_THUMBR),
+    (4, 0x08, Key::BTN_THUMBL),
+    (4, 0x10, Key::BTN_MODE),
+    (4, 0x20, Key::BTN_Z),
+    (5, 0x01, Key::BTN_DPAD_DOWN),
+    (5, 0x02, Key::BTN_DPAD_UP),
+    (5, 0x04, Key::BTN_DPAD_RIGHT),
+    (5, 0x08, Key::BTN_DPAD_LEFT),
+    (5, 0x40, Key::BTN_TL),
+    (5, 0x80, Key::BTN_TL2),
+];
+
+/// The sticks in the standard report, by byte offset, and their axes.
+const STICKS: &[(usize, AbsoluteAxisType, AbsoluteAxisType)] = &[
+    (6, AbsoluteAxisType::ABS_X, AbsoluteAxisType::ABS_Y),
+    (9, AbsoluteAxisType::ABS_RX, AbsoluteAxisType::ABS_RY),
+];
+
+/// The center of the sticks' 12-bit range.
+const STICK_CENTER: i32 = 2048;
+/// The top of the sticks' 12-bit range.
+const STICK_MAX: i32 = 0xfff;
+
+/// Where each stick's factory calibration is in SPI flash.
+const SPI_FACTORY_CALIBRATION: [u32; 2] = [0x603d, 0x6046];
+/// Where each stick's user calibration is in SPI flash, preceded by a 2-byte magic that says
+/// whether there is one.
+const SPI_USER_CALIBRATION: [u32; 2] = [0x8010, 0x801b];
+const SPI_USER_CALIBRATION_MAGIC: [u8; 2] = [0xb2, 0xa1];
+/// A user calibration block: the magic, then the calibration.
+const USER_CALIBRATION_LEN: u8 = 11;
+
+pub struct HidrawJoyCon {
+    file: File,
+    name: String,
+    uniq: Option<String>,
+    id: Option<InputId>,
+    /// Where to send the UIDs of tapped NFC tags, if the reader should be used.
+    nfc: Option<mpsc::Sender<TagUid>>,
+    /// Where to send whether something's near the IR camera, if it should be used.
+    presence: Option<(Presence, mpsc::Sender<bool>)>,
+    /// Where to send the IMU's temperature, if it should be read.
+    temperature: Option<mpsc::Sender<f64>>,
+}
+
+impl HidrawJoyCon {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let node = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a hidraw device"))?;
+        let uevent = fs::read_to_string(
+            Path::new("/sys/class/hidraw")
+                .join(node)
+                .join("device/uevent"),
+        )?;
+        let field = |key: &str| {
+            uevent
+                .lines()
+                .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+                .map(str::to_owned)
+        };
+        let name = field("HID_NAME").unwrap_or_default();
+        // only the controllers that speak this protocol, so enumerating doesn't poke at
+        // arbitrary HID devices
+        if !["Joy-Con", "Pro Controller"]
+            .iter()
+            .any(|known| name.contains(known))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} isn't a Joy-Con", name),
+            ));
+        }
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Ok(HidrawJoyCon {
+            file,
+            name,
+            uniq: field("HID_UNIQ").filter(|uniq| !uniq.is_empty()),
+            id: field("HID_ID").as_deref().and_then(parse_hid_id),
+            nfc: None,
+            presence: None,
+            temperature: None,
+        })
+    }
+
+    fn send_subcommand(&mut self, subcommand: u8, args: &[u8]) -> io::Result<()> {
+        let mut report = vec![OUTPUT_SUBCOMMAND, 0];
+        report.extend_from_slice(&RUMBLE_NEUTRAL);
+        report.push(subcommand);
+        report.extend_from_slice(args);
+        self.file.write_all(&report)
+    }
+
+    fn set_report_mode(&mut self, mode: u8) -> io::Result<()> {
+        self.send_subcommand(SUBCOMMAND_SET_REPORT_MODE, &[mode])
+    }
+
+    /// Switch the MCU into `mode`, and the reports to the ones that include its data.
+    fn start_mcu(&mut self, mode: u8) -> io::Result<()> {
+        self.send_subcommand(nfc::SUBCOMMAND_SET_MCU_STATE, &[nfc::MCU_RESUME])?;
+        self.reply(nfc::SUBCOMMAND_SET_MCU_STATE)?;
+        self.send_subcommand(nfc::SUBCOMMAND_SET_MCU_CONFIG, &nfc::mcu_mode(mode))?;
+        self.reply(nfc::SUBCOMMAND_SET_MCU_CONFIG)?;
+        if mode == presence::MCU_MODE_IR {
+            self.send_subcommand(nfc::SUBCOMMAND_SET_MCU_CONFIG, &presence::ir_moment_mode())?;
+            self.reply(nfc::SUBCOMMAND_SET_MCU_CONFIG)?;
+        }
+        self.set_report_mode(nfc::REPORT_NFC_IR)
+    }
+
+    fn send_mcu_request(&mut self, args: &[u8]) -> io::Result<()> {
+        let mut report = vec![nfc::OUTPUT_MCU_REQUEST, 0];
+        report.extend_from_slice(&RUMBLE_NEUTRAL);
+        report.extend_from_slice(args);
+        self.file.write_all(&report)
+    }
+
+    /// Wait for the reply to `subcommand`, returning the whole report.
+    fn reply(&mut self, subcommand: u8) -> io::Result<Vec<u8>> {
+        let mut buf = [0; REPORT_LEN_MAX];
+        for _ in 0..REPLY_ATTEMPTS {
+            let read = self.file.read(&mut buf)?;
+            let reply = &buf[..read];
+            if reply.len() > 15 && reply[0] == REPORT_SUBCOMMAND_REPLY && reply[14] == subcommand {
+                return Ok(reply.to_vec());
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("no reply to subcommand {:#04x}", subcommand),
+        ))
+    }
+
+    /// Read `len` bytes (at most 29) of SPI flash at `addr`.
+    fn read_spi(&mut self, addr: u32, len: u8) -> io::Result<Vec<u8>> {
+        let mut args = addr.to_le_bytes().to_vec();
+        args.push(len);
+        self.send_subcommand(SUBCOMMAND_SPI_READ, &args)?;
+        let reply = self.reply(SUBCOMMAND_SPI_READ)?;
+        // the reply echoes the address and length, followed by the data
+        match reply.get(20..20 + usize::from(len)) {
+            Some(data) if reply[15..20] == args[..] => Ok(data.to_vec()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected reply to an SPI flash read",
+            )),
+        }
+    }
+
+    /// Write `data` (at most 29 bytes) to SPI flash at `addr`.
+    fn write_spi(&mut self, addr: u32, data: &[u8]) -> io::Result<()> {
+        let mut args = addr.to_le_bytes().to_vec();
+        args.push(data.len() as u8);
+        args.extend_from_slice(data);
+        self.send_subcommand(SUBCOMMAND_SPI_WRITE, &args)?;
+        // the reply's status is 0 if it was written
+        match self.reply(SUBCOMMAND_SPI_WRITE)?[15] {
+            0 => Ok(()),
+            status => Err(io::Error::other(format!(
+                "the controller refused the write, with status {:#04x}",
+                status
+            ))),
+        }
+    }
+
+    /// The calibration of each stick, from SPI flash.
+    fn read_calibration(&mut self) -> io::Result<[StickCalibration; 2]> {
+        let mut calibration = [StickCalibration::DEFAULT; 2];
+        for (i, stick) in calibration.iter_mut().enumerate() {
+            let user = self.read_spi(SPI_USER_CALIBRATION[i], USER_CALIBRATION_LEN)?;
+            let data = match user[..2] == SPI_USER_CALIBRATION_MAGIC {
+                true => user[2..].to_vec(),
+                false => self.read_spi(SPI_FACTORY_CALIBRATION[i], 9)?,
+            };
+            // a controller without this stick (e.g. the other half of a pair) has nothing there
+            *stick = StickCalibration::parse(i, &data).unwrap_or(StickCalibration::DEFAULT);
+        }
+        Ok(calibration)
+    }
+
+    /// Write a user calibration for the stick on `side`, moving its current center by `bias`
+    /// (as joykbd's `--x-bias`/`--y-bias` would), so it's centered everywhere the controller is
+    /// used, not just in joykbd.
+    pub fn flash_user_calibration(&mut self, side: StickSide, bias: (i32, i32)) -> io::Result<()> {
+        let i = side_index(side);
+        let mut calibration = self.read_calibration()?[i];
+        // the bias is added after scaling, with the y axis upside down
+        let to_raw = |axis: usize, value: i32| {
+            value * (calibration.below[axis] + calibration.above[axis]) / 2 / 32767
+        };
+        calibration.center[0] -= to_raw(0, bias.0);
+        calibration.center[1] += to_raw(1, bias.1);
+        // the values are 12 bits, and anything else would be silently cut down to them
+        if let Some(center) = calibration
+            .center
+            .iter()
+            .find(|center| !(0..=STICK_MAX).contains(*center))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the bias moves the center to {}, outside the stick's range of 0 to {}",
+                    center, STICK_MAX
+                ),
+            ));
+        }
+        let mut data = SPI_USER_CALIBRATION_MAGIC.to_vec();
+        data.extend_from_slice(&calibration.encode(i));
+        self.write_user_calibration(i, &data)
+    }
+
+    /// The raw user calibration block for the stick on `side`, to keep before flashing a new one.
+    pub fn user_calibration(&mut self, side: StickSide) -> io::Result<Vec<u8>> {
+        self.read_spi(SPI_USER_CALIBRATION[side_index(side)], USER_CALIBRATION_LEN)
+    }
+
+    /// Put back a user calibration block for the stick on `side` that
+    /// [`user_calibration`](Self::user_calibration) read.
+    pub fn restore_user_calibration(&mut self, side: StickSide, block: &[u8]) -> io::Result<()> {
+        if block.len() != usize::from(USER_CALIBRATION_LEN) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "a user calibration is {} bytes, not {}",
+                    USER_CALIBRATION_LEN,
+                    block.len()
+                ),
+            ));
+        }
+        self.write_user_calibration(side_index(side), block)
+    }
+
+    /// Write stick `i`'s user calibration block, and read it back to check it was written.
+    fn write_user_calibration(&mut self, i: usize, data: &[u8]) -> io::Result<()> {
+        self.write_spi(SPI_USER_CALIBRATION[i], data)?;
+        let written = self.read_spi(SPI_USER_CALIBRATION[i], USER_CALIBRATION_LEN)?;
+        match written == data {
+            true => Ok(()),
+            false => Err(io::Error::other(format!(
+                "the calibration reads back as {:02x?} instead of {:02x?}",
+                written, data
+            ))),
+        }
+    }
+}
+
+/// The IDs in a `HID_ID` uevent field, e.g. `0005:0000057E:00002007` for a bluetooth Joy-Con (R).
+fn parse_hid_id(s: &str) -> Option<InputId> {
+    let mut fields = s
+        .split(':')
+        .map(|field| u32::from_str_radix(field, 16).ok());
+    let (bus, vendor, product) = (fields.next()??, fields.next()??, fields.next()??);
+    Some(InputId::new(
+        BusType(bus as u16),
+        vendor as u16,
+        product as u16,
+        0,
+    ))
+}
+
+impl InputSource for HidrawJoyCon {
+    fn name(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+
+    fn unique_name(&self) -> Option<&str> {
+        self.uniq.as_deref()
+    }
+
+    fn input_id(&self) -> Option<InputId> {
+        self.id.clone()
+    }
+
+    fn supported_keys(&self) -> AttributeSet<Key> {
+        BUTTONS.iter().map(|&(_, _, key)| key).collect()
+    }
+
+    fn supported_relative_axes(&self) -> AttributeSet<RelativeAxisType> {
+        AttributeSet::new()
+    }
+
+    fn evdev(&self) -> Option<&Device> {
+        None
+    }
+
+    fn evdev_mut(&mut self) -> Option<&mut Device> {
+        None
+    }
+
+    fn enable_nfc(&mut self, tx: mpsc::Sender<TagUid>) -> bool {
+        // only the right Joy-Con and the Pro Controller have a reader
+        let has_reader = ["(R)", "Right", "Pro Controller"]
+            .iter()
+            .any(|name| self.name.contains(name));
+        if has_reader {
+            self.nfc = Some(tx);
+        }
+        has_reader
+    }
+
+    fn enable_ir_presence(&mut self, threshold: u8, tx: mpsc::Sender<bool>) -> bool {
+        // only the right Joy-Con has a camera
+        let has_camera = ["(R)", "Right"].iter().any(|name| self.name.contains(name));
+        if has_camera {
+            self.presence = Some((Presence::new(threshold), tx));
+        }
+        has_camera
+    }
+
+    fn enable_temperature(&mut self, tx: mpsc::Sender<f64>) -> bool {
+        // every one of them has an IMU
+        self.temperature = Some(tx);
+        true
+    }
+
+    fn hd_rumbler(&self) -> Option<HdRumbler> {
+        self.file.try_clone().ok().map(HdRumbler::spawn)
+    }
+
+    fn spawn(
+        mut self: Box<Self>,
+        kind: SourceKind,
+        tx: mpsc::Sender<SourceEvent>,
+    ) -> io::Result<()> {
+        // hidraw reads block, so this gets a thread of its own
+        tokio::task::spawn_blocking(move || {
+            let calibration = self.read_calibration().unwrap_or_else(|e| {
+                eprintln!(
+                    "warning: couldn't read the stick calibration from {}: {}",
+                    self.name, e
+                );
+                [StickCalibration::DEFAULT; 2]
+            });
+            let temperature_tx = self.temperature.take();
+            let res = match temperature_tx {
+                Some(_) => self
+                    .send_subcommand(SUBCOMMAND_ENABLE_IMU, &[1])
+                    .and_then(|()| self.reply(SUBCOMMAND_ENABLE_IMU).map(drop)),
+                None => Ok(()),
+            };
+            // the MCU can only do one thing at a time
+            let res = res.and_then(|()| match (&self.nfc, &self.presence) {
+                (Some(_), _) => self.start_mcu(nfc::MCU_MODE_NFC),
+                (None, Some(_)) => self.start_mcu(presence::MCU_MODE_IR),
+                (None, None) => self.set_report_mode(REPORT_STANDARD_FULL),
+            });
+            if let Err(e) = res {
+                let _ = tx.blocking_send(SourceEvent {
+                    kind,
+                    event: Err(e),
+                });
+                return;
+            }
+            let mut state = ReportState {
+                calibration,
+                ..ReportState::default()
+            };
+            let mut buf = [0; REPORT_LEN_MAX];
+            let mut reports = 0u32;
+            let mut tag = None;
+            let nfc_tx = self.nfc.take();
+            let mut presence = self.presence.take();
+            loop {
+                // a report comes in every few milliseconds, changed or not
+                if tx.is_closed() {
+                    return;
+                }
+                let (events, report) = match self.file.read(&mut buf) {
+                    Ok(len) => (
+                        state.update(&buf[..len]).into_iter().map(Ok).collect(),
+                        &buf[..len],
+                    ),
+                    Err(e) => (vec![Err(e)], &buf[..0]),
+                };
+                if let (Some((presence, presence_tx)), Some(intensity)) =
+                    (&mut presence, presence::intensity(report))
+                {
+                    if let Some(near) = presence.update(intensity) {
+                        let _ = presence_tx.blocking_send(near);
+                    }
+                }
+                if let (Some(temperature_tx), Some(temperature)) =
+                    (&temperature_tx, temperature(report))
+                {
+                    let _ = temperature_tx.blocking_send(temperature);
+                }
+                reports = reports.wrapping_add(1);
+                if temperature_tx.is_some() && reports % TEMPERATURE_POLL_REPORTS == 1 {
+                    // the reply comes in with the reports, above
+                    let _ =
+                        self.send_subcommand(SUBCOMMAND_READ_IMU, &[IMU_REGISTER_TEMPERATURE, 2]);
+                }
+                if let Some(nfc_tx) = &nfc_tx {
+                    // each tap is reported once, however long the tag stays in range
+                    match nfc::tag_in(report) {
+                        Some(now) if now != tag => {
+                            if let Some(uid) = &now {
+                                let _ = nfc_tx.blocking_send(uid.clone());
+                            }
+                            tag = now;
+                        }
+                        _ => {}
+                    }
+                    if reports.is_multiple_of(nfc::POLL_INTERVAL_REPORTS) {
+                        // a write error means the controller is gone, which the next read reports
+                        let _ = self.send_mcu_request(&nfc::start_polling());
+                    }
+                }
+                for event in events {
+                    let is_err = event.is_err();
+                    if tx.blocking_send(SourceEvent { kind, event }).is_err() || is_err {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Which of the calibrations in SPI flash is for the stick on `side`.
+fn side_index(side: StickSide) -> usize {
+    match side {
+        StickSide::Left => 0,
+        StickSide::Right => 1,
+    }
+}
+
+/// The IMU's temperature in °C, if `report` is the reply to reading it.
+fn temperature(report: &[u8]) -> Option<f64> {
+    // the reply echoes the register and count, followed by the registers' values
+    let reply = report.get(..19)?;
+    if reply[0] != REPORT_SUBCOMMAND_REPLY
+        || reply[14] != SUBCOMMAND_READ_IMU
+        || reply[15] != IMU_REGISTER_TEMPERATURE
+    {
+        return None;
+    }
+    Some(25.0 + f64::from(i16::from_le_bytes([reply[17], reply[18]])) / 16.0)
+}
+
+/// Where a stick's center is, and how far it reaches either side of it, in its 12-bit range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct StickCalibration {
+    center: [i32; 2],
+    below: [i32; 2],
+    above: [i32; 2],
+}
+
+impl StickCalibration {
+    const DEFAULT: Self = StickCalibration {
+        center: [STICK_CENTER; 2],
+        below: [STICK_CENTER; 2],
+        above: [STICK_CENTER; 2],
+    };
+
+    /// Parse the 9 bytes of calibration for stick `i` (0 for the left stick, 1 for the right).
+    fn parse(i: usize, data: &[u8]) -> Option<Self> {
+        // six 12-bit values, packed into pairs of 3 bytes
+        let values: Vec<i32> = data
+            .get(..9)?
+            .chunks(3)
+            .flat_map(|b| {
+                let (b0, b1, b2) = (i32::from(b[0]), i32::from(b[1]), i32::from(b[2]));
+                [(b1 & 0x0f) << 8 | b0, b2 << 4 | b1 >> 4]
+            })
+            .collect();
+        let pair = |j: usize| [values[j], values[j + 1]];
+        // the two sticks store them in different orders
+        let (above, center, below) = match i {
+            0 => (pair(0), pair(2), pair(4)),
+            _ => (pair(4), pair(0), pair(2)),
+        };
+        // erased flash reads as all ones
+        if values.iter().all(|&v| v == 0xfff) || above.contains(&0) || below.contains(&0) {
+            return None;
+        }
+        Some(StickCalibration {
+            center,
+            below,
+            above,
+        })
+    }
+
+    /// The 9 bytes of calibration for stick `i`, as [`parse`](Self::parse) reads them.
+    fn encode(&self, i: usize) -> [u8; 9] {
+        let pairs = match i {
+            0 => [self.above, self.center, self.below],
+            _ => [self.center, self.below, self.above],
+        };
+        let mut data = [0; 9];
+        for (bytes, [a, b]) in data.chunks_mut(3).zip(pairs) {
+            bytes[0] = (a & 0xff) as u8;
+            bytes[1] = ((a >> 8) & 0x0f | (b & 0x0f) << 4) as u8;
+            bytes[2] = (b >> 4) as u8;
+        }
+        data
+    }
+
+    /// Scale a raw reading along `axis` (0 for x, 1 for y) to hid-nintendo's range.
+    fn apply(&self, axis: usize, value: i32) -> i32 {
+        let value = value - self.center[axis];
+        let reach = match value < 0 {
+            true => self.below[axis],
+            false => self.above[axis],
+        };
+        (value * 32767 / reach).clamp(-32767, 32767)
+    }
+}
+
+/// The state from the previous report, so only changes are turned into events.
+struct ReportState {
+    calibration: [StickCalibration; 2],
+    buttons: [u8; 3],
+    sticks: [(i32, i32); 2],
+}
+
+impl Default for ReportState {
+    fn default() -> Self {
+        ReportState {
+            calibration: [StickCalibration::DEFAULT; 2],
+            buttons: [0; 3],
+            sticks: [(0, 0); 2],
+        }
+    }
+}
+
+impl ReportState {
+    fn update(&mut self, report: &[u8]) -> Vec<InputEvent> {
+        if report.len() < 12 || ![REPORT_STANDARD_FULL, nfc::REPORT_NFC_IR].contains(&report[0]) {
+            return vec![];
+        }
+        let mut events = vec![];
+        for &(byte, bit, key) in BUTTONS {
+            let pressed = report[byte] & bit != 0;
+            if pressed != (self.buttons[byte - 3] & bit != 0) {
+                events.push(InputEvent::new(EventType::KEY, key.code(), pressed as i32));
+            }
+        }
+        self.buttons.copy_from_slice(&report[3..6]);
+        for (i, &(offset, x_axis, y_axis)) in STICKS.iter().enumerate() {
+            let data = &report[offset..offset + 3];
+            let x = i32::from(data[0]) | i32::from(data[1] & 0x0f) << 8;
+            let y = i32::from(data[1] >> 4) | i32::from(data[2]) << 4;
+            // scaled to hid-nintendo's range, with up being negative like it is there
+            let x = self.calibration[i].apply(0, x);
+            let y = -self.calibration[i].apply(1, y);
+            let (last_x, last_y) = self.sticks[i];
+            if x != last_x {
+                events.push(InputEvent::new(EventType::ABSOLUTE, x_axis.0, x));
+            }
+            if y != last_y {
+                events.push(InputEvent::new(EventType::ABSOLUTE, y_axis.0, y));
+            }
+            self.sticks[i] = (x, y);
+        }
+        if !events.is_empty() {
+            events.push(InputEvent::new(
+                EventType::SYNCHRONIZATION,
+                Synchronization::SYN_REPORT.0,
+                0,
+            ));
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StickCalibration;
+
+    #[test]
+    fn parses_calibration() {
+        let left = [0x00, 0x07, 0x70, 0x00, 0x08, 0x80, 0x00, 0x05, 0x50];
+        let right = [0x00, 0x08, 0x80, 0x00, 0x05, 0x50, 0x00, 0x07, 0x70];
+        let calibration = StickCalibration {
+            center: [0x800, 0x800],
+            below: [0x500, 0x500],
+            above: [0x700, 0x700],
+        };
+        let cases = [
+            (0, &left[..], Some(calibration)),
+            (1, &right[..], Some(calibration)),
+            // erased flash
+            (0, &[0xff; 9][..], None),
+            (1, &[0xff; 9][..], None),
+            // no reach
+            (0, &[0; 9][..], None),
+            (0, &left[..8], None),
+        ];
+        for (i, data, expected) in cases {
+            assert_eq!(
+                StickCalibration::parse(i, data),
+                expected,
+                "stick {} {:x?}",
+                i,
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn calibration_round_trips() {
+        let calibrations = [
+            StickCalibration::DEFAULT,
+            StickCalibration {
+                center: [0x7d3, 0x812],
+                below: [0x5a1, 0x4f0],
+                above: [0x6e2, 0x723],
+            },
+            StickCalibration {
+                center: [0xfff, 0x001],
+                below: [0x001, 0xffe],
+                above: [0xabc, 0x123],
+            },
+        ];
+        for calibration in calibrations {
+            for i in 0..2 {
+                let encoded = calibration.encode(i);
+                assert_eq!(
+                    StickCalibration::parse(i, &encoded),
+                    Some(calibration),
+                    "stick {} {:x?}",
+                    i,
+                    encoded
+                );
+            }
+        }
+    }
+}
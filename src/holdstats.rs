@@ -0,0 +1,94 @@
+//! An opt-in record of how long each button is held and how quickly it's pressed again, for
+//! picking tap/hold and double-press thresholds that suit the hands actually using the
+//! controller.
+
+use evdev::{InputEvent, InputEventKind, Key};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Presses held for longer than this count as holds rather than taps.
+const TAP_MAX: Duration = Duration::from_millis(500);
+
+/// Presses following the previous one within this count as part of a double press.
+const DOUBLE_MAX: Duration = Duration::from_millis(500);
+
+/// How many samples are needed before suggesting a threshold.
+const MIN_SAMPLES: usize = 5;
+
+/// The margin added on top of the slowest usual tap/double press.
+const MARGIN: f64 = 1.25;
+
+#[derive(Default)]
+pub struct HoldStats {
+    /// When each held button was pressed.
+    pressed: HashMap<Key, SystemTime>,
+    /// When each button was last pressed, whether or not it's still held.
+    last_press: HashMap<Key, SystemTime>,
+    durations: BTreeMap<Key, Vec<Duration>>,
+    intervals: BTreeMap<Key, Vec<Duration>>,
+}
+
+impl HoldStats {
+    /// Take note of a button event, by its time.
+    pub fn record(&mut self, ev: &InputEvent) {
+        let key = match ev.kind() {
+            InputEventKind::Key(key) => key,
+            _ => return,
+        };
+        // synthesized events (e.g. from the hidraw backend) aren't timestamped
+        let time = match ev.timestamp() {
+            UNIX_EPOCH => SystemTime::now(),
+            time => time,
+        };
+        match ev.value() {
+            1 => {
+                if let Some(last) = self.last_press.insert(key, time) {
+                    let interval = time.duration_since(last).unwrap_or_default();
+                    self.intervals.entry(key).or_default().push(interval);
+                }
+                self.pressed.insert(key, time);
+            }
+            0 => {
+                if let Some(pressed) = self.pressed.remove(&key) {
+                    let duration = time.duration_since(pressed).unwrap_or_default();
+                    self.durations.entry(key).or_default().push(duration);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Describe each button's presses so far, with suggested thresholds where there's enough to
+    /// go on.
+    pub fn report(&self) -> String {
+        if self.durations.is_empty() {
+            return "no presses recorded yet".to_owned();
+        }
+        self.durations
+            .iter()
+            .map(|(key, durations)| {
+                let mut line = format!("{:?}: {} presses", key, durations.len());
+                if let Some(tap) = suggest(durations, TAP_MAX) {
+                    line += &format!(", hold after {}ms", tap.as_millis());
+                }
+                let intervals = self.intervals.get(key).map_or(&[][..], |v| &v[..]);
+                if let Some(double) = suggest(intervals, DOUBLE_MAX) {
+                    line += &format!(", double press within {}ms", double.as_millis());
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+/// A threshold just above most of the `samples` under `max`: the 90th percentile, plus a margin.
+fn suggest(samples: &[Duration], max: Duration) -> Option<Duration> {
+    let mut quick: Vec<_> = samples.iter().filter(|&&d| d < max).copied().collect();
+    if quick.len() < MIN_SAMPLES {
+        return None;
+    }
+    quick.sort();
+    let p90 = quick[(quick.len() - 1) * 9 / 10];
+    Some(p90.mul_f64(MARGIN))
+}
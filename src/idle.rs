@@ -0,0 +1,102 @@
+//! Acting after a while without any input (e.g. blanking an HTPC's screen, or switching to a
+//! profile that only wakes on one button), and undoing it once input resumes. Each profile can
+//! have its own timeout and action, see [`IdleConfig`]; the ones of the profile that was active
+//! when going idle apply until it resumes.
+
+use crate::config::IdleConfig;
+use std::time::Duration;
+use tokio::process::Command;
+
+pub struct Idle {
+    /// Each profile's settings, by its index among the config's profiles (only one without
+    /// profiles).
+    settings: Vec<IdleConfig>,
+    /// The profile whose settings apply.
+    active: usize,
+    idle: bool,
+    /// Whether the timeout has to start over, because there was input or the settings changed.
+    restart: bool,
+    /// The profile to switch back to on resuming, if going idle switched away from it.
+    resume_profile: Option<usize>,
+}
+
+impl Idle {
+    pub fn new(settings: Vec<IdleConfig>) -> Self {
+        Idle {
+            settings,
+            active: 0,
+            idle: false,
+            restart: true,
+            resume_profile: None,
+        }
+    }
+
+    fn settings(&self) -> &IdleConfig {
+        &self.settings[self.active]
+    }
+
+    /// How long without input counts as idle, if the active profile goes idle at all.
+    pub fn timeout(&self) -> Option<Duration> {
+        let minutes = self.settings().timeout_minutes?;
+        Some(Duration::from_secs(minutes * 60))
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    /// The profile at `index` was switched to. While idle, the settings that went idle still
+    /// apply, e.g. to the profile it switched to.
+    pub fn select(&mut self, index: usize) {
+        if !self.idle {
+            self.active = index;
+            self.restart = true;
+        }
+    }
+
+    /// Start the timeout over, without it counting as input.
+    pub fn restart(&mut self) {
+        self.restart = true;
+    }
+
+    /// Whether the timeout has to start over, since the last call.
+    pub fn take_restart(&mut self) -> bool {
+        std::mem::take(&mut self.restart)
+    }
+
+    /// The idle timeout ran out. Returns the profile to switch to, if any.
+    pub fn start(&mut self) -> Option<String> {
+        eprintln!("No input for a while, going idle");
+        self.idle = true;
+        if let Some(command) = &self.settings().command {
+            run(command);
+        }
+        let profile = self.settings().profile.clone();
+        if profile.is_some() {
+            self.resume_profile = Some(self.active);
+        }
+        profile
+    }
+
+    /// There was input. Returns the profile to switch back to, if going idle switched away from
+    /// it.
+    pub fn activity(&mut self) -> Option<usize> {
+        self.restart = true;
+        if !self.idle {
+            return None;
+        }
+        self.idle = false;
+        if let Some(command) = &self.settings().resume_command {
+            run(command);
+        }
+        self.resume_profile.take()
+    }
+}
+
+pub fn run(command: &str) {
+    match Command::new("sh").args(["-c", command]).spawn() {
+        // reaped in the background by tokio
+        Ok(_) => {}
+        Err(e) => eprintln!("warning: failed to run {:?}: {}", command, e),
+    }
+}
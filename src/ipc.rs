@@ -0,0 +1,182 @@
+//! The control socket, which lets other programs send commands to a running joykbd. The protocol
+//! is line based: each line is a command, and each gets a single line reply, either `ok` or
+//! `error: <message>`.
+
+use crate::drift::DriftAdjust;
+use crate::rotation::Rotation;
+use crate::rumble::Pattern;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+
+pub enum Command {
+    /// `rumble <pattern>`: buzz the joy-con, see [`Pattern`] for the format.
+    Rumble(Pattern),
+    /// `rumble <name>`: play the config file's HD rumble envelope called `name`, see
+    /// [`crate::rumble`].
+    RumbleEnvelope(String),
+    /// `drift-threshold [+N|-N|N|save]`: adjust the stick's drift threshold, see [`DriftAdjust`],
+    /// or just report it.
+    DriftThreshold(Option<DriftAdjust>),
+    /// `rotate [0|90|180|270]`: rotate the cursor's motion to match a rotated display, or report
+    /// the current rotation.
+    Rotate(Option<Rotation>),
+    /// `enable <name>`/`disable <name>`: turn a remap on or off, by its name (by default, its
+    /// FROM key's name, or `layer:<key>` for a layer remap).
+    SetBindingEnabled(String, bool),
+    /// `bindings`: list the remaps by name, marking the disabled ones.
+    Bindings,
+    /// `status [--json]`: describe the device, profile and output joykbd is using.
+    Status { json: bool },
+    /// `power-off`: disconnect the controller, putting it to sleep.
+    PowerOff,
+    /// `hold-stats`: report how long each button's been held, with suggested tap/hold and
+    /// double-press thresholds, with --hold-stats.
+    HoldStats,
+    /// `recent-events`: the latest events read, as a JSON array of `--record` records (with
+    /// keyboard keys left out).
+    RecentEvents,
+}
+
+impl FromStr for Command {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let name = words.next().ok_or_else(|| "empty command".to_owned())?;
+        let rest: Vec<&str> = words.collect();
+        match (name, rest.as_slice()) {
+            // patterns start with a duration, and envelopes with a letter
+            ("rumble", [name]) if name.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                Ok(Command::RumbleEnvelope((*name).to_owned()))
+            }
+            ("rumble", [pattern]) => pattern.parse().map(Command::Rumble),
+            ("rumble", _) => {
+                Err("usage: rumble <ms>[,<ms>...][@<intensity>] | rumble <name>".to_owned())
+            }
+            ("drift-threshold", []) => Ok(Command::DriftThreshold(None)),
+            ("drift-threshold", [adjust]) => adjust
+                .parse()
+                .map(|adjust| Command::DriftThreshold(Some(adjust))),
+            ("drift-threshold", _) => Err("usage: drift-threshold [+[N]|-[N]|N|save]".to_owned()),
+            ("rotate", []) => Ok(Command::Rotate(None)),
+            ("rotate", [rotation]) => rotation
+                .parse()
+                .map(|rotation| Command::Rotate(Some(rotation))),
+            ("rotate", _) => Err("usage: rotate [0|90|180|270]".to_owned()),
+            ("enable" | "disable", [binding]) => Ok(Command::SetBindingEnabled(
+                (*binding).to_owned(),
+                name == "enable",
+            )),
+            ("enable" | "disable", _) => Err(format!("usage: {} <name>", name)),
+            ("bindings", []) => Ok(Command::Bindings),
+            ("bindings", _) => Err("usage: bindings".to_owned()),
+            ("status", []) => Ok(Command::Status { json: false }),
+            ("status", ["--json"]) => Ok(Command::Status { json: true }),
+            ("status", _) => Err("usage: status [--json]".to_owned()),
+            ("power-off", []) => Ok(Command::PowerOff),
+            ("power-off", _) => Err("usage: power-off".to_owned()),
+            ("hold-stats", []) => Ok(Command::HoldStats),
+            ("hold-stats", _) => Err("usage: hold-stats".to_owned()),
+            ("recent-events", []) => Ok(Command::RecentEvents),
+            ("recent-events", _) => Err("usage: recent-events".to_owned()),
+            _ => Err(format!("unknown command {:?}", name)),
+        }
+    }
+}
+
+pub type Reply = Result<String, String>;
+
+/// A command along with where to send its reply.
+pub struct Request {
+    pub command: Command,
+    pub reply: oneshot::Sender<Reply>,
+}
+
+/// The default location, `$XDG_RUNTIME_DIR/joykbd.sock`.
+pub fn default_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR").map(|dir| Path::new(&dir).join("joykbd.sock"))
+}
+
+/// Send `command` to the joykbd listening on `path`, returning its reply.
+pub fn send(path: &Path, command: &str) -> io::Result<Reply> {
+    use std::io::{BufRead, Write};
+    let mut stream = std::os::unix::net::UnixStream::connect(path)?;
+    writeln!(stream, "{}", command)?;
+    let mut reply = String::new();
+    std::io::BufReader::new(stream).read_line(&mut reply)?;
+    let reply = reply.trim_end();
+    Ok(match reply.strip_prefix("error: ") {
+        Some(e) => Err(e.to_owned()),
+        None => Ok(reply.to_owned()),
+    })
+}
+
+/// Listen on `path`, forwarding every command received to `tx`. Fails if another joykbd is already
+/// listening there.
+pub fn listen(path: &Path, tx: mpsc::Sender<Request>) -> io::Result<()> {
+    if path.exists() {
+        match std::os::unix::net::UnixStream::connect(path) {
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("another joykbd is already listening on {}", path.display()),
+                ))
+            }
+            // a leftover socket from a joykbd that didn't exit cleanly
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => std::fs::remove_file(path)?,
+            Err(e) => return Err(e),
+        }
+    }
+    let listener = UnixListener::bind(path)?;
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    eprintln!("warning: control socket error: {}", e);
+                    continue;
+                }
+            };
+            tokio::spawn(serve(stream, tx.clone()));
+        }
+    });
+    Ok(())
+}
+
+async fn serve(stream: UnixStream, tx: mpsc::Sender<Request>) {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match line.parse() {
+            Ok(command) => {
+                let (reply_tx, reply_rx) = oneshot::channel();
+                let request = Request {
+                    command,
+                    reply: reply_tx,
+                };
+                if tx.send(request).await.is_err() {
+                    break;
+                }
+                reply_rx
+                    .await
+                    .unwrap_or_else(|_| Err("no reply".to_owned()))
+            }
+            Err(e) => Err(e),
+        };
+        let mut reply = match reply {
+            Ok(msg) if msg.is_empty() => "ok".to_owned(),
+            Ok(msg) => msg,
+            Err(e) => format!("error: {}", e),
+        };
+        reply.push('\n');
+        if write.write_all(reply.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
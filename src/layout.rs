@@ -0,0 +1,92 @@
+//! Which of a controller's evdev codes is which button. Everything past reading the device
+//! identifies buttons the way hid-nintendo reports a Joy-Con's, which by the kernel's gamepad
+//! conventions is where they are on the controller (`BTN_SOUTH` is the bottom face button, and so
+//! on), so xpad's and hid-playstation's pads work as they are. Controllers that report other
+//! codes for them (e.g. a generic HID pad's `BTN_TRIGGER`, `BTN_THUMB`, ...) are given a layout
+//! in the config file, by the buttons' names on a Joy-Con:
+//!
+//! ```toml
+//! [layout]
+//! A = "BTN_THUMB"
+//! B = "BTN_THUMB2"
+//! X = "BTN_TRIGGER"
+//! Y = "BTN_TOP"
+//! ```
+//!
+//! Their codes are then turned into the Joy-Con's as they're read.
+
+use crate::bindings::parse_key;
+use evdev::{EventType, InputEvent, InputEventKind, Key};
+use std::collections::{BTreeMap, HashMap};
+
+/// The names of the Joy-Cons' (and Pro Controller's) buttons, and what hid-nintendo reports them
+/// as.
+pub const BUTTONS: &[(&str, Key)] = &[
+    ("A", Key::BTN_EAST),
+    ("B", Key::BTN_SOUTH),
+    ("X", Key::BTN_NORTH),
+    ("Y", Key::BTN_WEST),
+    ("L", Key::BTN_TL),
+    ("R", Key::BTN_TR),
+    ("ZL", Key::BTN_TL2),
+    ("ZR", Key::BTN_TR2),
+    ("MINUS", Key::BTN_SELECT),
+    ("PLUS", Key::BTN_START),
+    ("HOME", Key::BTN_MODE),
+    ("CAPTURE", Key::BTN_Z),
+    ("LSTICK", Key::BTN_THUMBL),
+    ("RSTICK", Key::BTN_THUMBR),
+    ("UP", Key::BTN_DPAD_UP),
+    ("DOWN", Key::BTN_DPAD_DOWN),
+    ("LEFT", Key::BTN_DPAD_LEFT),
+    ("RIGHT", Key::BTN_DPAD_RIGHT),
+];
+
+/// A button by its name on a Joy-Con (case-insensitively).
+pub fn button(name: &str) -> Option<Key> {
+    BUTTONS
+        .iter()
+        .find(|(button, _)| button.eq_ignore_ascii_case(name))
+        .map(|&(_, key)| key)
+}
+
+/// How a controller's codes translate to the Joy-Con's. The default is the Joy-Con's own, which
+/// leaves every code as it is.
+#[derive(Default, Clone)]
+pub struct Layout {
+    /// The controller's code for a button, and the Joy-Con's.
+    keys: HashMap<Key, Key>,
+}
+
+impl Layout {
+    /// The layout with each button (by its name, or the Joy-Con's evdev name for it) reported as
+    /// the evdev code it's paired with.
+    pub fn new(buttons: &BTreeMap<String, String>) -> Result<Self, String> {
+        let mut keys = HashMap::new();
+        for (name, code) in buttons {
+            let button = button(name)
+                .or_else(|| parse_key(name))
+                .ok_or_else(|| format!("unknown button {:?}", name))?;
+            let code = parse_key(code).ok_or_else(|| format!("unknown key {:?}", code))?;
+            if keys.insert(code, button).is_some() {
+                return Err(format!("{:?} is given to more than one button", code));
+            }
+        }
+        Ok(Layout { keys })
+    }
+
+    /// The Joy-Con's code for the button the controller reports as `key`.
+    pub fn key(&self, key: Key) -> Key {
+        self.keys.get(&key).copied().unwrap_or(key)
+    }
+
+    /// `ev` with the Joy-Con's code, if it's a button.
+    pub fn map(&self, ev: InputEvent) -> InputEvent {
+        match ev.kind() {
+            InputEventKind::Key(key) if !self.keys.is_empty() => {
+                InputEvent::new(EventType::KEY, self.key(key).code(), ev.value())
+            }
+            _ => ev,
+        }
+    }
+}
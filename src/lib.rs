@@ -0,0 +1,74 @@
+//! joykbd's mapping, stick math and virtual device plumbing, for embedding a Joy-Con (or another
+//! controller) as a mouse and keyboard in another program, rather than running the `joykbd`
+//! binary.
+//!
+//! The simplest way in is [`Remapper::run`], which runs joykbd's whole event loop with
+//! [`Options`] parsed as from its command line. To drive the translation yourself, a [`Remapper`]
+//! translates a controller's evdev events, with [`Bindings`] built from [`Remap`]s and the
+//! stick's [`StickConstants`] (set up from a [`StickConfig`], like the config file's `[stick]`
+//! table), and a [`VirtualOutput`] emits the result, through a virtual device or one of the other
+//! [`SinkSpec`]s, keeping track of what's held. Input devices are found and opened with [`device`]
+//! and read through [`source`].
+
+#[cfg(feature = "alloc-audit")]
+mod alloc_audit;
+mod arrows;
+mod battery;
+pub mod bindings;
+pub mod config;
+mod conflict;
+mod cursor;
+pub mod device;
+mod devwatch;
+mod drift;
+mod edges;
+mod event_loop;
+mod feedback;
+mod gamepad;
+mod gyro;
+mod hat;
+pub mod hidraw;
+mod holdstats;
+mod idle;
+pub mod ipc;
+pub mod layout;
+pub mod migrate;
+mod mouse;
+mod multitouch;
+mod nfc;
+pub mod option_set;
+mod options;
+pub mod output;
+mod overlay;
+pub mod partner;
+mod passthrough;
+mod policy;
+mod power;
+mod presence;
+pub mod preset;
+pub mod record;
+pub mod remapper;
+mod rotation;
+mod rumble;
+mod scroll;
+pub mod source;
+mod speed;
+pub mod state;
+mod status;
+pub mod stick;
+mod sticky;
+mod switcher;
+mod textentry;
+mod trackpad;
+mod unmapped;
+mod wiimote;
+mod wlroots;
+mod xbox;
+mod xtest;
+
+pub use bindings::{Action, Bindings, Remap};
+pub use config::{Config, StickConfig};
+pub use options::Options;
+pub use output::{Capabilities, SinkSpec, VirtualOutput};
+pub use remapper::Remapper;
+pub use stick::{StickConstants, StickSide};
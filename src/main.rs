@@ -1,211 +1,592 @@
 use clap::Parser;
-use evdev::uinput::VirtualDeviceBuilder;
-use evdev::{
-    AbsoluteAxisType, AttributeSet, Device, EventType, InputEvent, InputEventKind, Key,
-    RelativeAxisType,
-};
-use std::path::PathBuf;
+use evdev::InputEvent;
+use joykbd::device::{self, WaitWindow};
+use joykbd::hidraw::HidrawJoyCon;
+use joykbd::output::{NullSink, RecordingSink};
+use joykbd::source::{self, Backend, InputSource, SourceKind};
+use joykbd::state::StateFile;
+use joykbd::{config, ipc, migrate, option_set, partner};
+use joykbd::{Options, Remapper, StickSide, VirtualOutput};
+use std::path::{Path, PathBuf};
 use tokio::time;
 
+mod calibrate;
+mod cheatsheet;
+mod daemon;
+mod monitor;
+mod replay;
+mod report;
+mod service;
+mod soak;
+mod verify;
+
 #[derive(Parser)]
+#[clap(args_conflicts_with_subcommands = true, args_override_self = true)]
 struct Args {
-    /// The path to the evdev device file representing the joy-con you want to use. By default,
-    /// joykbd searches for the first device that has "Joy-Con" in it's name.
-    device: Option<PathBuf>,
-    /// The cursor speed; how fast it'll move when the stick is held all the way to one direction.
-    #[clap(long, default_value_t = 20.0)]
-    speed: f64,
-    /// The repeat timeout for the pseudo-mouse, in milliseconds
-    #[clap(long, default_value_t = 16)]
-    repeat_timeout: u64,
-    /// The minimum value that the magnitude of the stick reading has to be so it's not ignored as
-    /// joy-con drift.
-    #[clap(long, default_value_t = 2000)]
-    drift_threshold: u32,
-    /// A bias to add to the X axis of the stick before further processing.
-    #[clap(long, allow_hyphen_values = true, default_value_t = 0)]
-    x_bias: i32,
-    /// A bias to add to the Y axis of the stick before further processing.
-    #[clap(long, allow_hyphen_values = true, default_value_t = 0)]
-    y_bias: i32,
+    #[clap(subcommand)]
+    command: Option<Subcommand>,
+    #[clap(flatten)]
+    options: Options,
+    /// Detach from the terminal and run in the background, logging to syslog. Only takes effect
+    /// on the command line, not from a --profile.
+    #[clap(long)]
+    daemon: bool,
+    /// Where --daemon writes its process ID. Defaults to $XDG_RUNTIME_DIR/joykbd.pid.
+    #[clap(long, value_name = "PATH", requires = "daemon")]
+    pidfile: Option<PathBuf>,
 }
 
-impl Args {
-    fn stick_constants(&self) -> StickConstants {
-        StickConstants {
-            factor: self.speed / 30_000f64.powi(StickConstants::CURVE_POWER),
-            drift_threshold: self.drift_threshold,
-            axis_bias: (self.x_bias, self.y_bias),
-        }
-    }
+#[derive(clap::Subcommand)]
+enum Subcommand {
+    /// Upgrade a config or state file written for an older version of joykbd to the current
+    /// format, reporting what changed. joykbd upgrades them as it loads them anyway, this writes
+    /// the upgrade back. The original is kept with .bak added to its name. Defaults to the state
+    /// file.
+    MigrateConfig { path: Option<PathBuf> },
+    /// Send a command to a running joykbd over its control socket and print the reply, e.g.
+    /// `joykbd ctl status --json`.
+    #[clap(trailing_var_arg = true)]
+    Ctl {
+        /// The control socket to connect to, instead of the default.
+        #[clap(long)]
+        control_socket: Option<PathBuf>,
+        #[clap(required = true, multiple_values = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Write a user stick calibration to a Joy-Con or Pro Controller, in the same place the
+    /// Switch's own calibration does, so it's used everywhere the controller is. The stick's
+    /// current center is moved by --x-bias/--y-bias, as they'd apply in joykbd. The calibration
+    /// it replaces is backed up first (unless there's already a backup, which is kept), and
+    /// --restore puts it back.
+    FlashUserCalibration {
+        /// The controller's hidraw device, e.g. /dev/hidraw3.
+        device: PathBuf,
+        /// Which stick to calibrate, by default a Joy-Con's only one.
+        #[clap(long, arg_enum)]
+        stick: Option<StickSide>,
+        #[clap(long, allow_hyphen_values = true, default_value_t = 0)]
+        x_bias: i32,
+        #[clap(long, allow_hyphen_values = true, default_value_t = 0)]
+        y_bias: i32,
+        /// Where to back up the calibration, instead of joykbd's state directory.
+        #[clap(long, value_name = "PATH")]
+        backup: Option<PathBuf>,
+        /// Put back the backed up calibration, instead of writing a new one.
+        #[clap(long, conflicts_with_all = &["x-bias", "y-bias"])]
+        restore: bool,
+    },
+    /// Measure a stick's center, drift and range, by leaving it alone and then rolling it around
+    /// its rim, and save them to the config file (its `x-bias`, `y-bias`, `drift-threshold` and
+    /// `range` under `[stick]`). With --flash, the center is written to the controller's user
+    /// calibration instead, as `flash-user-calibration` does, and the biases saved are 0.
+    Calibrate {
+        /// The controller, found like joykbd's own by default.
+        device: Option<PathBuf>,
+        /// Which stick to calibrate, by default a Joy-Con's only one.
+        #[clap(long, arg_enum)]
+        stick: Option<StickSide>,
+        #[clap(long, default_value = "evdev", parse(try_from_str = source::parse_backend))]
+        backend: &'static Backend,
+        /// The config file to write to, instead of the default.
+        #[clap(long, value_name = "PATH")]
+        config: Option<PathBuf>,
+        /// The controller's hidraw device (e.g. /dev/hidraw3), to write the measured center to.
+        #[clap(long, value_name = "HIDRAW")]
+        flash: Option<PathBuf>,
+    },
+    /// Show the controller's events as they come in, each next to what the mapping turns it
+    /// into, or why that's nothing (e.g. the stick being within the deadzone). Mapping options
+    /// after `--` are used, e.g. `joykbd monitor -- --drift-threshold 3000`.
+    Monitor {
+        /// The controller, found like joykbd's own by default.
+        device: Option<PathBuf>,
+        #[clap(long, default_value = "evdev", parse(try_from_str = source::parse_backend))]
+        backend: &'static Backend,
+        #[clap(last = true)]
+        args: Vec<String>,
+    },
+    /// Print what each of the controller's buttons does with the config file (joykbd's own,
+    /// unless there's a --config) and the mapping options after `--`, with and without the
+    /// layer, e.g. to print out as a reminder:
+    /// `joykbd cheatsheet --format svg -- --config joykbd.toml > cheatsheet.svg`.
+    Cheatsheet {
+        #[clap(long, arg_enum, default_value = "text")]
+        format: cheatsheet::Format,
+        /// The config file's profile to show, instead of the one the running joykbd has active
+        /// (or the default one, if there's none).
+        #[clap(long, value_name = "NAME")]
+        profile: Option<String>,
+        #[clap(last = true)]
+        args: Vec<String>,
+    },
+    /// Install a udev rule and a systemd unit template that start joykbd for each controller as
+    /// it connects, e.g. when a sleeping Joy-Con is woken by pressing a button. Options after
+    /// `--` are passed to each instance, e.g. `joykbd install-service -- --watch --speed 30`.
+    InstallService {
+        /// Install a user service, started in the session of whoever's logged in, instead of a
+        /// system one.
+        #[clap(long)]
+        user: bool,
+        /// Write both files into this directory instead of installing them, e.g. for packaging.
+        #[clap(long, value_name = "DIR")]
+        dest: Option<PathBuf>,
+        #[clap(last = true)]
+        args: Vec<String>,
+    },
+    /// Play a --record recording back through a virtual Joy-Con, for a joykbd started on it to
+    /// translate, e.g. to reproduce a bug report. Only the controller's own events are played.
+    Replay {
+        path: PathBuf,
+        /// How fast to play it back, e.g. 0.25 for a quarter of the recorded speed.
+        #[clap(long, default_value_t = 1.0)]
+        speed: f64,
+        /// Pause this many seconds into the recording, until Enter is pressed. Can be passed
+        /// multiple times.
+        #[clap(long, value_name = "SECS")]
+        pause_at: Vec<f64>,
+        /// Play one report at a time, printing its events and waiting for Enter before each.
+        #[clap(long)]
+        step: bool,
+    },
+    /// Check that recordings still translate into the events they used to, as described by
+    /// golden-output fixtures (TOML files pairing a --record recording with the expected
+    /// events), e.g. after changing the mapping engine.
+    Verify {
+        #[clap(required = true)]
+        fixtures: Vec<PathBuf>,
+        /// Write what's emitted now as each fixture's expected events, instead of checking them.
+        #[clap(long)]
+        bless: bool,
+    },
+    /// Bundle what's needed to look into a bug into a .tar.gz to attach to an issue: versions,
+    /// the environment, the controllers' capabilities, the config file, and a running joykbd's
+    /// status and latest events (without what was typed on --keyboard sources). The home
+    /// directory, hostname and controllers' addresses are redacted.
+    Report {
+        /// Where to write the report, instead of joykbd-report-TIME.tar.gz.
+        #[clap(long, short, value_name = "PATH")]
+        output: Option<PathBuf>,
+        /// The config file to include, instead of the default.
+        #[clap(long, value_name = "PATH")]
+        config: Option<PathBuf>,
+        /// The running joykbd's control socket, instead of the default.
+        #[clap(long, value_name = "PATH")]
+        control_socket: Option<PathBuf>,
+    },
+    /// Run synthetic controller input through the mapping for a long time, into a sink that
+    /// discards it, checking for memory leaks, timers falling behind and state that doesn't
+    /// return to rest, e.g. before a release. Mapping options after `--` are used, e.g.
+    /// `joykbd soak --hours 8 -- --remap BTN_EAST=KEY_LEFTCTRL+KEY_C`.
+    Soak {
+        /// Events per second.
+        #[clap(long, default_value_t = 2000)]
+        rate: u32,
+        #[clap(long, default_value_t = 1.0)]
+        hours: f64,
+        /// How often to check the state and report, in seconds.
+        #[clap(long, value_name = "SECS", default_value_t = 60.0)]
+        report_every: f64,
+        /// Seed the synthetic input, e.g. to repeat a failed run, instead of picking one.
+        #[clap(long)]
+        seed: Option<u64>,
+        #[clap(last = true)]
+        args: Vec<String>,
+    },
 }
 
-struct StickConstants {
-    factor: f64,
-    drift_threshold: u32,
-    axis_bias: (i32, i32),
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    // forking has to happen before the runtime starts
+    let detach = args.daemon && args.command.is_none();
+    // removed once this returns (or panics)
+    let _pidfile = match detach {
+        true => daemon::detach(
+            args.pidfile
+                .clone()
+                .or_else(daemon::default_pidfile)
+                .as_deref(),
+        )?,
+        false => None,
+    };
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(run(args))
 }
 
-enum Axis {
-    X,
-    Y,
+async fn run(mut args: Args) -> anyhow::Result<()> {
+    if let Some(command) = args.command {
+        return run_subcommand(command).await;
+    }
+    let option_set = args.options.option_set()?;
+    if let Some(option_set) = &option_set {
+        eprintln!("Using option set {}", option_set);
+        let mut argv = std::env::args_os();
+        let argv = argv
+            .next()
+            .into_iter()
+            .chain(option_set::load(option_set)?)
+            .chain(argv);
+        args = Args::parse_from(argv);
+    }
+    Remapper::run(args.options, option_set, daemon::notify_ready).await
 }
 
-impl StickConstants {
-    fn map_axis(&self, axis: Axis, value: i32) -> i32 {
-        let value = value
-            + match axis {
-                Axis::X => self.axis_bias.0,
-                Axis::Y => self.axis_bias.1,
+async fn run_subcommand(command: Subcommand) -> anyhow::Result<()> {
+    match command {
+        Subcommand::MigrateConfig { path } => {
+            let path = path
+                .or_else(StateFile::default_path)
+                .ok_or_else(|| anyhow::anyhow!("no file given, and no default location"))?;
+            let changes = migrate::migrate_file(&path)?;
+            if changes.is_empty() {
+                println!("{} is already up to date", path.display());
+            }
+            for change in changes {
+                println!("{}: {}", path.display(), change);
+            }
+        }
+        Subcommand::Ctl {
+            control_socket,
+            command,
+        } => {
+            let path = control_socket.or_else(ipc::default_path).ok_or_else(|| {
+                anyhow::anyhow!("no --control-socket given, and no default location")
+            })?;
+            let reply = ipc::send(&path, &command.join(" ")).map_err(|e| {
+                anyhow::anyhow!("couldn't reach joykbd at {}: {}", path.display(), e)
+            })?;
+            match reply {
+                Ok(reply) => println!("{}", reply),
+                Err(e) => anyhow::bail!(e),
+            }
+        }
+        Subcommand::FlashUserCalibration {
+            device,
+            stick,
+            x_bias,
+            y_bias,
+            backup,
+            restore,
+        } => match restore {
+            true => restore_user_calibration(&device, stick, backup)?,
+            false => flash_user_calibration(&device, stick, (x_bias, y_bias), backup)?,
+        },
+        Subcommand::Calibrate {
+            device,
+            stick,
+            backend,
+            config,
+            flash,
+        } => {
+            let path = config
+                .or_else(config::default_path)
+                .ok_or_else(|| anyhow::anyhow!("no --config given, and no default location"))?;
+            // before the calibration, which would otherwise be for nothing
+            let format = config::Format::of(&path);
+            if format != config::Format::Toml {
+                anyhow::bail!(
+                    "{} is a {} config, and only TOML ones can be written to",
+                    path.display(),
+                    format.name()
+                );
+            }
+            let (_, dev) =
+                device::open(backend, device.as_deref(), None, WaitWindow::Secs(0), false).await?;
+            let stick = stick
+                .or_else(|| dev.name().and_then(partner::side))
+                .ok_or_else(|| anyhow::anyhow!("pass --stick to pick which stick"))?;
+            let mut calibration = calibrate::run(dev, stick).await?;
+            println!(
+                "Center bias {}, {}; drift threshold {}; range {:?}",
+                calibration.x_bias,
+                calibration.y_bias,
+                calibration.drift_threshold,
+                calibration.range
+            );
+            if let Some(flash) = flash {
+                let bias = (calibration.x_bias, calibration.y_bias);
+                flash_user_calibration(&flash, Some(stick), bias, None)?;
+                // the controller's centered now, so joykbd shouldn't move it again
+                calibration.x_bias = 0;
+                calibration.y_bias = 0;
+                println!("Reconnect the controller for its new center to take effect");
+            }
+            calibrate::save(&path, &calibration)?;
+            println!("Saved to {}", path.display());
+        }
+        Subcommand::InstallService { user, dest, args } => {
+            let locations = match &dest {
+                Some(dest) => service::Locations::in_dir(dest),
+                None => service::Locations::standard(user)
+                    .ok_or_else(|| anyhow::anyhow!("no location for user services"))?,
+            };
+            let exe = std::env::current_exe()?;
+            for path in service::install(&locations, user, &exe, &args)? {
+                println!("Wrote {}", path.display());
+            }
+            if dest.is_none() {
+                let systemctl = if user {
+                    "systemctl --user"
+                } else {
+                    "systemctl"
+                };
+                println!(
+                    "Run `udevadm control --reload` and `{} daemon-reload` to start using them",
+                    systemctl
+                );
+            }
+        }
+        Subcommand::Replay {
+            path,
+            speed,
+            pause_at,
+            step,
+        } => {
+            if speed <= 0.0 {
+                anyhow::bail!("--speed has to be more than 0");
+            }
+            let options = replay::Options {
+                speed,
+                pause_at,
+                step,
             };
-        if value.unsigned_abs() < self.drift_threshold {
-            0
-        } else {
-            (f64::from(value).powi(Self::CURVE_POWER) * self.factor) as i32
+            replay::run(&path, options).await?;
+        }
+        Subcommand::Report {
+            output,
+            config,
+            control_socket,
+        } => {
+            let output = output.unwrap_or_else(report::default_output);
+            let config = config.or_else(|| config::default_path().filter(|path| path.exists()));
+            let control_socket = control_socket.or_else(ipc::default_path);
+            report::create(&output, config.as_deref(), control_socket.as_deref())?;
+            println!("Wrote {}", output.display());
+        }
+        Subcommand::Monitor {
+            device,
+            backend,
+            args,
+        } => {
+            let (mut remapper, _) = offline_remapper(&args, None)?;
+            let (path, dev) =
+                device::open(backend, device.as_deref(), None, WaitWindow::Secs(0), false).await?;
+            eprintln!("Monitoring {}, ^C to stop", path.display());
+            monitor::run(dev, &mut remapper).await?;
+        }
+        Subcommand::Cheatsheet {
+            format,
+            profile,
+            args,
+        } => {
+            let profile = profile.or_else(active_profile);
+            // unlike the other offline uses, it's about the user's own config
+            let mut args = args;
+            let has_config =
+                (args.iter()).any(|arg| arg == "--config" || arg.starts_with("--config="));
+            if let Some(path) = config::default_path().filter(|path| !has_config && path.exists()) {
+                args.splice(0..0, ["--config".to_owned(), path.display().to_string()]);
+            }
+            let (remapper, _) = offline_remapper(&args, profile.as_deref())?;
+            let title = format!(
+                "joykbd, profile {}",
+                profile.as_deref().unwrap_or("default")
+            );
+            print!("{}", cheatsheet::render(&remapper, &title, format));
+        }
+        Subcommand::Soak {
+            rate,
+            hours,
+            report_every,
+            seed,
+            args,
+        } => {
+            if hours <= 0.0 || report_every <= 0.0 || rate == 0 {
+                anyhow::bail!("--rate, --hours and --report-every have to be more than 0");
+            }
+            let (remapper, options) = offline_remapper(&args, None)?;
+            let output = options.virtual_output(Box::new(NullSink));
+            let seed = seed.unwrap_or_else(|| {
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_nanos() as u64
+            });
+            let options = soak::Options {
+                rate,
+                duration: time::Duration::from_secs_f64(hours * 3600.0),
+                report_every: time::Duration::from_secs_f64(report_every),
+                seed,
+            };
+            soak::run(&options, &mut SoakPipeline { remapper, output }).await?;
+        }
+        Subcommand::Verify { fixtures, bless } => {
+            let mut failed = 0;
+            for path in &fixtures {
+                let fixture = verify::Fixture::load(path)
+                    .map_err(|e| anyhow::anyhow!("can't load {}: {}", path.display(), e))?;
+                let emitted = translate_fixture(&fixture)
+                    .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+                if bless {
+                    fixture.bless(&emitted)?;
+                    println!("{}: wrote {} events", path.display(), emitted.len());
+                } else if let Some(diff) = fixture.check(&emitted)? {
+                    println!("{}: FAILED, {}", path.display(), diff);
+                    failed += 1;
+                } else {
+                    println!("{}: ok", path.display());
+                }
+            }
+            if failed > 0 {
+                anyhow::bail!("{} of {} fixtures failed", failed, fixtures.len());
+            }
         }
     }
-
-    const CURVE_POWER: i32 = 5;
+    Ok(())
 }
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+/// Set up the per-event translation, like joykbd's with the options in `argv` (as on the command
+/// line) and the config file's `profile`'s remaps on top, for `verify`, `soak` and the like (see
+/// [`Options::remapper`]).
+fn offline_remapper(argv: &[String], profile: Option<&str>) -> anyhow::Result<(Remapper, Options)> {
+    let argv = std::iter::once("joykbd").chain(argv.iter().map(String::as_str));
+    let options = Options::try_parse_from(argv)?;
+    Ok((options.remapper(profile)?, options))
+}
 
-    let stick_constants = args.stick_constants();
-    let repeat_timeout = time::Duration::from_millis(args.repeat_timeout);
+/// Open the controller at `device`, and the stick whose calibration to write.
+fn open_for_calibration(
+    device: &Path,
+    stick: Option<StickSide>,
+) -> anyhow::Result<(HidrawJoyCon, StickSide)> {
+    let joycon = HidrawJoyCon::open(device)
+        .map_err(|e| anyhow::anyhow!("can't open {}: {}", device.display(), e))?;
+    let stick = stick
+        .or_else(|| joycon.name().and_then(partner::side))
+        .ok_or_else(|| anyhow::anyhow!("pass --stick to pick which stick"))?;
+    Ok((joycon, stick))
+}
 
-    let dev = if let Some(dev_path) = &args.device {
-        Device::open(dev_path)?
-    } else {
-        eprintln!("Searching for joy-con, please wait...");
-        let (_, dev) = evdev::enumerate()
-            .find(|(_, dev)| dev.name().map_or(false, |name| name.contains("Joy-Con")))
-            .ok_or_else(|| {
-                anyhow::anyhow!(
-                    "could not find a connected joy-con, please pass one on the command line"
-                )
-            })?;
-        eprintln!("Found joy-con!");
-        dev
+/// Where to back up a controller's user calibration for `stick`, by default in joykbd's state
+/// directory, named after the controller's serial so a pair's halves don't share one.
+fn calibration_backup(
+    joycon: &HidrawJoyCon,
+    stick: StickSide,
+    backup: Option<PathBuf>,
+) -> anyhow::Result<PathBuf> {
+    if let Some(backup) = backup {
+        return Ok(backup);
+    }
+    let dir = StateFile::default_path()
+        .and_then(|path| Some(path.parent()?.to_owned()))
+        .ok_or_else(|| anyhow::anyhow!("pass --backup to say where to back it up"))?;
+    let side = match stick {
+        StickSide::Left => "left",
+        StickSide::Right => "right",
     };
+    let serial = joycon.unique_name().unwrap_or("unknown").replace(':', "");
+    Ok(dir.join(format!("user-calibration-{}-{}.bin", serial, side)))
+}
 
-    let mut uinp = VirtualDeviceBuilder::new()?
-        .name("joykbd")
-        .with_relative_axes(&AttributeSet::from_iter([
-            RelativeAxisType::REL_X,
-            RelativeAxisType::REL_Y,
-        ]))?
-        .with_keys(&AttributeSet::from_iter([
-            Key::BTN_LEFT,
-            Key::BTN_RIGHT,
-            Key::BTN_MIDDLE,
-            Key::KEY_UP,
-            Key::KEY_RIGHT,
-            Key::KEY_DOWN,
-            Key::KEY_LEFT,
-        ]))?
-        .build()?;
-
-    let mut ev_stream = dev.into_event_stream()?;
-
-    let sleep_x = time::sleep(time::Duration::MAX);
-    let mut prev_x = 0;
-    let sleep_y = time::sleep(time::Duration::MAX);
-    let mut prev_y = 0;
-    tokio::pin!(sleep_x, sleep_y);
-
-    loop {
-        tokio::select! {
-            ev = ev_stream.next_event() => {
-                let ev = if let Some(ev) = map_event(ev?, &stick_constants) {
-                    ev
-                } else {
-                    continue
-                };
-                match ev.kind() {
-                    InputEventKind::RelAxis(RelativeAxisType::REL_X) => {
-                        sleep_x.as_mut().reset(time::Instant::now() + repeat_timeout);
-                        prev_x = ev.value();
-                    }
-                    InputEventKind::RelAxis(RelativeAxisType::REL_Y) => {
-                        sleep_y.as_mut().reset(time::Instant::now() + repeat_timeout);
-                        prev_y = ev.value();
-                    }
-                    _ => {}
-                }
-                uinp.emit(&[ev])?;
-            }
-            () = &mut sleep_x => {
-                uinp.emit(&[InputEvent::new(
-                    EventType::RELATIVE,
-                    RelativeAxisType::REL_X.0,
-                    prev_x,
-                )])?;
-                sleep_x.as_mut().reset(time::Instant::now() + repeat_timeout);
-            }
-            () = &mut sleep_y => {
-                uinp.emit(&[InputEvent::new(
-                    EventType::RELATIVE,
-                    RelativeAxisType::REL_Y.0,
-                    prev_y,
-                )])?;
-                sleep_y.as_mut().reset(time::Instant::now() + repeat_timeout);
-            }
+/// Write a user calibration moving `stick`'s center by `bias`, after backing up the one it
+/// replaces. An existing backup is kept, since it's the calibration from before joykbd's first.
+fn flash_user_calibration(
+    device: &Path,
+    stick: Option<StickSide>,
+    bias: (i32, i32),
+    backup: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let (mut joycon, stick) = open_for_calibration(device, stick)?;
+    let backup = calibration_backup(&joycon, stick, backup)?;
+    if backup.exists() {
+        println!("Keeping the earlier backup at {}", backup.display());
+    } else {
+        let previous = joycon
+            .user_calibration(stick)
+            .map_err(|e| anyhow::anyhow!("couldn't read the calibration: {}", e))?;
+        if let Some(dir) = backup.parent() {
+            std::fs::create_dir_all(dir)?;
         }
+        std::fs::write(&backup, previous)
+            .map_err(|e| anyhow::anyhow!("can't back up to {}: {}", backup.display(), e))?;
+        println!("Backed up the previous calibration to {}", backup.display());
+    }
+    joycon
+        .flash_user_calibration(stick, bias)
+        .map_err(|e| anyhow::anyhow!("couldn't write the calibration: {}", e))?;
+    println!("Wrote the user calibration to {}", device.display());
+    Ok(())
+}
+
+/// Put back the user calibration backed up by [`flash_user_calibration`].
+fn restore_user_calibration(
+    device: &Path,
+    stick: Option<StickSide>,
+    backup: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let (mut joycon, stick) = open_for_calibration(device, stick)?;
+    let backup = calibration_backup(&joycon, stick, backup)?;
+    let block = std::fs::read(&backup)
+        .map_err(|e| anyhow::anyhow!("can't read {}: {}", backup.display(), e))?;
+    joycon
+        .restore_user_calibration(stick, &block)
+        .map_err(|e| anyhow::anyhow!("couldn't write the calibration: {}", e))?;
+    println!(
+        "Restored the user calibration from {} to {}",
+        backup.display(),
+        device.display()
+    );
+    Ok(())
+}
+
+/// The profile the joykbd listening on the default control socket has active, if there's one.
+fn active_profile() -> Option<String> {
+    let reply = ipc::send(&ipc::default_path()?, "status --json")
+        .ok()?
+        .ok()?;
+    let status: serde_json::Value = serde_json::from_str(&reply).ok()?;
+    Some(status.get("profile")?.as_str()?.to_owned())
+}
+
+/// Translate a `verify` fixture's recording like joykbd would with its options, and emit it the
+/// same way, describing each emitted event.
+fn translate_fixture(fixture: &verify::Fixture) -> anyhow::Result<Vec<String>> {
+    let (mut remapper, options) = offline_remapper(&fixture.args, None)?;
+    let sink = RecordingSink::default();
+    let mut output = options.virtual_output(Box::new(sink.clone()));
+    for (kind, ev) in fixture.inputs()? {
+        remapper.feed(kind, ev, &mut output)?;
     }
+    // whatever --syn-batch is still holding back
+    output.flush()?;
+    Ok(sink.take().iter().map(verify::describe).collect())
+}
+
+/// What `soak` runs its input through: the translation, then the output's own handling of it
+/// (held keys, sticky keys, rotation, ...), into a sink that discards it.
+struct SoakPipeline {
+    remapper: Remapper,
+    output: VirtualOutput,
 }
 
-fn map_event(ev: InputEvent, stick_constants: &StickConstants) -> Option<InputEvent> {
-    match ev.kind() {
-        // ZL/ZR
-        InputEventKind::Key(Key::BTN_TR2) | InputEventKind::Key(Key::BTN_TL2) => Some(
-            InputEvent::new(EventType::KEY, Key::BTN_LEFT.code(), ev.value()),
-        ),
-        // L
-        InputEventKind::Key(Key::BTN_TR) | InputEventKind::Key(Key::BTN_TL) => Some(
-            InputEvent::new(EventType::KEY, Key::BTN_RIGHT.code(), ev.value()),
-        ),
-        // press R stick
-        InputEventKind::Key(Key::BTN_THUMBR) | InputEventKind::Key(Key::BTN_THUMBL) => Some(
-            InputEvent::new(EventType::KEY, Key::BTN_MIDDLE.code(), ev.value()),
-        ),
-        // A
-        InputEventKind::Key(Key::BTN_EAST) => Some(InputEvent::new(
-            EventType::KEY,
-            Key::KEY_RIGHT.code(),
-            ev.value(),
-        )),
-        // B
-        InputEventKind::Key(Key::BTN_SOUTH) => Some(InputEvent::new(
-            EventType::KEY,
-            Key::KEY_DOWN.code(),
-            ev.value(),
-        )),
-        // X
-        InputEventKind::Key(Key::BTN_NORTH) => Some(InputEvent::new(
-            EventType::KEY,
-            Key::KEY_UP.code(),
-            ev.value(),
-        )),
-        // Y
-        InputEventKind::Key(Key::BTN_WEST) => Some(InputEvent::new(
-            EventType::KEY,
-            Key::KEY_LEFT.code(),
-            ev.value(),
-        )),
-        InputEventKind::AbsAxis(AbsoluteAxisType::ABS_RX)
-        | InputEventKind::AbsAxis(AbsoluteAxisType::ABS_X) => Some(InputEvent::new(
-            EventType::RELATIVE,
-            RelativeAxisType::REL_X.0,
-            stick_constants.map_axis(Axis::X, ev.value()),
-        )),
-        InputEventKind::AbsAxis(AbsoluteAxisType::ABS_RY)
-        | InputEventKind::AbsAxis(AbsoluteAxisType::ABS_Y) => Some(InputEvent::new(
-            EventType::RELATIVE,
-            RelativeAxisType::REL_Y.0,
-            stick_constants.map_axis(Axis::Y, ev.value()),
-        )),
-        _ => None,
+impl soak::Pipeline for SoakPipeline {
+    fn feed(&mut self, ev: InputEvent) -> anyhow::Result<usize> {
+        let evs = self
+            .remapper
+            .feed(SourceKind::JoyCon, ev, &mut self.output)?;
+        Ok(evs.len())
+    }
+
+    fn check_idle(&self) -> Vec<String> {
+        let mut problems = vec![];
+        let held: Vec<_> = self.output.held_keys().collect();
+        if !held.is_empty() {
+            problems.push(format!("keys still held: {:?}", held));
+        }
+        if self.remapper.bindings.layer_active() && !self.remapper.bindings.layer_latched() {
+            problems.push("the layer is still active".to_owned());
+        }
+        problems
     }
 }
@@ -0,0 +1,164 @@
+//! Upgrading joykbd's files from older formats. Every file records the `version` of the format it
+//! was written in, and each change to the format bumps it and adds a step here that rewrites the
+//! previous version's contents, describing what it changed, so existing setups keep working. The
+//! config file (in any of its formats) and the state file are upgraded as they're loaded, and
+//! `joykbd migrate-config` upgrades them on disk.
+
+use crate::config::{self, Format};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+
+/// The format version joykbd currently writes.
+pub const CURRENT_VERSION: i64 = 1;
+
+/// A file's contents, whatever it's written in.
+pub type Table = Map<String, Value>;
+
+/// Upgrades a table from one version to the next, noting each change made.
+type Step = fn(&mut Table, &mut Vec<String>);
+
+/// `STEPS[n]` upgrades version `n` to `n + 1`.
+const STEPS: &[Step] = &[v0_to_v1];
+
+/// Files from before formats were versioned.
+fn v0_to_v1(_: &mut Table, changes: &mut Vec<String>) {
+    changes.push("added format version".to_owned());
+}
+
+/// Upgrade `table` to the current format, returning a description of every change made.
+pub fn migrate(table: &mut Table) -> Result<Vec<String>, String> {
+    let version = match table.get("version") {
+        Some(version) => version
+            .as_i64()
+            .ok_or_else(|| format!("invalid version {}", version))?,
+        None => 0,
+    };
+    if !(0..=CURRENT_VERSION).contains(&version) {
+        return Err(format!(
+            "unsupported version {} (this joykbd supports up to {})",
+            version, CURRENT_VERSION
+        ));
+    }
+    let mut changes = vec![];
+    for step in &STEPS[version as usize..] {
+        step(table, &mut changes);
+    }
+    table.insert("version".to_owned(), CURRENT_VERSION.into());
+    Ok(changes)
+}
+
+/// Upgrade the file at `path` in place, in the format its extension says it's in, keeping the
+/// original next to it with `.bak` added to its name if anything changed. A TOML file keeps its
+/// comments and layout, but a YAML one is written out anew, so its comments are only in the
+/// original.
+pub fn migrate_file(path: &Path) -> anyhow::Result<Vec<String>> {
+    let format = Format::of(path);
+    let s = fs::read_to_string(path)?;
+    let original: Table = config::parse(format, &s).map_err(anyhow::Error::msg)?;
+    let mut table = original.clone();
+    let changes = migrate(&mut table).map_err(anyhow::Error::msg)?;
+    if changes.is_empty() {
+        return Ok(changes);
+    }
+    let migrated = match format {
+        Format::Toml => {
+            let mut doc: toml_edit::Document = s.parse()?;
+            update(doc.as_table_mut(), &original, &table);
+            doc.to_string()
+        }
+        Format::Yaml => serde_yaml::to_string(&table)?,
+        Format::Json => serde_json::to_string_pretty(&table)? + "\n",
+    };
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    fs::copy(path, backup)?;
+    fs::write(path, migrated)?;
+    Ok(changes)
+}
+
+/// Bring the TOML `doc` from `old` to `new`, only touching what changed, so the rest keeps its
+/// comments and formatting.
+fn update(doc: &mut dyn toml_edit::TableLike, old: &Table, new: &Table) {
+    for key in old.keys().filter(|key| !new.contains_key(*key)) {
+        doc.remove(key);
+    }
+    for (key, value) in new {
+        let old = old.get(key);
+        if old == Some(value) {
+            continue;
+        }
+        let item = doc.get_mut(key).and_then(|item| item.as_table_like_mut());
+        match (item, old, value) {
+            (Some(item), Some(Value::Object(old)), Value::Object(new)) => update(item, old, new),
+            _ => match to_toml(value) {
+                Some(value) => {
+                    doc.insert(key, toml_edit::Item::Value(value));
+                }
+                None => {
+                    doc.remove(key);
+                }
+            },
+        }
+    }
+}
+
+/// `value` as TOML, if it can be (TOML has no null).
+fn to_toml(value: &Value) -> Option<toml_edit::Value> {
+    Some(match value {
+        Value::Null => return None,
+        Value::Bool(b) => (*b).into(),
+        Value::Number(n) => match n.as_i64() {
+            Some(n) => n.into(),
+            None => n.as_f64()?.into(),
+        },
+        Value::String(s) => s.as_str().into(),
+        Value::Array(values) => values
+            .iter()
+            .filter_map(to_toml)
+            .collect::<toml_edit::Array>()
+            .into(),
+        Value::Object(table) => table
+            .iter()
+            .filter_map(|(key, value)| Some((key.as_str(), to_toml(value)?)))
+            .collect::<toml_edit::InlineTable>()
+            .into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{migrate, Table, CURRENT_VERSION};
+    use serde_json::json;
+
+    #[test]
+    fn migrates() {
+        let cases = [
+            (json!({}), Ok(vec!["added format version"])),
+            (
+                json!({ "version": 0, "speed": 30 }),
+                Ok(vec!["added format version"]),
+            ),
+            (json!({ "version": CURRENT_VERSION }), Ok(vec![])),
+            (json!({ "version": CURRENT_VERSION + 1 }), Err(())),
+            (json!({ "version": -1 }), Err(())),
+            (json!({ "version": "1" }), Err(())),
+        ];
+        for (value, expected) in cases {
+            let mut table: Table = serde_json::from_value(value.clone()).unwrap();
+            let changes = migrate(&mut table);
+            let changes =
+                (changes.as_ref()).map(|changes| changes.iter().map(String::as_str).collect());
+            assert_eq!(changes.map_err(|_| ()), expected, "migrating {}", value);
+            if expected.is_ok() {
+                assert_eq!(table["version"], CURRENT_VERSION, "migrating {}", value);
+                // the rest of the contents is untouched
+                for (key, original) in value.as_object().unwrap() {
+                    if key != "version" {
+                        assert_eq!(&table[key], original, "migrating {}", value);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! `joykbd monitor`: a live view of the controller's events, each next to what joykbd's mapping
+//! turns it into, or why that's nothing (a button that isn't mapped, the stick within the
+//! deadzone, ...), to see exactly why a movement doesn't do what's expected.
+
+use evdev::{EventType, InputEvent, InputEventKind};
+use joykbd::remapper::Remapper;
+use joykbd::source::{InputSource, SourceEvent, SourceKind};
+use joykbd::stick::{Stage, StickSide};
+use tokio::sync::mpsc;
+
+/// How wide the column of raw events is.
+const RAW_WIDTH: usize = 32;
+
+/// Print each of `dev`'s events and what `remapper` maps it to, until it's disconnected.
+pub async fn run(dev: Box<dyn InputSource>, remapper: &mut Remapper) -> anyhow::Result<()> {
+    let (tx, mut rx) = mpsc::channel(64);
+    dev.spawn(SourceKind::JoyCon, tx)?;
+    println!("{:<width$} mapped", "raw", width = RAW_WIDTH);
+    while let Some(SourceEvent { event, .. }) = rx.recv().await {
+        let ev = event?;
+        if ev.event_type() == EventType::SYNCHRONIZATION {
+            continue;
+        }
+        let mapped: Vec<_> = remapper
+            .translate(SourceKind::JoyCon, ev)
+            .into_iter()
+            // the stick maps to motion along both axes, even when it's none
+            .filter(|ev| ev.event_type() != EventType::RELATIVE || ev.value() != 0)
+            .map(|ev| describe(&ev))
+            .collect();
+        let mapped = match (mapped.is_empty(), remapper.take_drift_threshold()) {
+            (true, Some(threshold)) => format!("(drift threshold now {})", threshold),
+            (true, None) => format!("(nothing: {})", why_nothing(remapper, &ev)),
+            (false, _) => mapped.join(", "),
+        };
+        println!("{:<width$} {}", describe(&ev), mapped, width = RAW_WIDTH);
+    }
+    Ok(())
+}
+
+fn describe(ev: &InputEvent) -> String {
+    match ev.kind() {
+        InputEventKind::Key(key) => {
+            let action = match ev.value() {
+                0 => "up",
+                1 => "down",
+                _ => "repeat",
+            };
+            format!("{:?} {}", key, action)
+        }
+        InputEventKind::AbsAxis(axis) => format!("{:?} {}", axis, ev.value()),
+        InputEventKind::RelAxis(axis) => format!("{:?} {:+}", axis, ev.value()),
+        kind => format!("{:?} {}", kind, ev.value()),
+    }
+}
+
+/// Why `ev` was mapped to nothing.
+fn why_nothing(remapper: &Remapper, ev: &InputEvent) -> String {
+    match ev.kind() {
+        InputEventKind::Key(key) if remapper.bindings.is_bound(key) => {
+            "used by its binding, e.g. as a layer key".to_owned()
+        }
+        InputEventKind::Key(_) => "not mapped".to_owned(),
+        InputEventKind::AbsAxis(axis) => {
+            let Some((side, axis)) = StickSide::of(axis) else {
+                return "not one of the sticks".to_owned();
+            };
+            let constants = &remapper.stick_constants;
+            match constants.stopped_by(side, axis) {
+                Some(Stage::Calibration) => "at the center, after the bias".to_owned(),
+                Some(Stage::Deadzone) => format!(
+                    "within the deadzone, --drift-threshold {}",
+                    constants.drift_threshold
+                ),
+                Some(Stage::Curve) => "too little deflection for the curve".to_owned(),
+                Some(Stage::Smoothing(_)) => "smoothed away".to_owned(),
+                Some(Stage::RateLimit(_)) => "held back by the rate limit".to_owned(),
+                None if ev.value() == 0 => "at the center".to_owned(),
+                None => "less than a pixel of motion".to_owned(),
+            }
+        }
+        _ => "not something joykbd maps".to_owned(),
+    }
+}
@@ -0,0 +1,25 @@
+//! Re-curving the motion of mice/trackballs used as input sources.
+
+/// Scales relative motion, carrying the fractional remainder over to the next event so slowing a
+/// device down doesn't just swallow small movements.
+pub struct MouseCurve {
+    speed: f64,
+    remainder: [f64; 2],
+}
+
+impl MouseCurve {
+    pub fn new(speed: f64) -> Self {
+        MouseCurve {
+            speed,
+            remainder: [0.0; 2],
+        }
+    }
+
+    /// Scale a delta on the X (`axis == 0`) or Y (`axis == 1`) axis.
+    pub fn map(&mut self, axis: usize, delta: i32) -> i32 {
+        let exact = f64::from(delta) * self.speed + self.remainder[axis];
+        let whole = exact.trunc();
+        self.remainder[axis] = exact - whole;
+        whole as i32
+    }
+}
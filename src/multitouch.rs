@@ -0,0 +1,65 @@
+//! Tracking the individual contacts of a multitouch device, via the kernel's slot-based (type B)
+//! protocol.
+
+use evdev::{AbsoluteAxisType, Device, InputEvent, InputEventKind};
+use std::io;
+
+#[derive(Clone, Copy, Default)]
+pub struct Contact {
+    /// The tracking ID of the finger in this slot, or `None` if it's not in use.
+    pub id: Option<i32>,
+    pub x: i32,
+    pub y: i32,
+}
+
+pub struct Contacts {
+    slot: usize,
+    slots: Vec<Contact>,
+}
+
+impl Contacts {
+    pub fn new(dev: &Device) -> io::Result<Self> {
+        let abs = dev.get_abs_state()?;
+        let num_slots = abs[AbsoluteAxisType::ABS_MT_SLOT.0 as usize].maximum + 1;
+        let mut slots = vec![Contact::default(); num_slots.max(1) as usize];
+        let slot = abs[AbsoluteAxisType::ABS_MT_SLOT.0 as usize].value.max(0) as usize;
+        // contacts that are already down when we start
+        if let Some(contact) = slots.get_mut(slot) {
+            let id = abs[AbsoluteAxisType::ABS_MT_TRACKING_ID.0 as usize].value;
+            contact.id = (id >= 0).then_some(id);
+        }
+        Ok(Contacts { slot, slots })
+    }
+
+    pub fn handle(&mut self, ev: &InputEvent) {
+        let axis = match ev.kind() {
+            InputEventKind::AbsAxis(axis) => axis,
+            _ => return,
+        };
+        if axis == AbsoluteAxisType::ABS_MT_SLOT {
+            self.slot = ev.value().max(0) as usize;
+            return;
+        }
+        let contact = match self.slots.get_mut(self.slot) {
+            Some(contact) => contact,
+            None => return,
+        };
+        match axis {
+            AbsoluteAxisType::ABS_MT_TRACKING_ID => {
+                contact.id = (ev.value() >= 0).then(|| ev.value())
+            }
+            AbsoluteAxisType::ABS_MT_POSITION_X => contact.x = ev.value(),
+            AbsoluteAxisType::ABS_MT_POSITION_Y => contact.y = ev.value(),
+            _ => {}
+        }
+    }
+
+    /// The contacts that are currently touching.
+    pub fn active(&self) -> impl Iterator<Item = &Contact> {
+        self.slots.iter().filter(|c| c.id.is_some())
+    }
+
+    pub fn count(&self) -> usize {
+        self.active().count()
+    }
+}
@@ -0,0 +1,141 @@
+//! Option sets: named sets of extra command line options, kept in
+//! `$XDG_CONFIG_HOME/joykbd/option-sets/<name>`, one option per line (e.g.
+//! `--remap BTN_EAST=KEY_ENTER`). Which one is used at startup can be picked with `--option-set`,
+//! or by rules in `$XDG_CONFIG_HOME/joykbd/option-set-rules.toml`, matching on the time of day,
+//! the hostname or an environment variable:
+//!
+//! ```toml
+//! [[rule]]
+//! option-set = "htpc"
+//! hostname = "livingroom"
+//!
+//! [[rule]]
+//! option-set = "night"
+//! hours = "22-7"
+//! ```
+//!
+//! The first rule whose conditions all match wins. Unlike the config file's profiles, which are
+//! switched between at runtime, an option set is only picked once, at startup.
+
+use serde::Deserialize;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize)]
+struct Rules {
+    #[serde(default)]
+    rule: Vec<Rule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct Rule {
+    option_set: String,
+    /// Matches the machine's hostname exactly.
+    hostname: Option<String>,
+    /// `VAR` matches if the environment variable is set (and not empty), `VAR=value` if it has
+    /// that value.
+    env: Option<String>,
+    /// A range of hours in local time, `START-END`, e.g. `9-17`, or `22-7` wrapping around
+    /// midnight. The end hour is exclusive.
+    hours: Option<String>,
+}
+
+impl Rule {
+    fn matches(&self) -> Result<bool, String> {
+        if let Some(hostname) = &self.hostname {
+            if hostname_now().as_deref() != Some(hostname) {
+                return Ok(false);
+            }
+        }
+        if let Some(env) = &self.env {
+            let matches = match env.split_once('=') {
+                Some((var, value)) => std::env::var_os(var).is_some_and(|v| v == value),
+                None => std::env::var_os(env).is_some_and(|v| !v.is_empty()),
+            };
+            if !matches {
+                return Ok(false);
+            }
+        }
+        if let Some(hours) = &self.hours {
+            let invalid = || format!("invalid hours {:?}, expected e.g. 9-17", hours);
+            let (start, end) = hours.split_once('-').ok_or_else(invalid)?;
+            let start: u32 = start.trim().parse().map_err(|_| invalid())?;
+            let end: u32 = end.trim().parse().map_err(|_| invalid())?;
+            let hour = local_hour().ok_or("can't determine the local time")?;
+            let within = if start <= end {
+                (start..end).contains(&hour)
+            } else {
+                hour >= start || hour < end
+            };
+            if !within {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("joykbd"))
+}
+
+/// The default location of the rules, `$XDG_CONFIG_HOME/joykbd/option-set-rules.toml`.
+pub fn default_rules_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("option-set-rules.toml"))
+}
+
+/// The option set the rules at `path` pick, if the file exists and any of them match.
+pub fn select(path: &Path) -> anyhow::Result<Option<String>> {
+    let rules: Rules = match fs::read_to_string(path) {
+        Ok(s) => toml::from_str(&s)?,
+        Err(_) => return Ok(None),
+    };
+    for rule in rules.rule {
+        if rule.matches().map_err(anyhow::Error::msg)? {
+            return Ok(Some(rule.option_set));
+        }
+    }
+    Ok(None)
+}
+
+/// The command line options the option set `name` adds.
+pub fn load(name: &str) -> anyhow::Result<Vec<OsString>> {
+    let dir = config_dir().ok_or_else(|| anyhow::anyhow!("can't find the config directory"))?;
+    let path = dir.join("option-sets").join(name);
+    let s = fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("can't read option set {}: {}", path.display(), e))?;
+    let mut args = vec![];
+    for line in s.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // the value can have spaces in it, e.g. `--remap BTN_EAST=nudge-right 5`
+        match line.split_once(char::is_whitespace) {
+            Some((flag, value)) => args.extend([flag.into(), value.trim().into()]),
+            None => args.push(line.into()),
+        }
+    }
+    Ok(args)
+}
+
+fn hostname_now() -> Option<String> {
+    let hostname = fs::read_to_string("/proc/sys/kernel/hostname").ok()?;
+    Some(hostname.trim().to_owned())
+}
+
+fn local_hour() -> Option<u32> {
+    // SAFETY: a null pointer is allowed for time(), and localtime_r only writes to `tm`
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return None;
+        }
+        u32::try_from(tm.tm_hour).ok()
+    }
+}
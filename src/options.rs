@@ -0,0 +1,691 @@
+//! The options joykbd runs with, as on its command line, and setting up the translation from
+//! them (along with the config file).
+
+use crate::bindings::{self, Bindings, Remap};
+use crate::config::{self, Config, StickConfig};
+use crate::conflict::ConflictPolicy;
+use crate::cursor::{Rect, Size};
+use crate::device::WaitWindow;
+use crate::edges::EdgeAction;
+use crate::feedback::Sounds;
+use crate::gamepad::StickTuning;
+use crate::mouse::MouseCurve;
+use crate::nfc::TagTrigger;
+use crate::option_set;
+use crate::output::{OutputSink, SinkSpec, VirtualOutput};
+use crate::preset::{self, PointerButtons, Preset};
+use crate::remapper::Remapper;
+use crate::rotation::Rotation;
+use crate::scroll::StickScroll;
+use crate::source::{self, Backend};
+use crate::speed::Speed;
+use crate::stick::{Axis, Curve, DeadzoneShape, Repeat, StickConstants, StickSide};
+use clap::Parser;
+use evdev::Key;
+use regex::Regex;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// joykbd's options, as on its command line: which controller to use, how it's mapped, and where
+/// the result goes. Parse them from arguments with [`clap::Parser`], e.g.
+/// `Options::try_parse_from(["joykbd", "--speed", "30"])`, and run with them with
+/// [`Remapper::run`].
+#[derive(Parser)]
+#[clap(name = "joykbd", args_override_self = true, about = None, long_about = None)]
+pub struct Options {
+    /// The path to the evdev device file representing the joy-con you want to use. By default,
+    /// joykbd searches for the first device that has "Joy-Con" in it's name, or is one of the
+    /// other controllers it has a --preset for (or, with --match-name, whose name matches).
+    pub(crate) device: Option<PathBuf>,
+    /// Add the options from this option set, $XDG_CONFIG_HOME/joykbd/option-sets/NAME. Options
+    /// given on the command line take precedence. By default, the option set is picked by the
+    /// rules in --option-set-rules, if any match. (The config file's profiles are something else,
+    /// switched between at runtime.)
+    #[clap(long, value_name = "NAME")]
+    pub(crate) option_set: Option<String>,
+    /// Rules for picking the option set at startup, based on the time of day, hostname or an
+    /// environment variable. Defaults to $XDG_CONFIG_HOME/joykbd/option-set-rules.toml.
+    #[clap(long, value_name = "PATH")]
+    pub(crate) option_set_rules: Option<PathBuf>,
+    /// How many seconds to keep looking for the device if it isn't there at startup, e.g. because
+    /// bluetooth hasn't reconnected it yet. Pass "forever" to never give up.
+    #[clap(long, value_name = "SECS", default_value = "0")]
+    pub(crate) wait_for_device: WaitWindow,
+    /// Wait for the controller to connect however long it takes, like --wait-for-device forever,
+    /// e.g. to leave joykbd running in the background. It's picked up as soon as its device node
+    /// appears.
+    #[clap(long, conflicts_with = "wait-for-device")]
+    pub(crate) wait: bool,
+    /// Keep running through any error reading the controller, not just it disconnecting, and
+    /// resume with whichever joy-con shows up next if it isn't the same one. The virtual device
+    /// stays the whole time. Best combined with --wait-for-device forever.
+    #[clap(long)]
+    pub(crate) watch: bool,
+    /// If several controllers are connected, pick the one to use by pressing a button on it,
+    /// rather than using whichever is found first.
+    #[clap(long)]
+    pub(crate) claim: bool,
+    /// The cursor speed; how fast it'll move when the stick is held all the way to one direction.
+    #[clap(long, default_value_t = 20.0)]
+    pub(crate) speed: f64,
+    /// The cursor's horizontal speed, instead of --speed.
+    #[clap(long, value_name = "SPEED")]
+    pub(crate) speed_x: Option<f64>,
+    /// The cursor's vertical speed, instead of --speed, e.g. to slow it down on a widescreen
+    /// monitor.
+    #[clap(long, value_name = "SPEED")]
+    pub(crate) speed_y: Option<f64>,
+    /// How the stick's deflection maps to cursor speed: linear, quadratic, cubic, quintic, or
+    /// an exponent to raise the deflection to. Steeper curves give finer control near the
+    /// center. The config file can also give a table of points. [default: quintic]
+    #[clap(long)]
+    pub(crate) curve: Option<Curve>,
+    /// What to multiply the cursor speed by while a `sprint` binding is held.
+    #[clap(long, default_value_t = 3.0)]
+    pub(crate) sprint_speed: f64,
+    /// What to multiply the cursor speed by while a `precision` binding is held.
+    #[clap(long, default_value_t = 0.25)]
+    pub(crate) precision_speed: f64,
+    /// Speed the cursor up after successive quick flicks of the stick in the same direction, by
+    /// up to this factor, so long distances can be covered without raising --speed. It wears off
+    /// once the stick has been left alone for a moment.
+    #[clap(long, value_name = "MAX")]
+    pub(crate) flick_acceleration: Option<f64>,
+    /// Smooth out the stick's jitter by averaging its readings exponentially, each new one
+    /// counting for this fraction of the result: lower is smoother, but slower to respond, and 1
+    /// doesn't smooth at all. The average keeps catching up with the stick between readings, so
+    /// the cursor eases in and out of motion.
+    #[clap(long, value_name = "ALPHA", parse(try_from_str = parse_smoothing))]
+    pub(crate) smoothing: Option<f64>,
+    /// The repeat timeout for the pseudo-mouse, in milliseconds
+    #[clap(long, default_value_t = 16)]
+    pub(crate) repeat_timeout: u64,
+    /// How the cursor's motion is repeated until the stick's next reading: `live` works it out
+    /// again from where the stick last read, so letting go of it quickly doesn't leave the
+    /// cursor coasting on a stale speed, and `replay` repeats the last motion as it was (though
+    /// --smoothing still catches up).
+    #[clap(long, arg_enum, default_value = "live")]
+    pub(crate) repeat: Repeat,
+    /// Time repeats from when the stick's event happened, rather than from when joykbd got
+    /// around to handling it, so delays (e.g. from a busy system) don't slow the cursor down.
+    #[clap(long)]
+    pub(crate) repeat_latency_compensation: bool,
+    /// The minimum value that the magnitude of the stick reading has to be so it's not ignored as
+    /// joy-con drift. Defaults to the value last saved with `drift-threshold save`, or the config
+    /// file's, or 2000.
+    #[clap(long)]
+    pub(crate) drift_threshold: Option<u32>,
+    /// How far past the drift threshold the stick has to be pushed to start moving the cursor,
+    /// and how far below it it has to fall back to stop, so a stick hovering right at the
+    /// threshold doesn't make the cursor stutter.
+    #[clap(long, value_name = "N", default_value_t = 0)]
+    pub(crate) drift_hysteresis: u32,
+    /// Keep following where the stick rests as its drift changes over a long session (e.g. as
+    /// the controller warms up), and treat that as its center.
+    #[clap(long)]
+    pub(crate) drift_tracking: bool,
+    /// With --drift-tracking, learn how the stick's drift depends on the controller's
+    /// temperature (read from its IMU every 10 seconds or so), so the center follows it straight
+    /// away as the controller warms up. Needs the hidraw backend.
+    #[clap(long, requires = "drift-tracking")]
+    pub(crate) drift_temperature: bool,
+    /// Whether the drift threshold applies to the stick's distance from the center (radial), or
+    /// to each axis on its own (axial), which makes diagonals feel square and sticky near the
+    /// axes.
+    #[clap(long, arg_enum, default_value = "radial")]
+    pub(crate) deadzone_shape: DeadzoneShape,
+    /// A bias to add to the X axis of the stick before further processing. Defaults to the
+    /// config file's, as measured by `joykbd calibrate`, or 0.
+    #[clap(long, allow_hyphen_values = true)]
+    pub(crate) x_bias: Option<i32>,
+    /// A bias to add to the Y axis of the stick before further processing. Defaults to the
+    /// config file's, or 0.
+    #[clap(long, allow_hyphen_values = true)]
+    pub(crate) y_bias: Option<i32>,
+    /// Suspend translation while another process (e.g. a game) has grabbed the joy-con, so it
+    /// only sees the controller directly. The grab is noticed once the controller is used, as
+    /// its input stops arriving, and never while joykbd has grabbed it itself.
+    #[clap(long)]
+    pub(crate) passthrough_on_grab: bool,
+    /// Suspend translation while a window with this class is focused (X11 only; requires
+    /// xdotool). Can be passed multiple times.
+    #[clap(long = "passthrough-window", value_name = "CLASS")]
+    pub(crate) passthrough_windows: Vec<String>,
+    /// Which user each controller belongs to, by bluetooth address (e.g.
+    /// `"98:b6:e9:12:34:56" = "alice"`), for a system service on a shared machine. A claimed
+    /// controller's output goes to the seat its owner's session is the active one on, and is
+    /// suspended while it isn't that on any (or logind can't be asked).
+    #[clap(long, value_name = "PATH")]
+    pub(crate) owners: Option<PathBuf>,
+    /// How often to check whether to suspend translation, in milliseconds
+    #[clap(long, default_value_t = 1000)]
+    pub(crate) passthrough_poll: u64,
+    /// What to do if the joy-con is already grabbed or in use by another remapper.
+    #[clap(long, arg_enum, default_value = "warn")]
+    pub(crate) on_conflict: ConflictPolicy,
+    /// Grab the joy-con exclusively, whether or not anything else is using it, so games and
+    /// Steam don't act on its buttons as well. The grab is let go of when joykbd exits or the
+    /// joy-con has an error, and taken again when it reconnects.
+    #[clap(long, conflicts_with = "passthrough-on-grab")]
+    pub(crate) grab: bool,
+    /// When grabbing a device (--keyboard and the like, or --on-conflict take-over), wait up to
+    /// this many seconds for the keys held down on it to be released first, so they don't stay
+    /// stuck down for other programs.
+    #[clap(long, value_name = "SECS", default_value_t = 5.0)]
+    pub(crate) grab_grace: f64,
+    /// When the joy-con is attached (at startup or reconnecting), press what's mapped to the
+    /// buttons already held down on it, instead of waiting for them to be pressed again. Where
+    /// the sticks are is always picked up.
+    #[clap(long)]
+    pub(crate) sync_held: bool,
+    /// A keyboard to use as an additional input source. Its keys are grabbed and go through the
+    /// same remapping as the joy-con's buttons; keys without a remap pass through unchanged. Can
+    /// be passed multiple times.
+    #[clap(long = "keyboard", value_name = "PATH")]
+    pub(crate) keyboards: Vec<PathBuf>,
+    /// Remap a key or button, by evdev name, e.g. `KEY_CAPSLOCK=KEY_ESC` or
+    /// `BTN_EAST=KEY_ENTER`. Takes precedence over the default joy-con mapping. Instead of a key,
+    /// TO can be up to 4 keys joined by `+` to press together, e.g. `KEY_LEFTCTRL+KEY_C`,
+    /// `nudge-left`, `nudge-right`, `nudge-up` or `nudge-down`, optionally followed by a distance
+    /// in pixels (default 1), e.g. `"BTN_DPAD_LEFT=nudge-left 5"`, `page-up`,
+    /// `page-down`, `home` or `end`, `drift-threshold +[N]|-[N]|N|save` to adjust
+    /// --drift-threshold live, `swap-clicks` to swap the left and right mouse buttons (remembered
+    /// across restarts), `sprint` or `precision` to speed up or slow down the stick while held
+    /// (see --sprint-speed and --precision-speed), `latch KEY` to hold KEY down until it's
+    /// pressed again, `turbo KEY [MS]` to press and release KEY every MS milliseconds (default
+    /// 100) while held, `mod-tap HOLD TAP` to hold HOLD if another key is pressed while it's
+    /// held and tap TAP otherwise, `macro STEP...` to tap up to 8 keys or chords in order, or
+    /// `power-off` to disconnect the controller. Prefix it with `NAME:` to
+    /// name it for the `enable`/`disable` commands, instead of by its FROM key. Can be passed
+    /// multiple times.
+    #[clap(long = "remap", value_name = "[NAME:]FROM=TO")]
+    pub(crate) remaps: Vec<Remap>,
+    /// A file mapping the controller's buttons to keys or actions, by their names (e.g.
+    /// `A = "KEY_ENTER"` under `[buttons]`), in TOML, or YAML or JSON by its extension. Defaults
+    /// to $XDG_CONFIG_HOME/joykbd/config.toml (or .yaml, .yml or .json), if it exists. --remap
+    /// takes precedence over it.
+    #[clap(long, value_name = "PATH")]
+    pub(crate) config: Option<PathBuf>,
+    /// A mouse or trackball to use as an additional input source. Its buttons go through the same
+    /// remapping as the joy-con's, and its motion is scaled by --mouse-speed. Can be passed
+    /// multiple times.
+    #[clap(long = "mouse", value_name = "PATH")]
+    pub(crate) mice: Vec<PathBuf>,
+    /// A factor to scale the motion of --mouse sources by, e.g. 0.5 to slow down a too-sensitive
+    /// trackball.
+    #[clap(long, default_value_t = 1.0)]
+    pub(crate) mouse_speed: f64,
+    /// A key or button, from any source, that shifts to the layer remaps (--layer-remap) while
+    /// it's held, e.g. a foot pedal. The key itself isn't emitted. Can be passed multiple times.
+    #[clap(long = "layer-key", value_name = "KEY", parse(try_from_str = parse_key_arg))]
+    pub(crate) layer_keys: Vec<Key>,
+    /// Like --remap, but only active while a --layer-key is held. Can be passed multiple times.
+    #[clap(long = "layer-remap", value_name = "[NAME:]FROM=TO")]
+    pub(crate) layer_remaps: Vec<Remap>,
+    /// A comma-separated group of remaps, by name (see --remap), of which only one can be active
+    /// at a time: activating one releases (or unlatches) the others, e.g. for switching between
+    /// modes with `latch` remaps. Can be passed multiple times.
+    #[clap(long = "group", value_name = "NAME,NAME...")]
+    pub(crate) groups: Vec<String>,
+    /// A key or button that latches the layer remaps on or off each time it's pressed. Can be
+    /// passed multiple times.
+    #[clap(long = "layer-toggle", value_name = "KEY", parse(try_from_str = parse_key_arg))]
+    pub(crate) layer_toggle_keys: Vec<Key>,
+    /// A button that switches between mouse mode and keyboard mode, where the sticks press the
+    /// arrow keys and A and B are Enter and Escape (or whatever the config file's
+    /// `[keyboard-mode]` says), e.g. HOME for navigating a TV's menus.
+    #[clap(long, value_name = "BUTTON", parse(try_from_str = parse_button_arg))]
+    pub(crate) mode_key: Option<Key>,
+    /// A button that turns text entry on or off, where the stick picks characters laid out
+    /// around it, A types the one picked, B deletes, X is space and Y flips to the next page of
+    /// characters (the config file's `[text-entry]` pages, if it has any).
+    #[clap(long, value_name = "BUTTON", parse(try_from_str = parse_button_arg))]
+    pub(crate) text_entry_key: Option<Key>,
+    /// Buttons that, pressed together, switch to the next of the config file's profiles, e.g.
+    /// MINUS+PLUS.
+    #[clap(long, value_name = "BUTTON+BUTTON...")]
+    pub(crate) profile_chord: Option<String>,
+    /// What tapping an NFC tag (e.g. an amiibo) on the right Joy-Con or a Pro Controller does,
+    /// by the tag's UID: `profile NAME` switches to one of the config file's profiles, and
+    /// `run COMMAND` runs a shell command, e.g. `04:a2:3b:12:c4:5e:80=profile media`. Tapped
+    /// tags' UIDs are printed, to find them. Needs the hidraw backend. Can be passed multiple
+    /// times.
+    #[clap(long = "nfc-tag", value_name = "UID=ACTION")]
+    pub(crate) nfc_tags: Vec<TagTrigger>,
+    /// Treat something coming near the right Joy-Con's IR camera (e.g. a hand reaching for it)
+    /// as activity: it wakes from --idle-timeout, which doesn't run out while it stays near.
+    /// The value is how bright (0-255) its reflection has to be, e.g. 40. Needs the hidraw
+    /// backend, and can't be used along with --nfc-tag.
+    #[clap(long, value_name = "THRESHOLD", conflicts_with = "nfc-tags")]
+    pub(crate) ir_presence: Option<u8>,
+    /// Also switch to this one of the config file's profiles when something comes near the IR
+    /// camera, e.g. an HTPC profile.
+    #[clap(long, value_name = "PROFILE", requires = "ir-presence")]
+    pub(crate) ir_presence_profile: Option<String>,
+    /// Show which of the config file's profiles is active on the Joy-Con's player LEDs.
+    #[clap(long, requires = "profile-chord")]
+    pub(crate) profile_leds: bool,
+    /// A button that left-clicks, instead of the preset's (ZL/ZR on joy-cons). Can be passed
+    /// multiple times.
+    #[clap(long = "left-click", value_name = "KEY", parse(try_from_str = parse_key_arg))]
+    pub(crate) left_click: Vec<Key>,
+    /// A button that right-clicks, instead of the preset's (L/R on joy-cons). Can be passed
+    /// multiple times.
+    #[clap(long = "right-click", value_name = "KEY", parse(try_from_str = parse_key_arg))]
+    pub(crate) right_click: Vec<Key>,
+    /// A button that middle-clicks, instead of the preset's (the stick click on joy-cons). Can be
+    /// passed multiple times.
+    #[clap(long = "middle-click", value_name = "KEY", parse(try_from_str = parse_key_arg))]
+    pub(crate) middle_click: Vec<Key>,
+    /// Swap the left and right mouse buttons.
+    #[clap(long)]
+    pub(crate) left_handed: bool,
+    /// Scroll with the stick while it's clicked in and held, instead of moving the cursor.
+    /// Clicking it without moving it still middle-clicks.
+    #[clap(long)]
+    pub(crate) stick_click_scroll: bool,
+    /// Use this stick (left or right) as arrow keys instead of for the cursor, e.g. with both
+    /// Joy-Cons, or the combined device joycond makes of them, so the other one moves the cursor.
+    #[clap(long, arg_enum, value_name = "STICK")]
+    pub(crate) arrow_stick: Option<StickSide>,
+    /// Also move the cursor by turning the joy-con, with its gyroscope.
+    #[clap(long)]
+    pub(crate) gyro: bool,
+    /// How far the cursor moves, in pixels, per degree the joy-con is turned with --gyro.
+    #[clap(long, value_name = "PIXELS", default_value_t = 10.0)]
+    pub(crate) gyro_sensitivity: f64,
+    /// Invert the horizontal direction of --gyro.
+    #[clap(long)]
+    pub(crate) gyro_invert_x: bool,
+    /// Invert the vertical direction of --gyro.
+    #[clap(long)]
+    pub(crate) gyro_invert_y: bool,
+    /// Only move the cursor with --gyro while this button is held, e.g. ZL. The button still does
+    /// whatever it's mapped to.
+    #[clap(long, value_name = "BUTTON", parse(try_from_str = parse_button_arg))]
+    pub(crate) gyro_hold: Option<Key>,
+    /// Use this stick (left or right) to scroll instead of for the cursor, e.g. with both
+    /// Joy-Cons, or the combined device joycond makes of them. The further it's pushed, the
+    /// faster it scrolls.
+    #[clap(long, arg_enum, value_name = "STICK")]
+    pub(crate) scroll_stick: Option<StickSide>,
+    /// A factor for how fast a scrolling stick (--scroll-stick, or the other Joy-Con of a pair)
+    /// scrolls.
+    #[clap(long, default_value_t = 1.0)]
+    pub(crate) scroll_speed: f64,
+    /// How often a scrolling stick scrolls again while held, in milliseconds. Defaults to
+    /// --repeat-timeout.
+    #[clap(long, value_name = "MS")]
+    pub(crate) scroll_repeat_timeout: Option<u64>,
+    /// While scrolling with --stick-click-scroll, pushing the stick most of the way up or down
+    /// pages up or down (and left or right goes to the start or end), rather than scrolling by
+    /// lines.
+    #[clap(long, requires = "stick-click-scroll")]
+    pub(crate) scroll_pages: bool,
+    /// The size of the screen, e.g. 1920x1080. With it, joykbd tracks where the cursor is and
+    /// positions it absolutely, rather than moving it relatively.
+    #[clap(long, value_name = "WxH")]
+    pub(crate) screen: Option<Size>,
+    /// Keep the cursor within this region of the --screen, e.g. a specific application's window,
+    /// given as WIDTHxHEIGHT+X+Y.
+    #[clap(long, value_name = "WxH+X+Y", requires = "screen")]
+    pub(crate) confine: Option<Rect>,
+    /// Fire an action when the cursor is pushed against an edge or into a corner of the
+    /// --screen (or --confine region) and kept there for --edge-dwell, e.g.
+    /// `top-left=KEY_LEFTMETA`. EDGE is top, bottom, left, right, top-left, top-right,
+    /// bottom-left or bottom-right; ACTION is anything --remap accepts, with keys being tapped.
+    /// Can be passed multiple times.
+    #[clap(long = "edge-action", value_name = "EDGE=ACTION", requires = "screen")]
+    pub(crate) edge_actions: Vec<EdgeAction>,
+    /// How long the cursor has to stay at an edge before its action fires, in milliseconds.
+    #[clap(long, default_value_t = 300)]
+    pub(crate) edge_dwell: u64,
+    /// After this many minutes without any input, run --idle-command and switch to
+    /// --idle-profile. A config file profile can have its own, under `[profiles.NAME.idle]`.
+    #[clap(long, value_name = "MINUTES")]
+    pub(crate) idle_timeout: Option<u64>,
+    /// A shell command to run when going idle, e.g. to blank the screen.
+    #[clap(long, value_name = "COMMAND", requires = "idle-timeout")]
+    pub(crate) idle_command: Option<String>,
+    /// A shell command to run on the first input after going idle.
+    #[clap(long, value_name = "COMMAND", requires = "idle-timeout")]
+    pub(crate) idle_resume_command: Option<String>,
+    /// Switch to this one of the config file's profiles when going idle, and back on the first
+    /// input after, e.g. one that ignores the stick.
+    #[clap(long, value_name = "PROFILE", requires = "idle-timeout")]
+    pub(crate) idle_profile: Option<String>,
+    /// Rotate the cursor's motion to match a display rotated clockwise by this many degrees: 0,
+    /// 90, 180 or 270. Can be changed at runtime with the `rotate` command.
+    #[clap(long, value_name = "DEGREES", default_value = "0")]
+    pub(crate) rotate: Rotation,
+    /// Keep modifier keys (ctrl, shift, alt and super) held after they're let go of, until the
+    /// next key is pressed and released, for shortcuts without holding two buttons at once.
+    /// Pressing a held modifier again lets go of it.
+    #[clap(long)]
+    pub(crate) sticky_keys: bool,
+    /// Release any key or button that's been held down for longer than this many seconds, in
+    /// case its release got lost (e.g. over a flaky bluetooth connection).
+    #[clap(long, value_name = "SECONDS")]
+    pub(crate) max_hold: Option<u64>,
+    /// Collect the output events generated within this many milliseconds (e.g. 1-4) and emit them
+    /// together, in a single report, so chords don't reach applications half done. A key released
+    /// after being pressed among them still starts a new report, so a tap isn't lost.
+    #[clap(long, value_name = "MS")]
+    pub(crate) syn_batch: Option<u64>,
+    /// Where to save runtime state (like a latched layer) so it survives restarts. Defaults to
+    /// $XDG_STATE_HOME/joykbd/state.toml.
+    #[clap(long)]
+    pub(crate) state_file: Option<PathBuf>,
+    /// Where to listen for commands from other programs. Defaults to
+    /// $XDG_RUNTIME_DIR/joykbd.sock.
+    #[clap(long)]
+    pub(crate) control_socket: Option<PathBuf>,
+    /// Mirror the joy-con onto a virtual gamepad instead of translating it into mouse/keyboard
+    /// input, e.g. for games that don't support it directly. Remaps still apply to its buttons,
+    /// and rumble from games is forwarded back to the joy-con.
+    #[clap(long)]
+    pub(crate) gamepad: bool,
+    /// With --gamepad, the fraction (0-1) of the sticks' travel from the center, in any
+    /// direction, that games see as centered, for games whose own deadzone is too small for the
+    /// joy-con's drift. Best kept in a config file profile for the game (`[profiles.NAME.gamepad]`,
+    /// with the game's window class in its `windows`).
+    #[clap(
+        long,
+        value_name = "FRACTION",
+        default_value_t = 0.0,
+        requires = "gamepad"
+    )]
+    pub(crate) gamepad_deadzone: f64,
+    /// With --gamepad, the exponent of the sticks' response curve: above 1 gives finer control
+    /// near the center, below 1 makes small movements count for more.
+    #[clap(
+        long,
+        value_name = "EXPONENT",
+        default_value_t = 1.0,
+        requires = "gamepad"
+    )]
+    pub(crate) gamepad_curve: f64,
+    /// With --gamepad, present it as an Xbox 360 controller (by its IDs and button/axis layout),
+    /// for games that only recognize XInput-style pads. ZL/ZR become the triggers and the D-pad
+    /// a hat.
+    #[clap(long, requires = "gamepad")]
+    pub(crate) gamepad_xbox: bool,
+    /// Which controller's default button mapping to use: joycon, nes, snes, n64 or wiimote. By default,
+    /// it's picked based on the device's name.
+    #[clap(long, parse(try_from_str = parse_preset))]
+    pub(crate) preset: Option<&'static Preset>,
+    /// Search for a device whose name matches this regular expression, instead of a joy-con or
+    /// another controller there's a --preset for, e.g. "Xbox|DualShock|8BitDo". It uses the
+    /// joycon preset unless --preset says otherwise, and a controller that reports its buttons as
+    /// other codes than a joy-con's can be given a [layout] in the config file.
+    #[clap(long, value_name = "REGEX", parse(try_from_str = parse_regex))]
+    pub(crate) match_name: Option<Regex>,
+    /// The unique name (bluetooth address) of the other Joy-Con of the pair, so that only it joins
+    /// in as the partner. By default, the first Joy-Con of the other side to connect does, and
+    /// after that only the same one can rejoin.
+    #[clap(long, value_name = "ADDR")]
+    pub(crate) partner: Option<String>,
+    /// How to read the controller: evdev (through the kernel's driver), or hidraw (speaking the
+    /// Joy-Con's HID protocol directly, for Joy-Cons and Pro Controllers without hid-nintendo).
+    /// --gamepad, rumble and --on-conflict take-over need evdev.
+    #[clap(long, default_value = "evdev", parse(try_from_str = source::parse_backend))]
+    pub(crate) backend: &'static Backend,
+    /// Where to send the translated input: uinput (a virtual device), tcp:HOST:PORT (forwarded
+    /// to another machine), xtest (the X server's XTEST extension, in $DISPLAY), wlroots (a
+    /// wlroots-based compositor's virtual pointer and keyboard, in $WAYLAND_DISPLAY), or null
+    /// (discarded, e.g. for testing a setup). xtest and wlroots are for sessions joykbd can't
+    /// create uinput devices in, and wlroots types as a US layout.
+    #[clap(long, value_name = "SINK", default_value = "uinput")]
+    pub(crate) output: SinkSpec,
+    /// A (multitouch) touchpad to use as an additional input source, e.g. a Steam Deck's. One
+    /// finger moves the cursor, dragging two fingers or swiping along the right/bottom edge
+    /// scrolls, and tapping clicks.
+    /// Can be passed multiple times.
+    #[clap(long = "trackpad", value_name = "PATH")]
+    pub(crate) trackpads: Vec<PathBuf>,
+    /// How many pixels the cursor moves when swiping across the whole width of a --trackpad.
+    #[clap(long, default_value_t = 1200.0)]
+    pub(crate) trackpad_speed: f64,
+    /// Scroll --trackpad content in the direction the fingers move, like on a phone.
+    #[clap(long)]
+    pub(crate) natural_scroll: bool,
+    /// Play a sound when the layer is toggled, translation is suspended/resumed, or something
+    /// goes wrong.
+    #[clap(long, arg_enum, value_name = "KIND")]
+    pub(crate) sounds: Option<Sounds>,
+    /// Announce layer toggles, suspends, errors and a low controller battery through
+    /// speech-dispatcher, e.g. for use alongside a screen reader.
+    #[clap(long)]
+    pub(crate) speak: bool,
+    /// Warn, in the log and with a desktop notification (through notify-send), when the
+    /// controller's battery drops to this level in percent.
+    #[clap(long, value_name = "PERCENT", parse(try_from_str = parse_percent))]
+    pub(crate) battery_warn: Option<u8>,
+    /// Rumble briefly on mouse clicks, profile switches, and when the stick is pushed past the
+    /// drift threshold.
+    #[clap(long)]
+    pub(crate) haptics: bool,
+    /// Record how long each of the controller's buttons is held and how quickly it's pressed
+    /// again, to suggest tap/hold and double-press thresholds that suit you. The control socket's
+    /// `hold-stats` command reports them.
+    #[clap(long)]
+    pub(crate) hold_stats: bool,
+    /// Record every event read from the controller and the other sources to this file, as JSON
+    /// lines, e.g. to attach to a bug report.
+    #[clap(long, value_name = "PATH")]
+    pub(crate) record: Option<PathBuf>,
+    /// Leave the keys of --keyboard sources out of the --record recording, so it can be shared
+    /// without giving away what was typed.
+    #[clap(long, requires = "record")]
+    pub(crate) record_private: bool,
+    /// Serve joykbd's state (layer, modes, profile, ...) over HTTP on this address, e.g.
+    /// 127.0.0.1:7878, for on-screen indicators: `GET /` returns it as JSON, and `GET /events`
+    /// streams it as server-sent events.
+    #[clap(long, value_name = "ADDR")]
+    pub(crate) overlay: Option<String>,
+    /// Print the startup description of the device, profile and output as JSON on stdout, for
+    /// scripts.
+    #[clap(long)]
+    pub(crate) json: bool,
+}
+
+fn parse_regex(s: &str) -> Result<Regex, String> {
+    Regex::new(s).map_err(|e| e.to_string())
+}
+
+fn parse_preset(s: &str) -> Result<&'static Preset, String> {
+    preset::by_name(s).ok_or_else(|| format!("unknown preset {:?}", s))
+}
+
+fn parse_key_arg(s: &str) -> Result<Key, String> {
+    bindings::parse_key(s).ok_or_else(|| format!("unknown key {:?}", s))
+}
+
+fn parse_percent(s: &str) -> Result<u8, String> {
+    match s.parse() {
+        Ok(percent @ 1..=100) => Ok(percent),
+        _ => Err(format!("expected a percentage from 1 to 100, got {:?}", s)),
+    }
+}
+
+fn parse_smoothing(s: &str) -> Result<f64, String> {
+    match s.parse() {
+        Ok(alpha) if alpha > 0.0 && alpha <= 1.0 => Ok(alpha),
+        _ => Err(format!("expected a fraction above 0, up to 1, got {:?}", s)),
+    }
+}
+
+fn parse_button_arg(s: &str) -> Result<Key, String> {
+    config::parse_button(s).ok_or_else(|| format!("unknown button {:?}", s))
+}
+
+impl Options {
+    /// The stick's settings: the config file's `[stick]` table, except where these override it.
+    pub fn stick_constants(&self, config: &StickConfig) -> anyhow::Result<StickConstants> {
+        let mut stick_constants = StickConstants::new([self.speed; 2], 2000, (0, 0));
+        config
+            .apply(&mut stick_constants)
+            .map_err(anyhow::Error::msg)?;
+        if let Some(alpha) = self.smoothing {
+            stick_constants.smooth(alpha);
+        }
+        if let Some(bias) = self.x_bias {
+            stick_constants.axis_bias.0 = bias;
+        }
+        if let Some(bias) = self.y_bias {
+            stick_constants.axis_bias.1 = bias;
+        }
+        if let Some(threshold) = self.drift_threshold {
+            stick_constants.drift_threshold = threshold;
+        }
+        // --speed-x/--speed-y take precedence over the config file, which takes precedence over
+        // --speed
+        if let Some(speed) = self.speed_x {
+            stick_constants.speed[Axis::X as usize] = speed;
+        }
+        if let Some(speed) = self.speed_y {
+            stick_constants.speed[Axis::Y as usize] = speed;
+        }
+        if let Some(curve) = &self.curve {
+            curve
+                .validate()
+                .map_err(|e| anyhow::anyhow!("invalid curve: {}", e))?;
+            stick_constants.curve = curve.clone();
+        }
+        stick_constants.drift_hysteresis = self.drift_hysteresis;
+        stick_constants.drift_tracking = self.drift_tracking;
+        stick_constants.deadzone_shape = self.deadzone_shape;
+        Ok(stick_constants)
+    }
+
+    /// The option set to use: --option-set, or else the one --option-set-rules (or the default
+    /// rules file) picks, if any.
+    pub fn option_set(&self) -> anyhow::Result<Option<String>> {
+        Ok(match (&self.option_set, &self.option_set_rules) {
+            (Some(option_set), _) => Some(option_set.clone()),
+            (None, Some(rules)) => option_set::select(rules)?,
+            (None, None) => match option_set::default_rules_path() {
+                Some(rules) => option_set::select(&rules)?,
+                None => None,
+            },
+        })
+    }
+
+    /// Every profile's bindings, from the config file and these options: the default profile's
+    /// first, then those of the config file's `[profiles.*]`, in order, each with its own remaps
+    /// on top of the default's.
+    pub fn profile_bindings(&self, config: &Config) -> anyhow::Result<Vec<(String, Bindings)>> {
+        // later remaps of the same key take precedence
+        let mut remaps = config.buttons.remaps().map_err(anyhow::Error::msg)?;
+        remaps.extend(self.remaps.iter().cloned());
+        let mut layer_remaps = config.layer.remaps().map_err(anyhow::Error::msg)?;
+        layer_remaps.extend(self.layer_remaps.iter().cloned());
+        let keyboard_remaps = config
+            .keyboard_mode_remaps()
+            .map_err(|e| anyhow::anyhow!("invalid [keyboard-mode]: {}", e))?;
+        let tuning = StickTuning::new(self.gamepad_deadzone, self.gamepad_curve)
+            .map_err(|e| anyhow::anyhow!("invalid --gamepad tuning: {}", e))?;
+        let make_bindings = |remaps: &[Remap], layer_remaps: &[Remap]| -> anyhow::Result<_> {
+            let mut bindings = Bindings::new(
+                remaps,
+                &self.layer_keys,
+                &self.layer_toggle_keys,
+                layer_remaps,
+            )
+            .map_err(anyhow::Error::msg)?;
+            if self.mode_key.is_some() {
+                bindings.set_keyboard_remaps(&keyboard_remaps);
+            }
+            for group in &self.groups {
+                let names: Vec<_> = group.split(',').map(str::trim).collect();
+                bindings
+                    .add_group(&names)
+                    .map_err(|e| anyhow::anyhow!("invalid group {:?}: {}", group, e))?;
+            }
+            Ok(bindings)
+        };
+        let mut default = make_bindings(&remaps, &layer_remaps)?;
+        default.set_ignore(config.ignore);
+        default.set_stick_tuning(tuning);
+        let mut all = vec![("default".to_owned(), default)];
+        for (name, profile) in &config.profiles {
+            let mut profile_remaps = remaps.clone();
+            profile_remaps.extend(profile.buttons.remaps().map_err(anyhow::Error::msg)?);
+            let mut profile_layer_remaps = layer_remaps.clone();
+            profile_layer_remaps.extend(profile.layer.remaps().map_err(anyhow::Error::msg)?);
+            let mut bindings = make_bindings(&profile_remaps, &profile_layer_remaps)?;
+            bindings.set_ignore(profile.ignore.unwrap_or(config.ignore));
+            let profile_tuning = StickTuning::new(
+                profile.gamepad.deadzone.unwrap_or(tuning.deadzone),
+                profile.gamepad.curve.unwrap_or(tuning.curve),
+            )
+            .map_err(|e| anyhow::anyhow!("invalid [profiles.{}.gamepad]: {}", name, e))?;
+            bindings.set_stick_tuning(profile_tuning);
+            all.push((name.clone(), bindings));
+        }
+        Ok(all)
+    }
+
+    /// A remapper translating with `bindings` and `stick_constants`, and the rest of its settings
+    /// from these options.
+    pub fn new_remapper(
+        &self,
+        preset: &'static Preset,
+        bindings: Bindings,
+        stick_constants: StickConstants,
+    ) -> Remapper {
+        let mut remapper = Remapper::new(preset, bindings, stick_constants);
+        remapper.pointer = PointerButtons::new(
+            &self.left_click,
+            &self.right_click,
+            &self.middle_click,
+            self.left_handed,
+        );
+        remapper.mouse_curve = MouseCurve::new(self.mouse_speed);
+        remapper.speed = Speed::new(
+            self.sprint_speed,
+            self.precision_speed,
+            self.flick_acceleration,
+        );
+        // "hard" is most of the way to the stick's full speed
+        let page_threshold = (self.scroll_pages)
+            .then_some((remapper.stick_constants.speed[Axis::Y as usize] * 0.8) as i32);
+        remapper.stick_scroll = StickScroll::new(self.stick_click_scroll, page_threshold);
+        remapper
+    }
+
+    /// Set up the per-event translation, like joykbd's with these options and the config file's
+    /// `profile` (or its default one), but without any devices, e.g. to translate recorded events.
+    /// Unlike [`Remapper::run`], only an explicit --config is used, so the result doesn't depend
+    /// on whoever runs it.
+    pub fn remapper(&self, profile: Option<&str>) -> anyhow::Result<Remapper> {
+        let config = match &self.config {
+            Some(path) => config::load(path, true)?,
+            None => Config::default(),
+        };
+        let stick_constants = self.stick_constants(&config.stick)?;
+        let name = profile.unwrap_or("default");
+        let (_, bindings) = (self.profile_bindings(&config)?.into_iter())
+            .find(|(profile, _)| profile == name)
+            .ok_or_else(|| anyhow::anyhow!("no profile {:?} in the config file", name))?;
+        Ok(self.new_remapper(
+            self.preset.unwrap_or(&preset::JOYCON),
+            bindings,
+            stick_constants,
+        ))
+    }
+
+    /// An output to `sink` that releases keys held longer than --max-hold, with --sticky-keys,
+    /// --rotate and --syn-batch.
+    pub fn virtual_output(&self, sink: Box<dyn OutputSink>) -> VirtualOutput {
+        let mut output = VirtualOutput::new(
+            sink,
+            None,
+            self.max_hold.map(Duration::from_secs),
+            self.sticky_keys,
+        );
+        output.set_rotation(self.rotate);
+        output.set_batch_window(self.syn_batch.map(Duration::from_millis));
+        output
+    }
+}
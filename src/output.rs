@@ -0,0 +1,709 @@
+//! Where joykbd's output goes. Events are emitted to an [`OutputSink`], by default a uinput
+//! virtual device, picked with `--output`.
+
+use crate::cursor::Cursor;
+use crate::rotation::Rotation;
+use crate::sticky::StickyKeys;
+use crate::wlroots::WlrootsSink;
+use crate::xtest::XtestSink;
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{
+    AttributeSet, EventType, InputEvent, InputEventKind, Key, RelativeAxisType, UinputAbsSetup,
+};
+use smallvec::SmallVec;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Everything the virtual device can emit, derived from the sources and bindings in use.
+#[derive(Default)]
+pub struct Capabilities {
+    pub keys: AttributeSet<Key>,
+    pub axes: AttributeSet<RelativeAxisType>,
+    pub abs: Vec<UinputAbsSetup>,
+}
+
+/// Something that injects events into the system (or elsewhere).
+pub trait OutputSink {
+    /// Emit `events` as one report. If the sink can't take them right now, this fails with
+    /// [`io::ErrorKind::WouldBlock`], without having emitted any of them.
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()>;
+    /// The device node other programs see the output as, if it's a local device.
+    fn dev_node(&mut self) -> Option<PathBuf> {
+        None
+    }
+    /// The sink's device in sysfs, if it's a local device.
+    fn sys_path(&mut self) -> Option<PathBuf> {
+        None
+    }
+}
+
+impl OutputSink for VirtualDevice {
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        VirtualDevice::emit(self, events)
+    }
+
+    fn dev_node(&mut self) -> Option<PathBuf> {
+        self.enumerate_dev_nodes_blocking()
+            .ok()?
+            .filter_map(Result::ok)
+            .find(|node| {
+                node.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("event"))
+            })
+    }
+
+    fn sys_path(&mut self) -> Option<PathBuf> {
+        self.get_syspath().ok()
+    }
+}
+
+/// Copy `source`'s timestamp onto `ev`, e.g. for an event mapped from it. Note that uinput
+/// stamps events itself as they're injected, so this only carries through to sinks that
+/// preserve timestamps.
+pub fn stamp(ev: InputEvent, source: &InputEvent) -> InputEvent {
+    let mut raw = *ev.as_ref();
+    raw.time = source.as_ref().time;
+    raw.into()
+}
+
+/// Forwards events over TCP to another machine, each as a little-endian `i64` timestamp (in
+/// microseconds since the unix epoch, from the event it was mapped from, or when it was sent for
+/// synthesized ones), `u16` type, `u16` code and `i32` value. The socket is non-blocking, so a
+/// slow connection backs events up in [`VirtualOutput`] rather than stalling the input.
+pub struct NetSink {
+    stream: TcpStream,
+    /// The rest of a record that only partly fit in the socket's buffer.
+    unsent: Vec<u8>,
+}
+
+impl NetSink {
+    fn send_unsent(&mut self) -> io::Result<()> {
+        while !self.unsent.is_empty() {
+            match self.stream.write(&self.unsent)? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                n => drop(self.unsent.drain(..n)),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OutputSink for NetSink {
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        self.send_unsent()?;
+        let mut buf = Vec::with_capacity(events.len() * 16);
+        let now = SystemTime::now();
+        for ev in events {
+            let time = match ev.timestamp() {
+                UNIX_EPOCH => now,
+                time => time,
+            };
+            let micros = time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_micros();
+            buf.extend_from_slice(&(micros as i64).to_le_bytes());
+            buf.extend_from_slice(&ev.event_type().0.to_le_bytes());
+            buf.extend_from_slice(&ev.code().to_le_bytes());
+            buf.extend_from_slice(&ev.value().to_le_bytes());
+        }
+        let written = self.stream.write(&buf)?;
+        self.unsent.extend_from_slice(&buf[written..]);
+        Ok(())
+    }
+}
+
+/// Drops everything, e.g. for trying out a setup without it affecting the desktop.
+pub struct NullSink;
+
+impl OutputSink for NullSink {
+    fn emit(&mut self, _: &[InputEvent]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Keeps everything emitted, e.g. for `joykbd verify` to compare with what's expected. Clones
+/// share what they've kept, so one can be handed to a [`VirtualOutput`] and another read.
+#[derive(Clone, Default)]
+pub struct RecordingSink {
+    emitted: Rc<RefCell<Vec<InputEvent>>>,
+}
+
+impl RecordingSink {
+    /// Everything emitted since the last call.
+    pub fn take(&self) -> Vec<InputEvent> {
+        self.emitted.take()
+    }
+}
+
+impl OutputSink for RecordingSink {
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        self.emitted.borrow_mut().extend_from_slice(events);
+        Ok(())
+    }
+}
+
+/// Which sink to use, as passed to `--output`.
+#[derive(Clone)]
+pub enum SinkSpec {
+    Uinput,
+    /// `tcp:HOST:PORT`
+    Tcp(String),
+    /// The X server's XTEST extension.
+    Xtest,
+    /// A wlroots-based compositor's virtual pointer and keyboard.
+    Wlroots,
+    Null,
+}
+
+impl FromStr for SinkSpec {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uinput" => Ok(SinkSpec::Uinput),
+            "xtest" => Ok(SinkSpec::Xtest),
+            "wlroots" => Ok(SinkSpec::Wlroots),
+            "null" => Ok(SinkSpec::Null),
+            _ => match s.strip_prefix("tcp:") {
+                Some(addr) => Ok(SinkSpec::Tcp(addr.to_owned())),
+                None => Err(format!(
+                    "unknown output {:?}, expected uinput, tcp:HOST:PORT, xtest, wlroots or null",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for SinkSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SinkSpec::Uinput => f.write_str("uinput"),
+            SinkSpec::Tcp(addr) => write!(f, "tcp:{}", addr),
+            SinkSpec::Xtest => f.write_str("xtest"),
+            SinkSpec::Wlroots => f.write_str("wlroots"),
+            SinkSpec::Null => f.write_str("null"),
+        }
+    }
+}
+
+impl SinkSpec {
+    /// Create the sink, able to emit `caps`.
+    pub fn open(&self, caps: &Capabilities) -> io::Result<Box<dyn OutputSink>> {
+        Ok(match self {
+            SinkSpec::Uinput => {
+                let mut builder = VirtualDeviceBuilder::new()?
+                    .name("joykbd")
+                    .with_relative_axes(&caps.axes)?
+                    .with_keys(&caps.keys)?;
+                for abs in &caps.abs {
+                    builder = builder.with_absolute_axis(abs)?;
+                }
+                Box::new(builder.build()?)
+            }
+            SinkSpec::Tcp(addr) => {
+                let stream = TcpStream::connect(addr)?;
+                stream.set_nodelay(true)?;
+                stream.set_nonblocking(true)?;
+                Box::new(NetSink {
+                    stream,
+                    unsent: vec![],
+                })
+            }
+            SinkSpec::Xtest => Box::new(XtestSink::connect()?),
+            SinkSpec::Wlroots => Box::new(WlrootsSink::connect(caps)?),
+            SinkSpec::Null => Box::new(NullSink),
+        })
+    }
+}
+
+/// Where joykbd's translated events go: a virtual device (or another of the [`SinkSpec`]s),
+/// along with which of its keys are currently held down.
+pub struct VirtualOutput {
+    sink: Box<dyn OutputSink>,
+    /// Held keys, how many of the sources' keys are holding each (e.g. both ZL and ZR holding
+    /// BTN_LEFT), and when they were first pressed.
+    held: HashMap<Key, (u32, Instant)>,
+    /// How long a key can be held before [`VirtualOutput::release_stuck`] releases it.
+    max_hold: Option<Duration>,
+    /// If the cursor is positioned absolutely, where it is.
+    cursor: Option<Cursor>,
+    /// Whether BTN_LEFT and BTN_RIGHT are swapped, whichever source they come from.
+    clicks_swapped: bool,
+    sticky_keys: Option<StickyKeys>,
+    /// How relative motion is rotated to match the display.
+    rotation: Rotation,
+    /// How long to collect events for before emitting them together, in a single report.
+    batch_window: Option<Duration>,
+    /// The events collected so far, and when they're due to be emitted.
+    batch: Vec<InputEvent>,
+    batch_deadline: Option<Instant>,
+    /// Events the sink couldn't take yet, coalesced while they wait.
+    backlog: Vec<InputEvent>,
+    /// Buffers for the events being emitted, kept around so that emitting doesn't allocate.
+    remapped: Vec<InputEvent>,
+    unlatched: Vec<InputEvent>,
+    positioned: Vec<InputEvent>,
+    reconciled: Vec<InputEvent>,
+    collected: Vec<InputEvent>,
+}
+
+impl VirtualOutput {
+    pub fn new(
+        sink: Box<dyn OutputSink>,
+        cursor: Option<Cursor>,
+        max_hold: Option<Duration>,
+        sticky_keys: bool,
+    ) -> Self {
+        VirtualOutput {
+            sink,
+            held: HashMap::new(),
+            max_hold,
+            cursor,
+            clicks_swapped: false,
+            sticky_keys: sticky_keys.then(StickyKeys::default),
+            rotation: Rotation::default(),
+            batch_window: None,
+            batch: vec![],
+            batch_deadline: None,
+            backlog: vec![],
+            remapped: vec![],
+            unlatched: vec![],
+            positioned: vec![],
+            reconciled: vec![],
+            collected: vec![],
+        }
+    }
+
+    pub fn set_batch_window(&mut self, window: Option<Duration>) {
+        self.batch_window = window;
+    }
+
+    /// When the collected events are due to be emitted with [`VirtualOutput::flush`], if there are
+    /// any.
+    pub fn flush_deadline(&self) -> Option<Instant> {
+        self.batch_deadline
+    }
+
+    /// Emit the events collected during the batching window.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.batch_deadline = None;
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let mut batch = std::mem::take(&mut self.batch);
+        let res = self.deliver(&batch);
+        batch.clear();
+        self.batch = batch;
+        res
+    }
+
+    /// Whether there are events waiting for the sink to catch up, to [`VirtualOutput::retry`]
+    /// later.
+    pub fn backed_up(&self) -> bool {
+        !self.backlog.is_empty()
+    }
+
+    /// Try emitting the events that the sink couldn't take before.
+    pub fn retry(&mut self) -> io::Result<()> {
+        while !self.backlog.is_empty() {
+            let len = report_len(&self.backlog);
+            match self.sink.emit(&self.backlog[..len]) {
+                Ok(()) => drop(self.backlog.drain(..len)),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Emit `events` to the sink, or if it's backed up, queue them behind what's already waiting.
+    /// Rather than let the queue grow, motion waiting in it is summed up and autorepeats dropped,
+    /// so button presses still get through promptly once the sink catches up.
+    fn deliver(&mut self, mut events: &[InputEvent]) -> io::Result<()> {
+        while self.backlog.is_empty() && !events.is_empty() {
+            let (report, rest) = events.split_at(report_len(events));
+            match self.sink.emit(report) {
+                Ok(()) => events = rest,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.backlog.extend_from_slice(events);
+        coalesce(&mut self.backlog);
+        self.retry()
+    }
+
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.rotation = rotation;
+    }
+
+    /// Swap the mouse buttons or not. A button held down while they're swapped is released, so
+    /// it can't get stuck when its release comes out as the other button.
+    pub fn set_clicks_swapped(&mut self, swapped: bool) -> io::Result<()> {
+        if swapped == self.clicks_swapped {
+            return Ok(());
+        }
+        let held: SmallVec<[_; 2]> = [Key::BTN_LEFT, Key::BTN_RIGHT]
+            .into_iter()
+            .filter(|key| self.held.contains_key(key))
+            .collect();
+        self.release(&held)?;
+        self.clicks_swapped = swapped;
+        Ok(())
+    }
+
+    pub fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        let mut remapped = std::mem::take(&mut self.remapped);
+        remapped.clear();
+        let events = if self.clicks_swapped || self.rotation != Rotation::default() {
+            remapped.extend(events.iter().map(|&ev| {
+                let ev = self.rotation.apply(ev);
+                if self.clicks_swapped {
+                    swap_click(ev)
+                } else {
+                    ev
+                }
+            }));
+            &remapped[..]
+        } else {
+            events
+        };
+        let res = match &mut self.sticky_keys {
+            Some(sticky_keys) => {
+                let mut unlatched = std::mem::take(&mut self.unlatched);
+                unlatched.clear();
+                sticky_keys.map(events, &mut unlatched);
+                let res = self.send(&unlatched);
+                self.unlatched = unlatched;
+                res
+            }
+            None => self.send(events),
+        };
+        self.remapped = remapped;
+        res
+    }
+
+    /// Like [`VirtualOutput::emit`], for events that aren't in a slice yet, e.g. a binding's
+    /// releases as they're drained.
+    pub fn emit_iter(&mut self, events: impl IntoIterator<Item = InputEvent>) -> io::Result<()> {
+        let mut collected = std::mem::take(&mut self.collected);
+        collected.clear();
+        collected.extend(events);
+        let res = self.emit(&collected);
+        self.collected = collected;
+        res
+    }
+
+    /// Emit `events`, which are already remapped for the output.
+    fn send(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        let mut positioned = std::mem::take(&mut self.positioned);
+        positioned.clear();
+        let events = match &mut self.cursor {
+            Some(cursor) => {
+                cursor.map(events, &mut positioned);
+                &positioned[..]
+            }
+            None => events,
+        };
+        // several source keys can hold the same output key (e.g. ZL and ZR both clicking), so
+        // it's only pressed by the first of them and released by the last, and a release that
+        // was already synthesized by `release_all` on disconnect is dropped
+        let mut reconciled = std::mem::take(&mut self.reconciled);
+        reconciled.clear();
+        for &ev in events {
+            match (ev.kind(), ev.value()) {
+                (InputEventKind::Key(key), 0) => match self.held.get_mut(&key) {
+                    Some((1, _)) => {
+                        self.held.remove(&key);
+                        reconciled.push(ev);
+                    }
+                    Some((count, _)) => *count -= 1,
+                    None => {}
+                },
+                (InputEventKind::Key(key), 1) => {
+                    let (count, _) = self.held.entry(key).or_insert((0, Instant::now()));
+                    *count += 1;
+                    if *count == 1 {
+                        reconciled.push(ev);
+                    }
+                }
+                // autorepeat
+                (InputEventKind::Key(key), _) => {
+                    if self.held.contains_key(&key) {
+                        reconciled.push(ev);
+                    }
+                }
+                _ => reconciled.push(ev),
+            }
+        }
+        let res = match self.batch_window {
+            _ if reconciled.is_empty() => Ok(()),
+            Some(window) => {
+                self.batch.extend_from_slice(&reconciled);
+                self.batch_deadline
+                    .get_or_insert_with(|| Instant::now() + window);
+                Ok(())
+            }
+            None => self.deliver(&reconciled),
+        };
+        self.reconciled = reconciled;
+        self.positioned = positioned;
+        res
+    }
+
+    pub fn dev_node(&mut self) -> Option<PathBuf> {
+        self.sink.dev_node()
+    }
+
+    pub fn sys_path(&mut self) -> Option<PathBuf> {
+        self.sink.sys_path()
+    }
+
+    pub fn cursor(&self) -> Option<&Cursor> {
+        self.cursor.as_ref()
+    }
+
+    /// The keys/buttons currently held down on the sink.
+    pub fn held_keys(&self) -> impl Iterator<Item = Key> + '_ {
+        self.held.keys().copied()
+    }
+
+    /// Release every key/button that's currently held down on the sink.
+    pub fn release_all(&mut self) -> io::Result<()> {
+        let held: SmallVec<[_; 8]> = self.held.keys().copied().collect();
+        self.release(&held)
+    }
+
+    /// Release the keys that have been held for longer than the maximum, e.g. because their
+    /// release got lost over a flaky connection, returning which ones those were.
+    pub fn release_stuck(&mut self) -> io::Result<Vec<Key>> {
+        let max_hold = match self.max_hold {
+            Some(max_hold) => max_hold,
+            None => return Ok(vec![]),
+        };
+        let stuck: Vec<_> = self
+            .held
+            .iter()
+            .filter(|(_, (_, pressed))| pressed.elapsed() > max_hold)
+            .map(|(&key, _)| key)
+            .collect();
+        self.release(&stuck)?;
+        Ok(stuck)
+    }
+
+    fn release(&mut self, keys: &[Key]) -> io::Result<()> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        let releases: SmallVec<[_; 8]> = keys
+            .iter()
+            .map(|key| InputEvent::new(EventType::KEY, key.code(), 0))
+            .collect();
+        if let Some(sticky_keys) = &mut self.sticky_keys {
+            sticky_keys.forget(keys);
+        }
+        // however many source keys are holding them
+        for key in keys {
+            if let Some((count, _)) = self.held.get_mut(key) {
+                *count = 1;
+            }
+        }
+        self.send(&releases)
+    }
+}
+
+/// How many of `events` go out together in the first report: all of them, unless a key is
+/// released after being pressed among them, which would make a click of no length at all that
+/// some clients miss, so its release starts the next report.
+fn report_len(events: &[InputEvent]) -> usize {
+    (1..events.len())
+        .find(|&i| {
+            let ev = events[i];
+            ev.event_type() == EventType::KEY
+                && ev.value() == 0
+                && (events[..i].iter()).any(|prev| {
+                    prev.event_type() == EventType::KEY
+                        && prev.code() == ev.code()
+                        && prev.value() == 1
+                })
+        })
+        .unwrap_or(events.len())
+}
+
+/// Sum up each run of relative motion between button events, keep only the latest position of
+/// each absolute axis in such a run, and drop autorepeats, which the next one supersedes anyway.
+/// This is done in place, as what's kept is never more than what's been looked at.
+fn coalesce(events: &mut Vec<InputEvent>) {
+    // how many events are kept so far, and where the current run of motion among them starts
+    let mut kept = 0;
+    let mut run = 0;
+    for i in 0..events.len() {
+        let ev = events[i];
+        match ev.kind() {
+            InputEventKind::Key(_) if ev.value() == 2 => continue,
+            InputEventKind::Key(_) => {
+                events[kept] = ev;
+                kept += 1;
+                run = kept;
+                continue;
+            }
+            InputEventKind::RelAxis(_) | InputEventKind::AbsAxis(_) => {}
+            _ => {
+                events[kept] = ev;
+                kept += 1;
+                continue;
+            }
+        }
+        let same_axis = events[run..kept]
+            .iter_mut()
+            .find(|prev| prev.event_type() == ev.event_type() && prev.code() == ev.code());
+        match same_axis {
+            Some(prev) => {
+                let mut raw = *ev.as_ref();
+                if ev.event_type() == EventType::RELATIVE {
+                    raw.value = raw.value.saturating_add(prev.value());
+                }
+                *prev = raw.into();
+            }
+            None => {
+                events[kept] = ev;
+                kept += 1;
+            }
+        }
+    }
+    events.truncate(kept);
+}
+
+fn swap_click(ev: InputEvent) -> InputEvent {
+    let to = match ev.kind() {
+        InputEventKind::Key(Key::BTN_LEFT) => Key::BTN_RIGHT,
+        InputEventKind::Key(Key::BTN_RIGHT) => Key::BTN_LEFT,
+        _ => return ev,
+    };
+    let mut raw = *ev.as_ref();
+    raw.code = to.code();
+    raw.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{coalesce, report_len, OutputSink, VirtualOutput};
+    use evdev::{EventType, InputEvent, Key, RelativeAxisType};
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    fn key(key: Key, value: i32) -> InputEvent {
+        InputEvent::new(EventType::KEY, key.code(), value)
+    }
+
+    fn rel(axis: RelativeAxisType, value: i32) -> InputEvent {
+        InputEvent::new(EventType::RELATIVE, axis.0, value)
+    }
+
+    fn x(value: i32) -> InputEvent {
+        rel(RelativeAxisType::REL_X, value)
+    }
+
+    fn y(value: i32) -> InputEvent {
+        rel(RelativeAxisType::REL_Y, value)
+    }
+
+    fn abs_x(value: i32) -> InputEvent {
+        InputEvent::new(EventType::ABSOLUTE, 0, value)
+    }
+
+    /// `events` in a form that can be compared.
+    fn raw(events: &[InputEvent]) -> Vec<(u16, u16, i32)> {
+        (events.iter())
+            .map(|ev| (ev.event_type().0, ev.code(), ev.value()))
+            .collect()
+    }
+
+    /// Each report emitted, as a separate list of events.
+    #[derive(Clone, Default)]
+    struct Reports(Rc<RefCell<Vec<Vec<InputEvent>>>>);
+
+    impl OutputSink for Reports {
+        fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+            self.0.borrow_mut().push(events.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn coalesces() {
+        let a = Key::KEY_A;
+        let cases: &[(&[InputEvent], &[InputEvent])] = &[
+            (&[], &[]),
+            (&[x(1), x(2), y(3), x(-1)], &[x(2), y(3)]),
+            // not across a button
+            (&[x(1), key(a, 1), x(2), x(3)], &[x(1), key(a, 1), x(5)]),
+            // only the latest position counts
+            (&[abs_x(10), abs_x(20), x(1)], &[abs_x(20), x(1)]),
+            // autorepeats are dropped, presses and releases aren't
+            (
+                &[key(a, 1), key(a, 2), key(a, 2), key(a, 0)],
+                &[key(a, 1), key(a, 0)],
+            ),
+            (&[x(i32::MAX), x(1)], &[x(i32::MAX)]),
+        ];
+        for &(events, expected) in cases {
+            let mut coalesced = events.to_vec();
+            coalesce(&mut coalesced);
+            assert_eq!(raw(&coalesced), raw(expected), "coalescing {:?}", events);
+        }
+    }
+
+    #[test]
+    fn splits_reports() {
+        let (a, b) = (Key::KEY_A, Key::KEY_B);
+        let cases: &[(&[InputEvent], usize)] = &[
+            (&[], 0),
+            (&[key(a, 1), key(b, 1), x(3)], 3),
+            (&[key(a, 1), x(3), key(a, 0), key(b, 1)], 2),
+            // released without having been pressed in the same report
+            (&[key(a, 0), key(b, 1), key(a, 1)], 3),
+            (&[key(a, 1), key(b, 1), key(b, 0), key(a, 0)], 2),
+            (&[key(a, 2), key(a, 0)], 2),
+        ];
+        for &(events, expected) in cases {
+            assert_eq!(report_len(events), expected, "splitting {:?}", events);
+        }
+    }
+
+    #[test]
+    fn batch_keeps_taps() {
+        let reports = Reports::default();
+        let mut output = VirtualOutput::new(Box::new(reports.clone()), None, None, false);
+        output.set_batch_window(Some(Duration::from_secs(1)));
+        let a = Key::KEY_A;
+        output.emit(&[key(a, 1), x(1)]).unwrap();
+        output.emit(&[key(a, 0), x(2)]).unwrap();
+        output.flush().unwrap();
+        let reports: Vec<_> = reports
+            .0
+            .borrow()
+            .iter()
+            .map(|report| raw(report))
+            .collect();
+        assert_eq!(reports, [raw(&[key(a, 1), x(1)]), raw(&[key(a, 0), x(2)])]);
+    }
+}
@@ -0,0 +1,126 @@
+//! A local HTTP endpoint reporting joykbd's state (layer, modes, profile, ...) for on-screen
+//! indicators, e.g. an OBS browser source or an eww/AGS widget. `GET /` returns the state as JSON,
+//! and `GET /events` streams it as server-sent events whenever it changes.
+
+use crate::textentry::Selection;
+use serde::Serialize;
+use std::io;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+
+#[derive(Serialize, Default)]
+struct OverlayState {
+    profile: Option<String>,
+    /// The text entry pages' characters, see [`crate::textentry`].
+    text_entry_pages: Vec<String>,
+    #[serde(flatten)]
+    indicators: Indicators,
+}
+
+/// The parts of the state that change while running.
+#[derive(Serialize, Clone, Copy, PartialEq, Default)]
+pub struct Indicators {
+    pub layer: bool,
+    /// `sprint` or `precision`, while held.
+    pub speed: Option<&'static str>,
+    pub clicks_swapped: bool,
+    /// The --rotate rotation, in degrees.
+    pub rotation: u16,
+    pub connected: bool,
+    pub suspended: bool,
+    /// What's selected in text entry, while it's on.
+    pub text_entry: Option<Selection>,
+}
+
+pub struct Overlay {
+    tx: watch::Sender<OverlayState>,
+}
+
+impl Overlay {
+    /// Start serving on `addr`, e.g. `127.0.0.1:7878`.
+    pub async fn serve(addr: &str, profile: Option<String>) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let (tx, rx) = watch::channel(OverlayState {
+            profile,
+            text_entry_pages: vec![],
+            indicators: Indicators::default(),
+        });
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(handle(stream, rx.clone()));
+                    }
+                    Err(e) => eprintln!("warning: overlay server error: {}", e),
+                }
+            }
+        });
+        Ok(Overlay { tx })
+    }
+
+    pub fn set_profile(&self, profile: Option<String>) {
+        self.tx.send_modify(|state| state.profile = profile);
+    }
+
+    pub fn set_text_entry_pages(&self, pages: Vec<String>) {
+        self.tx.send_modify(|state| state.text_entry_pages = pages);
+    }
+
+    /// Show `indicators`, which are different from the last ones.
+    pub fn update(&self, indicators: Indicators) {
+        self.tx.send_modify(|state| state.indicators = indicators);
+    }
+}
+
+async fn handle(stream: TcpStream, mut rx: watch::Receiver<OverlayState>) -> io::Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    let request = lines.next_line().await?.unwrap_or_default();
+    // the headers don't matter
+    while let Some(line) = lines.next_line().await? {
+        if line.is_empty() {
+            break;
+        }
+    }
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+    match path {
+        "/" => {
+            let body = to_json(&rx.borrow());
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                 Access-Control-Allow-Origin: *\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            write.write_all(response.as_bytes()).await
+        }
+        "/events" => {
+            write
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+                      Access-Control-Allow-Origin: *\r\nCache-Control: no-cache\r\n\r\n",
+                )
+                .await?;
+            loop {
+                let event = format!("data: {}\n\n", to_json(&rx.borrow_and_update()));
+                write.write_all(event.as_bytes()).await?;
+                if rx.changed().await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+        _ => {
+            write
+                .write_all(
+                    b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+                .await
+        }
+    }
+}
+
+fn to_json(state: &OverlayState) -> String {
+    serde_json::to_string(state).expect("the overlay state is always valid JSON")
+}
@@ -0,0 +1,54 @@
+//! The other Joy-Con of a pair. When joykbd is using a single Joy-Con, its partner can join in
+//! whenever it connects, without a restart: its buttons go through the same mappings, and its
+//! stick scrolls while the first one's moves the cursor (or moves the cursor, if the first one's is
+//! used as arrows with --arrow-stick). With --gamepad, it joins the virtual gamepad instead.
+
+use crate::source::{Backend, Found};
+use crate::stick::StickSide;
+
+/// The name the other Joy-Con of the pair shows up as, if `name` is a single Joy-Con (as named by
+/// hid-nintendo, or in its HID descriptor).
+pub fn partner_name(name: &str) -> Option<String> {
+    if !name.contains("Joy-Con") {
+        return None;
+    }
+    [("Left", "Right"), ("(L)", "(R)")]
+        .into_iter()
+        .find_map(|(left, right)| {
+            if name.contains(left) {
+                Some(name.replace(left, right))
+            } else if name.contains(right) {
+                Some(name.replace(right, left))
+            } else {
+                None
+            }
+        })
+}
+
+/// Which Joy-Con of a pair `name` is, if it's a single one.
+pub fn side(name: &str) -> Option<StickSide> {
+    if !name.contains("Joy-Con") {
+        None
+    } else if name.contains("Left") || name.contains("(L)") {
+        Some(StickSide::Left)
+    } else if name.contains("Right") || name.contains("(R)") {
+        Some(StickSide::Right)
+    } else {
+        None
+    }
+}
+
+/// Look for the partner named `name` among the devices `backend` can open. If its unique name
+/// `uniq` is known, it has to match too, so a Joy-Con of another pair (e.g. someone else's, or a
+/// spare) can't join in just because it's the other side.
+pub fn find(backend: &Backend, name: &str, uniq: Option<&str>) -> Option<Found> {
+    (backend.enumerate)().into_iter().find(|(_, dev)| {
+        dev.name() == Some(name)
+            && match uniq {
+                Some(uniq) => dev
+                    .unique_name()
+                    .is_some_and(|other| other.eq_ignore_ascii_case(uniq)),
+                None => true,
+            }
+    })
+}
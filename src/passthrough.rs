@@ -0,0 +1,96 @@
+//! Automatically suspending translation while something else wants the joy-con to itself, e.g. a
+//! game that grabs the device or that the user has told us about by window class, or while
+//! another user than the controller's owner is active (see [`crate::policy`]).
+
+use crate::conflict::GrabWatch;
+use crate::policy::Ownership;
+use std::path::PathBuf;
+use tokio::process::Command;
+
+pub struct Passthrough {
+    device_path: PathBuf,
+    on_grab: bool,
+    /// Watches for another process grabbing the device, once it's been opened.
+    grab_watch: Option<GrabWatch>,
+    /// Whether joykbd holds the grab on the device itself, so no other process can.
+    grabbed: bool,
+    windows: Vec<String>,
+    ownership: Option<Ownership>,
+}
+
+impl Passthrough {
+    pub fn new(device_path: PathBuf, on_grab: bool, windows: Vec<String>) -> Self {
+        Passthrough {
+            device_path,
+            on_grab,
+            grab_watch: None,
+            grabbed: false,
+            windows,
+            ownership: None,
+        }
+    }
+
+    /// Only translate while `ownership`'s user is active.
+    pub fn set_ownership(&mut self, ownership: Option<Ownership>) {
+        self.ownership = ownership;
+    }
+
+    /// Watch the device at `path` instead, e.g. after it reconnected.
+    pub fn set_device_path(&mut self, path: PathBuf) {
+        self.device_path = path;
+        self.grab_watch = None;
+    }
+
+    /// Whether joykbd grabbed the device itself (e.g. with --on-conflict take-over), in which
+    /// case nothing else can, and its events don't reach the grab watch either.
+    pub fn set_grabbed(&mut self, grabbed: bool) {
+        self.grabbed = grabbed;
+        self.grab_watch = None;
+    }
+
+    /// The restriction to the controller's owner, if it has one.
+    pub fn ownership(&self) -> Option<&Ownership> {
+        self.ownership.as_ref()
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.on_grab || !self.windows.is_empty() || self.ownership.is_some()
+    }
+
+    /// Whether translation should currently be suspended.
+    pub async fn should_suspend(&mut self) -> bool {
+        if self.on_grab && !self.grabbed {
+            if self.grab_watch.is_none() {
+                self.grab_watch = GrabWatch::open(&self.device_path).ok();
+            }
+            if self.grab_watch.as_mut().is_some_and(GrabWatch::grabbed) {
+                return true;
+            }
+        }
+        if let Some(ownership) = &self.ownership {
+            if ownership.seat().is_none() {
+                return true;
+            }
+        }
+        if !self.windows.is_empty() {
+            if let Some(class) = focused_window_class().await {
+                return self.windows.iter().any(|w| w.eq_ignore_ascii_case(&class));
+            }
+        }
+        false
+    }
+}
+
+/// The class of the currently focused X11 window, as reported by xdotool.
+pub async fn focused_window_class() -> Option<String> {
+    let output = Command::new("xdotool")
+        .args(["getactivewindow", "getwindowclassname"])
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let class = String::from_utf8(output.stdout).ok()?;
+    Some(class.trim().to_owned())
+}
@@ -0,0 +1,195 @@
+//! Sharing a machine between several users' controllers, e.g. a living-room PC with a system
+//! service per controller (see `joykbd install-service`): each controller can be claimed by a user
+//! in an owners file, and its output then only goes to that user's session. The virtual devices
+//! are moved to whichever seat the owner's session is the active one on, and while it isn't the
+//! active one on any (after switching users), translation is suspended, like --passthrough-on-grab
+//! does. So is it while logind can't be asked, rather than the input going to someone else.
+//!
+//! ```toml
+//! # /etc/joykbd/owners.toml: controllers by their bluetooth address
+//! "98:b6:e9:12:34:56" = "alice"
+//! "98:b6:e9:65:43:21" = "bob"
+//! ```
+//!
+//! Controllers without an owner aren't restricted.
+
+use dbus::message::MatchRule;
+use dbus::nonblock::stdintf::org_freedesktop_dbus::Properties;
+use dbus::nonblock::{Proxy, SyncConnection};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+
+const LOGIND: &str = "org.freedesktop.login1";
+const LOGIND_PATH: &str = "/org/freedesktop/login1";
+const MANAGER: &str = "org.freedesktop.login1.Manager";
+/// How long to wait for logind to answer.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The user of each seat's active session, by seat. Seats no one's logged in on are left out.
+pub type Seats = BTreeMap<String, String>;
+
+/// Which user each controller belongs to, by its unique name.
+pub struct Owners(BTreeMap<String, String>);
+
+impl Owners {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let s = fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("can't read owners {}: {}", path.display(), e))?;
+        let owners: BTreeMap<String, String> = toml::from_str(&s)
+            .map_err(|e| anyhow::anyhow!("invalid owners {}: {}", path.display(), e))?;
+        // addresses are compared without regard to case
+        Ok(Owners(
+            owners
+                .into_iter()
+                .map(|(uniq, user)| (uniq.to_ascii_lowercase(), user))
+                .collect(),
+        ))
+    }
+
+    /// The user the controller with unique name `uniq` belongs to, if any.
+    pub fn owner(&self, uniq: Option<&str>) -> Option<&str> {
+        self.0.get(&uniq?.to_ascii_lowercase()).map(String::as_str)
+    }
+
+    /// The restriction on the controller with unique name `uniq`, if it has an owner.
+    pub fn ownership(&self, uniq: Option<&str>, logind: &Logind) -> Option<Ownership> {
+        self.owner(uniq).map(|user| Ownership {
+            user: user.to_owned(),
+            seats: logind.seats(),
+        })
+    }
+}
+
+/// A controller's owner, and what logind last said about the seats.
+pub struct Ownership {
+    pub user: String,
+    seats: watch::Receiver<Option<Seats>>,
+}
+
+impl Ownership {
+    /// The seat the owner's session is the active one on, if it's that on any. If logind can't be
+    /// asked, it's on none, so the controller is held back rather than going to someone else.
+    pub fn seat(&self) -> Option<String> {
+        let seats = self.seats.borrow();
+        let (seat, _) = seats
+            .as_ref()?
+            .iter()
+            .find(|(_, user)| **user == self.user)?;
+        Some(seat.clone())
+    }
+}
+
+/// A connection to logind, keeping track of the seats' active sessions as it signals changes to
+/// them.
+pub struct Logind {
+    conn: Arc<SyncConnection>,
+    seats: watch::Receiver<Option<Seats>>,
+}
+
+impl Logind {
+    pub async fn connect() -> anyhow::Result<Self> {
+        let (resource, conn) = dbus_tokio::connection::new_system_sync()
+            .map_err(|e| anyhow::anyhow!("can't connect to the system bus: {}", e))?;
+        let (changed_tx, mut changed_rx) = mpsc::channel(1);
+        let lost_tx = changed_tx.clone();
+        tokio::spawn(async move {
+            resource.await;
+            // the next look at the seats fails, so nothing's let through
+            let _ = lost_tx.send(()).await;
+        });
+        // sessions coming and going, and the seats' and sessions' properties changing (such as
+        // which session is a seat's active one)
+        let mut matches = vec![];
+        for rule in [
+            MatchRule::new_signal(MANAGER, "SessionNew"),
+            MatchRule::new_signal(MANAGER, "SessionRemoved"),
+            MatchRule::new_signal("org.freedesktop.DBus.Properties", "PropertiesChanged"),
+        ] {
+            let mut rule = rule.with_sender(LOGIND);
+            rule.path_is_namespace = true;
+            rule.path = Some(LOGIND_PATH.into());
+            let changed_tx = changed_tx.clone();
+            let m = conn
+                .add_match(rule)
+                .await
+                .map_err(|e| anyhow::anyhow!("can't watch logind: {}", e))?
+                .msg_cb(move |_| {
+                    // a look already due covers this change too
+                    let _ = changed_tx.try_send(());
+                    true
+                });
+            matches.push(m);
+        }
+        let seats = active_sessions(&conn)
+            .await
+            .map_err(|e| anyhow::anyhow!("can't ask logind about the seats' sessions: {}", e))?;
+        let (seats_tx, seats_rx) = watch::channel(Some(seats));
+        let task_conn = conn.clone();
+        tokio::spawn(async move {
+            // the matches last as long as this does
+            let _matches = matches;
+            while changed_rx.recv().await.is_some() {
+                let seats = active_sessions(&task_conn).await.ok();
+                seats_tx.send_if_modified(|old| {
+                    let changed = *old != seats;
+                    *old = seats;
+                    changed
+                });
+            }
+        });
+        Ok(Logind {
+            conn,
+            seats: seats_rx,
+        })
+    }
+
+    /// The seats' active sessions' users, or `None` while logind can't be asked, updated as they
+    /// change.
+    pub fn seats(&self) -> watch::Receiver<Option<Seats>> {
+        self.seats.clone()
+    }
+
+    /// Move the device at `sys_path` (e.g. joykbd's virtual device) to `seat`, so its input goes
+    /// to that seat's active session. logind remembers this with a udev rule.
+    pub async fn attach(&self, seat: &str, sys_path: &Path) -> anyhow::Result<()> {
+        let sys_path = sys_path
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("{} isn't UTF-8", sys_path.display()))?;
+        self.manager()
+            .method_call::<(), _, _, _>(MANAGER, "AttachDevice", (seat, sys_path, false))
+            .await
+            .map_err(|e| anyhow::anyhow!("logind couldn't move it to {}: {}", seat, e))
+    }
+
+    fn manager(&self) -> Proxy<'static, &SyncConnection> {
+        Proxy::new(LOGIND, LOGIND_PATH, TIMEOUT, &*self.conn)
+    }
+}
+
+/// Ask logind for the user of each seat's active session.
+async fn active_sessions(conn: &SyncConnection) -> Result<Seats, dbus::Error> {
+    let manager = Proxy::new(LOGIND, LOGIND_PATH, TIMEOUT, conn);
+    let (seats,): (Vec<(String, dbus::Path<'static>)>,) =
+        manager.method_call(MANAGER, "ListSeats", ()).await?;
+    let mut active = Seats::new();
+    for (seat, path) in seats {
+        let seat_proxy = Proxy::new(LOGIND, path, TIMEOUT, conn);
+        let (_, session): (String, dbus::Path<'static>) = seat_proxy
+            .get("org.freedesktop.login1.Seat", "ActiveSession")
+            .await?;
+        // no one's logged in on the seat
+        if &*session == "/" {
+            continue;
+        }
+        let session_proxy = Proxy::new(LOGIND, session, TIMEOUT, conn);
+        let user: String = session_proxy
+            .get("org.freedesktop.login1.Session", "Name")
+            .await?;
+        active.insert(seat, user);
+    }
+    Ok(active)
+}
@@ -0,0 +1,37 @@
+//! Turning the controller off from the couch, with the `power-off` action or control socket
+//! command, instead of waiting for its idle timer. The controller is disconnected through BlueZ,
+//! after which a Joy-Con goes to sleep until a button wakes it up again.
+
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// Disconnect the Bluetooth controller whose unique name (its address) is `uniq`, in the
+/// background.
+pub fn power_off(uniq: Option<&str>) -> Result<(), String> {
+    let address = uniq
+        .filter(|uniq| is_address(uniq))
+        .ok_or_else(|| "the controller isn't connected over Bluetooth".to_owned())?
+        .to_owned();
+    tokio::spawn(async move {
+        let res = Command::new("bluetoothctl")
+            .args(["disconnect", &address])
+            .stdout(Stdio::null())
+            .status()
+            .await;
+        match res {
+            Ok(status) if status.success() => eprintln!("Disconnected {}", address),
+            Ok(status) => eprintln!("warning: bluetoothctl disconnect failed: {}", status),
+            Err(e) => eprintln!("warning: failed to run bluetoothctl: {}", e),
+        }
+    });
+    Ok(())
+}
+
+/// Whether `s` looks like a Bluetooth address, e.g. `98:b6:e9:12:34:56`.
+fn is_address(s: &str) -> bool {
+    let bytes: Vec<_> = s.split(':').collect();
+    bytes.len() == 6
+        && bytes
+            .iter()
+            .all(|byte| byte.len() == 2 && byte.chars().all(|c| c.is_ascii_hexdigit()))
+}
@@ -0,0 +1,67 @@
+//! Noticing a hand near the right Joy-Con (or Pro Controller) with its IR camera, through the
+//! hidraw backend, e.g. to wake an HTPC as someone reaches for the controller. Only the camera's
+//! coarse "moment" readings (how much of its own IR light reflects back) are used; no image is
+//! ever transferred.
+//!
+//! Like NFC, the camera is behind the controller's MCU, see [`crate::nfc`].
+
+use crate::nfc;
+
+/// The MCU's IR camera mode, see [`nfc::mcu_mode`].
+pub const MCU_MODE_IR: u8 = 0x05;
+
+/// The camera's mode that only reports overall intensity.
+const IR_MODE_MOMENT: u8 = 0x03;
+/// The MCU's report type for the camera's readings.
+const MCU_IR_DATA: u8 = 0x03;
+/// Where the MCU's data starts in a 0x31 report.
+const MCU_DATA: usize = 49;
+/// Where a moment reading's intensity is, within the MCU's data.
+const INTENSITY: usize = 10;
+
+/// The arguments of the MCU config subcommand that put the camera in its moment mode.
+pub fn ir_moment_mode() -> [u8; 38] {
+    let mut args = [0; 38];
+    // the mode, with no image fragments, for the camera firmware version 5.18
+    args[..8].copy_from_slice(&[0x23, 0x01, IR_MODE_MOMENT, 0x00, 0x00, 0x05, 0x00, 0x18]);
+    args[37] = nfc::crc8(&args[1..37]);
+    args
+}
+
+/// The camera's coarse intensity reading (0-255) in `report`, if it has one.
+pub fn intensity(report: &[u8]) -> Option<u8> {
+    let mcu = report.get(MCU_DATA..)?;
+    if report[0] != nfc::REPORT_NFC_IR || mcu.first() != Some(&MCU_IR_DATA) {
+        return None;
+    }
+    mcu.get(INTENSITY).copied()
+}
+
+/// Turns intensity readings into a hand arriving near the camera or leaving it.
+pub struct Presence {
+    /// The intensity above which something's near.
+    threshold: u8,
+    near: bool,
+}
+
+impl Presence {
+    pub fn new(threshold: u8) -> Self {
+        Presence {
+            threshold,
+            near: false,
+        }
+    }
+
+    /// Returns whether something is now near, if that changed. It has to drop a bit below the
+    /// threshold to count as gone, so readings around it don't flicker.
+    pub fn update(&mut self, intensity: u8) -> Option<bool> {
+        let near = match self.near {
+            true => u32::from(intensity) * 5 >= u32::from(self.threshold) * 4,
+            false => intensity >= self.threshold,
+        };
+        (near != self.near).then(|| {
+            self.near = near;
+            near
+        })
+    }
+}
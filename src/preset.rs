@@ -0,0 +1,233 @@
+//! Default button mappings for the different controllers joykbd knows about, picked based on the
+//! device's name, or its vendor and product IDs if the name isn't one joykbd knows (e.g. renamed
+//! by a driver or bridge).
+
+use evdev::{AttributeSet, InputId, Key};
+use std::collections::HashMap;
+
+/// Source buttons and the output key they're all mapped to.
+pub type ButtonMap = &'static [(&'static [Key], Key)];
+
+pub struct Preset {
+    pub name: &'static str,
+    /// Substrings of the evdev device name that identify this kind of controller.
+    pub device_names: &'static [&'static str],
+    /// The USB/bluetooth vendor and product IDs of this kind of controller.
+    pub ids: &'static [(u16, u16)],
+    pub buttons: ButtonMap,
+}
+
+impl Preset {
+    pub fn matches(&self, device_name: &str) -> bool {
+        self.device_names
+            .iter()
+            .any(|name| device_name.contains(name))
+    }
+
+    pub fn matches_id(&self, id: &InputId) -> bool {
+        self.ids.contains(&(id.vendor(), id.product()))
+    }
+
+    pub fn button(&self, key: Key) -> Option<Key> {
+        self.buttons
+            .iter()
+            .find(|(from, _)| from.contains(&key))
+            .map(|&(_, to)| to)
+    }
+}
+
+/// Changes to which buttons click which mouse button, on top of a preset's mapping.
+#[derive(Default)]
+pub struct PointerButtons {
+    /// Source buttons assigned to mouse buttons.
+    clicks: HashMap<Key, Key>,
+    /// The mouse buttons that have been assigned, so the preset's buttons no longer click them.
+    assigned: AttributeSet<Key>,
+    left_handed: bool,
+}
+
+impl PointerButtons {
+    pub fn new(left: &[Key], right: &[Key], middle: &[Key], left_handed: bool) -> Self {
+        let mut pointer = PointerButtons {
+            left_handed,
+            ..Default::default()
+        };
+        for (from, to) in [
+            (left, Key::BTN_LEFT),
+            (right, Key::BTN_RIGHT),
+            (middle, Key::BTN_MIDDLE),
+        ] {
+            for &key in from {
+                pointer.clicks.insert(key, to);
+                pointer.assigned.insert(to);
+            }
+        }
+        pointer
+    }
+
+    /// What `key` maps to, using `preset` for everything but the changed mouse buttons.
+    pub fn button(&self, preset: &Preset, key: Key) -> Option<Key> {
+        let to = match self.clicks.get(&key) {
+            Some(&to) => to,
+            None => preset
+                .button(key)
+                .filter(|&to| !self.assigned.contains(to))?,
+        };
+        Some(match to {
+            Key::BTN_LEFT if self.left_handed => Key::BTN_RIGHT,
+            Key::BTN_RIGHT if self.left_handed => Key::BTN_LEFT,
+            to => to,
+        })
+    }
+}
+
+/// Nintendo's vendor ID.
+const NINTENDO: u16 = 0x057e;
+
+pub const JOYCON: Preset = Preset {
+    name: "joycon",
+    device_names: &["Joy-Con"],
+    // left, right, and the pair combined by joycond
+    ids: &[(NINTENDO, 0x2006), (NINTENDO, 0x2007), (NINTENDO, 0x2008)],
+    buttons: &[
+        // ZL/ZR
+        (&[Key::BTN_TR2, Key::BTN_TL2], Key::BTN_LEFT),
+        // L
+        (&[Key::BTN_TR, Key::BTN_TL], Key::BTN_RIGHT),
+        // press R stick
+        (&[Key::BTN_THUMBR, Key::BTN_THUMBL], Key::BTN_MIDDLE),
+        // A
+        (&[Key::BTN_EAST], Key::KEY_RIGHT),
+        // B
+        (&[Key::BTN_SOUTH], Key::KEY_DOWN),
+        // X
+        (&[Key::BTN_NORTH], Key::KEY_UP),
+        // Y
+        (&[Key::BTN_WEST], Key::KEY_LEFT),
+    ],
+};
+
+/// The Nintendo Switch Online NES controllers, which only have a D-pad and A/B/Start/Select.
+pub const NES: Preset = Preset {
+    name: "nes",
+    device_names: &["Nintendo Switch NES Controller"],
+    // they report the Joy-Cons' IDs, so only their name tells them apart
+    ids: &[],
+    buttons: &[
+        (&[Key::BTN_DPAD_UP], Key::KEY_UP),
+        (&[Key::BTN_DPAD_DOWN], Key::KEY_DOWN),
+        (&[Key::BTN_DPAD_LEFT], Key::KEY_LEFT),
+        (&[Key::BTN_DPAD_RIGHT], Key::KEY_RIGHT),
+        // A
+        (&[Key::BTN_EAST], Key::KEY_ENTER),
+        // B
+        (&[Key::BTN_SOUTH], Key::KEY_ESC),
+        (&[Key::BTN_START], Key::KEY_SPACE),
+        (&[Key::BTN_SELECT], Key::KEY_TAB),
+    ],
+};
+
+/// The NSO SNES controller: no sticks, so it's a keyboard navigation remote.
+pub const SNES: Preset = Preset {
+    name: "snes",
+    device_names: &["Nintendo Switch SNES Controller"],
+    ids: &[(NINTENDO, 0x2017)],
+    buttons: &[
+        (&[Key::BTN_DPAD_UP], Key::KEY_UP),
+        (&[Key::BTN_DPAD_DOWN], Key::KEY_DOWN),
+        (&[Key::BTN_DPAD_LEFT], Key::KEY_LEFT),
+        (&[Key::BTN_DPAD_RIGHT], Key::KEY_RIGHT),
+        // A
+        (&[Key::BTN_EAST], Key::KEY_ENTER),
+        // B
+        (&[Key::BTN_SOUTH], Key::KEY_ESC),
+        // X
+        (&[Key::BTN_NORTH], Key::KEY_PAGEUP),
+        // Y
+        (&[Key::BTN_WEST], Key::KEY_PAGEDOWN),
+        // L/R
+        (&[Key::BTN_TL], Key::KEY_BACK),
+        (&[Key::BTN_TR], Key::KEY_FORWARD),
+        (&[Key::BTN_START], Key::KEY_SPACE),
+        (&[Key::BTN_SELECT], Key::KEY_TAB),
+    ],
+};
+
+/// The NSO N64 controller, whose stick drives the cursor like a joy-con's.
+pub const N64: Preset = Preset {
+    name: "n64",
+    device_names: &["Nintendo Switch N64 Controller"],
+    ids: &[(NINTENDO, 0x2019)],
+    buttons: &[
+        // Z
+        (&[Key::BTN_TL2], Key::BTN_LEFT),
+        // A
+        (&[Key::BTN_SOUTH], Key::BTN_LEFT),
+        // B
+        (&[Key::BTN_EAST], Key::BTN_RIGHT),
+        // L/R
+        (&[Key::BTN_TL, Key::BTN_TR], Key::BTN_MIDDLE),
+        (&[Key::BTN_DPAD_UP], Key::KEY_UP),
+        (&[Key::BTN_DPAD_DOWN], Key::KEY_DOWN),
+        (&[Key::BTN_DPAD_LEFT], Key::KEY_LEFT),
+        (&[Key::BTN_DPAD_RIGHT], Key::KEY_RIGHT),
+        (&[Key::BTN_START], Key::KEY_ENTER),
+    ],
+};
+
+/// A Wii Remote, as exposed by hid-wiimote. Its IR camera is a separate device, used to point.
+pub const WIIMOTE: Preset = Preset {
+    name: "wiimote",
+    device_names: &["Nintendo Wii Remote"],
+    // the original and the Wii Remote Plus
+    ids: &[(NINTENDO, 0x0306), (NINTENDO, 0x0330)],
+    buttons: &[
+        // A and B (hid-wiimote reports them as BTN_A/BTN_B, aliases of these)
+        (&[Key::BTN_SOUTH], Key::BTN_LEFT),
+        (&[Key::BTN_EAST], Key::BTN_RIGHT),
+        (&[Key::KEY_UP], Key::KEY_UP),
+        (&[Key::KEY_DOWN], Key::KEY_DOWN),
+        (&[Key::KEY_LEFT], Key::KEY_LEFT),
+        (&[Key::KEY_RIGHT], Key::KEY_RIGHT),
+        // +/-
+        (&[Key::KEY_NEXT], Key::KEY_VOLUMEUP),
+        (&[Key::KEY_PREVIOUS], Key::KEY_VOLUMEDOWN),
+        (&[Key::BTN_1], Key::KEY_ESC),
+        (&[Key::BTN_2], Key::KEY_ENTER),
+    ],
+};
+
+/// Every preset, in the order they're tried when detecting a device.
+pub const PRESETS: &[&Preset] = &[&JOYCON, &NES, &SNES, &N64, &WIIMOTE];
+
+/// Suffixes of the names of the extra devices that drivers create for a controller's sensors,
+/// which are never what we want to use as the main device.
+const SENSOR_SUFFIXES: &[&str] = &[" IMU", " IR", " Accelerometer", " Motion Plus"];
+
+/// Whether the device named `device_name` is one of a controller's sensors rather than the
+/// controller itself.
+pub fn is_sensor(device_name: &str) -> bool {
+    SENSOR_SUFFIXES
+        .iter()
+        .any(|suffix| device_name.ends_with(suffix))
+}
+
+/// The preset for the device named `device_name` with IDs `id`, if it's a controller joykbd
+/// knows about. The name is tried first, since some controllers share their IDs.
+pub fn detect(device_name: &str, id: Option<InputId>) -> Option<&'static Preset> {
+    if is_sensor(device_name) {
+        return None;
+    }
+    PRESETS
+        .iter()
+        .copied()
+        .find(|p| p.matches(device_name))
+        .or_else(|| {
+            let id = id.as_ref()?;
+            PRESETS.iter().copied().find(|p| p.matches_id(id))
+        })
+}
+
+pub fn by_name(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().copied().find(|p| p.name == name)
+}
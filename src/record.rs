@@ -0,0 +1,121 @@
+//! Recording the events joykbd reads from every source (`--record`), one JSON object per line,
+//! e.g. to attach to a bug report about a mapping:
+//!
+//! ```text
+//! {"time":0.512,"source":"joy-con","type":1,"code":305,"value":1}
+//! ```
+//!
+//! With `--record-private`, the keys of keyboard sources are left out (their `code` is `null`,
+//! and scan codes aren't recorded at all), so whatever was typed, passwords included, isn't in
+//! the recording, while the controller's buttons and axes still are.
+//!
+//! Regardless, the latest events are kept in memory (keyboard keys left out the same way), for
+//! the control socket's `recent-events` command, and so for `joykbd report`.
+
+use crate::source::SourceKind;
+use evdev::{EventType, InputEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A recorded event.
+#[derive(Serialize, Deserialize)]
+pub struct Record {
+    /// Seconds since the recording started.
+    pub time: f64,
+    pub source: SourceKind,
+    #[serde(rename = "type")]
+    pub kind: u16,
+    /// The event's code, or `None` if it was redacted.
+    pub code: Option<u16>,
+    pub value: i32,
+}
+
+pub struct Recorder {
+    file: LineWriter<File>,
+    private: bool,
+    start: SystemTime,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, private: bool) -> io::Result<Self> {
+        Ok(Recorder {
+            file: LineWriter::new(File::create(path)?),
+            private,
+            start: SystemTime::now(),
+        })
+    }
+
+    pub fn record(&mut self, source: SourceKind, ev: &InputEvent) -> io::Result<()> {
+        let Some(record) = Record::new(self.start, self.private, source, ev) else {
+            return Ok(());
+        };
+        serde_json::to_writer(&mut self.file, &record)?;
+        self.file.write_all(b"\n")
+    }
+}
+
+impl Record {
+    /// `ev`, from `source`, as recorded since `start`, or `None` if it's left out for privacy.
+    fn new(start: SystemTime, private: bool, source: SourceKind, ev: &InputEvent) -> Option<Self> {
+        let mut code = Some(ev.code());
+        if private && source == SourceKind::Keyboard {
+            match ev.event_type() {
+                EventType::KEY => code = None,
+                EventType::SYNCHRONIZATION => {}
+                // e.g. MSC_SCAN, which gives the key away just the same
+                _ => return None,
+            }
+        }
+        // synthesized events (e.g. from the hidraw backend) aren't timestamped
+        let time = match ev.timestamp() {
+            UNIX_EPOCH => SystemTime::now(),
+            time => time,
+        };
+        Some(Record {
+            time: time.duration_since(start).unwrap_or_default().as_secs_f64(),
+            source,
+            kind: ev.event_type().0,
+            code,
+            value: ev.value(),
+        })
+    }
+}
+
+/// How many of the latest events [`History`] keeps.
+const HISTORY_LEN: usize = 1000;
+
+/// The latest events, with keyboard keys always left out like with `--record-private`.
+pub struct History {
+    records: VecDeque<Record>,
+    start: SystemTime,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            records: VecDeque::with_capacity(HISTORY_LEN),
+            start: SystemTime::now(),
+        }
+    }
+}
+
+impl History {
+    pub fn push(&mut self, source: SourceKind, ev: &InputEvent) {
+        let Some(record) = Record::new(self.start, true, source, ev) else {
+            return;
+        };
+        if self.records.len() == HISTORY_LEN {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    /// The events as a JSON array, oldest first.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.records).expect("records are always valid JSON")
+    }
+}
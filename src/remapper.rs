@@ -0,0 +1,212 @@
+//! Translating a source's events into what the virtual device emits: the bindings first, then
+//! each kind of source's default treatment, e.g. the stick moving the cursor. [`Remapper`] bundles
+//! the state that takes, for embedding joykbd's mapping in another program, and
+//! [`Remapper::feed`] is the path from a source's event to the [`VirtualOutput`] that joykbd's
+//! event loop and `joykbd verify` both take.
+
+use crate::bindings::{Bindings, KeyAction};
+use crate::mouse::MouseCurve;
+use crate::output::{stamp, VirtualOutput};
+use crate::preset::{PointerButtons, Preset};
+use crate::scroll::StickScroll;
+use crate::source::SourceKind;
+use crate::speed::Speed;
+use crate::stick::{StickConstants, StickSide};
+use crate::wiimote;
+use evdev::{EventType, InputEvent, InputEventKind, RelativeAxisType};
+use smallvec::{smallvec, SmallVec};
+use std::io;
+
+/// Everything the translation of one controller's events depends on.
+pub struct Remapper {
+    pub preset: &'static Preset,
+    pub pointer: PointerButtons,
+    pub bindings: Bindings,
+    pub stick_constants: StickConstants,
+    pub mouse_curve: MouseCurve,
+    /// How the speed modes scale the stick's motion on its way out.
+    pub(crate) speed: Speed,
+    /// The stick's motion scrolls instead while it's clicked in.
+    pub(crate) stick_scroll: StickScroll,
+    /// What a binding last set the drift threshold to, until it's taken.
+    drift_threshold: Option<u32>,
+}
+
+impl Remapper {
+    /// Translate like `preset`'s default mapping, with `bindings` on top and the stick moving the
+    /// cursor as `stick_constants` say.
+    pub fn new(
+        preset: &'static Preset,
+        bindings: Bindings,
+        stick_constants: StickConstants,
+    ) -> Self {
+        Remapper {
+            preset,
+            pointer: PointerButtons::default(),
+            bindings,
+            stick_constants,
+            mouse_curve: MouseCurve::new(1.0),
+            speed: Speed::new(1.0, 1.0, None),
+            stick_scroll: StickScroll::new(false, None),
+            drift_threshold: None,
+        }
+    }
+
+    /// Translate `ev`, from a source of `kind`, into what to emit for it, see [`map_event`].
+    pub fn translate(&mut self, kind: SourceKind, ev: InputEvent) -> SmallVec<[InputEvent; 4]> {
+        let threshold = self.stick_constants.drift_threshold;
+        let evs = map_event(
+            kind,
+            ev,
+            self.preset,
+            &self.pointer,
+            &mut self.bindings,
+            &mut self.stick_constants,
+            &mut self.mouse_curve,
+        );
+        if self.stick_constants.drift_threshold != threshold {
+            self.drift_threshold = Some(self.stick_constants.drift_threshold);
+        }
+        evs
+    }
+
+    /// Translate `ev`, from a source of `kind`, and emit the result to `output`: the stick's
+    /// motion scaled by the speed modes held (or scrolling, while it's clicked in), and everything
+    /// through the output's own handling (swapped clicks, sticky keys, rotation, ...). Returns
+    /// what it was translated into, before that, e.g. to repeat the stick's motion.
+    pub fn feed(
+        &mut self,
+        kind: SourceKind,
+        ev: InputEvent,
+        output: &mut VirtualOutput,
+    ) -> io::Result<SmallVec<[InputEvent; 4]>> {
+        let evs = self.translate(kind, ev);
+        output.set_clicks_swapped(self.bindings.clicks_swapped())?;
+        let from_stick = matches!(kind, SourceKind::JoyCon | SourceKind::Partner)
+            && matches!(ev.kind(), InputEventKind::AbsAxis(_));
+        for &mapped in &evs {
+            let mapped = stamp(mapped, &ev);
+            if from_stick {
+                self.speed.track(&mapped);
+                let scaled = self.speed.scale(mapped, &self.bindings);
+                output.emit(&self.stick_scroll.map(scaled))?;
+            } else {
+                output.emit(&[mapped])?;
+            }
+        }
+        Ok(evs)
+    }
+
+    /// The drift threshold a binding adjusted it to, if one did since the last call.
+    pub fn take_drift_threshold(&mut self) -> Option<u32> {
+        self.drift_threshold.take()
+    }
+}
+
+/// Translate `ev`, from a source of `kind`, with the state it depends on passed separately. What
+/// to emit for it comes after the releases it causes (e.g. of another member of a group), which
+/// go first, as they happened before it took effect.
+pub fn map_event(
+    kind: SourceKind,
+    ev: InputEvent,
+    preset: &Preset,
+    pointer: &PointerButtons,
+    bindings: &mut Bindings,
+    stick_constants: &mut StickConstants,
+    mouse_curve: &mut MouseCurve,
+) -> SmallVec<[InputEvent; 4]> {
+    let mapped = map_one(
+        kind,
+        ev,
+        preset,
+        pointer,
+        bindings,
+        stick_constants,
+        mouse_curve,
+    );
+    let mut evs: SmallVec<[InputEvent; 4]> = (bindings.take_releases())
+        .map(|key| InputEvent::new(EventType::KEY, key.code(), 0))
+        .collect();
+    // a held mod-tap binding's modifier goes down before the key it modifies
+    evs.extend((bindings.take_presses()).map(|key| InputEvent::new(EventType::KEY, key.code(), 1)));
+    evs.extend(mapped);
+    evs
+}
+
+fn map_one(
+    kind: SourceKind,
+    ev: InputEvent,
+    preset: &Preset,
+    pointer: &PointerButtons,
+    bindings: &mut Bindings,
+    stick_constants: &mut StickConstants,
+    mouse_curve: &mut MouseCurve,
+) -> SmallVec<[InputEvent; 2]> {
+    if let InputEventKind::Key(key) = ev.kind() {
+        match bindings.key(key, ev.value()) {
+            KeyAction::Emit(to) => {
+                return smallvec![InputEvent::new(EventType::KEY, to.code(), ev.value())]
+            }
+            KeyAction::Chord(to) => return to.events(ev.value()).into_iter().collect(),
+            KeyAction::Macro(steps) => return steps.events().collect(),
+            KeyAction::Nudge(axis, px) => {
+                return smallvec![InputEvent::new(EventType::RELATIVE, axis.0, px)]
+            }
+            KeyAction::DriftThreshold(adjust) => {
+                stick_constants.adjust_drift(adjust);
+                return smallvec![];
+            }
+            KeyAction::Consumed => return smallvec![],
+            KeyAction::Unbound => {}
+        }
+    }
+    match kind {
+        SourceKind::JoyCon | SourceKind::Partner => {}
+        SourceKind::Keyboard | SourceKind::Trackpad(_) => {
+            return match ev.kind() {
+                InputEventKind::Key(_) => smallvec![ev],
+                _ => smallvec![],
+            };
+        }
+        SourceKind::IrPointer => return wiimote::map_ir(ev).into_iter().collect(),
+        SourceKind::Imu => return smallvec![],
+        SourceKind::Mouse => {
+            return match ev.kind() {
+                InputEventKind::Key(_) => smallvec![ev],
+                InputEventKind::RelAxis(RelativeAxisType::REL_X) => smallvec![InputEvent::new(
+                    EventType::RELATIVE,
+                    RelativeAxisType::REL_X.0,
+                    mouse_curve.map(0, ev.value()),
+                )],
+                InputEventKind::RelAxis(RelativeAxisType::REL_Y) => smallvec![InputEvent::new(
+                    EventType::RELATIVE,
+                    RelativeAxisType::REL_Y.0,
+                    mouse_curve.map(1, ev.value()),
+                )],
+                InputEventKind::RelAxis(_) => smallvec![ev],
+                _ => smallvec![],
+            };
+        }
+    }
+    match ev.kind() {
+        InputEventKind::Key(key) => pointer
+            .button(preset, key)
+            .map(|to| InputEvent::new(EventType::KEY, to.code(), ev.value()))
+            .into_iter()
+            .collect(),
+        InputEventKind::AbsAxis(axis) => {
+            let Some((side, axis)) = StickSide::of(axis) else {
+                return smallvec![];
+            };
+            let motion = stick_constants.map_stick(side, axis, ev.value());
+            [RelativeAxisType::REL_X, RelativeAxisType::REL_Y]
+                .into_iter()
+                .zip(motion)
+                .filter_map(|(rel, value)| {
+                    Some(InputEvent::new(EventType::RELATIVE, rel.0, value?))
+                })
+                .collect()
+        }
+        _ => smallvec![],
+    }
+}
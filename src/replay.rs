@@ -0,0 +1,128 @@
+//! `joykbd replay`: playing a `--record` recording back through a virtual Joy-Con, for a joykbd
+//! started on it to translate like the real thing, e.g. to reproduce a bug report. It can be
+//! slowed down, paused at given points, or stepped through one report at a time.
+//!
+//! Only the controller's own events are played back; other sources' aren't.
+
+use crate::calibrate::prompt;
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{
+    AbsInfo, AbsoluteAxisType, AttributeSet, EventType, InputEvent, Key, Synchronization,
+    UinputAbsSetup,
+};
+use joykbd::record::Record;
+use joykbd::source::SourceKind;
+use std::fs;
+use std::path::Path;
+use tokio::time;
+
+/// The range of the virtual Joy-Con's stick axes, like hid-nintendo's after calibration.
+const STICK_RANGE: i32 = 32767;
+
+pub struct Options {
+    /// How fast to play the recording back, relative to how it was recorded.
+    pub speed: f64,
+    /// Where to pause, in seconds into the recording.
+    pub pause_at: Vec<f64>,
+    /// Wait for Enter before each report.
+    pub step: bool,
+}
+
+/// A report's worth of events, and when it was read, in seconds into the recording.
+struct Report {
+    time: f64,
+    events: Vec<InputEvent>,
+}
+
+pub async fn run(path: &Path, options: Options) -> anyhow::Result<()> {
+    let reports = load(path)?;
+    let mut device = create_device(&reports)?;
+    for path in device.enumerate_dev_nodes_blocking()? {
+        eprintln!("Replaying through {}", path?.display());
+    }
+    let mut pauses = options.pause_at.clone();
+    pauses.sort_by(f64::total_cmp);
+    let mut pauses = pauses.into_iter().peekable();
+    // when the recording would have started, moved along by every pause
+    let mut start = time::Instant::now();
+    for report in &reports {
+        if pauses.peek().is_some_and(|&at| report.time >= at) {
+            while pauses.next_if(|&at| report.time >= at).is_some() {}
+            prompt(&format!(
+                "Paused at {:.3}s, press Enter to go on",
+                report.time
+            ))
+            .await?;
+            start =
+                time::Instant::now() - time::Duration::from_secs_f64(report.time / options.speed);
+        }
+        if options.step {
+            for ev in &report.events {
+                eprintln!(
+                    "{:.3}s {:?} {} {}",
+                    report.time,
+                    ev.event_type(),
+                    ev.code(),
+                    ev.value()
+                );
+            }
+            prompt("Press Enter to play this report").await?;
+        } else {
+            time::sleep_until(start + time::Duration::from_secs_f64(report.time / options.speed))
+                .await;
+        }
+        device.emit(&report.events)?;
+    }
+    eprintln!("Done");
+    Ok(())
+}
+
+/// The controller's reports in the recording at `path`.
+fn load(path: &Path) -> anyhow::Result<Vec<Report>> {
+    let mut reports = vec![];
+    let mut events = vec![];
+    for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let record: Record = serde_json::from_str(line)
+            .map_err(|e| anyhow::anyhow!("{}:{}: {}", path.display(), i + 1, e))?;
+        let (SourceKind::JoyCon, Some(code)) = (record.source, record.code) else {
+            continue;
+        };
+        if record.kind == EventType::SYNCHRONIZATION.0 {
+            if code == Synchronization::SYN_REPORT.0 && !events.is_empty() {
+                reports.push(Report {
+                    time: record.time,
+                    events: std::mem::take(&mut events),
+                });
+            }
+            continue;
+        }
+        events.push(InputEvent::new(EventType(record.kind), code, record.value));
+    }
+    Ok(reports)
+}
+
+/// A virtual Joy-Con with every button and axis the recording uses.
+fn create_device(reports: &[Report]) -> anyhow::Result<VirtualDevice> {
+    let mut keys = AttributeSet::<Key>::new();
+    let mut axes = vec![];
+    for ev in reports.iter().flat_map(|report| &report.events) {
+        match ev.event_type() {
+            EventType::KEY => keys.insert(Key::new(ev.code())),
+            EventType::ABSOLUTE if !axes.contains(&ev.code()) => axes.push(ev.code()),
+            _ => {}
+        }
+    }
+    // hid-nintendo names a single Joy-Con after its side, which the right one's stick axes give
+    // away
+    let right = axes.contains(&AbsoluteAxisType::ABS_RX.0);
+    let name = match right {
+        true => "joykbd replay Joy-Con (R)",
+        false => "joykbd replay Joy-Con (L)",
+    };
+    let mut builder = VirtualDeviceBuilder::new()?.name(name).with_keys(&keys)?;
+    for axis in axes {
+        let info = AbsInfo::new(0, -STICK_RANGE, STICK_RANGE, 0, 0, 0);
+        builder = builder.with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType(axis), info))?;
+    }
+    Ok(builder.build()?)
+}
@@ -0,0 +1,228 @@
+//! `joykbd report`: bundling what's needed to look into a bug report into a single `.tar.gz` to
+//! attach to an issue: versions, the environment, the controllers' capabilities, the config file,
+//! and, from a running joykbd, its status and latest events (with keyboard keys left out, see
+//! [`joykbd::record`]). The home directory, hostname and controllers' addresses are redacted.
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use joykbd::config;
+use joykbd::ipc;
+use joykbd::preset;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Write a report to `output`, including the config file at `config` and asking the joykbd
+/// listening on `control_socket` for its state, if there is one.
+pub fn create(
+    output: &Path,
+    config: Option<&Path>,
+    control_socket: Option<&Path>,
+) -> anyhow::Result<()> {
+    let redactor = Redactor::new();
+    let mut files = vec![
+        ("versions.txt", versions()),
+        ("environment.txt", environment()),
+        ("devices.txt", devices()),
+    ];
+    if let Some(config) = config {
+        let contents = fs::read_to_string(config)
+            .unwrap_or_else(|e| format!("# can't read {}: {}\n", config.display(), e));
+        let name = match config::Format::of(config) {
+            config::Format::Toml => "config.toml",
+            config::Format::Yaml => "config.yaml",
+            config::Format::Json => "config.json",
+        };
+        files.push((name, contents));
+    }
+    for (name, command) in [
+        ("status.json", "status --json"),
+        ("recent-events.json", "recent-events"),
+    ] {
+        let reply = match control_socket.map(|path| ipc::send(path, command)) {
+            Some(Ok(Ok(reply))) => reply,
+            Some(Ok(Err(e))) => format!("error: {}", e),
+            Some(Err(e)) => format!("no running joykbd to ask: {}", e),
+            None => "no control socket to ask".to_owned(),
+        };
+        files.push((name, reply + "\n"));
+    }
+
+    let mtime = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut tar = tar::Builder::new(GzEncoder::new(
+        File::create(output)?,
+        Compression::default(),
+    ));
+    for (name, contents) in files {
+        let contents = redactor.redact(&contents);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        header.set_cksum();
+        tar.append_data(
+            &mut header,
+            format!("joykbd-report/{}", name),
+            contents.as_bytes(),
+        )?;
+    }
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
+
+fn versions() -> String {
+    let mut s = format!("joykbd {}\n", env!("CARGO_PKG_VERSION"));
+    let kernel = fs::read_to_string("/proc/sys/kernel/osrelease").unwrap_or_default();
+    let _ = writeln!(s, "kernel {}", kernel.trim());
+    let hid_nintendo = match Path::new("/sys/module/hid_nintendo").exists() {
+        true => "loaded",
+        false => "not loaded",
+    };
+    let _ = writeln!(s, "hid-nintendo {}", hid_nintendo);
+    let bluez = Command::new("bluetoothctl").arg("--version").output();
+    let bluez = match &bluez {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).trim().to_owned(),
+        Err(e) => format!("unknown ({})", e),
+    };
+    let _ = writeln!(s, "bluez {}", bluez);
+    s
+}
+
+fn environment() -> String {
+    let mut s = String::new();
+    for var in ["XDG_SESSION_TYPE", "XDG_CURRENT_DESKTOP", "XDG_SEAT"] {
+        let value = std::env::var(var).unwrap_or_else(|_| "(unset)".to_owned());
+        let _ = writeln!(s, "{}={}", var, value);
+    }
+    // whether they're set is what matters, not what they're set to
+    for var in [
+        "WAYLAND_DISPLAY",
+        "DISPLAY",
+        "INVOCATION_ID",
+        "NOTIFY_SOCKET",
+    ] {
+        let set = std::env::var_os(var).is_some();
+        let _ = writeln!(s, "{} {}", var, if set { "set" } else { "unset" });
+    }
+    let uinput = match fs::OpenOptions::new().write(true).open("/dev/uinput") {
+        Ok(_) => "writable".to_owned(),
+        Err(e) => e.to_string(),
+    };
+    let _ = writeln!(s, "/dev/uinput: {}", uinput);
+    s
+}
+
+/// The capabilities of every controller joykbd recognizes.
+fn devices() -> String {
+    let mut s = String::new();
+    for (path, dev) in evdev::enumerate() {
+        let name = dev.name().unwrap_or("");
+        let preset = match preset::detect(name, Some(dev.input_id())) {
+            Some(preset) => preset.name,
+            // the IMU and other sensors, which joykbd uses alongside the controllers
+            None if name.contains("Joy-Con") || name.contains("Nintendo") => "none",
+            None => continue,
+        };
+        let _ = writeln!(s, "{} ({}), preset {}", name, path.display(), preset);
+        if dev.unique_name().is_some() {
+            let _ = writeln!(s, "  unique name: (redacted)");
+        }
+        let id = dev.input_id();
+        let _ = writeln!(
+            s,
+            "  bus {:04x} vendor {:04x} product {:04x} version {:04x}",
+            id.bus_type().0,
+            id.vendor(),
+            id.product(),
+            id.version()
+        );
+        if let Some(keys) = dev.supported_keys() {
+            let keys: Vec<_> = keys.iter().map(|key| format!("{:?}", key)).collect();
+            let _ = writeln!(s, "  keys: {}", keys.join(" "));
+        }
+        if let (Some(axes), Ok(state)) = (dev.supported_absolute_axes(), dev.get_abs_state()) {
+            for axis in axes.iter() {
+                let info = &state[axis.0 as usize];
+                let _ = writeln!(
+                    s,
+                    "  {:?}: {}..{}, fuzz {}, flat {}, resolution {}",
+                    axis, info.minimum, info.maximum, info.fuzz, info.flat, info.resolution
+                );
+            }
+        }
+    }
+    if s.is_empty() {
+        s.push_str("no controllers found\n");
+    }
+    s
+}
+
+/// Takes out what identifies the user or machine.
+struct Redactor {
+    home: Option<String>,
+    hostname: Option<String>,
+}
+
+impl Redactor {
+    fn new() -> Self {
+        let hostname = fs::read_to_string("/proc/sys/kernel/hostname")
+            .ok()
+            .map(|s| s.trim().to_owned())
+            // a short one would be replaced all over the place
+            .filter(|s| s.len() >= 4);
+        Redactor {
+            home: std::env::var("HOME").ok().filter(|home| home != "/"),
+            hostname,
+        }
+    }
+
+    fn redact(&self, s: &str) -> String {
+        let mut s = s.to_owned();
+        if let Some(home) = &self.home {
+            s = s.replace(home.as_str(), "~");
+        }
+        if let Some(hostname) = &self.hostname {
+            s = s.replace(hostname.as_str(), "<hostname>");
+        }
+        redact_addresses(&s)
+    }
+}
+
+/// Replace anything that looks like a bluetooth address (`aa:bb:cc:dd:ee:ff`).
+fn redact_addresses(s: &str) -> String {
+    const LEN: usize = 17;
+    let is_address = |window: &[u8]| {
+        window.iter().enumerate().all(|(i, &b)| match i % 3 {
+            2 => b == b':',
+            _ => b.is_ascii_hexdigit(),
+        })
+    };
+    let bytes = s.as_bytes();
+    let mut out = String::with_capacity(s.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes.get(i..i + LEN).is_some_and(is_address) {
+            out.push_str("<address>");
+            i += LEN;
+        } else {
+            let c = s[i..].chars().next().unwrap();
+            out.push(c);
+            i += c.len_utf8();
+        }
+    }
+    out
+}
+
+/// Where to write the report by default, named after the current time.
+pub fn default_output() -> PathBuf {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    PathBuf::from(format!("joykbd-report-{}.tar.gz", now))
+}
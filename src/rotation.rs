@@ -0,0 +1,57 @@
+//! Rotating the cursor's motion to match a rotated display, e.g. a portrait monitor, so pushing
+//! the stick up still moves the cursor up on screen.
+
+use evdev::{EventType, InputEvent, InputEventKind, RelativeAxisType};
+use std::fmt;
+use std::str::FromStr;
+
+/// A clockwise rotation of the display, in steps of 90 degrees.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rotation(u8);
+
+impl FromStr for Rotation {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(Rotation(0)),
+            "90" => Ok(Rotation(1)),
+            "180" => Ok(Rotation(2)),
+            "270" => Ok(Rotation(3)),
+            _ => Err(format!(
+                "invalid rotation {:?}, expected 0, 90, 180 or 270",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.degrees())
+    }
+}
+
+impl Rotation {
+    pub fn degrees(self) -> u16 {
+        u16::from(self.0) * 90
+    }
+
+    /// Rotate relative cursor motion; everything else is passed through as is.
+    pub fn apply(self, ev: InputEvent) -> InputEvent {
+        let (axis, value) = match (ev.kind(), self.0) {
+            (_, 0) => return ev,
+            (InputEventKind::RelAxis(RelativeAxisType::REL_X), 1) => (RelativeAxisType::REL_Y, 1),
+            (InputEventKind::RelAxis(RelativeAxisType::REL_Y), 1) => (RelativeAxisType::REL_X, -1),
+            (InputEventKind::RelAxis(RelativeAxisType::REL_X), 2) => (RelativeAxisType::REL_X, -1),
+            (InputEventKind::RelAxis(RelativeAxisType::REL_Y), 2) => (RelativeAxisType::REL_Y, -1),
+            (InputEventKind::RelAxis(RelativeAxisType::REL_X), 3) => (RelativeAxisType::REL_Y, -1),
+            (InputEventKind::RelAxis(RelativeAxisType::REL_Y), 3) => (RelativeAxisType::REL_X, 1),
+            _ => return ev,
+        };
+        let mut raw = *ev.as_ref();
+        raw.type_ = EventType::RELATIVE.0;
+        raw.code = axis.0;
+        raw.value *= value;
+        raw.into()
+    }
+}
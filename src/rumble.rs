@@ -0,0 +1,285 @@
+//! Playing rumble patterns on the joy-con via evdev force feedback, and, through the hidraw
+//! backend, HD rumble envelopes that vary the frequency as well as the strength over time.
+//!
+//! With `--haptics`, short patterns also confirm clicks, profile switches and the stick leaving
+//! the deadzone, see [`Haptic`].
+//!
+//! Envelopes are named in the config file, and can be played over the control socket
+//! (`rumble NAME`) or in place of a feedback cue's sound, by naming them after the cue:
+//!
+//! ```toml
+//! [rumble.layer-on]
+//! points = [{ t = 0, freq = 160, amp = 0.0 }, { t = 40, freq = 320, amp = 0.6 }, { t = 80, freq = 320, amp = 0.0 }]
+//! ```
+//!
+//! The encoding of frequencies and amplitudes follows dekuNukem's reverse-engineering notes.
+
+use evdev::{Device, FFEffectData, FFEffectKind, FFEffectType, FFReplay, FFTrigger};
+use serde::Deserialize;
+use smallvec::{smallvec, SmallVec};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use tokio::sync::mpsc;
+use tokio::time;
+
+/// A rumble pattern: alternating on/off durations in milliseconds, starting with "on", played at
+/// the given intensity (0 to 1).
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pub segments: SmallVec<[u16; 4]>,
+    pub intensity: f64,
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+    /// Parses e.g. `200,100,200` or `200,100,200@0.5`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (segments, intensity) = match s.split_once('@') {
+            Some((segments, intensity)) => (
+                segments,
+                intensity
+                    .parse::<f64>()
+                    .map_err(|e| format!("invalid intensity: {}", e))?,
+            ),
+            None => (s, 1.0),
+        };
+        if !(0.0..=1.0).contains(&intensity) {
+            return Err("intensity must be between 0 and 1".to_owned());
+        }
+        let segments = segments
+            .split(',')
+            .map(|ms| ms.trim().parse())
+            .collect::<Result<SmallVec<_>, _>>()
+            .map_err(|e| format!("invalid duration: {}", e))?;
+        Ok(Pattern {
+            segments,
+            intensity,
+        })
+    }
+}
+
+/// What `--haptics` rumbles for.
+#[derive(Clone, Copy)]
+pub enum Haptic {
+    /// A mouse button was pressed.
+    Click,
+    ProfileSwitch,
+    /// The stick was pushed past the drift threshold, and the cursor started moving.
+    DriftThreshold,
+}
+
+impl Haptic {
+    fn pattern(self) -> Pattern {
+        let (segments, intensity) = match self {
+            Haptic::Click => (smallvec![30], 0.5),
+            Haptic::ProfileSwitch => (smallvec![80, 60, 80], 0.8),
+            // the lightest tick, since it's felt every time the stick's pushed
+            Haptic::DriftThreshold => (smallvec![15], 0.25),
+        };
+        Pattern {
+            segments,
+            intensity,
+        }
+    }
+}
+
+/// A handle to the task that plays rumble patterns, one after the other.
+#[derive(Clone)]
+pub struct Rumbler {
+    tx: mpsc::Sender<Pattern>,
+}
+
+impl Rumbler {
+    /// Open the device at `path` for force feedback and start the playback task.
+    pub fn spawn(path: &Path) -> io::Result<Self> {
+        let mut dev = Device::open(path)?;
+        if !dev
+            .supported_ff()
+            .is_some_and(|ff| ff.contains(FFEffectType::FF_RUMBLE))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the device doesn't support rumble",
+            ));
+        }
+        let mut effect = dev.upload_ff_effect(effect_data(0, 0.0))?;
+        let (tx, mut rx) = mpsc::channel::<Pattern>(8);
+        tokio::spawn(async move {
+            while let Some(pattern) = rx.recv().await {
+                for (i, &ms) in pattern.segments.iter().enumerate() {
+                    if i % 2 == 0 {
+                        let res = effect
+                            .update(effect_data(ms, pattern.intensity))
+                            .and_then(|()| effect.play(1));
+                        if let Err(e) = res {
+                            eprintln!("warning: failed to rumble: {}", e);
+                            break;
+                        }
+                    }
+                    time::sleep(time::Duration::from_millis(ms.into())).await;
+                }
+            }
+        });
+        Ok(Rumbler { tx })
+    }
+
+    /// Queue `pattern` to be played.
+    pub fn rumble(&self, pattern: Pattern) -> Result<(), String> {
+        self.tx
+            .try_send(pattern)
+            .map_err(|_| "too many rumble patterns queued".to_owned())
+    }
+
+    /// Queue `haptic`'s pattern, unless there's already too much queued for it to be felt in
+    /// time anyway.
+    pub fn haptic(&self, haptic: Haptic) {
+        let _ = self.rumble(haptic.pattern());
+    }
+}
+
+/// One point of an [`Envelope`].
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Point {
+    /// Milliseconds since the start.
+    pub t: u32,
+    /// Hz, from about 40 to 1250.
+    pub freq: f64,
+    /// 0 to 1.
+    pub amp: f64,
+}
+
+/// A frequency/amplitude envelope, interpolated linearly between its points and silent after the
+/// last one.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Envelope {
+    pub points: Vec<Point>,
+}
+
+impl Envelope {
+    /// Check that the envelope can be played, e.g. that its points are in order.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.points.is_empty() {
+            return Err("it has no points".into());
+        }
+        if self.points.windows(2).any(|w| w[0].t > w[1].t) {
+            return Err("its points must be in order of time".into());
+        }
+        if self.points.iter().any(|p| !(0.0..=1.0).contains(&p.amp)) {
+            return Err("amplitudes must be between 0 and 1".into());
+        }
+        if self.points.iter().any(|p| p.freq <= 0.0) {
+            return Err("frequencies must be positive".into());
+        }
+        Ok(())
+    }
+
+    /// The frequency and amplitude `t` ms in, or `None` once it's over.
+    fn at(&self, t: u32) -> Option<(f64, f64)> {
+        match self.points.iter().position(|p| p.t > t) {
+            Some(0) => Some((self.points[0].freq, 0.0)),
+            Some(i) => {
+                let (a, b) = (self.points[i - 1], self.points[i]);
+                let k = f64::from(t - a.t) / f64::from(b.t - a.t);
+                Some((a.freq + (b.freq - a.freq) * k, a.amp + (b.amp - a.amp) * k))
+            }
+            None => None,
+        }
+    }
+}
+
+/// The output report with just rumble data.
+const OUTPUT_RUMBLE: u8 = 0x10;
+/// How often the envelope's next step is sent. The controller keeps playing the last one it got
+/// until the next arrives.
+const HD_STEP: time::Duration = time::Duration::from_millis(10);
+/// The ranges of the high and low frequency bands, in Hz.
+const HIGH_BAND: (f64, f64) = (81.75, 1252.57);
+const LOW_BAND: (f64, f64) = (40.88, 626.29);
+/// One side's rumble data that doesn't rumble.
+const NEUTRAL: [u8; 4] = [0x00, 0x01, 0x40, 0x40];
+
+/// The 4 bytes of rumble data for one side, playing `freq` on whichever band(s) can at `amp`.
+fn encode(freq: f64, amp: f64) -> [u8; 4] {
+    let freq_code = |(min, max): (f64, f64)| ((freq.clamp(min, max) / 10.0).log2() * 32.0).round();
+    let hf = ((freq_code(HIGH_BAND) - 96.0) * 4.0) as u16;
+    let lf = (freq_code(LOW_BAND) - 64.0) as u8;
+    let amp = amp.clamp(0.0, 1.0);
+    let amp_code = if amp > 0.23 {
+        ((amp * 8.7).log2() * 32.0).round()
+    } else if amp > 0.12 {
+        ((amp * 17.0).log2() * 16.0).round()
+    } else {
+        // the table gets too fine to follow down here, so just fade out
+        (amp / 0.12 * 16.0).round()
+    } as u8;
+    let hf_amp = amp_code * 2;
+    let lf_amp = amp_code / 2 + 0x40;
+    // an odd amplitude code's low bit goes in the top bit of the low frequency's byte
+    let lf = lf | (amp_code & 1) << 7;
+    [hf as u8, hf_amp.wrapping_add((hf >> 8) as u8), lf, lf_amp]
+}
+
+/// A handle to the task that plays HD rumble envelopes through a hidraw device, one after the
+/// other.
+#[derive(Clone)]
+pub struct HdRumbler {
+    tx: mpsc::Sender<Envelope>,
+}
+
+impl HdRumbler {
+    /// Start the playback task, writing rumble reports to `file`.
+    pub fn spawn(mut file: File) -> Self {
+        let (tx, mut rx) = mpsc::channel::<Envelope>(8);
+        tokio::spawn(async move {
+            let mut counter = 0u8;
+            let mut send = move |data: [u8; 4]| {
+                let mut report = vec![OUTPUT_RUMBLE, counter];
+                // the same on both sides
+                report.extend_from_slice(&data);
+                report.extend_from_slice(&data);
+                counter = (counter + 1) & 0x0f;
+                file.write_all(&report)
+            };
+            while let Some(envelope) = rx.recv().await {
+                let start = time::Instant::now();
+                let mut step = time::interval(HD_STEP);
+                let mut res = Ok(());
+                while let Some((freq, amp)) = envelope.at(start.elapsed().as_millis() as u32) {
+                    res = send(encode(freq, amp));
+                    if res.is_err() {
+                        break;
+                    }
+                    step.tick().await;
+                }
+                if let Err(e) = res.and_then(|()| send(NEUTRAL)) {
+                    eprintln!("warning: failed to rumble: {}", e);
+                }
+            }
+        });
+        HdRumbler { tx }
+    }
+
+    /// Queue `envelope` to be played.
+    pub fn play(&self, envelope: Envelope) -> Result<(), String> {
+        self.tx
+            .try_send(envelope)
+            .map_err(|_| "too many rumble patterns queued".to_owned())
+    }
+}
+
+fn effect_data(length: u16, intensity: f64) -> FFEffectData {
+    let magnitude = (intensity * f64::from(u16::MAX)) as u16;
+    FFEffectData {
+        direction: 0,
+        trigger: FFTrigger::default(),
+        replay: FFReplay { length, delay: 0 },
+        kind: FFEffectKind::Rumble {
+            strong_magnitude: magnitude,
+            weak_magnitude: magnitude,
+        },
+    }
+}
@@ -0,0 +1,122 @@
+//! Scrolling with the stick while it's clicked in, like holding a ThinkPad's middle button while
+//! using the TrackPoint. Clicking the stick without moving it still middle-clicks. Optionally,
+//! pushing the stick hard pages instead of scrolling line by line.
+
+use crate::bindings::Bindings;
+use evdev::{EventType, InputEvent, InputEventKind, Key, RelativeAxisType};
+use smallvec::{smallvec, SmallVec};
+
+/// How far the cursor would have moved, in pixels, for each step of the scroll wheel.
+const PIXELS_PER_STEP: f64 = 60.0;
+
+/// The keys that a hard push of the stick pages with, for the horizontal (right, left) and
+/// vertical (up, down) axes.
+const PAGE_KEYS: [[Key; 2]; 2] = [
+    [Key::KEY_END, Key::KEY_HOME],
+    [Key::KEY_PAGEUP, Key::KEY_PAGEDOWN],
+];
+
+pub struct StickScroll {
+    enabled: bool,
+    /// How hard the stick has to be pushed to page instead of scroll, if it can at all.
+    page_threshold: Option<i32>,
+    /// Whether the stick has paged along each axis since it was last pushed less than that.
+    paged: [bool; 2],
+    held: bool,
+    /// Whether the stick was moved since it was clicked in.
+    moved: bool,
+    remainder: [f64; 2],
+}
+
+impl StickScroll {
+    pub fn new(enabled: bool, page_threshold: Option<i32>) -> Self {
+        StickScroll {
+            enabled,
+            page_threshold,
+            paged: [false; 2],
+            held: false,
+            moved: false,
+            remainder: [0.0; 2],
+        }
+    }
+
+    /// Whether `ev` is a stick click that we handle, rather than the bindings/preset.
+    pub fn handles(&self, ev: &InputEvent, bindings: &Bindings) -> bool {
+        match ev.kind() {
+            InputEventKind::Key(key @ (Key::BTN_THUMBL | Key::BTN_THUMBR)) => {
+                self.enabled && !bindings.is_bound(key)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn press(&mut self) {
+        self.held = true;
+        self.moved = false;
+        self.remainder = [0.0; 2];
+    }
+
+    /// Returns whether the stick should be clicked, since it wasn't moved while held.
+    pub fn release(&mut self) -> bool {
+        self.held = false;
+        !self.moved
+    }
+
+    /// The keys paging can emit.
+    pub fn page_keys(&self) -> impl Iterator<Item = Key> {
+        let keys = match self.page_threshold {
+            Some(_) => &PAGE_KEYS[..],
+            None => &[],
+        };
+        keys.iter().flat_map(|keys| keys.iter().copied())
+    }
+
+    /// Turn cursor motion from the stick into scrolling while the stick is held in.
+    pub fn map(&mut self, ev: InputEvent) -> SmallVec<[InputEvent; 2]> {
+        if !self.held {
+            return smallvec![ev];
+        }
+        if matches!(ev.kind(), InputEventKind::RelAxis(_)) && ev.value() != 0 {
+            self.moved = true;
+        }
+        self.wheel(ev)
+    }
+
+    /// Turn cursor motion into scrolling, whether or not the stick is held in.
+    pub fn wheel(&mut self, ev: InputEvent) -> SmallVec<[InputEvent; 2]> {
+        let (axis, i, value) = match ev.kind() {
+            InputEventKind::RelAxis(RelativeAxisType::REL_X) => {
+                (RelativeAxisType::REL_HWHEEL, 0, ev.value())
+            }
+            // pushing the stick down scrolls down, which is a negative wheel movement
+            InputEventKind::RelAxis(RelativeAxisType::REL_Y) => {
+                (RelativeAxisType::REL_WHEEL, 1, -ev.value())
+            }
+            _ => return smallvec![ev],
+        };
+        if let Some(threshold) = self.page_threshold {
+            if value.abs() < threshold {
+                self.paged[i] = false;
+            } else {
+                // one page per push, however long it's held for
+                if self.paged[i] {
+                    return smallvec![];
+                }
+                self.paged[i] = true;
+                self.remainder[i] = 0.0;
+                let key = PAGE_KEYS[i][usize::from(value < 0)];
+                return smallvec![
+                    InputEvent::new(EventType::KEY, key.code(), 1),
+                    InputEvent::new(EventType::KEY, key.code(), 0),
+                ];
+            }
+        }
+        let exact = f64::from(value) / PIXELS_PER_STEP + self.remainder[i];
+        let whole = exact.trunc();
+        self.remainder[i] = exact - whole;
+        if whole == 0.0 {
+            return smallvec![];
+        }
+        smallvec![InputEvent::new(EventType::RELATIVE, axis.0, whole as i32)]
+    }
+}
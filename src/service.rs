@@ -0,0 +1,108 @@
+//! Starting joykbd whenever a controller shows up, e.g. when a sleeping Joy-Con is woken by
+//! pressing any of its buttons: `joykbd install-service` writes a udev rule that matches the
+//! controller's event device and has systemd start an instance of a `joykbd@.service` template
+//! for it, which stops again once the device goes away.
+
+use joykbd::option_set;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The name of the systemd unit template.
+const UNIT: &str = "joykbd@.service";
+/// The name of the udev rule's file.
+const RULE: &str = "70-joykbd.rules";
+
+/// Where the unit and the rule go.
+pub struct Locations {
+    pub unit_dir: PathBuf,
+    pub rule_dir: PathBuf,
+}
+
+impl Locations {
+    /// The standard locations for a system service, or a user service if `user`.
+    pub fn standard(user: bool) -> Option<Self> {
+        let unit_dir = match user {
+            // next to joykbd's own config dir
+            true => option_set::config_dir()?.parent()?.join("systemd/user"),
+            false => PathBuf::from("/etc/systemd/system"),
+        };
+        Some(Locations {
+            unit_dir,
+            rule_dir: PathBuf::from("/etc/udev/rules.d"),
+        })
+    }
+
+    /// Both files in `dir`, e.g. to package them.
+    pub fn in_dir(dir: &Path) -> Self {
+        Locations {
+            unit_dir: dir.to_owned(),
+            rule_dir: dir.to_owned(),
+        }
+    }
+}
+
+/// Write the unit template and the udev rule, for running `exe` with `args` (and the device),
+/// returning the files written.
+pub fn install(
+    locations: &Locations,
+    user: bool,
+    exe: &Path,
+    args: &[String],
+) -> io::Result<Vec<PathBuf>> {
+    let unit = locations.unit_dir.join(UNIT);
+    let rule = locations.rule_dir.join(RULE);
+    fs::create_dir_all(&locations.unit_dir)?;
+    fs::write(&unit, unit_file(exe, args))?;
+    fs::create_dir_all(&locations.rule_dir)?;
+    fs::write(&rule, udev_rule(user))?;
+    Ok(vec![unit, rule])
+}
+
+/// The unit template, whose instance name is the event device's kernel name, e.g. event5.
+fn unit_file(exe: &Path, args: &[String]) -> String {
+    let mut command = quote(&exe.to_string_lossy());
+    for arg in args {
+        command.push(' ');
+        command.push_str(&quote(arg));
+    }
+    format!(
+        "[Unit]
+Description=joykbd for /dev/input/%I
+BindsTo=dev-input-%i.device
+After=dev-input-%i.device
+
+[Service]
+ExecStart={} /dev/input/%I
+",
+        command
+    )
+}
+
+/// The rule matching the controllers' event devices (but not their IMUs), and starting an
+/// instance of the unit for each.
+fn udev_rule(user: bool) -> String {
+    let wants = match user {
+        true => "SYSTEMD_USER_WANTS",
+        false => "SYSTEMD_WANTS",
+    };
+    format!(
+        r#"# written by `joykbd install-service`
+ACTION=="add", SUBSYSTEM=="input", KERNEL=="event*", ATTRS{{name}}=="*Joy-Con*|*Pro Controller*", ATTRS{{name}}!="*IMU", TAG+="systemd", ENV{{{}}}+="joykbd@%k.service"
+"#,
+        wants
+    )
+}
+
+/// Quote `arg` for an ExecStart line, if it needs it.
+fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || "\"'\\%$;".contains(c)) {
+        return arg.to_owned();
+    }
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    format!("\"{}\"", escaped)
+}
@@ -0,0 +1,209 @@
+//! `joykbd soak`: a long run of synthetic controller input through the mapping, into a sink that
+//! discards it, to catch what only shows up over hours before a release: memory that's never
+//! freed, timers that fall behind, and state that no longer returns to rest (e.g. a key that's
+//! still held after every button's been let go of).
+//!
+//! The input is a random walk of the sticks, with buttons pressed and released at random, from a
+//! seeded generator so a failing run can be repeated. Every `--report-every`, everything is let
+//! go of and the state checked, along with the memory use and how late the timer fired.
+
+use evdev::{AbsoluteAxisType, EventType, InputEvent, Key};
+use std::fs;
+use std::time::Duration;
+use tokio::time::{self, Instant, MissedTickBehavior};
+
+/// How often events are generated, in as large a burst as is due.
+const TICK: Duration = Duration::from_millis(1);
+
+/// How much the memory use can grow after the first report before it counts as a leak.
+const LEAK_TOLERANCE: u64 = 16 << 20;
+
+/// How late the timer can fire before it counts as falling behind.
+const MAX_LATENESS: Duration = Duration::from_millis(50);
+
+/// The buttons pressed at random.
+const BUTTONS: &[Key] = &[
+    Key::BTN_EAST,
+    Key::BTN_SOUTH,
+    Key::BTN_NORTH,
+    Key::BTN_WEST,
+    Key::BTN_TL,
+    Key::BTN_TR,
+    Key::BTN_TL2,
+    Key::BTN_TR2,
+    Key::BTN_SELECT,
+    Key::BTN_START,
+    Key::BTN_THUMBL,
+    Key::BTN_THUMBR,
+    Key::BTN_DPAD_UP,
+    Key::BTN_DPAD_DOWN,
+    Key::BTN_DPAD_LEFT,
+    Key::BTN_DPAD_RIGHT,
+];
+
+const STICK_AXES: [AbsoluteAxisType; 2] = [AbsoluteAxisType::ABS_X, AbsoluteAxisType::ABS_Y];
+
+pub struct Options {
+    /// Events per second.
+    pub rate: u32,
+    pub duration: Duration,
+    pub report_every: Duration,
+    pub seed: u64,
+}
+
+/// The mapping being soaked.
+pub trait Pipeline {
+    /// Feed one of the controller's events through, returning how many events were emitted.
+    fn feed(&mut self, ev: InputEvent) -> anyhow::Result<usize>;
+    /// What's wrong with the state, once every button's been released and the stick centered.
+    fn check_idle(&self) -> Vec<String>;
+}
+
+/// Synthetic controller input.
+struct Generator {
+    /// xorshift64 state.
+    state: u64,
+    stick: [i32; 2],
+    held: Vec<Key>,
+}
+
+impl Generator {
+    fn new(seed: u64) -> Self {
+        Generator {
+            // xorshift gets stuck at 0
+            state: seed.max(1),
+            stick: [0; 2],
+            held: vec![],
+        }
+    }
+
+    fn random(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn next(&mut self) -> InputEvent {
+        let roll = self.random() % 100;
+        // mostly stick motion, like a real controller
+        if roll < 80 {
+            let axis = (roll % 2) as usize;
+            let step = (self.random() % 4001) as i32 - 2000;
+            self.stick[axis] = (self.stick[axis] + step).clamp(-32767, 32767);
+            return InputEvent::new(EventType::ABSOLUTE, STICK_AXES[axis].0, self.stick[axis]);
+        }
+        let key = BUTTONS[(self.random() % BUTTONS.len() as u64) as usize];
+        let value = match self.held.iter().position(|&held| held == key) {
+            Some(i) => {
+                self.held.swap_remove(i);
+                0
+            }
+            None => {
+                self.held.push(key);
+                1
+            }
+        };
+        InputEvent::new(EventType::KEY, key.code(), value)
+    }
+
+    /// Release every held button and center the stick.
+    fn settle(&mut self) -> Vec<InputEvent> {
+        let releases = self
+            .held
+            .drain(..)
+            .map(|key| InputEvent::new(EventType::KEY, key.code(), 0));
+        let center = STICK_AXES.map(|axis| InputEvent::new(EventType::ABSOLUTE, axis.0, 0));
+        self.stick = [0; 2];
+        releases.chain(center).collect()
+    }
+}
+
+/// The resident memory use, in bytes.
+fn rss() -> Option<u64> {
+    let statm = fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * page_size as u64)
+}
+
+fn mib(bytes: u64) -> f64 {
+    bytes as f64 / f64::from(1 << 20)
+}
+
+/// Run `pipeline` for as long as `options` says, printing a report every so often, and fail if
+/// anything went wrong.
+pub async fn run(options: &Options, pipeline: &mut impl Pipeline) -> anyhow::Result<()> {
+    let mut generator = Generator::new(options.seed);
+    let mut tick = time::interval(TICK);
+    tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let start = Instant::now();
+    let mut next_report = start + options.report_every;
+    let (mut generated, mut emitted) = (0u64, 0u64);
+    let mut max_lateness = Duration::ZERO;
+    let mut baseline = None;
+    let mut problems = 0;
+    println!(
+        "Soaking at {} events/s for {:.1} hours (seed {})",
+        options.rate,
+        options.duration.as_secs_f64() / 3600.0,
+        options.seed
+    );
+    loop {
+        let scheduled = tick.tick().await;
+        let now = Instant::now();
+        max_lateness = max_lateness.max(now - scheduled);
+        let elapsed = now - start;
+        let due = (elapsed.as_secs_f64() * f64::from(options.rate)) as u64;
+        while generated < due {
+            emitted += pipeline.feed(generator.next())? as u64;
+            generated += 1;
+        }
+        let done = elapsed >= options.duration;
+        if now < next_report && !done {
+            continue;
+        }
+        next_report += options.report_every;
+
+        for ev in generator.settle() {
+            emitted += pipeline.feed(ev)? as u64;
+        }
+        let mut found = pipeline.check_idle();
+        if max_lateness > MAX_LATENESS {
+            found.push(format!(
+                "the timer fired {:.1}ms late",
+                max_lateness.as_secs_f64() * 1000.0
+            ));
+        }
+        let rss = rss().unwrap_or(0);
+        // the first report's is after everything's warmed up
+        let baseline = *baseline.get_or_insert(rss);
+        if rss > baseline + LEAK_TOLERANCE {
+            found.push(format!(
+                "memory use grew by {:.1} MiB since the first report",
+                mib(rss - baseline)
+            ));
+        }
+        println!(
+            "{:.0}s: {} events in, {} out, RSS {:.1} MiB, timer up to {:.1}ms late",
+            elapsed.as_secs_f64(),
+            generated,
+            emitted,
+            mib(rss),
+            max_lateness.as_secs_f64() * 1000.0
+        );
+        for problem in &found {
+            println!("  problem: {}", problem);
+        }
+        problems += found.len();
+        max_lateness = Duration::ZERO;
+        if done {
+            break;
+        }
+    }
+    if problems > 0 {
+        anyhow::bail!("{} problems found, with seed {}", problems, options.seed);
+    }
+    println!("No problems found");
+    Ok(())
+}
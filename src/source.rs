@@ -0,0 +1,196 @@
+//! Input sources: every device joykbd reads from gets its own task that forwards events into a
+//! single channel, so the main loop can treat them uniformly.
+//!
+//! Devices are read through an [`InputSource`], which each backend (evdev, the Joy-Con's raw HID
+//! protocol, ...) implements, and backends are looked up by name in [`BACKENDS`].
+
+use crate::hat::{self, HatDpad};
+use crate::hidraw;
+use crate::nfc::TagUid;
+use crate::rumble::HdRumbler;
+use evdev::{AttributeSet, Device, EventType, InputEvent, InputId, Key, RelativeAxisType};
+use serde::{Deserialize, Serialize};
+use smallvec::{smallvec, SmallVec};
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SourceKind {
+    JoyCon,
+    Keyboard,
+    Mouse,
+    /// A Wii Remote's IR camera, pointing the cursor absolutely.
+    IrPointer,
+    /// A Joy-Con's IMU, moving the cursor with its gyroscope.
+    Imu,
+    /// A touchpad, with its index among the trackpads passed on the command line.
+    Trackpad(usize),
+    /// The other Joy-Con of the pair, which scrolls with its stick.
+    Partner,
+}
+
+pub struct SourceEvent {
+    pub kind: SourceKind,
+    pub event: io::Result<InputEvent>,
+}
+
+/// A device that produces evdev-style input events.
+pub trait InputSource: Send {
+    fn name(&self) -> Option<&str>;
+    /// An identifier that stays the same across reconnects, e.g. the bluetooth address.
+    fn unique_name(&self) -> Option<&str>;
+    /// Its bus, vendor and product IDs, if it has them.
+    fn input_id(&self) -> Option<InputId> {
+        None
+    }
+    fn supported_keys(&self) -> AttributeSet<Key>;
+    fn supported_relative_axes(&self) -> AttributeSet<RelativeAxisType>;
+    /// The underlying evdev device, for the features that need one (grabbing, force feedback,
+    /// virtual gamepads, ...).
+    fn evdev(&self) -> Option<&Device>;
+    fn evdev_mut(&mut self) -> Option<&mut Device>;
+    /// Send the UIDs of the NFC tags tapped on the controller to `tx`, once it's spawned.
+    /// Returns whether it has an NFC reader joykbd can use.
+    fn enable_nfc(&mut self, _tx: mpsc::Sender<TagUid>) -> bool {
+        false
+    }
+    /// Send whether something (e.g. a hand) is near the controller's IR camera to `tx` whenever
+    /// that changes, once it's spawned, with `threshold` being how bright its reflection has to
+    /// be. Returns whether it has a camera joykbd can use.
+    fn enable_ir_presence(&mut self, _threshold: u8, _tx: mpsc::Sender<bool>) -> bool {
+        false
+    }
+    /// Send the controller's temperature (in °C) to `tx` every so often, once it's spawned.
+    /// Returns whether it can be read.
+    fn enable_temperature(&mut self, _tx: mpsc::Sender<f64>) -> bool {
+        false
+    }
+    /// Events that bring the mapping up to date with the device as it is now: where its axes are,
+    /// and, with `held`, presses for the buttons already held down. Sent before the events it
+    /// reads, when it's attached, so it isn't assumed to start centered with nothing held.
+    fn current_state(&self, _held: bool) -> Vec<InputEvent> {
+        Vec::new()
+    }
+    /// Start a task that plays HD rumble envelopes on the controller, if it can.
+    fn hd_rumbler(&self) -> Option<HdRumbler> {
+        None
+    }
+    /// Start forwarding events to `tx`. The task ends after the first read error (which is
+    /// forwarded too), or once the receiving end is dropped.
+    fn spawn(self: Box<Self>, kind: SourceKind, tx: mpsc::Sender<SourceEvent>) -> io::Result<()>;
+}
+
+impl InputSource for Device {
+    fn name(&self) -> Option<&str> {
+        Device::name(self)
+    }
+
+    fn unique_name(&self) -> Option<&str> {
+        Device::unique_name(self)
+    }
+
+    fn input_id(&self) -> Option<InputId> {
+        Some(Device::input_id(self))
+    }
+
+    fn supported_keys(&self) -> AttributeSet<Key> {
+        Device::supported_keys(self)
+            .into_iter()
+            .flat_map(|keys| keys.iter())
+            .chain(hat::keys(self))
+            .collect()
+    }
+
+    fn supported_relative_axes(&self) -> AttributeSet<RelativeAxisType> {
+        Device::supported_relative_axes(self)
+            .map_or_else(AttributeSet::new, |axes| axes.iter().collect())
+    }
+
+    fn evdev(&self) -> Option<&Device> {
+        Some(self)
+    }
+
+    fn evdev_mut(&mut self) -> Option<&mut Device> {
+        Some(self)
+    }
+
+    fn current_state(&self, held: bool) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        if let (Some(axes), Ok(state)) = (self.supported_absolute_axes(), self.get_abs_state()) {
+            // a hat is turned into buttons by whoever reads it, which would then never see it
+            // pressed
+            let axes = axes.iter().filter(|&axis| !hat::is_hat(axis));
+            events.extend(axes.map(|axis| {
+                InputEvent::new(EventType::ABSOLUTE, axis.0, state[axis.0 as usize].value)
+            }));
+        }
+        if held {
+            if let Ok(keys) = self.get_key_state() {
+                events.extend(
+                    keys.iter()
+                        .map(|key| InputEvent::new(EventType::KEY, key.code(), 1)),
+                );
+            }
+        }
+        events
+    }
+
+    fn spawn(self: Box<Self>, kind: SourceKind, tx: mpsc::Sender<SourceEvent>) -> io::Result<()> {
+        let mut stream = self.into_event_stream()?;
+        tokio::spawn(async move {
+            let mut hat = HatDpad::default();
+            loop {
+                // stop (and close the device) as soon as no one's listening, even if it's idle
+                let next = tokio::select! {
+                    next = stream.next_event() => next,
+                    () = tx.closed() => return,
+                };
+                let events: SmallVec<[_; 2]> = match next {
+                    Ok(ev) => hat.map(ev).into_iter().map(Ok).collect(),
+                    Err(e) => smallvec![Err(e)],
+                };
+                for event in events {
+                    let is_err = event.is_err();
+                    if tx.send(SourceEvent { kind, event }).await.is_err() || is_err {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// A device along with its path.
+pub type Found = (PathBuf, Box<dyn InputSource>);
+
+/// A way of reading controllers.
+pub struct Backend {
+    pub name: &'static str,
+    pub open: fn(&Path) -> io::Result<Box<dyn InputSource>>,
+    /// Every device this backend can currently open.
+    pub enumerate: fn() -> Vec<Found>,
+}
+
+pub const EVDEV: Backend = Backend {
+    name: "evdev",
+    open: |path| Ok(Box::new(Device::open(path)?)),
+    enumerate: || {
+        evdev::enumerate()
+            .map(|(path, dev)| (path, Box::new(dev) as Box<dyn InputSource>))
+            .collect()
+    },
+};
+
+pub const BACKENDS: &[Backend] = &[EVDEV, hidraw::BACKEND];
+
+pub fn backend(name: &str) -> Option<&'static Backend> {
+    BACKENDS.iter().find(|backend| backend.name == name)
+}
+
+/// The backend called `s`, for parsing it as an argument.
+pub fn parse_backend(s: &str) -> Result<&'static Backend, String> {
+    backend(s).ok_or_else(|| format!("unknown backend {:?}", s))
+}
@@ -0,0 +1,144 @@
+//! Temporarily changing how fast the stick moves the cursor: faster while a `sprint` binding is
+//! held, slower while a `precision` one is, and (with flick acceleration) faster after a few
+//! quick flicks in the same direction, like touchpad pointer ballistics.
+
+use crate::bindings::Bindings;
+use evdev::{InputEvent, InputEventKind, RelativeAxisType};
+use std::time::{Duration, Instant};
+
+/// The longest the stick can be pushed for to count as a flick.
+const FLICK_MAX: Duration = Duration::from_millis(250);
+/// How soon after a flick the next one has to start to build on it; the acceleration also wears
+/// off once the stick has rested for this long.
+const FLICK_GAP: Duration = Duration::from_millis(500);
+/// How much each successive flick speeds the cursor up.
+const FLICK_STEP: f64 = 1.5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SpeedMode {
+    Sprint,
+    Precision,
+}
+
+impl SpeedMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            SpeedMode::Sprint => "sprint",
+            SpeedMode::Precision => "precision",
+        }
+    }
+}
+
+pub struct Speed {
+    sprint: f64,
+    precision: f64,
+    flicks: Option<Flicks>,
+    /// The fractional motion left over, per axis, so slow movement isn't rounded away.
+    remainder: [f64; 2],
+}
+
+impl Speed {
+    /// `flick_max` enables flick acceleration, up to that factor.
+    pub fn new(sprint: f64, precision: f64, flick_max: Option<f64>) -> Self {
+        Speed {
+            sprint,
+            precision,
+            flicks: flick_max.map(Flicks::new),
+            remainder: [0.0; 2],
+        }
+    }
+
+    /// Watch the (mapped) motion from the stick for flicks.
+    pub fn track(&mut self, ev: &InputEvent) {
+        if let (Some(flicks), Some(i)) = (&mut self.flicks, axis_index(ev)) {
+            flicks.track(i, ev.value());
+        }
+    }
+
+    /// Scale stick motion by the speed modes that are currently held.
+    pub fn scale(&mut self, ev: InputEvent, bindings: &Bindings) -> InputEvent {
+        let i = match axis_index(&ev) {
+            Some(i) => i,
+            None => return ev,
+        };
+        let mut factor = self.flicks.as_ref().map_or(1.0, |flicks| flicks.boost);
+        if bindings.speed_held(SpeedMode::Sprint) {
+            factor *= self.sprint;
+        }
+        if bindings.speed_held(SpeedMode::Precision) {
+            factor *= self.precision;
+        }
+        if factor == 1.0 || ev.value() == 0 {
+            self.remainder[i] = 0.0;
+            return ev;
+        }
+        let value = f64::from(ev.value()) * factor + self.remainder[i];
+        self.remainder[i] = value.fract();
+        let mut raw = *ev.as_ref();
+        raw.value = value.trunc() as i32;
+        raw.into()
+    }
+}
+
+fn axis_index(ev: &InputEvent) -> Option<usize> {
+    match ev.kind() {
+        InputEventKind::RelAxis(RelativeAxisType::REL_X) => Some(0),
+        InputEventKind::RelAxis(RelativeAxisType::REL_Y) => Some(1),
+        _ => None,
+    }
+}
+
+struct Flicks {
+    max: f64,
+    boost: f64,
+    /// When the stick was pushed along each axis, and which way, if it currently is.
+    pushed: [Option<(Instant, i32)>; 2],
+    /// The direction of the last flick.
+    last: Option<(usize, i32)>,
+    /// When the stick last came back to rest.
+    rested: Option<Instant>,
+}
+
+impl Flicks {
+    fn new(max: f64) -> Self {
+        Flicks {
+            max,
+            boost: 1.0,
+            pushed: [None; 2],
+            last: None,
+            rested: None,
+        }
+    }
+
+    fn track(&mut self, axis: usize, value: i32) {
+        let now = Instant::now();
+        match (self.pushed[axis], value.signum()) {
+            (None, 0) => {}
+            (None, sign) => {
+                if self.rested.is_some_and(|rested| now - rested > FLICK_GAP) {
+                    self.boost = 1.0;
+                    self.last = None;
+                }
+                self.pushed[axis] = Some((now, sign));
+            }
+            (Some((pushed, sign)), 0) => {
+                self.pushed[axis] = None;
+                self.rested = Some(now);
+                if now - pushed > FLICK_MAX {
+                    return;
+                }
+                if self.last == Some((axis, sign)) {
+                    self.boost = (self.boost * FLICK_STEP).min(self.max);
+                } else {
+                    self.boost = 1.0;
+                }
+                self.last = Some((axis, sign));
+            }
+            // a change of direction without coming to rest in between
+            (Some((pushed, sign)), new_sign) if new_sign != sign => {
+                self.pushed[axis] = Some((pushed, new_sign));
+            }
+            (Some(_), _) => {}
+        }
+    }
+}
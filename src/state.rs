@@ -0,0 +1,94 @@
+//! Runtime state that should survive a restart (or a crash), saved to a small TOML file whenever
+//! it changes.
+
+use crate::migrate;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct State {
+    /// Whether the layer was latched on with a --layer-toggle key.
+    pub layer_latched: bool,
+    /// The drift threshold saved with a `drift-threshold save` action/command.
+    pub drift_threshold: Option<u32>,
+    /// Whether the mouse buttons were swapped with a `swap-clicks` action.
+    pub clicks_swapped: bool,
+}
+
+pub struct StateFile {
+    path: PathBuf,
+    saved: State,
+}
+
+impl StateFile {
+    /// The default location, `$XDG_STATE_HOME/joykbd/state.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        let state_home = std::env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/state"))
+            })?;
+        Some(state_home.join("joykbd/state.toml"))
+    }
+
+    /// Load the state saved at `path`, falling back to the default state if there's none (or it
+    /// can't be read).
+    pub fn load(path: PathBuf) -> (Self, State) {
+        let state = match fs::read_to_string(&path) {
+            Ok(s) => parse(&s).unwrap_or_else(|e| {
+                eprintln!(
+                    "warning: ignoring invalid state file {}: {}",
+                    path.display(),
+                    e
+                );
+                State::default()
+            }),
+            Err(_) => State::default(),
+        };
+        let file = StateFile {
+            path,
+            saved: state.clone(),
+        };
+        (file, state)
+    }
+
+    /// Write `state` out if it differs from what was last saved.
+    pub fn save_if_changed(&mut self, state: &State) {
+        if *state == self.saved {
+            return;
+        }
+        if let Err(e) = self.write(state) {
+            eprintln!(
+                "warning: failed to save state to {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+        self.saved = state.clone();
+    }
+
+    fn write(&self, state: &State) -> anyhow::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // write then rename, so a crash mid-write can't leave a truncated file behind
+        let tmp = self.path.with_extension("toml.tmp");
+        let mut value = toml::Value::try_from(state)?;
+        if let Some(table) = value.as_table_mut() {
+            table.insert("version".to_owned(), migrate::CURRENT_VERSION.into());
+        }
+        fs::write(&tmp, toml::to_string(&value)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Parse a state file, upgrading it from older formats as needed.
+fn parse(s: &str) -> anyhow::Result<State> {
+    let mut value: serde_json::Value = toml::from_str(s)?;
+    let table = (value.as_object_mut()).ok_or_else(|| anyhow::anyhow!("it isn't a table"))?;
+    migrate::migrate(table).map_err(anyhow::Error::msg)?;
+    Ok(serde_json::from_value(value)?)
+}
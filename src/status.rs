@@ -0,0 +1,79 @@
+//! A description of what joykbd is running with, printed on startup and reported by the `status`
+//! command, both for people and (as JSON) for scripts.
+
+use serde::Serialize;
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Serialize)]
+pub struct Status {
+    pub device: String,
+    pub device_path: PathBuf,
+    pub unique_name: Option<String>,
+    pub backend: &'static str,
+    pub preset: &'static str,
+    /// The option set whose options were added to the command line's, if any.
+    pub option_set: Option<String>,
+    /// The active one of the config file's profiles, if it has any.
+    pub profile: Option<String>,
+    /// The keys/buttons and relative axes the device reports.
+    pub keys: Vec<String>,
+    pub axes: Vec<String>,
+    pub output: String,
+    /// The virtual device's node, if the output is one.
+    pub output_node: Option<PathBuf>,
+    pub connected: bool,
+    /// Where the other Joy-Con of the pair is, if it joined in.
+    pub partner_path: Option<PathBuf>,
+    pub suspended: bool,
+    /// Whether joykbd has grabbed the controller, so nothing else sees its input.
+    pub grabbed: bool,
+    /// The controller's battery level in percent, as of the last check.
+    pub battery: Option<u8>,
+}
+
+impl Status {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("a status is always valid JSON")
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at {} ({} backend, {} preset, {} keys, {} axes)",
+            self.device,
+            self.device_path.display(),
+            self.backend,
+            self.preset,
+            self.keys.len(),
+            self.axes.len()
+        )?;
+        if let Some(option_set) = &self.option_set {
+            write!(f, ", option set {}", option_set)?;
+        }
+        if let Some(profile) = &self.profile {
+            write!(f, ", profile {}", profile)?;
+        }
+        write!(f, ", output {}", self.output)?;
+        if let Some(node) = &self.output_node {
+            write!(f, " at {}", node.display())?;
+        }
+        if let Some(partner) = &self.partner_path {
+            write!(f, ", partner at {}", partner.display())?;
+        }
+        if let Some(battery) = self.battery {
+            write!(f, ", battery {}%", battery)?;
+        }
+        if !self.connected {
+            write!(f, ", disconnected")?;
+        } else if self.suspended {
+            write!(f, ", suspended")?;
+        }
+        if self.grabbed {
+            write!(f, ", grabbed")?;
+        }
+        Ok(())
+    }
+}
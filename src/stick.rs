@@ -0,0 +1,553 @@
+//! Turning the stick's position into cursor speed. Each axis goes through a pipeline of stages,
+//! by default calibration (--x-bias/--y-bias), then the deadzone (--drift-threshold), then the
+//! response curve (scaled by --speed). By default, the deadzone and the curve apply to the
+//! stick's position as a whole (its distance from the center), rather than to each axis on its
+//! own, so diagonals aren't stuck to the axes (see --deadzone-shape). The pipelines can be
+//! reordered, cut down or extended in the config file:
+//!
+//! ```toml
+//! [stick]
+//! x = ["calibration", "deadzone", { smoothing = 0.5 }, "curve"]
+//! y = ["calibration", "deadzone", "curve", { rate-limit = 20 }]
+//! ```
+//!
+//! The response curve is picked with `--curve`, or in the config file, which can also give it as
+//! a table of points to interpolate between:
+//!
+//! ```toml
+//! [stick]
+//! curve = { table = [[0.0, 0.0], [0.5, 0.1], [0.8, 0.3], [1.0, 1.0]] }
+//! ```
+//!
+//! Each axis's speed can be set on its own (`--speed-x`/`--speed-y`), e.g. to slow the vertical
+//! axis on a widescreen monitor, and each stick's speed scaled by a sensitivity:
+//!
+//! ```toml
+//! [stick]
+//! speed-y = 12
+//! sensitivity = { left = 1.0, right = 0.5 }
+//! ```
+
+use crate::drift::DriftAdjust;
+use evdev::AbsoluteAxisType;
+use serde::Deserialize;
+use std::str::FromStr;
+use std::time::Instant;
+
+/// How long drift tracking takes to follow a change in where the stick rests, in seconds: the
+/// learned center goes about two thirds of the way in this long, however often the stick reads.
+const DRIFT_TRACKING_TIME: f64 = 30.0;
+
+/// The longest gap between readings at rest that counts towards drift tracking, in seconds, so a
+/// reading after a quiet spell doesn't count for more than the ones before it.
+const DRIFT_TRACKING_MAX_STEP: f64 = 0.1;
+
+/// How long the drift's dependence on the temperature is fitted over, in seconds, with older
+/// readings fading out.
+const DRIFT_MODEL_MEMORY: f64 = 1800.0;
+
+/// How much the temperature has to have varied (its standard deviation, in °C) over the readings
+/// at rest before the drift is predicted from it.
+const DRIFT_MODEL_MIN_DEVIATION: f64 = 1.0;
+
+/// How far the stick reads when it's pushed all the way, give or take.
+const FULL_DEFLECTION: f64 = 30_000.0;
+
+#[derive(Clone, Copy)]
+pub enum Axis {
+    X,
+    Y,
+}
+
+impl Axis {
+    fn other(self) -> Axis {
+        match self {
+            Axis::X => Axis::Y,
+            Axis::Y => Axis::X,
+        }
+    }
+}
+
+/// Whether the deadzone (and the curve) look at each axis on its own, or at the stick's distance
+/// from the center.
+#[derive(clap::ArgEnum, Clone, Copy, PartialEq, Eq)]
+pub enum DeadzoneShape {
+    /// Each axis has its own deadzone, so the deadzone is a cross, and diagonals feel square.
+    Axial,
+    /// The deadzone is a circle around the center.
+    Radial,
+}
+
+/// What the cursor's motion is repeated as until the stick's next reading.
+#[derive(clap::ArgEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Repeat {
+    /// The motion is worked out again from where the stick last read, each time, so it follows
+    /// the stick as closely as its readings do, and smoothing keeps catching up between them.
+    Live,
+    /// The motion from the last reading is repeated as it was.
+    Replay,
+}
+
+/// One of the sticks of a pair of Joy-Cons (or a controller with two): the left one reports
+/// ABS_X/ABS_Y, and the right one ABS_RX/ABS_RY.
+#[derive(clap::ArgEnum, Clone, Copy, PartialEq, Eq)]
+pub enum StickSide {
+    Left,
+    Right,
+}
+
+impl StickSide {
+    /// The stick's axes, horizontal then vertical.
+    pub fn axes(self) -> [AbsoluteAxisType; 2] {
+        match self {
+            StickSide::Left => [AbsoluteAxisType::ABS_X, AbsoluteAxisType::ABS_Y],
+            StickSide::Right => [AbsoluteAxisType::ABS_RX, AbsoluteAxisType::ABS_RY],
+        }
+    }
+
+    /// Whether `axis` is this stick's horizontal (0) or vertical (1) axis, if it's either.
+    pub fn axis_index(self, axis: AbsoluteAxisType) -> Option<usize> {
+        self.axes().iter().position(|&a| a == axis)
+    }
+
+    /// The stick that `axis` belongs to, and which of its axes it is.
+    pub fn of(axis: AbsoluteAxisType) -> Option<(StickSide, Axis)> {
+        [StickSide::Left, StickSide::Right]
+            .into_iter()
+            .find_map(|side| match side.axis_index(axis)? {
+                0 => Some((side, Axis::X)),
+                _ => Some((side, Axis::Y)),
+            })
+    }
+}
+
+/// How much to scale each stick's speed by.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sensitivity {
+    pub left: f64,
+    pub right: f64,
+}
+
+impl Default for Sensitivity {
+    fn default() -> Self {
+        Sensitivity {
+            left: 1.0,
+            right: 1.0,
+        }
+    }
+}
+
+impl Sensitivity {
+    fn of(self, side: StickSide) -> f64 {
+        match side {
+            StickSide::Left => self.left,
+            StickSide::Right => self.right,
+        }
+    }
+}
+
+/// One step of an axis's pipeline. Stages before the curve work in the stick's own units, and
+/// ones after it in pixels per report.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stage {
+    /// Add the axis's bias, to recenter a stick that leans one way.
+    Calibration,
+    /// Ignore readings within the drift threshold of the center, give or take the hysteresis.
+    Deadzone,
+    /// Map the deflection to speed, along the response curve.
+    Curve,
+    /// Average the readings exponentially, each new one counting for this fraction (0-1).
+    Smoothing(f64),
+    /// Limit how much the value can change by from one reading to the next.
+    RateLimit(f64),
+}
+
+impl Stage {
+    pub const DEFAULT: &'static [Stage] = &[Stage::Calibration, Stage::Deadzone, Stage::Curve];
+}
+
+/// How the stick's deflection maps to cursor speed, both as fractions of their full range.
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub enum Curve {
+    Linear,
+    Quadratic,
+    Cubic,
+    Quintic,
+    /// The deflection raised to this power.
+    Power(f64),
+    /// Straight lines between these `[deflection, speed]` points, in order of deflection.
+    Table(Vec<(f64, f64)>),
+}
+
+impl Curve {
+    fn apply(&self, deflection: f64) -> f64 {
+        let power = match self {
+            Curve::Linear => 1.0,
+            Curve::Quadratic => 2.0,
+            Curve::Cubic => 3.0,
+            Curve::Quintic => 5.0,
+            Curve::Power(power) => *power,
+            Curve::Table(points) => return interpolate(points, deflection),
+        };
+        deflection.powf(power)
+    }
+
+    /// Check that the curve makes sense, e.g. that a table's points are in order.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Curve::Power(power) if *power <= 0.0 => {
+                Err("the curve's power must be positive".into())
+            }
+            Curve::Table(points) if points.is_empty() => Err("the curve's table is empty".into()),
+            Curve::Table(points) if points.windows(2).any(|w| w[0].0 >= w[1].0) => {
+                Err("the curve's table must be in order of deflection".into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromStr for Curve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let curve = match s {
+            "linear" => Curve::Linear,
+            "quadratic" => Curve::Quadratic,
+            "cubic" => Curve::Cubic,
+            "quintic" => Curve::Quintic,
+            _ => Curve::Power(s.parse().map_err(|_| {
+                format!(
+                    "unknown curve {:?}, expected linear, quadratic, cubic, quintic or an exponent",
+                    s
+                )
+            })?),
+        };
+        curve.validate()?;
+        Ok(curve)
+    }
+}
+
+/// The value of the piecewise linear function through `points` at `x`, holding the ends steady
+/// beyond them.
+fn interpolate(points: &[(f64, f64)], x: f64) -> f64 {
+    match points.iter().position(|&(px, _)| px > x) {
+        Some(0) => points[0].1,
+        Some(i) => {
+            let ((x0, y0), (x1, y1)) = (points[i - 1], points[i]);
+            y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+        }
+        None => points.last().map_or(0.0, |&(_, y)| y),
+    }
+}
+
+/// How where the stick rests depends on the controller's temperature: a line fitted through the
+/// readings at rest at each temperature, from sums weighted by how long each one lasted.
+#[derive(Default)]
+struct DriftModel {
+    weight: f64,
+    t: f64,
+    r: f64,
+    tt: f64,
+    tr: f64,
+}
+
+impl DriftModel {
+    /// Count the stick resting at `rest` at `temperature`, for `dt` seconds.
+    fn learn(&mut self, temperature: f64, rest: f64, dt: f64) {
+        let fade = 1.0 - (dt / DRIFT_MODEL_MEMORY).min(1.0);
+        self.weight = self.weight * fade + dt;
+        self.t = self.t * fade + dt * temperature;
+        self.r = self.r * fade + dt * rest;
+        self.tt = self.tt * fade + dt * temperature * temperature;
+        self.tr = self.tr * fade + dt * temperature * rest;
+    }
+
+    /// Where the stick rests at `temperature`, once it's been seen resting at enough different
+    /// ones to tell.
+    fn predict(&self, temperature: f64) -> Option<f64> {
+        if self.weight <= 0.0 {
+            return None;
+        }
+        let (t, r) = (self.t / self.weight, self.r / self.weight);
+        let variance = self.tt / self.weight - t * t;
+        if variance < DRIFT_MODEL_MIN_DEVIATION * DRIFT_MODEL_MIN_DEVIATION {
+            return None;
+        }
+        let slope = (self.tr / self.weight - t * r) / variance;
+        Some(r + slope * (temperature - t))
+    }
+}
+
+/// The state of one stick's axis's pipeline, for the stages that remember previous readings.
+/// Each stick has its own, so one's readings don't smooth or recenter the other's.
+struct PipelineState {
+    /// The last input of each stage, for the other axis's radial stages.
+    input: Vec<f64>,
+    /// The last output of each stage.
+    last: Vec<Option<f64>>,
+    /// Where the stick rests, relative to the calibrated center, as learned by drift tracking.
+    rest: f64,
+    /// When the stick last read as resting, unless it has moved since.
+    rested_at: Option<Instant>,
+    /// How where it rests depends on the temperature.
+    model: DriftModel,
+}
+
+impl PipelineState {
+    fn new(stages: &[Stage]) -> Self {
+        PipelineState {
+            input: vec![0.0; stages.len()],
+            last: vec![None; stages.len()],
+            rest: 0.0,
+            rested_at: None,
+            model: DriftModel::default(),
+        }
+    }
+
+    /// The last input of the first of `stages` of the same kind as `stage`, if there is one.
+    fn input_of(&self, stages: &[Stage], stage: Stage) -> f64 {
+        let kind = std::mem::discriminant(&stage);
+        stages
+            .iter()
+            .position(|s| std::mem::discriminant(s) == kind)
+            .map_or(0.0, |i| self.input[i])
+    }
+}
+
+pub struct StickConstants {
+    /// How fast the cursor moves at full deflection, in pixels per report, horizontally and
+    /// vertically.
+    pub speed: [f64; 2],
+    pub sensitivity: Sensitivity,
+    pub curve: Curve,
+    pub drift_threshold: u32,
+    /// How far past the drift threshold the stick has to go to start moving, and how far below it
+    /// to stop again.
+    pub drift_hysteresis: u32,
+    /// Whether to keep following where the stick rests, as its drift changes over a session
+    /// (e.g. as the controller warms up), and treat that as the center.
+    pub drift_tracking: bool,
+    /// The controller's temperature in °C, as last read, for drift tracking to learn how the
+    /// drift depends on it (`--drift-temperature`).
+    pub temperature: Option<f64>,
+    pub deadzone_shape: DeadzoneShape,
+    /// The latest reading of each stick's axes, by [`StickSide`] and [`Axis`].
+    raw: [[i32; 2]; 2],
+    /// The drift threshold to persist in the state file.
+    pub saved_drift_threshold: Option<u32>,
+    pub axis_bias: (i32, i32),
+    /// How far the stick reaches below and above its center along each axis, by [`Axis`].
+    pub range: [(f64, f64); 2],
+    /// Each axis's stages, by [`Axis`], which both sticks go through.
+    stages: [Vec<Stage>; 2],
+    /// Their state, by [`StickSide`] and [`Axis`].
+    state: [[PipelineState; 2]; 2],
+}
+
+impl StickConstants {
+    pub fn new(speed: [f64; 2], drift_threshold: u32, axis_bias: (i32, i32)) -> Self {
+        StickConstants {
+            speed,
+            sensitivity: Sensitivity::default(),
+            curve: Curve::Quintic,
+            drift_threshold,
+            drift_hysteresis: 0,
+            drift_tracking: false,
+            temperature: None,
+            deadzone_shape: DeadzoneShape::Radial,
+            raw: [[0; 2]; 2],
+            saved_drift_threshold: None,
+            axis_bias,
+            range: [(-FULL_DEFLECTION, FULL_DEFLECTION); 2],
+            stages: [Stage::DEFAULT.to_vec(), Stage::DEFAULT.to_vec()],
+            state: std::array::from_fn(|_| {
+                std::array::from_fn(|_| PipelineState::new(Stage::DEFAULT))
+            }),
+        }
+    }
+
+    /// Replace `axis`'s pipeline.
+    pub fn set_pipeline(&mut self, axis: Axis, stages: &[Stage]) {
+        self.stages[axis as usize] = stages.to_vec();
+        for state in &mut self.state {
+            state[axis as usize] = PipelineState::new(stages);
+        }
+    }
+
+    /// Smooth the stick's readings, before anything else in each axis's pipeline, unless it's
+    /// already smoothed (see [`Stage::Smoothing`]).
+    pub fn smooth(&mut self, alpha: f64) {
+        for axis in [Axis::X, Axis::Y] {
+            let stages = &self.stages[axis as usize];
+            if !stages
+                .iter()
+                .any(|stage| matches!(stage, Stage::Smoothing(_)))
+            {
+                let mut smoothed = vec![Stage::Smoothing(alpha)];
+                smoothed.extend(stages);
+                self.set_pipeline(axis, &smoothed);
+            }
+        }
+    }
+
+    /// Whether either axis's readings are smoothed, so its motion keeps changing after the
+    /// stick's stopped, as the average catches up.
+    pub fn smooths(&self) -> bool {
+        (self.stages.iter())
+            .flatten()
+            .any(|stage| matches!(stage, Stage::Smoothing(_)))
+    }
+
+    /// Map `side`'s latest reading of `axis` again, e.g. to repeat the motion for where the stick
+    /// is now, rather than where it was when the motion was last mapped.
+    pub fn remap(&mut self, side: StickSide, axis: Axis) -> i32 {
+        let value = self.raw[side as usize][axis as usize];
+        self.map_axis(side, axis, value, true)
+    }
+
+    /// Map a new reading of `side`'s `axis` to motion along each axis, by [`Axis`]. With a
+    /// radial deadzone, the other axis's motion depends on this one too, so both are mapped, but
+    /// only this one's stages step (see [`Self::map_axis`]).
+    pub fn map_stick(&mut self, side: StickSide, axis: Axis, value: i32) -> [Option<i32>; 2] {
+        self.raw[side as usize][axis as usize] = value;
+        let mut motion = [None; 2];
+        motion[axis as usize] = Some(self.map_axis(side, axis, value, true));
+        if self.deadzone_shape == DeadzoneShape::Radial {
+            let other = axis.other();
+            let value = self.raw[side as usize][other as usize];
+            motion[other as usize] = Some(self.map_axis(side, other, value, false));
+        }
+        motion
+    }
+
+    /// Map `value` through `axis`'s pipeline. Unless `step`, it's a reading that was already
+    /// mapped, so the stages that remember previous readings (smoothing, rate limiting, drift
+    /// tracking) don't advance, or they'd advance more than once per reading.
+    fn map_axis(&mut self, side: StickSide, axis: Axis, value: i32, step: bool) -> i32 {
+        let speed = self.speed[axis as usize] * self.sensitivity.of(side);
+        let bias = match axis {
+            Axis::X => self.axis_bias.0,
+            Axis::Y => self.axis_bias.1,
+        };
+        let radial = self.deadzone_shape == DeadzoneShape::Radial;
+        let range = self.range;
+        // how far along the axis's range `value` is, from -1 to 1
+        let deflection = |axis: Axis, value: f64| {
+            let (below, above) = range[axis as usize];
+            match value < 0.0 {
+                true => -value / below,
+                false => value / above,
+            }
+        };
+        let [x, y] = &mut self.state[side as usize];
+        let (pipeline, other) = match axis {
+            Axis::X => (x, &*y),
+            Axis::Y => (y, &*x),
+        };
+        let other_stages = &self.stages[axis.other() as usize];
+        let mut value = f64::from(value);
+        let stages = self.stages[axis as usize].iter().zip(&mut pipeline.input);
+        for ((stage, input), last) in stages.zip(&mut pipeline.last) {
+            if step {
+                *input = value;
+            }
+            // how far the stick is from the center, or just along this axis
+            let distance = |value: f64, other: f64| match radial {
+                true => value.hypot(other),
+                false => value.abs(),
+            };
+            value = match *stage {
+                Stage::Calibration => {
+                    let value = value + f64::from(bias);
+                    if !self.drift_tracking {
+                        value
+                    } else {
+                        let center = (self.temperature)
+                            .and_then(|temperature| pipeline.model.predict(temperature))
+                            .unwrap_or(pipeline.rest);
+                        // readings within the drift threshold of where the stick rests are it at
+                        // rest, so wherever they settle is where the center has drifted to
+                        if !step {
+                            // already learned from
+                        } else if (value - center).abs() < f64::from(self.drift_threshold) {
+                            let now = Instant::now();
+                            let dt = pipeline.rested_at.map_or(0.0, |then| {
+                                (now - then).as_secs_f64().min(DRIFT_TRACKING_MAX_STEP)
+                            });
+                            pipeline.rested_at = Some(now);
+                            let rate = 1.0 - (-dt / DRIFT_TRACKING_TIME).exp();
+                            pipeline.rest += (value - pipeline.rest) * rate;
+                            if let Some(temperature) = self.temperature {
+                                pipeline.model.learn(temperature, value, dt);
+                            }
+                        } else {
+                            pipeline.rested_at = None;
+                        }
+                        value - center
+                    }
+                }
+                Stage::Deadzone => {
+                    let moving = last.is_some_and(|last| last != 0.0);
+                    let threshold = match moving {
+                        true => self.drift_threshold.saturating_sub(self.drift_hysteresis),
+                        false => self.drift_threshold.saturating_add(self.drift_hysteresis),
+                    };
+                    if distance(value, other.input_of(other_stages, *stage)) < f64::from(threshold)
+                    {
+                        0.0
+                    } else {
+                        value
+                    }
+                }
+                Stage::Curve => {
+                    let this = deflection(axis, value);
+                    let other = deflection(axis.other(), other.input_of(other_stages, *stage));
+                    // the curve applies to the distance, and this axis gets its share of it
+                    match distance(this, other) {
+                        0.0 => 0.0,
+                        distance => self.curve.apply(distance) * speed * this / distance,
+                    }
+                }
+                Stage::Smoothing(alpha) => match *last {
+                    Some(last) => last + (value - last) * alpha,
+                    None => value,
+                },
+                Stage::RateLimit(limit) => match *last {
+                    Some(last) => last + (value - last).clamp(-limit, limit),
+                    None => value,
+                },
+            };
+            if step {
+                *last = Some(value);
+            }
+        }
+        value as i32
+    }
+
+    /// The stage that brought `side`'s latest reading of `axis` down to nothing, if one did, e.g.
+    /// to explain why moving the stick didn't move the cursor.
+    pub fn stopped_by(&self, side: StickSide, axis: Axis) -> Option<Stage> {
+        let pipeline = &self.state[side as usize][axis as usize];
+        self.stages[axis as usize]
+            .iter()
+            .zip(pipeline.input.iter().zip(&pipeline.last))
+            .find(|(_, (&input, &last))| input != 0.0 && last == Some(0.0))
+            .map(|(&stage, _)| stage)
+    }
+
+    /// Apply `adjust` to the drift threshold, returning the new value.
+    pub fn adjust_drift(&mut self, adjust: DriftAdjust) -> u32 {
+        match adjust {
+            DriftAdjust::By(by) => {
+                self.drift_threshold = self.drift_threshold.saturating_add_signed(by)
+            }
+            DriftAdjust::Set(threshold) => self.drift_threshold = threshold,
+            DriftAdjust::Save => self.saved_drift_threshold = Some(self.drift_threshold),
+        }
+        self.drift_threshold
+    }
+}
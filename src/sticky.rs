@@ -0,0 +1,62 @@
+//! Sticky keys, like the desktop accessibility feature: a modifier stays held after it's let go
+//! of, until the next key is pressed and released, so shortcuts don't need two controls held at
+//! once.
+
+use evdev::{AttributeSet, EventType, InputEvent, InputEventKind, Key};
+
+const MODIFIERS: [Key; 8] = [
+    Key::KEY_LEFTCTRL,
+    Key::KEY_RIGHTCTRL,
+    Key::KEY_LEFTSHIFT,
+    Key::KEY_RIGHTSHIFT,
+    Key::KEY_LEFTALT,
+    Key::KEY_RIGHTALT,
+    Key::KEY_LEFTMETA,
+    Key::KEY_RIGHTMETA,
+];
+
+#[derive(Default)]
+pub struct StickyKeys {
+    /// Modifiers that were let go of, but are still held on the output.
+    latched: AttributeSet<Key>,
+    /// Latched modifiers that were pressed again to cancel them, whose release should be dropped.
+    cancelled: AttributeSet<Key>,
+}
+
+impl StickyKeys {
+    /// Append what to emit for `events` to `out`.
+    pub fn map(&mut self, events: &[InputEvent], out: &mut Vec<InputEvent>) {
+        for &ev in events {
+            match ev.kind() {
+                InputEventKind::Key(key) if MODIFIERS.contains(&key) => match ev.value() {
+                    0 if self.cancelled.contains(key) => self.cancelled.remove(key),
+                    0 => self.latched.insert(key),
+                    // pressing a latched modifier again lets go of it
+                    1 if self.latched.contains(key) => {
+                        self.latched.remove(key);
+                        self.cancelled.insert(key);
+                        out.push(release(key));
+                    }
+                    _ => out.push(ev),
+                },
+                InputEventKind::Key(_) if ev.value() == 0 => {
+                    out.push(ev);
+                    out.extend(self.latched.iter().map(release));
+                    self.latched = AttributeSet::new();
+                }
+                _ => out.push(ev),
+            }
+        }
+    }
+
+    /// Stop tracking `keys`, because they were released some other way.
+    pub fn forget(&mut self, keys: &[Key]) {
+        for &key in keys {
+            self.latched.remove(key);
+        }
+    }
+}
+
+fn release(key: Key) -> InputEvent {
+    InputEvent::new(EventType::KEY, key.code(), 0)
+}
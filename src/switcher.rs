@@ -0,0 +1,129 @@
+//! Switching between the config file's profiles at runtime: pressing every button of the
+//! `--profile-chord` together moves on to the next profile, whose bindings take over at once.
+//! Profiles can also be switched to by name, e.g. by tapping an NFC tag (`--nfc-tag`).
+//! Optionally, the Joy-Con's player LEDs show which profile is active.
+
+use crate::bindings::Bindings;
+use evdev::Key;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct ProfileManager {
+    /// Each profile's name and bindings, in the order they're cycled through. The active
+    /// profile's bindings are the ones in use, so its slot here is empty.
+    profiles: Vec<(String, Bindings)>,
+    active: usize,
+    chord: Vec<Key>,
+    held: Vec<Key>,
+    /// The player LEDs' brightness files in sysfs, from the first player on.
+    leds: Vec<PathBuf>,
+}
+
+impl ProfileManager {
+    /// `profiles` starts out with the first of them active, and its bindings are returned to be
+    /// used.
+    pub fn new(mut profiles: Vec<(String, Bindings)>, chord: Vec<Key>) -> (Self, Bindings) {
+        let bindings = std::mem::take(&mut profiles[0].1);
+        let manager = ProfileManager {
+            profiles,
+            active: 0,
+            chord,
+            held: vec![],
+            leds: vec![],
+        };
+        (manager, bindings)
+    }
+
+    /// Show the active profile on the player LEDs of the input device at `dev_path` (e.g.
+    /// /dev/input/event5), if it has any.
+    pub fn find_leds(&mut self, dev_path: &Path) {
+        self.leds = player_leds(dev_path).unwrap_or_default();
+        self.show();
+    }
+
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    pub fn active_name(&self) -> &str {
+        &self.profiles[self.active].0
+    }
+
+    /// Every profile's bindings but the active one's.
+    pub fn inactive(&self) -> impl Iterator<Item = &Bindings> {
+        self.profiles
+            .iter()
+            .enumerate()
+            .filter(move |&(i, _)| i != self.active)
+            .map(|(_, (_, bindings))| bindings)
+    }
+
+    /// Track the chord's buttons, returning whether this press completed it.
+    pub fn key(&mut self, key: Key, value: i32) -> bool {
+        if !self.chord.contains(&key) {
+            return false;
+        }
+        match value {
+            0 => self.held.retain(|&held| held != key),
+            1 if !self.held.contains(&key) => self.held.push(key),
+            _ => return false,
+        }
+        value == 1 && self.held.len() == self.chord.len()
+    }
+
+    /// The profile after the active one.
+    pub fn next(&self) -> usize {
+        (self.active + 1) % self.profiles.len()
+    }
+
+    /// The profile called `name`.
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.profiles.iter().position(|(n, _)| n == name)
+    }
+
+    /// Switch to the profile at `next`, swapping its bindings for the active ones in `bindings`.
+    pub fn switch(&mut self, next: usize, bindings: &mut Bindings) {
+        if next == self.active {
+            return;
+        }
+        let mut next_bindings = std::mem::take(&mut self.profiles[next].1);
+        next_bindings.set_clicks_swapped(bindings.clicks_swapped());
+        next_bindings.set_keyboard_mode(bindings.keyboard_mode());
+        self.profiles[self.active].1 = std::mem::replace(bindings, next_bindings);
+        self.active = next;
+        self.show();
+    }
+
+    /// Light up the LEDs with the active profile's number, in binary.
+    fn show(&self) {
+        for (i, led) in self.leds.iter().enumerate() {
+            let on = (self.active + 1) >> i & 1;
+            if let Err(e) = fs::write(led, on.to_string()) {
+                eprintln!("warning: can't set {}: {}", led.display(), e);
+            }
+        }
+    }
+}
+
+/// The brightness files of hid-nintendo's player LEDs, e.g.
+/// `/sys/class/leds/0005:057E:2006.0001:green:player-1/brightness`, in order.
+fn player_leds(dev_path: &Path) -> Option<Vec<PathBuf>> {
+    let event = fs::canonicalize(dev_path).ok()?;
+    let event = event.file_name()?;
+    // the LEDs are registered under the HID device, like the battery
+    let leds = Path::new("/sys/class/input")
+        .join(event)
+        .join("device/device/leds");
+    let mut leds: Vec<_> = fs::read_dir(leds)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.contains(":player-"))
+        })
+        .collect();
+    leds.sort();
+    Some(leds.into_iter().map(|led| led.join("brightness")).collect())
+}
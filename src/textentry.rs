@@ -0,0 +1,239 @@
+//! Typing text with the stick (`--text-entry-key`), like the Steam controller's on-screen
+//! keyboard: a page of characters is laid out around the stick, clockwise from up, and pushing
+//! the stick towards one selects it. Then:
+//!
+//! - A types the selected character
+//! - B is backspace
+//! - X is space
+//! - Y flips to the next page
+//!
+//! (or, on a lone left Joy-Con, the buttons in the same places). The pages are the config file's
+//! `[text-entry]` ones, if it has any, and what's selected is shown by `--overlay`.
+//!
+//! The characters are typed as the keys that produce them on a US layout, with shift held for
+//! the shifted ones, since the virtual device can only press keys.
+
+use crate::bindings::Chord;
+use crate::stick::{Axis, StickSide};
+use evdev::{InputEvent, Key};
+use serde::Serialize;
+use smallvec::SmallVec;
+use std::f64::consts::TAU;
+
+/// The pages when the config file doesn't give any.
+pub const DEFAULT_PAGES: &[&str] = &[
+    "abcdefghijklm",
+    "nopqrstuvwxyz",
+    "ABCDEFGHIJKLM",
+    "NOPQRSTUVWXYZ",
+    "1234567890.,?!",
+    "-'\"/:;()@&#_\n",
+];
+
+/// How far the stick has to be pushed to select a character.
+const THRESHOLD: f64 = 16_000.0;
+
+/// The keys on a US layout, and the characters they type without and with shift.
+const US_LAYOUT: &[(Key, char, char)] = &[
+    (Key::KEY_A, 'a', 'A'),
+    (Key::KEY_B, 'b', 'B'),
+    (Key::KEY_C, 'c', 'C'),
+    (Key::KEY_D, 'd', 'D'),
+    (Key::KEY_E, 'e', 'E'),
+    (Key::KEY_F, 'f', 'F'),
+    (Key::KEY_G, 'g', 'G'),
+    (Key::KEY_H, 'h', 'H'),
+    (Key::KEY_I, 'i', 'I'),
+    (Key::KEY_J, 'j', 'J'),
+    (Key::KEY_K, 'k', 'K'),
+    (Key::KEY_L, 'l', 'L'),
+    (Key::KEY_M, 'm', 'M'),
+    (Key::KEY_N, 'n', 'N'),
+    (Key::KEY_O, 'o', 'O'),
+    (Key::KEY_P, 'p', 'P'),
+    (Key::KEY_Q, 'q', 'Q'),
+    (Key::KEY_R, 'r', 'R'),
+    (Key::KEY_S, 's', 'S'),
+    (Key::KEY_T, 't', 'T'),
+    (Key::KEY_U, 'u', 'U'),
+    (Key::KEY_V, 'v', 'V'),
+    (Key::KEY_W, 'w', 'W'),
+    (Key::KEY_X, 'x', 'X'),
+    (Key::KEY_Y, 'y', 'Y'),
+    (Key::KEY_Z, 'z', 'Z'),
+    (Key::KEY_1, '1', '!'),
+    (Key::KEY_2, '2', '@'),
+    (Key::KEY_3, '3', '#'),
+    (Key::KEY_4, '4', '$'),
+    (Key::KEY_5, '5', '%'),
+    (Key::KEY_6, '6', '^'),
+    (Key::KEY_7, '7', '&'),
+    (Key::KEY_8, '8', '*'),
+    (Key::KEY_9, '9', '('),
+    (Key::KEY_0, '0', ')'),
+    (Key::KEY_MINUS, '-', '_'),
+    (Key::KEY_EQUAL, '=', '+'),
+    (Key::KEY_LEFTBRACE, '[', '{'),
+    (Key::KEY_RIGHTBRACE, ']', '}'),
+    (Key::KEY_BACKSLASH, '\\', '|'),
+    (Key::KEY_SEMICOLON, ';', ':'),
+    (Key::KEY_APOSTROPHE, '\'', '"'),
+    (Key::KEY_GRAVE, '`', '~'),
+    (Key::KEY_COMMA, ',', '<'),
+    (Key::KEY_DOT, '.', '>'),
+    (Key::KEY_SLASH, '/', '?'),
+    (Key::KEY_SPACE, ' ', ' '),
+    (Key::KEY_ENTER, '\n', '\n'),
+    (Key::KEY_TAB, '\t', '\t'),
+];
+
+/// The buttons for typing, deleting, a space and the next page, on their own and as a lone left
+/// Joy-Con reports them.
+const TYPE: [Key; 2] = [Key::BTN_EAST, Key::BTN_DPAD_RIGHT];
+const BACKSPACE: [Key; 2] = [Key::BTN_SOUTH, Key::BTN_DPAD_DOWN];
+const SPACE: [Key; 2] = [Key::BTN_NORTH, Key::BTN_DPAD_UP];
+const NEXT_PAGE: [Key; 2] = [Key::BTN_WEST, Key::BTN_DPAD_LEFT];
+
+/// The key that types `c`, and whether shift has to be held for it.
+fn key_for(c: char) -> Option<(Key, bool)> {
+    US_LAYOUT.iter().find_map(|&(key, plain, shifted)| match c {
+        _ if c == plain => Some((key, false)),
+        _ if c == shifted => Some((key, true)),
+        _ => None,
+    })
+}
+
+/// What's selected, for `--overlay`: the page, by its index in the pages, and the character, by
+/// its index in the page.
+#[derive(Serialize, Clone, Copy, PartialEq)]
+pub struct Selection {
+    pub page: usize,
+    pub selected: Option<usize>,
+}
+
+pub struct TextEntry {
+    /// Each page's characters, with the chord that types them.
+    pages: Vec<Vec<(char, Chord)>>,
+    page: usize,
+    active: bool,
+    /// The latest position of each stick, by [`StickSide`] and [`Axis`].
+    sticks: [[i32; 2]; 2],
+    selected: Option<usize>,
+}
+
+impl TextEntry {
+    pub fn new<S: AsRef<str>>(pages: &[S]) -> Result<Self, String> {
+        let pages = pages
+            .iter()
+            .map(|page| {
+                let page = page.as_ref();
+                if page.is_empty() {
+                    return Err("a page can't be empty".to_owned());
+                }
+                page.chars()
+                    .map(|c| {
+                        let (key, shift) =
+                            key_for(c).ok_or_else(|| format!("can't type {:?}", c))?;
+                        let chord = match shift {
+                            true => Chord::new(&[Key::KEY_LEFTSHIFT, key])?,
+                            false => Chord::from(key),
+                        };
+                        Ok((c, chord))
+                    })
+                    .collect()
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if pages.is_empty() {
+            return Err("there has to be at least one page".to_owned());
+        }
+        Ok(TextEntry {
+            pages,
+            page: 0,
+            active: false,
+            sticks: [[0; 2]; 2],
+            selected: None,
+        })
+    }
+
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+        self.sticks = [[0; 2]; 2];
+        self.selected = None;
+    }
+
+    /// The keys it can press, for the virtual device's capabilities.
+    pub fn keys(&self) -> impl Iterator<Item = Key> + '_ {
+        self.pages
+            .iter()
+            .flatten()
+            .flat_map(|(_, chord)| chord.keys().iter().copied())
+            .chain([Key::KEY_BACKSPACE, Key::KEY_SPACE])
+    }
+
+    /// The current page's characters, in order clockwise from up.
+    pub fn page(&self) -> String {
+        self.pages[self.page].iter().map(|&(c, _)| c).collect()
+    }
+
+    pub fn selection(&self) -> Selection {
+        Selection {
+            page: self.page,
+            selected: self.selected,
+        }
+    }
+
+    /// Select the character the stick points at, after a new reading of `side`'s `axis`.
+    pub fn stick(&mut self, side: StickSide, axis: Axis, value: i32) {
+        self.sticks[side as usize][axis as usize] = value;
+        let [x, y] = self.sticks[side as usize].map(f64::from);
+        if x.hypot(y) < THRESHOLD {
+            self.selected = None;
+            return;
+        }
+        // clockwise from up, where the stick's y is negative
+        let angle = x.atan2(-y).rem_euclid(TAU);
+        let len = self.pages[self.page].len();
+        let sector = TAU / len as f64;
+        self.selected = Some((angle / sector).round() as usize % len);
+    }
+
+    /// Handle a button, returning what to emit for it, or `None` if it isn't one text entry
+    /// uses.
+    pub fn button(&mut self, key: Key, value: i32) -> Option<SmallVec<[InputEvent; 4]>> {
+        let tap = |chord: Chord| {
+            let mut events = chord.events(1);
+            events.extend(chord.events(0));
+            events
+        };
+        if ![TYPE, BACKSPACE, SPACE, NEXT_PAGE]
+            .iter()
+            .any(|keys| keys.contains(&key))
+        {
+            return None;
+        }
+        // only the press does anything
+        if value != 1 {
+            return Some(SmallVec::new());
+        }
+        let events = if TYPE.contains(&key) {
+            match self.selected {
+                Some(i) => tap(self.pages[self.page][i].1),
+                None => SmallVec::new(),
+            }
+        } else if BACKSPACE.contains(&key) {
+            tap(Chord::from(Key::KEY_BACKSPACE))
+        } else if SPACE.contains(&key) {
+            tap(Chord::from(Key::KEY_SPACE))
+        } else {
+            self.page = (self.page + 1) % self.pages.len();
+            self.selected = None;
+            eprintln!("Text entry page: {:?}", self.page());
+            SmallVec::new()
+        };
+        Some(events)
+    }
+}
@@ -0,0 +1,301 @@
+//! Using touchpads (e.g. a Steam Deck's or Steam Controller's) as sources: one finger moves the
+//! cursor, dragging two fingers or swiping along the right or bottom edge scrolls, and tapping
+//! clicks.
+
+use crate::multitouch::Contacts;
+use evdev::{
+    AbsoluteAxisType, Device, EventType, InputEvent, InputEventKind, Key, RelativeAxisType,
+};
+use smallvec::SmallVec;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// The fraction of the trackpad's width/height along the right/bottom edge that scrolls.
+const EDGE_ZONE: f64 = 0.1;
+/// How far the finger has to travel, as a fraction of the trackpad's height, to scroll one step.
+const SCROLL_STEP: f64 = 0.05;
+/// Touches shorter than this that don't move more than `TAP_SLOP` (as a fraction of the
+/// trackpad's width) are taps.
+const TAP_TIME: Duration = Duration::from_millis(180);
+const TAP_SLOP: f64 = 0.02;
+/// How far two fingers have to travel together before the scroll direction gets locked to the
+/// axis they moved along the most.
+const AXIS_LOCK_DISTANCE: f64 = 0.03;
+
+/// What a frame turns into: at most a click, or motion along two axes.
+pub type Events = SmallVec<[InputEvent; 2]>;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Zone {
+    Pointer,
+    ScrollVertical,
+    ScrollHorizontal,
+}
+
+struct Touch {
+    start: Instant,
+    zone: Zone,
+    /// The last position we've processed, if known yet.
+    last: (Option<i32>, Option<i32>),
+    travel: f64,
+    max_fingers: usize,
+}
+
+/// The state of a two finger scroll.
+struct TwoFinger {
+    /// The last midpoint between the two fingers.
+    last: (f64, f64),
+    travel: (f64, f64),
+    /// The axis scrolling is locked to, once decided.
+    lock: Option<Zone>,
+}
+
+pub struct Trackpad {
+    min: (i32, i32),
+    size: (f64, f64),
+    /// Pixels the cursor moves for a swipe across the whole width of the trackpad.
+    speed: f64,
+    /// Whether content should follow the fingers when scrolling, like on a phone.
+    natural_scroll: bool,
+    contacts: Contacts,
+    fingers: usize,
+    touch: Option<Touch>,
+    two_finger: Option<TwoFinger>,
+    /// Changes since the last SYN_REPORT.
+    pending_pos: (Option<i32>, Option<i32>),
+    pending_touch: Option<bool>,
+    remainder: [f64; 4],
+}
+
+impl Trackpad {
+    pub fn new(dev: &Device, speed: f64, natural_scroll: bool) -> io::Result<Self> {
+        let abs = dev.get_abs_state()?;
+        let x = &abs[AbsoluteAxisType::ABS_X.0 as usize];
+        let y = &abs[AbsoluteAxisType::ABS_Y.0 as usize];
+        Ok(Trackpad {
+            min: (x.minimum, y.minimum),
+            size: (
+                f64::from((x.maximum - x.minimum).max(1)),
+                f64::from((y.maximum - y.minimum).max(1)),
+            ),
+            speed,
+            natural_scroll,
+            contacts: Contacts::new(dev)?,
+            fingers: 0,
+            touch: None,
+            two_finger: None,
+            pending_pos: (None, None),
+            pending_touch: None,
+            remainder: [0.0; 4],
+        })
+    }
+
+    /// Whether `ev` is part of the touch data we process, as opposed to e.g. a physical click.
+    pub fn is_touch_event(ev: &InputEvent) -> bool {
+        match ev.kind() {
+            InputEventKind::AbsAxis(_) | InputEventKind::Synchronization(_) => true,
+            InputEventKind::Key(key) => Self::is_touch_key(key),
+            _ => false,
+        }
+    }
+
+    /// Whether `key` is one of the buttons touchpads use to report touches.
+    pub fn is_touch_key(key: Key) -> bool {
+        matches!(
+            key,
+            Key::BTN_TOUCH
+                | Key::BTN_TOOL_FINGER
+                | Key::BTN_TOOL_DOUBLETAP
+                | Key::BTN_TOOL_TRIPLETAP
+                | Key::BTN_TOOL_QUADTAP
+                | Key::BTN_TOOL_QUINTTAP
+        )
+    }
+
+    pub fn handle(&mut self, ev: InputEvent) -> Events {
+        self.contacts.handle(&ev);
+        match ev.kind() {
+            InputEventKind::Key(Key::BTN_TOUCH) => self.pending_touch = Some(ev.value() != 0),
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_X) => {
+                self.pending_pos.0 = Some(ev.value())
+            }
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_Y) => {
+                self.pending_pos.1 = Some(ev.value())
+            }
+            InputEventKind::Synchronization(_) => return self.frame(),
+            _ => {}
+        }
+        Events::new()
+    }
+
+    /// Process everything that's changed since the last frame.
+    fn frame(&mut self) -> Events {
+        let mut out = Events::new();
+        let pos = std::mem::take(&mut self.pending_pos);
+        let fingers = self.contacts.count();
+        let fingers_changed = fingers != self.fingers;
+        self.fingers = fingers;
+
+        match self.pending_touch.take() {
+            Some(true) => {
+                let zone = match pos {
+                    (Some(x), _) if self.fraction_x(x) > 1.0 - EDGE_ZONE => Zone::ScrollVertical,
+                    (_, Some(y)) if self.fraction_y(y) > 1.0 - EDGE_ZONE => Zone::ScrollHorizontal,
+                    _ => Zone::Pointer,
+                };
+                self.touch = Some(Touch {
+                    start: Instant::now(),
+                    zone,
+                    last: pos,
+                    travel: 0.0,
+                    max_fingers: fingers.max(1),
+                });
+                self.remainder = [0.0; 4];
+                return out;
+            }
+            Some(false) => {
+                if let Some(touch) = self.touch.take() {
+                    if touch.start.elapsed() < TAP_TIME
+                        && touch.travel < TAP_SLOP
+                        && touch.max_fingers == 1
+                    {
+                        out.push(InputEvent::new(EventType::KEY, Key::BTN_LEFT.code(), 1));
+                        out.push(InputEvent::new(EventType::KEY, Key::BTN_LEFT.code(), 0));
+                    }
+                }
+                self.two_finger = None;
+                return out;
+            }
+            None => {}
+        }
+        let touch = match &mut self.touch {
+            Some(touch) => touch,
+            None => return out,
+        };
+        touch.max_fingers = touch.max_fingers.max(fingers);
+        if fingers_changed {
+            // the single touch position jumps around when fingers are added or lifted
+            touch.last = (None, None);
+        }
+        let dx = delta(&mut touch.last.0, pos.0);
+        let dy = delta(&mut touch.last.1, pos.1);
+        let (fx, fy) = (f64::from(dx) / self.size.0, f64::from(dy) / self.size.1);
+        touch.travel += fx.hypot(fy);
+        let zone = touch.zone;
+
+        if fingers >= 2 {
+            self.two_finger_scroll(&mut out);
+            return out;
+        }
+        self.two_finger = None;
+
+        match zone {
+            Zone::Pointer => {
+                let px = fx * self.speed;
+                // keep the same scale on both axes, regardless of the trackpad's aspect ratio
+                let py = f64::from(dy) / self.size.0 * self.speed;
+                self.push_rel(&mut out, RelativeAxisType::REL_X, 0, px);
+                self.push_rel(&mut out, RelativeAxisType::REL_Y, 1, py);
+            }
+            Zone::ScrollVertical => self.scroll(&mut out, Zone::ScrollVertical, fy),
+            Zone::ScrollHorizontal => self.scroll(&mut out, Zone::ScrollHorizontal, fx),
+        }
+        out
+    }
+
+    fn two_finger_scroll(&mut self, out: &mut Events) {
+        let mut active = self.contacts.active();
+        let mid = match (active.next(), active.next()) {
+            (Some(a), Some(b)) => (
+                (f64::from(a.x) + f64::from(b.x)) / 2.0,
+                (f64::from(a.y) + f64::from(b.y)) / 2.0,
+            ),
+            _ => return,
+        };
+        drop(active);
+        let state = match &mut self.two_finger {
+            Some(state) => state,
+            None => {
+                self.two_finger = Some(TwoFinger {
+                    last: mid,
+                    travel: (0.0, 0.0),
+                    lock: None,
+                });
+                return;
+            }
+        };
+        let fx = (mid.0 - state.last.0) / self.size.0;
+        let fy = (mid.1 - state.last.1) / self.size.1;
+        state.last = mid;
+        state.travel.0 += fx.abs();
+        state.travel.1 += fy.abs();
+        if state.lock.is_none() && state.travel.0.hypot(state.travel.1) > AXIS_LOCK_DISTANCE {
+            state.lock = Some(if state.travel.0 > state.travel.1 {
+                Zone::ScrollHorizontal
+            } else {
+                Zone::ScrollVertical
+            });
+        }
+        match state.lock {
+            Some(Zone::ScrollHorizontal) => self.scroll(out, Zone::ScrollHorizontal, fx),
+            Some(_) => self.scroll(out, Zone::ScrollVertical, fy),
+            // not decided yet
+            None => {}
+        }
+    }
+
+    /// Scroll by `amount` (the distance the finger(s) moved as a fraction of the trackpad's size)
+    /// in the direction `zone`.
+    fn scroll(&mut self, out: &mut Events, zone: Zone, amount: f64) {
+        // a positive REL_WHEEL scrolls up, i.e. moves the content down, while a positive
+        // REL_HWHEEL moves the content left
+        let sign = if self.natural_scroll { 1.0 } else { -1.0 };
+        match zone {
+            Zone::ScrollVertical => self.push_rel(
+                out,
+                RelativeAxisType::REL_WHEEL,
+                2,
+                sign * amount / SCROLL_STEP,
+            ),
+            Zone::ScrollHorizontal => self.push_rel(
+                out,
+                RelativeAxisType::REL_HWHEEL,
+                3,
+                -sign * amount / SCROLL_STEP,
+            ),
+            Zone::Pointer => {}
+        }
+    }
+
+    fn push_rel(&mut self, out: &mut Events, axis: RelativeAxisType, i: usize, v: f64) {
+        let exact = v + self.remainder[i];
+        let whole = exact.trunc();
+        self.remainder[i] = exact - whole;
+        if whole != 0.0 {
+            out.push(InputEvent::new(EventType::RELATIVE, axis.0, whole as i32));
+        }
+    }
+
+    fn fraction_x(&self, x: i32) -> f64 {
+        f64::from(x - self.min.0) / self.size.0
+    }
+
+    fn fraction_y(&self, y: i32) -> f64 {
+        f64::from(y - self.min.1) / self.size.1
+    }
+}
+
+/// The change from `*last` to `new`, updating `*last`.
+fn delta(last: &mut Option<i32>, new: Option<i32>) -> i32 {
+    match (*last, new) {
+        (Some(old), Some(new)) => {
+            *last = Some(new);
+            new - old
+        }
+        (None, Some(new)) => {
+            *last = Some(new);
+            0
+        }
+        (_, None) => 0,
+    }
+}
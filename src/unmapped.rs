@@ -0,0 +1,136 @@
+//! Forwarding what the mapping has no use for (`forward-unmapped = true` in the config file) onto
+//! a second virtual device, unchanged, so a controller with more than joykbd knows about (extra
+//! buttons, triggers or dials reported as axes, `MSC_SCAN` codes, ...) loses none of it.
+//!
+//! The device has the controller's buttons that neither the preset nor the bindings map, its axes
+//! other than the sticks, and its misc and switch events. A button that's pressed while unmapped
+//! is released through it as well, even if it's been bound since.
+
+use crate::bindings::Bindings;
+use crate::hat;
+use crate::stick::StickSide;
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{
+    AbsInfo, AbsoluteAxisType, AttributeSet, Device, EventType, InputEvent, InputEventKind, Key,
+    MiscType, SwitchType, UinputAbsSetup,
+};
+use std::io;
+use std::path::PathBuf;
+
+pub struct Unmapped {
+    dev: VirtualDevice,
+    keys: AttributeSet<Key>,
+    axes: AttributeSet<AbsoluteAxisType>,
+    misc: AttributeSet<MiscType>,
+    switches: AttributeSet<SwitchType>,
+    /// The keys pressed through it, which it releases.
+    held: AttributeSet<Key>,
+}
+
+impl Unmapped {
+    /// A device for what `source` reports that isn't mapped, with `key` giving what its keys are
+    /// read as (see [`crate::layout`]) and `is_mapped` whether one of them is. `None` if there's
+    /// nothing to forward.
+    pub fn new(
+        source: &Device,
+        key: impl Fn(Key) -> Key,
+        is_mapped: impl Fn(Key) -> bool,
+    ) -> io::Result<Option<Self>> {
+        let keys: AttributeSet<Key> = source
+            .supported_keys()
+            .into_iter()
+            .flat_map(|keys| keys.iter())
+            .chain(hat::keys(source))
+            .map(key)
+            .filter(|&key| !is_mapped(key))
+            .collect();
+        let abs_state = source.get_abs_state()?;
+        let mut axes = AttributeSet::new();
+        let mut abs = vec![];
+        for axis in source.supported_absolute_axes().into_iter().flatten() {
+            // the hat arrives as the D-pad's buttons, above
+            if StickSide::of(axis).is_some() || hat::is_hat(axis) {
+                continue;
+            }
+            let info = &abs_state[axis.0 as usize];
+            let info = AbsInfo::new(
+                info.value,
+                info.minimum,
+                info.maximum,
+                info.fuzz,
+                info.flat,
+                info.resolution,
+            );
+            axes.insert(axis);
+            abs.push(UinputAbsSetup::new(axis, info));
+        }
+        let misc: AttributeSet<MiscType> = source.misc_properties().into_iter().flatten().collect();
+        let switches: AttributeSet<SwitchType> =
+            source.supported_switches().into_iter().flatten().collect();
+        if keys.iter().next().is_none()
+            && abs.is_empty()
+            && misc.iter().next().is_none()
+            && switches.iter().next().is_none()
+        {
+            return Ok(None);
+        }
+
+        let mut builder = VirtualDeviceBuilder::new()?
+            .name("joykbd unmapped")
+            .with_keys(&keys)?
+            .with_msc(&misc)?
+            .with_switches(&switches)?;
+        for abs in &abs {
+            builder = builder.with_absolute_axis(abs)?;
+        }
+        Ok(Some(Unmapped {
+            dev: builder.build()?,
+            keys,
+            axes,
+            misc,
+            switches,
+            held: AttributeSet::new(),
+        }))
+    }
+
+    /// Forward `ev` if it's one of the device's, and (for a button) isn't bound by `bindings`.
+    /// Returns whether it was.
+    pub fn forward(&mut self, ev: InputEvent, bindings: &Bindings) -> io::Result<bool> {
+        let forward = match ev.kind() {
+            InputEventKind::Key(key) if ev.value() == 0 && self.held.contains(key) => {
+                self.held.remove(key);
+                true
+            }
+            InputEventKind::Key(key) if self.keys.contains(key) && !bindings.is_bound(key) => {
+                if ev.value() == 1 {
+                    self.held.insert(key);
+                }
+                true
+            }
+            InputEventKind::AbsAxis(axis) => self.axes.contains(axis),
+            InputEventKind::Misc(misc) => self.misc.contains(misc),
+            InputEventKind::Switch(switch) => self.switches.contains(switch),
+            _ => false,
+        };
+        if forward {
+            self.dev.emit(&[ev])?;
+        }
+        Ok(forward)
+    }
+
+    /// Release every key pressed through it, e.g. when the controller disconnects.
+    pub fn release_all(&mut self) -> io::Result<()> {
+        let releases: Vec<_> = self
+            .held
+            .iter()
+            .map(|key| InputEvent::new(EventType::KEY, key.code(), 0))
+            .collect();
+        self.held = AttributeSet::new();
+        self.dev.emit(&releases)
+    }
+
+    /// The device in sysfs.
+    pub fn sys_path(&mut self) -> Option<PathBuf> {
+        self.dev.get_syspath().ok()
+    }
+}
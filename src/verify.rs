@@ -0,0 +1,140 @@
+//! `joykbd verify`: golden-output regression tests for the mapping, from real-world captures.
+//! A fixture is a TOML file pairing a `--record` recording with the events joykbd should emit
+//! for it, given some options:
+//!
+//! ```toml
+//! # the options to translate the recording with, as on the command line
+//! args = ["--remap", "BTN_EAST=KEY_LEFTCTRL+KEY_C"]
+//! # relative to the fixture
+//! recording = "copy.jsonl"
+//! expected = "copy.expected"
+//! ```
+//!
+//! The expected events are one per line, e.g. `KEY_LEFTCTRL 1` or `REL_X -12`, so a failure
+//! reads as a diff. `--bless` writes what joykbd emits now as the expected events instead.
+//!
+//! The recording goes the same way the event loop takes the controller's events, through the
+//! translation (bindings, the default button mapping and the stick's pipeline) and the output's
+//! own handling (swapped clicks, sticky keys, rotation, keys held by several buttons, ...). What
+//! depends on timing, like repeating the stick's motion, isn't covered.
+//!
+//! The fixtures in `tests/fixtures` are checked by `cargo test`.
+
+use evdev::{EventType, InputEvent, InputEventKind};
+use joykbd::record::Record;
+use joykbd::source::SourceKind;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How many lines to show around the first difference.
+const CONTEXT: usize = 3;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fixture {
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub recording: PathBuf,
+    pub expected: PathBuf,
+}
+
+impl Fixture {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut fixture: Fixture = toml::from_str(&fs::read_to_string(path)?)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        fixture.recording = dir.join(&fixture.recording);
+        fixture.expected = dir.join(&fixture.expected);
+        Ok(fixture)
+    }
+
+    /// The recording's events, leaving out redacted ones.
+    pub fn inputs(&self) -> anyhow::Result<Vec<(SourceKind, InputEvent)>> {
+        let mut inputs = vec![];
+        for (i, line) in fs::read_to_string(&self.recording)?.lines().enumerate() {
+            let record: Record = serde_json::from_str(line)
+                .map_err(|e| anyhow::anyhow!("{}:{}: {}", self.recording.display(), i + 1, e))?;
+            if let Some(code) = record.code {
+                let ev = InputEvent::new(EventType(record.kind), code, record.value);
+                inputs.push((record.source, ev));
+            }
+        }
+        Ok(inputs)
+    }
+
+    /// Compare `actual` with the expected events, returning a description of where they differ,
+    /// if they do.
+    pub fn check(&self, actual: &[String]) -> anyhow::Result<Option<String>> {
+        let expected = fs::read_to_string(&self.expected)?;
+        let expected: Vec<_> = expected.lines().collect();
+        let first = expected
+            .iter()
+            .zip(actual)
+            .position(|(expected, actual)| expected != actual)
+            .unwrap_or(expected.len().min(actual.len()));
+        if first == expected.len() && first == actual.len() {
+            return Ok(None);
+        }
+        let mut diff = format!(
+            "the events differ from line {} ({} expected, {} emitted):\n",
+            first + 1,
+            expected.len(),
+            actual.len()
+        );
+        let start = first.saturating_sub(CONTEXT);
+        for line in &expected[start..first] {
+            diff += &format!("  {}\n", line);
+        }
+        for line in expected.iter().skip(first).take(CONTEXT) {
+            diff += &format!("- {}\n", line);
+        }
+        for line in actual.iter().skip(first).take(CONTEXT) {
+            diff += &format!("+ {}\n", line);
+        }
+        Ok(Some(diff))
+    }
+
+    /// Write `actual` as the expected events, one per line, which is nothing at all when there
+    /// are none, so `check` reads them back the same.
+    pub fn bless(&self, actual: &[String]) -> std::io::Result<()> {
+        let contents: String = actual.iter().map(|line| format!("{}\n", line)).collect();
+        fs::write(&self.expected, contents)
+    }
+}
+
+/// An emitted event, as a line of a fixture's expected events.
+pub fn describe(ev: &InputEvent) -> String {
+    match ev.kind() {
+        InputEventKind::Key(key) => format!("{:?} {}", key, ev.value()),
+        InputEventKind::RelAxis(axis) => format!("{:?} {}", axis, ev.value()),
+        InputEventKind::AbsAxis(axis) => format!("{:?} {}", axis, ev.value()),
+        _ => format!("{:?} {} {}", ev.event_type(), ev.code(), ev.value()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Fixture;
+    use std::fs;
+    use std::path::Path;
+
+    /// Every fixture in `tests/fixtures`, as `joykbd verify` would check it.
+    #[test]
+    fn fixtures() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let mut checked = 0;
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "toml") {
+                continue;
+            }
+            let fixture = Fixture::load(&path).unwrap();
+            let emitted = crate::translate_fixture(&fixture).unwrap();
+            if let Some(diff) = fixture.check(&emitted).unwrap() {
+                panic!("{}: {}", path.display(), diff);
+            }
+            checked += 1;
+        }
+        assert!(checked > 0, "no fixtures in {}", dir.display());
+    }
+}
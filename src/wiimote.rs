@@ -0,0 +1,62 @@
+//! Wii Remote support (via the kernel's hid-wiimote driver), using its IR camera as an absolute
+//! pointer.
+
+use crate::cursor::Size;
+use evdev::{
+    AbsInfo, AbsoluteAxisType, Device, EventType, InputEvent, InputEventKind, UinputAbsSetup,
+};
+use std::path::PathBuf;
+
+/// The IR camera reports the position of up to 4 IR sources in this range; 1023 on both axes
+/// means that slot isn't tracking anything.
+const IR_WIDTH: i32 = 1024;
+const IR_HEIGHT: i32 = 768;
+const IR_MISSING: i32 = 1023;
+
+/// The range of the positions `map_ir` produces.
+pub const IR_SIZE: Size = Size {
+    width: IR_WIDTH,
+    height: IR_HEIGHT,
+};
+
+/// The absolute axes the virtual device needs to position the cursor from the IR camera.
+pub fn pointer_axes() -> [UinputAbsSetup; 2] {
+    [
+        UinputAbsSetup::new(
+            AbsoluteAxisType::ABS_X,
+            AbsInfo::new(0, 0, IR_WIDTH - 1, 0, 0, 0),
+        ),
+        UinputAbsSetup::new(
+            AbsoluteAxisType::ABS_Y,
+            AbsInfo::new(0, 0, IR_HEIGHT - 1, 0, 0, 0),
+        ),
+    ]
+}
+
+/// Find the IR camera device belonging to the Wii Remote `dev`.
+pub fn find_ir(dev: &Device) -> Option<(PathBuf, Device)> {
+    let ir_name = format!("{} IR", dev.name()?);
+    evdev::enumerate()
+        .find(|(_, ir)| ir.name() == Some(&ir_name) && ir.physical_path() == dev.physical_path())
+}
+
+/// Map an event from the IR camera device to an absolute cursor position event.
+pub fn map_ir(ev: InputEvent) -> Option<InputEvent> {
+    if ev.value() == IR_MISSING {
+        return None;
+    }
+    match ev.kind() {
+        // the camera sees the sensor bar moving the opposite way the remote is pointed
+        InputEventKind::AbsAxis(AbsoluteAxisType::ABS_HAT0X) => Some(InputEvent::new(
+            EventType::ABSOLUTE,
+            AbsoluteAxisType::ABS_X.0,
+            IR_WIDTH - 1 - ev.value(),
+        )),
+        InputEventKind::AbsAxis(AbsoluteAxisType::ABS_HAT0Y) => Some(InputEvent::new(
+            EventType::ABSOLUTE,
+            AbsoluteAxisType::ABS_Y.0,
+            ev.value(),
+        )),
+        _ => None,
+    }
+}
@@ -0,0 +1,216 @@
+//! Emitting through a wlroots-based Wayland compositor's virtual pointer and virtual keyboard
+//! protocols (`--output wlroots`), for sessions such as sway where joykbd can't create uinput
+//! devices. The compositor has to be told the keyboard's keymap, so a US layout with evdev
+//! keycodes is uploaded, which matches what the bindings' key names mean.
+
+use crate::output::{Capabilities, OutputSink};
+use evdev::{AbsoluteAxisType, InputEvent, InputEventKind, RelativeAxisType};
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::io::{AsFd, FromRawFd};
+use std::time::Instant;
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::wl_keyboard::KeymapFormat;
+use wayland_client::protocol::wl_pointer::{Axis, AxisSource, ButtonState};
+use wayland_client::protocol::{wl_registry::WlRegistry, wl_seat::WlSeat};
+use wayland_client::{delegate_noop, Connection, Dispatch, QueueHandle};
+use wayland_protocols_misc::zwp_virtual_keyboard_v1::client::{
+    zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1,
+    zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1,
+};
+use wayland_protocols_wlr::virtual_pointer::v1::client::{
+    zwlr_virtual_pointer_manager_v1::ZwlrVirtualPointerManagerV1,
+    zwlr_virtual_pointer_v1::ZwlrVirtualPointerV1,
+};
+
+/// The keymap uploaded for the virtual keyboard.
+const KEYMAP: &str = "xkb_keymap {
+    xkb_keycodes { include \"evdev+aliases(qwerty)\" };
+    xkb_types { include \"complete\" };
+    xkb_compat { include \"complete\" };
+    xkb_symbols { include \"pc+us+inet(evdev)\" };
+};
+";
+
+/// How far one click of the wheel scrolls, in the compositor's units, as libinput reports it.
+const WHEEL_STEP: f64 = 15.0;
+
+/// The first and one past the last of the mouse buttons' codes, which go to the pointer rather
+/// than the keyboard.
+const BUTTONS: std::ops::Range<u16> = 0x110..0x120;
+
+pub struct WlrootsSink {
+    conn: Connection,
+    pointer: ZwlrVirtualPointerV1,
+    keyboard: ZwpVirtualKeyboardV1,
+    /// What events' timestamps count from.
+    start: Instant,
+    /// The absolute cursor's range, if it has one, and where it is, as its events come an axis at
+    /// a time.
+    extent: (u32, u32),
+    position: (u32, u32),
+}
+
+/// The objects' events, none of which matter here.
+struct State;
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: <WlRegistry as wayland_client::Proxy>::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+delegate_noop!(State: ignore WlSeat);
+delegate_noop!(State: ZwlrVirtualPointerManagerV1);
+delegate_noop!(State: ZwlrVirtualPointerV1);
+delegate_noop!(State: ZwpVirtualKeyboardManagerV1);
+delegate_noop!(State: ZwpVirtualKeyboardV1);
+
+impl WlrootsSink {
+    /// Connect to the compositor in `$WAYLAND_DISPLAY`, and create a virtual pointer and keyboard
+    /// able to emit `caps`.
+    pub fn connect(caps: &Capabilities) -> io::Result<Self> {
+        let conn = Connection::connect_to_env().map_err(io::Error::other)?;
+        let (globals, mut queue) = registry_queue_init::<State>(&conn).map_err(io::Error::other)?;
+        let qh = queue.handle();
+        let unsupported = |what| {
+            move |e| {
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("the compositor doesn't support {}: {}", what, e),
+                )
+            }
+        };
+        let seat: WlSeat = globals.bind(&qh, 1..=1, ()).map_err(unsupported("seats"))?;
+        let pointers: ZwlrVirtualPointerManagerV1 = globals
+            .bind(&qh, 1..=2, ())
+            .map_err(unsupported("virtual pointers"))?;
+        let keyboards: ZwpVirtualKeyboardManagerV1 = globals
+            .bind(&qh, 1..=1, ())
+            .map_err(unsupported("virtual keyboards"))?;
+        let pointer = pointers.create_virtual_pointer(Some(&seat), &qh, ());
+        let keyboard = keyboards.create_virtual_keyboard(&seat, &qh, ());
+        let keymap = keymap_file()?;
+        keyboard.keymap(
+            KeymapFormat::XkbV1 as u32,
+            keymap.as_fd(),
+            KEYMAP.len() as u32 + 1,
+        );
+        // so a refusal (such as to an unprivileged client) shows up here rather than later
+        queue.roundtrip(&mut State).map_err(io::Error::other)?;
+        let extent = |axis| {
+            caps.abs
+                .iter()
+                .find(|abs| abs.code() == axis)
+                .map_or(0, |abs| abs.absinfo().maximum().max(0) as u32 + 1)
+        };
+        Ok(WlrootsSink {
+            conn,
+            pointer,
+            keyboard,
+            start: Instant::now(),
+            extent: (
+                extent(AbsoluteAxisType::ABS_X.0),
+                extent(AbsoluteAxisType::ABS_Y.0),
+            ),
+            position: (0, 0),
+        })
+    }
+
+    /// Scroll `clicks` of the wheel along `axis`.
+    fn scroll(&self, time: u32, axis: Axis, clicks: i32) {
+        self.pointer.axis_source(AxisSource::Wheel);
+        self.pointer
+            .axis_discrete(time, axis, clicks as f64 * WHEEL_STEP, clicks);
+    }
+}
+
+/// A file with [`KEYMAP`] in it, NUL-terminated as the compositor expects, to share with it.
+fn keymap_file() -> io::Result<File> {
+    let name = CString::new("joykbd-keymap").unwrap();
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    file.write_all(KEYMAP.as_bytes())?;
+    file.write_all(&[0])?;
+    Ok(file)
+}
+
+impl OutputSink for WlrootsSink {
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        let time = self.start.elapsed().as_millis() as u32;
+        let mut pointed = false;
+        let (mut dx, mut dy) = (0, 0);
+        let mut moved_to = false;
+        for ev in events {
+            match ev.kind() {
+                // the compositor repeats held keys itself
+                InputEventKind::Key(_) if ev.value() == 2 => {}
+                InputEventKind::Key(key) if BUTTONS.contains(&key.code()) => {
+                    let state = match ev.value() {
+                        0 => ButtonState::Released,
+                        _ => ButtonState::Pressed,
+                    };
+                    self.pointer.button(time, key.code().into(), state);
+                    pointed = true;
+                }
+                InputEventKind::Key(key) => {
+                    self.keyboard
+                        .key(time, key.code().into(), (ev.value() != 0).into())
+                }
+                InputEventKind::RelAxis(RelativeAxisType::REL_X) => dx += ev.value(),
+                InputEventKind::RelAxis(RelativeAxisType::REL_Y) => dy += ev.value(),
+                // evdev's wheel counts up for scrolling up, and Wayland's down
+                InputEventKind::RelAxis(RelativeAxisType::REL_WHEEL) => {
+                    self.scroll(time, Axis::VerticalScroll, -ev.value());
+                    pointed = true;
+                }
+                InputEventKind::RelAxis(RelativeAxisType::REL_HWHEEL) => {
+                    self.scroll(time, Axis::HorizontalScroll, ev.value());
+                    pointed = true;
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_X) => {
+                    self.position.0 = ev.value().max(0) as u32;
+                    moved_to = true;
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_Y) => {
+                    self.position.1 = ev.value().max(0) as u32;
+                    moved_to = true;
+                }
+                _ => {}
+            }
+        }
+        if dx != 0 || dy != 0 {
+            self.pointer.motion(time, dx.into(), dy.into());
+            pointed = true;
+        }
+        if moved_to && self.extent.0 > 0 && self.extent.1 > 0 {
+            let (x, y) = self.position;
+            let (width, height) = self.extent;
+            self.pointer.motion_absolute(time, x, y, width, height);
+            pointed = true;
+        }
+        if pointed {
+            self.pointer.frame();
+        }
+        match self.conn.flush() {
+            Ok(()) => Ok(()),
+            // the requests are queued, and go out with the next report's
+            Err(wayland_client::backend::WaylandError::Io(e))
+                if e.kind() == io::ErrorKind::WouldBlock =>
+            {
+                Ok(())
+            }
+            Err(e) => Err(io::Error::other(e)),
+        }
+    }
+}
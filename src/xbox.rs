@@ -0,0 +1,113 @@
+//! Presenting the virtual gamepad as an Xbox 360 controller, for games that only recognize
+//! XInput-style pads. The joy-con's events are translated to xpad's layout: ZL/ZR become the
+//! analog triggers, the D-pad becomes a hat, and the sticks cover the full signed 16-bit range.
+
+use evdev::{
+    AbsInfo, AbsoluteAxisType, AttributeSet, BusType, EventType, InputEvent, InputEventKind,
+    InputId, Key, UinputAbsSetup,
+};
+use std::collections::HashMap;
+
+pub const NAME: &str = "Microsoft X-Box 360 pad";
+
+/// The IDs of a wired Xbox 360 controller.
+pub fn id() -> InputId {
+    InputId::new(BusType::BUS_USB, 0x045e, 0x028e, 0x0110)
+}
+
+const BUTTONS: [Key; 11] = [
+    Key::BTN_SOUTH,
+    Key::BTN_EAST,
+    Key::BTN_NORTH,
+    Key::BTN_WEST,
+    Key::BTN_TL,
+    Key::BTN_TR,
+    Key::BTN_SELECT,
+    Key::BTN_START,
+    Key::BTN_MODE,
+    Key::BTN_THUMBL,
+    Key::BTN_THUMBR,
+];
+
+const STICK_MAX: i32 = i16::MAX as i32;
+const TRIGGER_MAX: i32 = u8::MAX as i32;
+
+/// The D-pad buttons, in the order of [`Xbox::dpad`].
+const DPAD: [Key; 4] = [
+    Key::BTN_DPAD_LEFT,
+    Key::BTN_DPAD_RIGHT,
+    Key::BTN_DPAD_UP,
+    Key::BTN_DPAD_DOWN,
+];
+
+/// The buttons and axes an Xbox 360 controller has.
+pub fn capabilities() -> (AttributeSet<Key>, Vec<UinputAbsSetup>) {
+    let stick = AbsInfo::new(0, -STICK_MAX - 1, STICK_MAX, 16, 128, 0);
+    let trigger = AbsInfo::new(0, 0, TRIGGER_MAX, 0, 0, 0);
+    let hat = AbsInfo::new(0, -1, 1, 0, 0, 0);
+    let axes = [
+        (AbsoluteAxisType::ABS_X, stick),
+        (AbsoluteAxisType::ABS_Y, stick),
+        (AbsoluteAxisType::ABS_RX, stick),
+        (AbsoluteAxisType::ABS_RY, stick),
+        (AbsoluteAxisType::ABS_Z, trigger),
+        (AbsoluteAxisType::ABS_RZ, trigger),
+        (AbsoluteAxisType::ABS_HAT0X, hat),
+        (AbsoluteAxisType::ABS_HAT0Y, hat),
+    ];
+    (
+        AttributeSet::from_iter(BUTTONS),
+        axes.into_iter()
+            .map(|(axis, info)| UinputAbsSetup::new(axis, info))
+            .collect(),
+    )
+}
+
+pub struct Xbox {
+    /// The ranges of the source's stick axes, by axis code.
+    ranges: HashMap<u16, (i32, i32)>,
+    /// Which of the D-pad buttons are held.
+    dpad: [bool; 4],
+}
+
+impl Xbox {
+    pub fn new(ranges: HashMap<u16, (i32, i32)>) -> Self {
+        Xbox {
+            ranges,
+            dpad: [false; 4],
+        }
+    }
+
+    /// Translate an event from the joy-con's layout, or drop it if the controller has nothing
+    /// like it.
+    pub fn map(&mut self, ev: InputEvent) -> Option<InputEvent> {
+        let (axis, value) = match ev.kind() {
+            InputEventKind::Key(Key::BTN_TL2) => (AbsoluteAxisType::ABS_Z, ev.value()),
+            InputEventKind::Key(Key::BTN_TR2) => (AbsoluteAxisType::ABS_RZ, ev.value()),
+            InputEventKind::Key(key) if DPAD.contains(&key) => {
+                let i = DPAD.iter().position(|&dpad| dpad == key).unwrap();
+                self.dpad[i] = ev.value() != 0;
+                let [left, right, up, down] = self.dpad.map(i32::from);
+                if i < 2 {
+                    (AbsoluteAxisType::ABS_HAT0X, right - left)
+                } else {
+                    (AbsoluteAxisType::ABS_HAT0Y, down - up)
+                }
+            }
+            InputEventKind::Key(key) => return BUTTONS.contains(&key).then_some(ev),
+            InputEventKind::AbsAxis(axis) => {
+                let &(min, max) = self.ranges.get(&axis.0)?;
+                let pos = f64::from(ev.value() - min) / f64::from((max - min).max(1));
+                let value = (pos * f64::from(2 * STICK_MAX + 1)).round() as i32 - STICK_MAX - 1;
+                return Some(InputEvent::new(EventType::ABSOLUTE, axis.0, value));
+            }
+            _ => return None,
+        };
+        let value = match axis {
+            // the triggers are all or nothing
+            AbsoluteAxisType::ABS_Z | AbsoluteAxisType::ABS_RZ => value.min(1) * TRIGGER_MAX,
+            _ => value,
+        };
+        Some(InputEvent::new(EventType::ABSOLUTE, axis.0, value))
+    }
+}
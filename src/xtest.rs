@@ -0,0 +1,134 @@
+//! Emitting through the X server's XTEST extension (`--output xtest`), for X11 sessions where
+//! joykbd can't create uinput devices, e.g. without access to /dev/uinput. Keys are sent by their
+//! X keycode (the evdev code plus 8, as the X server's evdev driver numbers them), so they're read
+//! with the server's keymap like a real keyboard's.
+
+use crate::output::OutputSink;
+use evdev::{AbsoluteAxisType, InputEvent, InputEventKind, Key, RelativeAxisType};
+use std::io;
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::xproto::{
+    Window, BUTTON_PRESS_EVENT, BUTTON_RELEASE_EVENT, KEY_PRESS_EVENT, KEY_RELEASE_EVENT,
+    MOTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::xtest::{self, ConnectionExt as _};
+use x11rb::rust_connection::RustConnection;
+
+/// The X keycode of evdev code 0.
+const KEYCODE_OFFSET: u16 = 8;
+
+pub struct XtestSink {
+    conn: RustConnection,
+    root: Window,
+    /// Where an absolute cursor (see [`crate::cursor`]) last put the pointer, as its events come
+    /// an axis at a time.
+    position: (i16, i16),
+}
+
+impl XtestSink {
+    /// Connect to the X server in `$DISPLAY`.
+    pub fn connect() -> io::Result<Self> {
+        let (conn, screen) = x11rb::connect(None).map_err(io::Error::other)?;
+        if conn
+            .extension_information(xtest::X11_EXTENSION_NAME)
+            .map_err(io::Error::other)?
+            .is_none()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the X server doesn't have the XTEST extension",
+            ));
+        }
+        let root = conn.setup().roots[screen].root;
+        Ok(XtestSink {
+            conn,
+            root,
+            position: (0, 0),
+        })
+    }
+
+    fn fake(&self, kind: u8, detail: u8, x: i16, y: i16) -> io::Result<()> {
+        self.conn
+            .xtest_fake_input(kind, detail, 0, self.root, x, y, 0)
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    /// Click X's scroll `button` `clicks` times.
+    fn scroll(&self, button: u8, clicks: i32) -> io::Result<()> {
+        for _ in 0..clicks.unsigned_abs() {
+            self.fake(BUTTON_PRESS_EVENT, button, 0, 0)?;
+            self.fake(BUTTON_RELEASE_EVENT, button, 0, 0)?;
+        }
+        Ok(())
+    }
+}
+
+/// The X pointer button for `key`, if it's a mouse button.
+fn button(key: Key) -> Option<u8> {
+    Some(match key {
+        Key::BTN_LEFT => 1,
+        Key::BTN_MIDDLE => 2,
+        Key::BTN_RIGHT => 3,
+        Key::BTN_SIDE => 8,
+        Key::BTN_EXTRA => 9,
+        _ => return None,
+    })
+}
+
+impl OutputSink for XtestSink {
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        // relative motion is sent once for the whole report, like a mouse's
+        let (mut dx, mut dy) = (0, 0);
+        let mut moved_to = false;
+        for ev in events {
+            match ev.kind() {
+                // X repeats held keys itself
+                InputEventKind::Key(_) if ev.value() == 2 => {}
+                InputEventKind::Key(key) => {
+                    let pressed = ev.value() != 0;
+                    if let Some(button) = button(key) {
+                        let kind = match pressed {
+                            true => BUTTON_PRESS_EVENT,
+                            false => BUTTON_RELEASE_EVENT,
+                        };
+                        self.fake(kind, button, 0, 0)?;
+                    } else if let Ok(keycode) = u8::try_from(key.code() + KEYCODE_OFFSET) {
+                        let kind = match pressed {
+                            true => KEY_PRESS_EVENT,
+                            false => KEY_RELEASE_EVENT,
+                        };
+                        self.fake(kind, keycode, 0, 0)?;
+                    }
+                }
+                InputEventKind::RelAxis(RelativeAxisType::REL_X) => dx += ev.value(),
+                InputEventKind::RelAxis(RelativeAxisType::REL_Y) => dy += ev.value(),
+                // up and down are buttons 4 and 5, and left and right 6 and 7
+                InputEventKind::RelAxis(RelativeAxisType::REL_WHEEL) => {
+                    self.scroll(if ev.value() > 0 { 4 } else { 5 }, ev.value())?
+                }
+                InputEventKind::RelAxis(RelativeAxisType::REL_HWHEEL) => {
+                    self.scroll(if ev.value() > 0 { 7 } else { 6 }, ev.value())?
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_X) => {
+                    self.position.0 = ev.value() as i16;
+                    moved_to = true;
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_Y) => {
+                    self.position.1 = ev.value() as i16;
+                    moved_to = true;
+                }
+                _ => {}
+            }
+        }
+        if moved_to {
+            let (x, y) = self.position;
+            self.fake(MOTION_NOTIFY_EVENT, 0, x, y)?;
+        }
+        if dx != 0 || dy != 0 {
+            // a detail of 1 makes the motion relative
+            self.fake(MOTION_NOTIFY_EVENT, 1, dx as i16, dy as i16)?;
+        }
+        self.conn.flush().map_err(io::Error::other)
+    }
+}